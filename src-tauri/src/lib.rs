@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 use thiserror::Error;
@@ -18,6 +19,9 @@ pub struct SearchResult {
     pub price: Option<String>,
     pub stock: Option<String>,
     pub image_url: Option<String>,
+    /// JLCPCB assembly class ("Basic" / "Extended") when the source reports it.
+    #[serde(default)]
+    pub jlc_class: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -32,6 +36,10 @@ pub enum JlcError {
     JsonError(#[from] serde_json::Error),
     #[error("Parse error: {0}")]
     ParseError(String),
+    /// An HTML page where data was expected, usually a captcha or rate-limit
+    /// page, sometimes a proxy's error page.
+    #[error("服务器返回了 HTML 页面而不是数据（可能是验证码、限流或代理错误页）: {0}")]
+    HtmlResponse(String),
 }
 
 impl Serialize for JlcError {
@@ -48,11 +56,42 @@ const EASYEDA_BASE_URLS: [&str; 2] = ["https://lceda.cn", "https://easyeda.com"]
 const PRO_EASYEDA_BASE_URLS: [&str; 2] = ["https://pro.lceda.cn", "https://pro.easyeda.com"];
 const MODEL_BASE_URLS: [&str; 2] = ["https://modules.lceda.cn", "https://modules.easyeda.com"];
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NetworkSettings {
     pub easyeda_use_proxy: bool,
     pub lcsc_use_proxy: bool,
     pub proxy_address: String,
+    /// EasyEDA session cookie for team/private parts. Kept in memory only.
+    #[serde(default)]
+    pub easyeda_cookie: String,
+    /// EasyEDA API token, sent as a bearer token. Kept in memory only.
+    #[serde(default)]
+    pub easyeda_token: String,
+    /// Seconds allowed for a search or metadata (text/JSON) request.
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout_secs: u64,
+    /// Seconds allowed for a 3D model download, which can run to megabytes.
+    #[serde(default = "default_download_timeout")]
+    pub download_timeout_secs: u64,
+    /// Extra headers on every EasyEDA request, e.g. a gateway token; the
+    /// login cookie and token above take precedence.
+    #[serde(default)]
+    pub easyeda_headers: BTreeMap<String, String>,
+    /// Extra headers on every LCSC request, replacing the browser-like
+    /// User-Agent and Referer when they name the same header.
+    #[serde(default)]
+    pub lcsc_headers: BTreeMap<String, String>,
+}
+
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_DOWNLOAD_TIMEOUT_SECS: u64 = 120;
+
+fn default_request_timeout() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT_SECS
+}
+
+fn default_download_timeout() -> u64 {
+    DEFAULT_DOWNLOAD_TIMEOUT_SECS
 }
 
 impl Default for NetworkSettings {
@@ -61,21 +100,137 @@ impl Default for NetworkSettings {
             easyeda_use_proxy: true,
             lcsc_use_proxy: false,
             proxy_address: "http://127.0.0.1:10808".to_string(),
+            easyeda_cookie: String::new(),
+            easyeda_token: String::new(),
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT_SECS,
+            download_timeout_secs: DEFAULT_DOWNLOAD_TIMEOUT_SECS,
+            easyeda_headers: BTreeMap::new(),
+            lcsc_headers: BTreeMap::new(),
+        }
+    }
+}
+
+// Credentials never reach the logs.
+impl std::fmt::Debug for NetworkSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redact = |v: &str| if v.trim().is_empty() { "" } else { "<redacted>" };
+        f.debug_struct("NetworkSettings")
+            .field("easyeda_use_proxy", &self.easyeda_use_proxy)
+            .field("lcsc_use_proxy", &self.lcsc_use_proxy)
+            .field("proxy_address", &self.proxy_address)
+            .field("easyeda_cookie", &redact(&self.easyeda_cookie))
+            .field("easyeda_token", &redact(&self.easyeda_token))
+            .field("request_timeout_secs", &self.request_timeout_secs)
+            .field("download_timeout_secs", &self.download_timeout_secs)
+            .field("easyeda_headers", &self.easyeda_headers.keys().collect::<Vec<_>>())
+            .field("lcsc_headers", &self.lcsc_headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl NetworkSettings {
+    /// Timeout of text requests; 0 means the default.
+    fn request_timeout(&self) -> Duration {
+        match self.request_timeout_secs {
+            0 => Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            secs => Duration::from_secs(secs),
+        }
+    }
+
+    /// Timeout of model downloads; never shorter than the text one.
+    fn download_timeout(&self) -> Duration {
+        let secs = match self.download_timeout_secs {
+            0 => DEFAULT_DOWNLOAD_TIMEOUT_SECS,
+            secs => secs,
+        };
+        Duration::from_secs(secs).max(self.request_timeout())
+    }
+
+    fn has_easyeda_auth(&self) -> bool {
+        !self.easyeda_cookie.trim().is_empty() || !self.easyeda_token.trim().is_empty()
+    }
+
+    /// Headers attached to every EasyEDA request, marked sensitive.
+    fn easyeda_auth_headers(&self) -> Result<reqwest::header::HeaderMap, JlcError> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let invalid = |_| JlcError::ApiError("EasyEDA 登录凭据包含无效字符".to_string());
+        let cookie = self.easyeda_cookie.trim();
+        if !cookie.is_empty() {
+            let mut value = reqwest::header::HeaderValue::from_str(cookie).map_err(invalid)?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::COOKIE, value);
+        }
+        let token = self.easyeda_token.trim();
+        if !token.is_empty() {
+            let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(invalid)?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+
+    /// All headers attached to EasyEDA requests: the extra ones, then the
+    /// login credentials over them.
+    fn easyeda_client_headers(&self) -> Result<reqwest::header::HeaderMap, JlcError> {
+        let mut headers = extra_headers(&self.easyeda_headers)?;
+        headers.extend(self.easyeda_auth_headers()?);
+        Ok(headers)
+    }
+
+    /// All headers attached to LCSC requests, which expect a browser.
+    fn lcsc_client_headers(&self) -> Result<reqwest::header::HeaderMap, JlcError> {
+        use reqwest::header::{HeaderValue, REFERER, USER_AGENT};
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(LCSC_USER_AGENT));
+        headers.insert(REFERER, HeaderValue::from_static("https://www.lcsc.com/"));
+        headers.extend(extra_headers(&self.lcsc_headers)?);
+        Ok(headers)
+    }
+}
+
+const LCSC_USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 \
+                               (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36";
+
+/// User-configured headers, checked for legal names and values. Values are
+/// marked sensitive, as they often carry tokens.
+fn extra_headers(
+    headers: &BTreeMap<String, String>,
+) -> Result<reqwest::header::HeaderMap, JlcError> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
         }
+        let header_name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| JlcError::ApiError(format!("无效的请求头名称: {}", name)))?;
+        let mut header_value = reqwest::header::HeaderValue::from_str(value.trim())
+            .map_err(|_| JlcError::ApiError(format!("请求头 {} 的值包含无效字符", name)))?;
+        header_value.set_sensitive(true);
+        map.insert(header_name, header_value);
     }
+    Ok(map)
 }
 
 static NETWORK_SETTINGS: OnceLock<Mutex<NetworkSettings>> = OnceLock::new();
+// Bumped on every settings change, under the settings lock.
+static NETWORK_GENERATION: AtomicU64 = AtomicU64::new(0);
 
 fn network_settings_store() -> &'static Mutex<NetworkSettings> {
     NETWORK_SETTINGS.get_or_init(|| Mutex::new(NetworkSettings::default()))
 }
 
 pub fn get_network_settings() -> NetworkSettings {
-    network_settings_store()
-        .lock()
-        .map(|s| s.clone())
-        .unwrap_or_default()
+    network_snapshot().0
+}
+
+/// Settings together with the generation they belong to, read atomically.
+fn network_snapshot() -> (NetworkSettings, u64) {
+    match network_settings_store().lock() {
+        Ok(s) => (s.clone(), NETWORK_GENERATION.load(Ordering::SeqCst)),
+        Err(_) => (NetworkSettings::default(), NETWORK_GENERATION.load(Ordering::SeqCst)),
+    }
 }
 
 pub fn set_network_settings(settings: NetworkSettings) -> Result<(), JlcError> {
@@ -90,16 +245,215 @@ pub fn set_network_settings(settings: NetworkSettings) -> Result<(), JlcError> {
         reqwest::Proxy::all(proxy_addr)
             .map_err(|e| JlcError::ApiError(format!("代理地址无效: {}", e)))?;
     }
+    settings.easyeda_client_headers()?;
+    settings.lcsc_client_headers()?;
 
     match network_settings_store().lock() {
         Ok(mut state) => {
             *state = settings;
+            NETWORK_GENERATION.fetch_add(1, Ordering::SeqCst);
             Ok(())
         }
         Err(_) => Err(JlcError::ApiError("无法写入网络设置".to_string())),
     }
 }
 
+/// Entries each in-memory response map keeps before the oldest are dropped.
+const MAX_CACHED_RESPONSES: usize = 500;
+
+/// A map that forgets its oldest entries past `MAX_CACHED_RESPONSES`, so a
+/// long session of searches and conversions doesn't grow without bound.
+struct BoundedCache<V> {
+    entries: BTreeMap<String, V>,
+    order: VecDeque<String>,
+}
+
+impl<V> Default for BoundedCache<V> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl<V> BoundedCache<V> {
+    fn get(&self, key: &str) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > MAX_CACHED_RESPONSES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn values(&self) -> std::collections::btree_map::Values<'_, String, V> {
+        self.entries.values()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[derive(Default)]
+struct ResponseCache {
+    search: BoundedCache<Vec<SearchResult>>,
+    metadata: BoundedCache<String>,
+    details: BoundedCache<ComponentDetails>,
+    /// Pro API 3D model UUID by LCSC code; `None` when the part has none.
+    step_uuids: BoundedCache<Option<String>>,
+}
+
+static RESPONSE_CACHE: OnceLock<Mutex<ResponseCache>> = OnceLock::new();
+// Serializes writes to and clearing of the on-disk cache directory.
+static CACHE_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+fn response_cache() -> &'static Mutex<ResponseCache> {
+    RESPONSE_CACHE.get_or_init(|| Mutex::new(ResponseCache::default()))
+}
+
+fn cache_root() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("jlc2kicad"))
+}
+
+fn cached_metadata(path: &str) -> Option<String> {
+    response_cache()
+        .lock()
+        .ok()
+        .and_then(|c| c.metadata.get(path).cloned())
+}
+
+fn store_metadata(path: &str, text: String) {
+    if let Ok(mut c) = response_cache().lock() {
+        c.metadata.insert(path.to_string(), text);
+    }
+}
+
+fn cached_search(query: &str) -> Option<Vec<SearchResult>> {
+    response_cache()
+        .lock()
+        .ok()
+        .and_then(|c| c.search.get(query).cloned())
+}
+
+/// Search metadata for a part id from any cached search.
+fn cached_search_result(component_id: &str) -> Option<SearchResult> {
+    let cache = response_cache().lock().ok()?;
+    cache
+        .search
+        .values()
+        .flatten()
+        .find(|r| r.id.eq_ignore_ascii_case(component_id))
+        .cloned()
+}
+
+fn store_search(query: &str, results: &[SearchResult]) {
+    if let Ok(mut c) = response_cache().lock() {
+        c.search.insert(query.to_string(), results.to_vec());
+    }
+}
+
+/// Cache file of a model. A model id that is a URL may hold any character,
+/// so its entry is named after a MurmurHash3 of the URL instead.
+fn model_cache_path(model_uuid: &str) -> Option<PathBuf> {
+    let name = if is_model_url(model_uuid) {
+        let hash = murmur3::murmur3_x64_128(&mut model_uuid.as_bytes(), 0).ok()?;
+        format!("url_{:032x}", hash)
+    } else {
+        sanitize_footprint_name(model_uuid)
+    };
+    if name.is_empty() {
+        return None;
+    }
+    cache_root().map(|d| d.join("models").join(format!("{}.step", name)))
+}
+
+fn read_cached_model(model_uuid: &str) -> Option<Vec<u8>> {
+    let path = model_cache_path(model_uuid)?;
+    let _guard = CACHE_DIR_LOCK.lock().ok()?;
+    fs::read(path).ok().filter(|b| !b.is_empty())
+}
+
+fn store_cached_model(model_uuid: &str, content: &[u8]) {
+    let Some(path) = model_cache_path(model_uuid) else {
+        return;
+    };
+    let Ok(_guard) = CACHE_DIR_LOCK.lock() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return;
+        }
+    }
+    if let Err(e) = fs::write(&path, content) {
+        log::warn!("Failed to cache STEP model {:?}: {}", path, e);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheClearReport {
+    pub entries_cleared: usize,
+    pub bytes_freed: u64,
+}
+
+fn remove_dir_contents(dir: &Path, report: &mut CacheClearReport) -> Result<(), JlcError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            remove_dir_contents(&path, report)?;
+            fs::remove_dir(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+            report.entries_cleared += 1;
+            report.bytes_freed += meta.len();
+        }
+    }
+    Ok(())
+}
+
+/// Empties the search, metadata and model caches, in memory and on disk.
+pub fn clear_caches() -> Result<CacheClearReport, JlcError> {
+    let mut report = CacheClearReport::default();
+
+    match response_cache().lock() {
+        Ok(mut c) => {
+            report.entries_cleared +=
+                c.search.len() + c.metadata.len() + c.details.len() + c.step_uuids.len();
+            report.bytes_freed += c.metadata.values().map(|t| t.len() as u64).sum::<u64>();
+            c.search.clear();
+            c.metadata.clear();
+            c.details.clear();
+            c.step_uuids.clear();
+        }
+        Err(_) => return Err(JlcError::ApiError("无法清理内存缓存".to_string())),
+    }
+
+    let _guard = CACHE_DIR_LOCK
+        .lock()
+        .map_err(|_| JlcError::ApiError("无法清理磁盘缓存".to_string()))?;
+    if let Some(root) = cache_root() {
+        if root.is_dir() {
+            remove_dir_contents(&root, &mut report)?;
+        }
+    }
+    Ok(report)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ComponentData {
     pub success: bool,
@@ -110,6 +464,95 @@ pub struct ComponentData {
 pub struct ComponentResult {
     #[serde(rename = "component_uuid")]
     pub component_uuid: String,
+    /// 2 = symbol, 4 = footprint; missing on older responses.
+    #[serde(rename = "docType", default)]
+    pub doc_type: Option<i64>,
+    /// Rendered preview of the document; not every entry has one.
+    #[serde(default)]
+    pub svg: Option<String>,
+}
+
+impl ComponentData {
+    /// Footprint entries in response order. Without docType the last entry is
+    /// the footprint, as the svgs endpoint has always returned it.
+    fn footprint_uuids(&self) -> Vec<String> {
+        let flagged: Vec<String> = self
+            .result
+            .iter()
+            .filter(|r| r.doc_type == Some(4))
+            .map(|r| r.component_uuid.clone())
+            .collect();
+        if !flagged.is_empty() {
+            return flagged;
+        }
+        self.result
+            .last()
+            .map(|r| vec![r.component_uuid.clone()])
+            .unwrap_or_default()
+    }
+
+    fn symbol_uuids(&self) -> Vec<String> {
+        let footprints = self.footprint_uuids();
+        self.result
+            .iter()
+            .map(|r| r.component_uuid.clone())
+            .filter(|uuid| !footprints.contains(uuid))
+            .collect()
+    }
+}
+
+/// Everything known about one part, merged from the svgs, device and search
+/// endpoints.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComponentDetails {
+    pub id: String,
+    pub name: String,
+    pub package: Option<String>,
+    pub manufacturer: Option<String>,
+    pub description: Option<String>,
+    pub datasheet: Option<String>,
+    /// Title of the default footprint.
+    pub footprint: Option<String>,
+    pub stock: Option<String>,
+    pub price: Option<String>,
+    pub jlc_class: Option<String>,
+    /// Whether EasyEDA links a 3D model; nothing is downloaded to find out.
+    pub has_3d_model: bool,
+    pub model_uuid: Option<String>,
+}
+
+/// Raw EasyEDA data of one footprint or symbol, as returned by `debug_dump`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShapeDump {
+    pub uuid: String,
+    pub title: String,
+    /// Head `x`/`y`, the origin shape coordinates are relative to.
+    pub origin: (f64, f64),
+    pub unit: Option<String>,
+    pub shape: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentDump {
+    pub id: String,
+    pub footprint: ShapeDump,
+    pub symbols: Vec<ShapeDump>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FootprintVariant {
+    pub uuid: String,
+    pub title: String,
+    pub is_default: bool,
+}
+
+/// A footprint variant with its preview image.
+#[derive(Debug, Clone, Serialize)]
+pub struct FootprintThumbnail {
+    #[serde(flatten)]
+    pub variant: FootprintVariant,
+    /// SVG markup as rendered by EasyEDA; `None` when it has no preview.
+    pub svg: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -135,6 +578,9 @@ pub struct FootprintDataStr {
 pub struct FootprintHead {
     pub x: f64,
     pub y: f64,
+    /// Coordinate unit; only set by sources that store millimetres.
+    #[serde(default)]
+    pub unit: Option<String>,
     #[serde(rename = "c_para")]
     pub c_para: Option<FootprintCPara>,
 }
@@ -142,6 +588,8 @@ pub struct FootprintHead {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FootprintCPara {
     pub link: Option<String>,
+    #[serde(rename = "Manufacturer", default)]
+    pub manufacturer: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -155,12 +603,16 @@ pub struct SymbolResult {
     pub title: String,
     #[serde(rename = "dataStr")]
     pub data_str: SymbolDataStr,
-    #[serde(rename = "packageDetail")]
-    pub package_detail: PackageDetail,
+    /// Some responses omit it; the prefix then comes from the symbol itself.
+    #[serde(rename = "packageDetail", default)]
+    pub package_detail: Option<PackageDetail>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SymbolDataStr {
+    /// Tilde-delimited shape lines; Pro-style pin objects are turned into
+    /// equivalent `P~` lines when read.
+    #[serde(deserialize_with = "deserialize_symbol_shape")]
     pub shape: Vec<String>,
     pub head: SymbolHead,
 }
@@ -169,12 +621,16 @@ pub struct SymbolDataStr {
 pub struct SymbolHead {
     pub x: f64,
     pub y: f64,
-    #[serde(rename = "c_para")]
+    /// Coordinate unit; only set by sources that store millimetres.
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(rename = "c_para", default)]
     pub c_para: SymbolCPara,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SymbolCPara {
+    #[serde(default)]
     pub pre: String,
     #[serde(rename = "Resistance")]
     pub resistance: Option<String>,
@@ -184,23 +640,29 @@ pub struct SymbolCPara {
     pub inductance: Option<String>,
     #[serde(rename = "Frequency")]
     pub frequency: Option<String>,
+    #[serde(rename = "Manufacturer", default)]
+    pub manufacturer: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageDetail {
+    /// UUID of the footprint the symbol is drawn for.
+    #[serde(default)]
+    pub uuid: Option<String>,
     #[serde(rename = "dataStr")]
     pub data_str: PackageDetailData,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageDetailData {
+    #[serde(default)]
     pub head: PackageDetailHead,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct PackageDetailHead {
-    #[serde(rename = "c_para")]
-    pub c_para: SymbolCPara,
+    #[serde(rename = "c_para", default)]
+    pub c_para: Option<SymbolCPara>,
 }
 
 #[derive(Debug, Clone)]
@@ -214,8 +676,76 @@ pub struct FootprintInfo {
     pub footprint_lib: String,
     pub model_base_variable: String,
     pub model_dir: String,
+    /// See `ConversionOptions::shared_model_dir`; empty when unused.
+    pub shared_model_dir: String,
     pub origin: (f64, f64),
     pub models: Vec<String>,
+    pub min_silk_width: f64,
+    /// Net names seen on pads and zones; a net's code is its index + 1.
+    pub nets: Vec<String>,
+    /// Placement of EasyEDA's own designator (`P`) and name (`N`) texts.
+    pub reference_text: Option<TextPlacement>,
+    pub value_text: Option<TextPlacement>,
+    /// Source coordinates are already in mm (see `is_metric_unit`).
+    pub metric: bool,
+    /// Douglas–Peucker tolerance in mm for logo and graphic polygons.
+    pub simplify_tolerance: f64,
+    /// Anchor of every primitive, collected when `geometry_report` is set.
+    pub geometry: Option<Vec<GeometryRow>>,
+    /// Primitives left out of the footprint, described for the report.
+    pub warnings: Vec<String>,
+}
+
+/// One primitive's anchor point as EasyEDA stores it and as first emitted,
+/// in mm; before the origin shift while collected, after it in a report.
+#[derive(Debug, Clone, Serialize)]
+pub struct GeometryRow {
+    pub kind: String,
+    pub source: (f64, f64),
+    pub converted: (f64, f64),
+}
+
+/// Coordinate table of one written footprint, see `geometry_report`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FootprintGeometry {
+    pub id: String,
+    pub footprint: String,
+    /// Unit of the source coordinates: "mm" or "10 mil".
+    pub source_unit: String,
+    pub rows: Vec<GeometryRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextPlacement {
+    pub x: f64,
+    pub y: f64,
+    pub rotation: f64,
+    pub size: f64,
+    pub thickness: f64,
+    pub hidden: bool,
+    /// Drawn mirrored, as text on a back layer must be to read correctly.
+    pub mirrored: bool,
+}
+
+impl FootprintInfo {
+    fn mm(&self, value: f64) -> f64 {
+        to_mm(value, self.metric)
+    }
+
+    fn net_code(&mut self, name: &str) -> Option<usize> {
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let idx = match self.nets.iter().position(|n| n == name) {
+            Some(idx) => idx,
+            None => {
+                self.nets.push(name.to_string());
+                self.nets.len() - 1
+            }
+        };
+        Some(idx + 1)
+    }
 }
 
 impl Default for FootprintInfo {
@@ -230,105 +760,933 @@ impl Default for FootprintInfo {
             footprint_lib: String::from("footprint"),
             model_base_variable: String::new(),
             model_dir: String::from("packages3d"),
+            shared_model_dir: String::new(),
             origin: (0.0, 0.0),
             models: vec![String::from("STEP")],
+            min_silk_width: 0.0,
+            nets: Vec::new(),
+            reference_text: None,
+            value_text: None,
+            metric: false,
+            simplify_tolerance: DEFAULT_SIMPLIFY_TOLERANCE,
+            geometry: None,
+            warnings: Vec::new(),
         }
     }
 }
 
-fn mil2mm(mils: f64) -> f64 {
-    mils / 3.937
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversionOptions {
+    /// Mirror generated footprints onto the back side (F.* <-> B.*).
+    pub mirror_to_back: bool,
+    /// Path variable standing for `output_dir` in (model ...) references,
+    /// e.g. `${KICAD8_3RD_PARTY}/JLC2KiCad_lib`. Empty keeps relative paths.
+    pub model_base_variable: String,
+    /// Emit alternate (de Morgan) units as KiCad body style 2.
+    pub demorgan: bool,
+    /// Target KiCad major version (5-9); 0 means the latest supported.
+    pub kicad_version: u32,
+    /// Footprint UUID or variant title to use instead of the default footprint.
+    pub footprint_variant: String,
+    /// Minimum silkscreen stroke width in mm; 0 disables clamping.
+    pub min_silk_width: f64,
+    /// Split footprints into one library per package family or manufacturer.
+    pub footprint_grouping: FootprintGrouping,
+    /// Also convert every other footprint variant, suffixed `_V<n>`.
+    pub all_footprint_variants: bool,
+    /// Convert a device bundling several components into one part per
+    /// component, see `composite_parts`.
+    pub split_composite_devices: bool,
+    /// Appended to the footprint name; set per variant, never by callers.
+    #[serde(skip)]
+    pub footprint_suffix: String,
+    /// Undo this component's file writes when its conversion fails.
+    pub rollback_on_failure: bool,
+    /// Store the STEP model inside the footprint (KiCad 9 embedded files)
+    /// instead of as a separate file.
+    pub embed_3d_model: bool,
+    /// Decimal places for emitted millimetre values; `None` uses 4 like KiCad.
+    pub coordinate_decimals: Option<u32>,
+    /// Tag put in front of symbol and footprint names, e.g. "PROJX".
+    pub name_prefix: String,
+    /// Also put the part's manufacturer in front of the names.
+    pub prefix_manufacturer: bool,
+    /// Keep an `index.md` in `output_dir` listing every converted part.
+    pub write_index: bool,
+    /// 3D format tried first; the other is only a fallback unless `Both`,
+    /// which also downloads it but references just the STEP file.
+    pub preferred_3d_format: ModelFormat,
+    /// Write the (model ...) line for the expected file without downloading
+    /// it, for users who keep their own 3D library.
+    pub model_reference_only: bool,
+    /// Symbol pin name and number text size in mm; `None` keeps 1.
+    pub pin_text_size: Option<f64>,
+    /// Distance between a pin's end and its name in mm; `None` leaves KiCad's default.
+    pub pin_name_offset: Option<f64>,
+    /// Fail the whole component when a requested 3D model cannot be fetched,
+    /// instead of keeping the footprint and symbol with a warning. Without it
+    /// a 3D-only conversion that gets no model is only a warning too.
+    pub strict_3d: bool,
+    /// Fail a part whose footprint or symbol uses element kinds the converter
+    /// does not handle, instead of leaving those elements out.
+    pub strict_parse: bool,
+    /// Name resistors, capacitors and inductors "R_0402_10k" style, from the
+    /// prefix, chip size and value, instead of their EasyEDA titles. Other
+    /// parts and explicit `name_overrides` are left alone.
+    pub passive_names: bool,
+    /// Keep footprint and 3D file names ASCII, replacing other characters and
+    /// falling back to the part id; symbol values keep the original title.
+    pub ascii_file_names: bool,
+    /// Emit footprint pads and symbol pins in natural order of their numbers
+    /// rather than EasyEDA's source order, for stable diffs.
+    pub sort_pads_and_pins: bool,
+    /// Douglas–Peucker tolerance in mm for logos and other graphic polygons;
+    /// `None` uses 0.02.
+    pub simplify_tolerance: Option<f64>,
+    /// Write each symbol to its own `<symbol>.kicad_sym` next to the others
+    /// instead of one library file named after the symbol library.
+    pub symbol_per_part: bool,
+    /// Local conversions: keep a progress file in `output_dir` and skip parts
+    /// an earlier, interrupted run already converted.
+    pub resume: bool,
+    /// Local conversions: convert the footprint of parts whose offline
+    /// footprint data is missing online, rather than failing them.
+    pub online_fallback: bool,
+    /// Local conversions: more folders searched for STEP/WRL models after the
+    /// input itself; the first place holding a name wins.
+    pub model_search_dirs: Vec<String>,
+    /// Exact footprint and symbol names by part id, used instead of the names
+    /// derived from EasyEDA titles.
+    pub name_overrides: BTreeMap<String, String>,
+    /// Datasheet URLs by part id, used instead of EasyEDA's (often stale)
+    /// link. Parts missing from a non-empty map are reported.
+    pub datasheet_overrides: BTreeMap<String, String>,
+    /// Order of a multi-unit part's symbols by part id, as 1-based positions
+    /// in EasyEDA's order; the first listed becomes unit 1. Unlisted units
+    /// follow in source order.
+    pub unit_order: BTreeMap<String, Vec<usize>>,
+    /// Absolute folder shared between projects (e.g. the team's
+    /// KICAD9_3DMODEL_DIR) that 3D models go to instead of the library's
+    /// model folder. Models already stored there are reused, not fetched again.
+    pub shared_model_dir: String,
+    /// Record this tool's version in library headers (KiCad 8+) and when the
+    /// EasyEDA data was fetched in symbol properties.
+    pub annotate_generator: bool,
+    /// Leave conversion timestamps out so repeated runs write identical files.
+    pub deterministic: bool,
+    /// List every footprint primitive's EasyEDA coordinates next to the
+    /// millimetres written, in the report's `geometry`.
+    pub geometry_report: bool,
+    /// Local conversions: afterwards load the written libraries with
+    /// kicad-cli, when installed, and warn about any it rejects.
+    pub kicad_cli_check: bool,
+    /// Add a silkscreen dot beside pad 1 (or A1) for assembly orientation;
+    /// EasyEDA has no pad-1 flag, so the pad is found by its number.
+    pub pad_one_marker: bool,
+    /// Part id used by `ascii_file_names` and `name_overrides`; set per
+    /// component, never by callers.
+    #[serde(skip)]
+    pub file_name_fallback: String,
+    /// Manufacturer that `prefix_manufacturer` names a local part's symbol
+    /// and footprint after; set per component, never by callers.
+    #[serde(skip)]
+    part_manufacturer: Option<String>,
+    #[serde(skip)]
+    journal: Option<WriteJournal>,
+    /// Libraries this conversion wrote, collected for `kicad_cli_check`.
+    #[serde(skip)]
+    written: Option<WrittenLibraries>,
 }
 
-fn sanitize_footprint_name(title: &str) -> String {
-    title
-        .replace(" ", "_")
-        .replace("/", "_")
-        .replace("(", "_")
-        .replace(")", "_")
+#[derive(Debug)]
+struct JournalEntry {
+    path: PathBuf,
+    /// Content the write replaced; `None` when the file did not exist before.
+    previous: Option<Vec<u8>>,
 }
 
-fn extract_model_uuid_from_shape(shape: &[String]) -> Option<String> {
-    for line in shape {
-        let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
-        if parts.first().copied() != Some("SVGNODE") {
-            continue;
+/// Files written during one component's conversion.
+#[derive(Debug, Clone, Default)]
+struct WriteJournal {
+    entries: std::sync::Arc<Mutex<Vec<JournalEntry>>>,
+}
+
+impl WriteJournal {
+    fn record(&self, path: &Path) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if !entries.iter().any(|e| e.path == path) {
+                entries.push(JournalEntry {
+                    path: path.to_path_buf(),
+                    previous: fs::read(path).ok(),
+                });
+            }
         }
-        if let Some(raw) = parts.get(1) {
-            if let Ok(json_data) = serde_json::from_str::<serde_json::Value>(raw) {
-                if let Some(uuid) = json_data
-                    .get("attrs")
-                    .and_then(|a| a.get("uuid"))
-                    .and_then(|u| u.as_str())
-                {
-                    return Some(uuid.to_string());
-                }
+    }
+
+    fn rollback(&self) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        for entry in entries.drain(..).rev() {
+            let result = match entry.previous {
+                Some(content) => fs::write(&entry.path, content),
+                None => fs::remove_file(&entry.path),
+            };
+            match result {
+                Ok(_) => log::info!("Rolled back {:?}", entry.path),
+                Err(e) => log::warn!("Failed to roll back {:?}: {}", entry.path, e),
             }
         }
     }
-    None
 }
 
-fn uuid_first_part(value: &str) -> String {
-    value.split('|').next().unwrap_or(value).to_string()
+/// Symbol library files and footprint library folders written by one
+/// conversion, shared by the per-component copies of its options.
+#[derive(Debug, Clone, Default)]
+struct WrittenLibraries {
+    paths: std::sync::Arc<Mutex<BTreeSet<PathBuf>>>,
 }
 
-fn first_non_empty_str(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
-    for key in keys {
-        if let Some(v) = value.get(*key).and_then(|v| v.as_str()) {
-            let trimmed = v.trim();
-            if !trimmed.is_empty() {
-                return Some(trimmed.to_string());
-            }
+impl WrittenLibraries {
+    fn record(&self, path: &Path) {
+        let library = match path.extension().and_then(|e| e.to_str()) {
+            Some("kicad_sym" | "lib") => path.to_path_buf(),
+            Some("kicad_mod") => match path.parent() {
+                Some(dir) => dir.to_path_buf(),
+                None => return,
+            },
+            _ => return,
+        };
+        if let Ok(mut paths) = self.paths.lock() {
+            paths.insert(library);
         }
     }
-    None
-}
 
-fn looks_like_uuidish(value: &str) -> bool {
-    let s = value.trim();
-    if s.is_empty() {
-        return false;
+    fn libraries(&self) -> Vec<PathBuf> {
+        self.paths
+            .lock()
+            .map(|paths| paths.iter().cloned().collect())
+            .unwrap_or_default()
     }
+}
 
-    let first = s.split('|').next().unwrap_or(s);
-    let is_hex = |c: char| c.is_ascii_hexdigit();
-
-    if first.len() == 32 && first.chars().all(is_hex) {
-        return true;
+/// Notes `path` in the options' journal and written libraries, if any,
+/// before it is written.
+fn record_write(path: &Path, options: &ConversionOptions) {
+    if let Some(journal) = &options.journal {
+        journal.record(path);
     }
-
-    if first.len() == 36 {
-        let bytes = first.as_bytes();
-        if bytes.get(8) == Some(&b'-')
-            && bytes.get(13) == Some(&b'-')
-            && bytes.get(18) == Some(&b'-')
-            && bytes.get(23) == Some(&b'-')
-            && first
-                .chars()
-                .enumerate()
-                .all(|(i, ch)| matches!(i, 8 | 13 | 18 | 23) || is_hex(ch))
-        {
-            return true;
-        }
+    if let Some(written) = &options.written {
+        written.record(path);
     }
+}
 
-    false
+fn write_output_file(path: &Path, content: &[u8], options: &ConversionOptions) -> Result<(), JlcError> {
+    record_write(path, options);
+    write_file_atomic(path, content)?;
+    Ok(())
 }
 
-fn first_readable_package(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
-    for key in keys {
-        if let Some(v) = value.get(*key).and_then(|v| v.as_str()) {
-            let trimmed = v.trim();
-            if !trimmed.is_empty() && !looks_like_uuidish(trimmed) {
-                return Some(trimmed.to_string());
-            }
-        }
+const PARTS_INDEX_FILE: &str = "index.md";
+const PARTS_INDEX_HEADER: &str = "| 编号 | 名称 | 封装 | 制造商 | 数据手册 | 封装文件 | 符号库 | 3D 模型 |\n\
+|---|---|---|---|---|---|---|---|\n";
+
+struct PartIndexEntry {
+    id: String,
+    name: String,
+    package: String,
+    manufacturer: String,
+    datasheet: String,
+    footprint: Option<PathBuf>,
+    symbol: Option<PathBuf>,
+    model: Option<PathBuf>,
+}
+
+impl PartIndexEntry {
+    fn row(&self, output_dir: &Path) -> String {
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        let file = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|p| {
+                    let rel = p.strip_prefix(output_dir).unwrap_or(p);
+                    format!("`{}`", cell(&rel.to_string_lossy().replace('\\', "/")))
+                })
+                .unwrap_or_default()
+        };
+        let datasheet = if self.datasheet.is_empty() {
+            String::new()
+        } else {
+            format!("[PDF]({})", cell(&self.datasheet))
+        };
+        format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} |",
+            cell(&self.id),
+            cell(&self.name),
+            cell(&self.package),
+            cell(&self.manufacturer),
+            datasheet,
+            file(&self.footprint),
+            file(&self.symbol),
+            file(&self.model)
+        )
     }
-    None
 }
 
-fn extract_package_name(value: &serde_json::Value) -> Option<String> {
+/// Adds or replaces the part's row in `output_dir/index.md`; rows of other
+/// parts are kept in their original order.
+fn update_parts_index(output_dir: &Path, entry: &PartIndexEntry) -> Result<(), JlcError> {
+    let path = output_dir.join(PARTS_INDEX_FILE);
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let row_id = |line: &str| {
+        line.strip_prefix("| ")
+            .and_then(|rest| rest.split(" | ").next())
+            .map(|id| id.trim().to_string())
+    };
+
+    let mut rows: Vec<String> = existing
+        .lines()
+        .filter(|line| {
+            row_id(line).is_some_and(|id| id.starts_with('C') && id[1..].parse::<u64>().is_ok())
+        })
+        .map(|line| line.to_string())
+        .collect();
+    let row = entry.row(output_dir);
+    match rows
+        .iter_mut()
+        .find(|line| row_id(line).is_some_and(|id| id.eq_ignore_ascii_case(&entry.id)))
+    {
+        Some(line) => *line = row,
+        None => rows.push(row),
+    }
+
+    let mut content = String::from("# JLC2KiCad 元件索引\n\n");
+    content.push_str(PARTS_INDEX_HEADER);
+    for row in rows {
+        content.push_str(&row);
+        content.push('\n');
+    }
+    fs::create_dir_all(output_dir)?;
+    write_file_atomic(&path, content.as_bytes())?;
+    Ok(())
+}
+
+/// Hidden scratch file beside `path` that is renamed over it once complete.
+fn atomic_tmp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+/// Writes through a temporary file in the same directory and renames it over
+/// `path`, so a failed write never leaves a truncated file behind.
+fn write_file_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = atomic_tmp_path(path);
+
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// How footprints are spread over libraries: all in `footprint_lib`, or in
+/// sibling libraries such as `footprint_SOT.pretty`, see
+/// `ConversionOptions::footprint_library`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FootprintGrouping {
+    #[default]
+    Flat,
+    Package,
+    Manufacturer,
+}
+
+/// Which 3D model format to download when both STEP and WRL exist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    #[default]
+    Step,
+    Wrl,
+    Both,
+}
+
+impl ModelFormat {
+    /// File extensions in the order they are tried.
+    fn extensions(self) -> [&'static str; 2] {
+        match self {
+            ModelFormat::Wrl => ["wrl", "step"],
+            ModelFormat::Step | ModelFormat::Both => ["step", "wrl"],
+        }
+    }
+}
+
+pub const LATEST_KICAD_VERSION: u32 = 9;
+
+/// Footprint and symbol element kinds the shape loops convert.
+const FOOTPRINT_SHAPE_KINDS: [&str; 10] = [
+    "PAD", "TRACK", "CIRCLE", "ARC", "RECT", "HOLE", "SOLIDREGION", "TEXT", "DIMENSION", "SVGNODE",
+];
+const SYMBOL_SHAPE_KINDS: [&str; 7] = ["P", "R", "E", "T", "PL", "PG", "A"];
+
+const DEFAULT_SIMPLIFY_TOLERANCE: f64 = 0.02;
+/// Graphics with more source points than this (dense bitmap logos) are skipped.
+const MAX_GRAPHIC_SOURCE_POINTS: usize = 20_000;
+/// Points kept per polygon; the tolerance grows until a polygon fits.
+const MAX_POLYGON_POINTS: usize = 1_000;
+
+impl ConversionOptions {
+    /// Target KiCad major version, with 0 or an unknown value meaning latest.
+    pub fn kicad_target(&self) -> u32 {
+        match self.kicad_version {
+            5..=LATEST_KICAD_VERSION => self.kicad_version,
+            _ => LATEST_KICAD_VERSION,
+        }
+    }
+
+    fn validate(&self) -> Result<(), JlcError> {
+        if !matches!(self.kicad_version, 0 | 5..=LATEST_KICAD_VERSION) {
+            return Err(JlcError::ApiError(format!(
+                "不支持的 KiCad 版本: {}（支持 5-{}）",
+                self.kicad_version, LATEST_KICAD_VERSION
+            )));
+        }
+        for (id, name) in &self.name_overrides {
+            if let Some(problem) = invalid_name_reason(name.trim()) {
+                return Err(JlcError::ApiError(format!(
+                    "元件 {} 的自定义名称 \"{}\" 无效: {}",
+                    id, name, problem
+                )));
+            }
+        }
+        for (id, url) in &self.datasheet_overrides {
+            let valid = reqwest::Url::parse(url.trim())
+                .map(|u| matches!(u.scheme(), "http" | "https") && u.has_host())
+                .unwrap_or(false);
+            if !valid {
+                return Err(JlcError::ApiError(format!(
+                    "元件 {} 的数据手册链接无效: {}",
+                    id, url
+                )));
+            }
+        }
+        for (id, order) in &self.unit_order {
+            let distinct: BTreeSet<&usize> = order.iter().collect();
+            if order.contains(&0) || distinct.len() != order.len() {
+                return Err(JlcError::ApiError(format!(
+                    "元件 {} 的单元顺序无效: 序号从 1 开始且不能重复",
+                    id
+                )));
+            }
+        }
+        let shared = self.shared_model_dir.trim();
+        if !shared.is_empty() && !Path::new(shared).is_absolute() {
+            return Err(JlcError::ApiError(format!(
+                "共享3D模型目录必须是绝对路径: {}",
+                shared
+            )));
+        }
+        Ok(())
+    }
+
+    /// Where 3D files go when a shared model folder is set; `None` when it is
+    /// not or when models are embedded in the footprint.
+    fn shared_model_dir(&self) -> Option<PathBuf> {
+        let dir = self.shared_model_dir.trim();
+        (!dir.is_empty() && !self.embeds_3d_model()).then(|| PathBuf::from(dir))
+    }
+
+    /// The caller's name for `component_id`, if any.
+    fn name_override(&self, component_id: &str) -> Option<&str> {
+        self.name_overrides
+            .iter()
+            .find(|(id, _)| id.trim().eq_ignore_ascii_case(component_id.trim()))
+            .map(|(_, name)| name.trim())
+    }
+
+    /// The caller's datasheet URL for `component_id`, if any.
+    fn datasheet_override(&self, component_id: &str) -> Option<&str> {
+        self.datasheet_overrides
+            .iter()
+            .find(|(id, _)| id.trim().eq_ignore_ascii_case(component_id.trim()))
+            .map(|(_, url)| url.trim())
+    }
+
+    /// `symbol_uuids` in the caller's unit order for `component_id`; positions
+    /// past the last unit are ignored.
+    fn ordered_units<'a>(&self, component_id: &str, symbol_uuids: &'a [String]) -> Vec<&'a String> {
+        let order = self
+            .unit_order
+            .iter()
+            .find(|(id, _)| id.trim().eq_ignore_ascii_case(component_id.trim()))
+            .map(|(_, order)| order.as_slice())
+            .unwrap_or_default();
+        let mut ordered: Vec<&String> = Vec::new();
+        for &position in order {
+            match symbol_uuids.get(position.wrapping_sub(1)) {
+                Some(uuid) => ordered.push(uuid),
+                None => log::warn!("{}: unit {} does not exist", component_id, position),
+            }
+        }
+        for uuid in symbol_uuids {
+            if !ordered.contains(&uuid) {
+                ordered.push(uuid);
+            }
+        }
+        ordered
+    }
+
+    /// A warning when datasheet overrides were given but none covers
+    /// `component_id`.
+    fn missing_datasheet(&self, component_id: &str) -> Option<String> {
+        (!self.datasheet_overrides.is_empty() && self.datasheet_override(component_id).is_none())
+            .then(|| "数据手册映射中没有该元件，保留 EasyEDA 链接".to_string())
+    }
+
+    /// With `strict_parse`, an error naming the element kinds in `shape` that
+    /// are not in `known`.
+    fn check_shape_kinds(&self, shape: &[String], known: &[&str]) -> Result<(), JlcError> {
+        if !self.strict_parse {
+            return Ok(());
+        }
+        let unknown: BTreeSet<&str> = shape
+            .iter()
+            .filter_map(|line| line.split('~').find(|s| !s.is_empty()))
+            .filter(|kind| !known.contains(kind))
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        let kinds: Vec<&str> = unknown.into_iter().collect();
+        Err(JlcError::ParseError(format!("存在未识别的图形元素: {}", kinds.join(", "))))
+    }
+
+    /// Options for part `n` (0-based) of a composite device: a name override
+    /// for the device gets the part number appended so the parts don't collide.
+    fn for_composite_part(&self, component_id: &str, n: usize) -> ConversionOptions {
+        let mut options = self.clone();
+        if let Some(name) = self.name_override(component_id) {
+            let name = format!("{}_{}", name, n + 1);
+            options
+                .name_overrides
+                .retain(|id, _| !id.trim().eq_ignore_ascii_case(component_id.trim()));
+            options.name_overrides.insert(component_id.to_string(), name);
+        }
+        options
+    }
+
+    /// "TAG_MANUFACTURER_" style prefix for generated names; empty when unused.
+    fn name_prefix(&self, manufacturer: Option<&str>) -> String {
+        let mut prefix = String::new();
+        let tag = sanitize_footprint_name(self.name_prefix.trim());
+        let manufacturer = self
+            .part_manufacturer
+            .as_deref()
+            .or(manufacturer)
+            .filter(|_| self.prefix_manufacturer)
+            .map(|m| sanitize_footprint_name(m.trim()))
+            .unwrap_or_default();
+        for part in [tag, manufacturer] {
+            let part = part.trim_matches('_');
+            if !part.is_empty() {
+                prefix.push_str(part);
+                prefix.push('_');
+            }
+        }
+        prefix
+    }
+
+    /// Library a footprint titled `title` goes to: `footprint_lib` itself,
+    /// or with grouping `footprint_lib` suffixed by the package family or
+    /// manufacturer, `.pretty` kept last. KiCad loads no nested folders, so
+    /// each group is a library of its own.
+    fn footprint_library(
+        &self,
+        footprint_lib: &str,
+        title: &str,
+        manufacturer: Option<&str>,
+    ) -> String {
+        let group = match self.footprint_grouping {
+            FootprintGrouping::Flat => return footprint_lib.to_string(),
+            FootprintGrouping::Package => package_family(title),
+            FootprintGrouping::Manufacturer => self
+                .part_manufacturer
+                .as_deref()
+                .or(manufacturer)
+                .map(|m| sanitize_footprint_name(m.trim()))
+                .filter(|m| invalid_name_reason(m).is_none())
+                .unwrap_or_else(|| "Other".to_string()),
+        };
+        let lib = footprint_lib.trim().trim_end_matches(['/', '\\']);
+        match lib.strip_suffix(".pretty") {
+            Some(base) => format!("{}_{}.pretty", base, group),
+            None => format!("{}_{}", lib, group),
+        }
+    }
+
+    /// Footprint (and 3D model) file name for an EasyEDA footprint title.
+    fn footprint_name(&self, title: &str, manufacturer: Option<&str>) -> String {
+        if let Some(name) = self.name_override(&self.file_name_fallback) {
+            return format!("{}{}", name, self.footprint_suffix);
+        }
+        let mut prefix = self.name_prefix(manufacturer);
+        let mut title = sanitize_footprint_name(title);
+        if self.ascii_file_names {
+            if !prefix.is_ascii() {
+                prefix = ascii_file_name(&prefix, "");
+                if !prefix.is_empty() {
+                    prefix.push('_');
+                }
+            }
+            title = ascii_file_name(&title, &self.file_name_fallback);
+        }
+        format!("{}{}{}", prefix, title, self.footprint_suffix)
+    }
+
+    fn coordinate_decimals(&self) -> usize {
+        self.coordinate_decimals.unwrap_or(4).min(9) as usize
+    }
+
+    fn simplify_tolerance(&self) -> f64 {
+        self.simplify_tolerance
+            .filter(|t| t.is_finite() && *t >= 0.0)
+            .unwrap_or(DEFAULT_SIMPLIFY_TOLERANCE)
+    }
+
+    fn pin_text_size(&self) -> f64 {
+        self.pin_text_size.filter(|s| s.is_finite() && *s > 0.0).unwrap_or(1.0)
+    }
+
+    /// `(pin_names ...)` of a symbol header; names stay hidden either way.
+    fn pin_names_attr(&self) -> String {
+        match self.pin_name_offset.filter(|o| o.is_finite() && *o >= 0.0) {
+            Some(offset) => format!("(pin_names (offset {}) hide)", offset),
+            None => "(pin_names hide)".to_string(),
+        }
+    }
+
+    /// Embedded files only exist from the KiCad 9 file format on.
+    fn embeds_3d_model(&self) -> bool {
+        self.embed_3d_model && !self.model_reference_only && self.kicad_target() >= 9
+    }
+
+    /// Model file a reference-only conversion points at, with no content:
+    /// the preferred format, the only one a footprint references.
+    fn referenced_models(&self, footprint_name: &str) -> Vec<(String, Vec<u8>)> {
+        let ext = self.preferred_3d_format.extensions()[0];
+        vec![(format!("{}.{}", footprint_name, ext), Vec::new())]
+    }
+}
+
+/// What `convert_to_bytes` should produce and how model paths are referenced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactOptions {
+    pub footprint: bool,
+    pub symbol: bool,
+    pub model: bool,
+    pub footprint_lib: String,
+    pub model_dir: String,
+    pub conversion: ConversionOptions,
+}
+
+impl Default for ArtifactOptions {
+    fn default() -> Self {
+        Self {
+            footprint: true,
+            symbol: true,
+            model: true,
+            footprint_lib: "footprint".to_string(),
+            model_dir: "packages3d".to_string(),
+            conversion: ConversionOptions::default(),
+        }
+    }
+}
+
+/// In-memory conversion result; the caller decides where the files go.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConvertedArtifacts {
+    pub footprint_name: String,
+    /// Footprint library, `footprint_lib` or its group with grouping on.
+    pub footprint_lib: String,
+    pub footprint_kicad_mod: Option<String>,
+    pub symbol_kicad_sym: Option<String>,
+    /// 3D model file name and contents, in the preferred format.
+    pub model_bytes: Option<(String, Vec<u8>)>,
+    pub model_error: Option<String>,
+    /// Footprint primitives left out, see `FootprintInfo::warnings`.
+    pub warnings: Vec<String>,
+}
+
+fn mil2mm(mils: f64) -> f64 {
+    mils / 3.937
+}
+
+/// Source units to mm: metric data is used as-is, legacy data is in mil units.
+fn to_mm(value: f64, metric: bool) -> f64 {
+    if metric {
+        value
+    } else {
+        mil2mm(value)
+    }
+}
+
+/// Whether a dataStr head declares millimetre coordinates; heads without a
+/// unit are legacy mil data.
+fn is_metric_unit(unit: Option<&str>) -> bool {
+    matches!(
+        unit.map(|u| u.trim().to_lowercase()).as_deref(),
+        Some("mm") | Some("millimeter") | Some("millimeters") | Some("metric")
+    )
+}
+
+/// `value` with at most `decimals` places and no trailing zeros.
+fn format_number(value: f64, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text.as_str()
+    };
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Rounds every decimal atom of generated S-expression text through
+/// `format_number`, so floating-point noise such as 1.2700000000000002 is
+/// written as 1.27. Quoted strings (names, values, paths) are copied as they
+/// are, whatever numbers they contain.
+fn round_coordinates(content: &str, decimals: usize) -> String {
+    static NUMBER_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let number_re = NUMBER_RE.get_or_init(|| regex::Regex::new(r"^-?\d+\.\d+$").unwrap());
+
+    let mut result = String::with_capacity(content.len());
+    let mut atom_start: Option<usize> = None;
+    let mut in_string = false;
+    let mut escaped = false;
+    let flush_atom = |result: &mut String, atom: &str| match atom.parse::<f64>() {
+        Ok(value) if number_re.is_match(atom) => result.push_str(&format_number(value, decimals)),
+        _ => result.push_str(atom),
+    };
+    for (i, ch) in content.char_indices() {
+        if in_string {
+            result.push(ch);
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        let delimiter = ch.is_whitespace() || matches!(ch, '(' | ')' | '"');
+        if !delimiter {
+            atom_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = atom_start.take() {
+            flush_atom(&mut result, &content[start..i]);
+        }
+        in_string = ch == '"';
+        result.push(ch);
+    }
+    if let Some(start) = atom_start {
+        flush_atom(&mut result, &content[start..]);
+    }
+    result
+}
+
+fn sanitize_footprint_name(title: &str) -> String {
+    title
+        .replace(" ", "_")
+        .replace("/", "_")
+        .replace("(", "_")
+        .replace(")", "_")
+}
+
+/// `name` with every run of non-ASCII characters turned into one `_`. When
+/// anything was replaced the part id goes in front, or stands alone when
+/// nothing readable is left.
+fn ascii_file_name(name: &str, fallback: &str) -> String {
+    if name.is_ascii() {
+        return name.to_string();
+    }
+    let mut ascii = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii() {
+            ascii.push(c);
+        } else if !ascii.ends_with('_') {
+            ascii.push('_');
+        }
+    }
+    let ascii = ascii.trim_matches('_');
+    match (ascii.is_empty(), fallback.is_empty()) {
+        (true, _) => fallback.to_string(),
+        (false, true) => ascii.to_string(),
+        (false, false) => format!("{}_{}", fallback, ascii),
+    }
+}
+
+/// Symbol Footprint property for footprint `name` in the `footprint_lib`
+/// folder: `lib:name`, the lib being the folder name without `.pretty`.
+fn footprint_reference(footprint_lib: &str, name: &str) -> String {
+    let lib = footprint_lib.trim().trim_end_matches(['/', '\\']);
+    let lib = lib.strip_suffix(".pretty").unwrap_or(lib);
+    let lib = lib.rsplit(['/', '\\']).next().unwrap_or(lib);
+    if name.is_empty() || lib.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}:{}", lib, name)
+    }
+}
+
+/// Why `name` cannot be used as a footprint, symbol and file name, if it
+/// cannot: it must be non-empty and free of path separators, characters
+/// Windows rejects in file names, quotes and control characters.
+fn invalid_name_reason(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        return Some("名称为空");
+    }
+    if name == "." || name == ".." {
+        return Some("不能是 . 或 ..");
+    }
+    if name.chars().count() > 200 {
+        return Some("名称过长");
+    }
+    if name.chars().any(|c| c.is_control() || "/\\:*?\"<>|".contains(c)) {
+        return Some("不能包含 / \\ : * ? \" < > | 或控制字符");
+    }
+    None
+}
+
+fn sanitize_symbol_name(title: &str) -> String {
+    title
+        .replace(" ", "_")
+        .replace(".", "_")
+        .replace("/", "{slash}")
+        .replace("\\", "{backslash}")
+        .replace("<", "{lt}")
+        .replace(">", "{gt}")
+        .replace(":", "{colon}")
+        .replace('"', "{dblquote}")
+}
+
+/// 3D model of a footprint's SVGNODE: its uuid, or a direct model URL when
+/// the attrs reference the model that way instead.
+fn extract_model_uuid_from_shape(shape: &[String]) -> Option<String> {
+    for line in shape {
+        let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
+        if parts.first().copied() != Some("SVGNODE") {
+            continue;
+        }
+        let Some(attrs) = parts
+            .get(1)
+            .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw).ok())
+            .and_then(|v| v.get("attrs").cloned())
+        else {
+            continue;
+        };
+        if let Some(uuid) = attrs.get("uuid").and_then(|u| u.as_str()) {
+            return Some(uuid.to_string());
+        }
+        let url = ["3dModel", "url", "modelUrl", "model_url"]
+            .iter()
+            .filter_map(|key| attrs.get(*key).and_then(|u| u.as_str()))
+            .map(str::trim)
+            .find(|u| is_model_url(u));
+        if let Some(url) = url {
+            return Some(url.to_string());
+        }
+    }
+    None
+}
+
+fn is_model_url(value: &str) -> bool {
+    value.starts_with("https://") || value.starts_with("http://")
+}
+
+/// Component code of a searchByCodes item, falling back to its uuid.
+fn code_item_id(item: &serde_json::Value) -> String {
+    item.get("product_code")
+        .or_else(|| item.get("code"))
+        .or_else(|| item.get("uuid"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+fn uuid_first_part(value: &str) -> String {
+    value.split('|').next().unwrap_or(value).to_string()
+}
+
+fn first_non_empty_str(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    for key in keys {
+        if let Some(v) = value.get(*key).and_then(|v| v.as_str()) {
+            let trimmed = v.trim();
+            if !trimmed.is_empty() {
+                return Some(trimmed.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn looks_like_uuidish(value: &str) -> bool {
+    let s = value.trim();
+    if s.is_empty() {
+        return false;
+    }
+
+    let first = s.split('|').next().unwrap_or(s);
+    let is_hex = |c: char| c.is_ascii_hexdigit();
+
+    if first.len() == 32 && first.chars().all(is_hex) {
+        return true;
+    }
+
+    if first.len() == 36 {
+        let bytes = first.as_bytes();
+        if bytes.get(8) == Some(&b'-')
+            && bytes.get(13) == Some(&b'-')
+            && bytes.get(18) == Some(&b'-')
+            && bytes.get(23) == Some(&b'-')
+            && first
+                .chars()
+                .enumerate()
+                .all(|(i, ch)| matches!(i, 8 | 13 | 18 | 23) || is_hex(ch))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn first_readable_package(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    for key in keys {
+        if let Some(v) = value.get(*key).and_then(|v| v.as_str()) {
+            let trimmed = v.trim();
+            if !trimmed.is_empty() && !looks_like_uuidish(trimmed) {
+                return Some(trimmed.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn extract_package_name(value: &serde_json::Value) -> Option<String> {
     if let Some(v) = first_readable_package(
         value,
         &[
@@ -444,21 +1802,61 @@ fn extract_manufacturer_name(value: &serde_json::Value) -> Option<String> {
     None
 }
 
-fn extract_brief_desc(value: &serde_json::Value) -> Option<String> {
-    if let Some(v) = first_non_empty_str(
+/// JLCPCB Basic/Extended assembly class, from the list fields or the device
+/// attributes.
+fn extract_jlc_class(value: &serde_json::Value) -> Option<String> {
+    let raw = first_non_empty_str(
         value,
         &[
-            "description",
-            "Description",
-            "comment",
-            "Comment",
-            "product_name",
-            "display_title",
-            "title",
-            "描述",
+            "jlc_class",
+            "jlcClass",
+            "componentLibraryType",
+            "libraryType",
+            "library_type",
+            "partType",
         ],
-    ) {
-        return Some(v);
+    )
+    .or_else(|| {
+        value.get("attributes").and_then(|attrs| {
+            first_non_empty_str(
+                attrs,
+                &["JLCPCB Part Class", "JLC Part Class", "JLCPCB Class", "元件类型"],
+            )
+        })
+    });
+
+    if let Some(raw) = raw {
+        let lower = raw.to_lowercase();
+        if lower.contains("basic") || lower == "base" || raw.contains("基础") {
+            return Some("Basic".to_string());
+        }
+        if lower.contains("extend") || lower == "expand" || raw.contains("扩展") {
+            return Some("Extended".to_string());
+        }
+    }
+
+    match value.get("isBasic").or_else(|| value.get("is_basic")) {
+        Some(serde_json::Value::Bool(true)) => Some("Basic".to_string()),
+        Some(serde_json::Value::Bool(false)) => Some("Extended".to_string()),
+        _ => None,
+    }
+}
+
+fn extract_brief_desc(value: &serde_json::Value) -> Option<String> {
+    if let Some(v) = first_non_empty_str(
+        value,
+        &[
+            "description",
+            "Description",
+            "comment",
+            "Comment",
+            "product_name",
+            "display_title",
+            "title",
+            "描述",
+        ],
+    ) {
+        return Some(v);
     }
 
     if let Some(attrs) = value.get("attributes") {
@@ -530,22 +1928,123 @@ fn extract_preferred_local_id(device: &serde_json::Value) -> Option<String> {
         .and_then(|s| normalize_component_token(&s))
 }
 
+const MAX_TEXT_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+const MAX_BINARY_RESPONSE_BYTES: usize = 128 * 1024 * 1024;
+
+fn looks_like_html(content_type: &str, body: &[u8]) -> bool {
+    if content_type.to_lowercase().contains("text/html") {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&body[..body.len().min(512)]).to_lowercase();
+    let head = head.trim_start();
+    head.starts_with('<') && (head.starts_with("<!doctype html") || head.contains("<html"))
+}
+
+async fn read_body_guarded(mut resp: reqwest::Response, limit: usize) -> Result<Vec<u8>, JlcError> {
+    let url = resp.url().to_string();
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    log::debug!("Response from {} (content-type: {})", url, content_type);
+
+    let too_large = || {
+        JlcError::ApiError(format!(
+            "响应体过大（超过 {} MB），已中止: {}",
+            limit / (1024 * 1024),
+            url
+        ))
+    };
+    if resp.content_length().map(|len| len > limit as u64).unwrap_or(false) {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    if looks_like_html(&content_type, &body) {
+        return Err(JlcError::HtmlResponse(url));
+    }
+    Ok(body)
+}
+
+async fn read_text_guarded(resp: reqwest::Response) -> Result<String, JlcError> {
+    let body = read_body_guarded(resp, MAX_TEXT_RESPONSE_BYTES).await?;
+    let text =
+        String::from_utf8(body).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+    Ok(match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    })
+}
+
+/// `serde_json::from_str` that tolerates a UTF-8 BOM and surrounding whitespace,
+/// as some EasyEDA/LCSC mirrors and Windows-saved exports carry them.
+fn parse_json_lenient<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(text.trim_start_matches('\u{feff}').trim())
+}
+
+/// Tells "requires login" apart from "not found" for EasyEDA HTTP errors.
+fn easyeda_status_error(e: reqwest::Error, has_auth: bool) -> JlcError {
+    match e.status().map(|s| s.as_u16()) {
+        Some(401) | Some(403) => {
+            if has_auth {
+                JlcError::ApiError("EasyEDA 登录凭据无效或已过期，请在网络设置中更新".to_string())
+            } else {
+                JlcError::ApiError(
+                    "该元件需要登录 EasyEDA 才能访问（团队/私有库），请在网络设置中填写登录凭据"
+                        .to_string(),
+                )
+            }
+        }
+        Some(404) => JlcError::ApiError("EasyEDA 未找到该元件".to_string()),
+        _ => JlcError::RequestError(e),
+    }
+}
+
 #[allow(dead_code)]
 fn get_user_agent() -> String {
     USER_AGENT.to_string()
 }
 
+/// HTTP clients configured from a snapshot of the network settings taken at
+/// construction; later settings changes never reach an existing client.
+#[derive(Clone)]
 pub struct JlcClient {
     easyeda_primary_client: reqwest::Client,
     easyeda_fallback_client: reqwest::Client,
     lcsc_client: reqwest::Client,
+    has_easyeda_auth: bool,
+    /// Proxy used by one of the EasyEDA clients; empty when both go direct.
+    proxy_address: String,
+    /// The primary EasyEDA client goes through `proxy_address`, the fallback direct.
+    primary_via_proxy: bool,
+    /// Settings generation the clients were built from.
+    generation: u64,
+    /// Per-request timeout of binary downloads; other requests use the
+    /// clients' own, shorter timeout.
+    download_timeout: Duration,
 }
 
+static SHARED_CLIENT: OnceLock<Mutex<Option<JlcClient>>> = OnceLock::new();
+
 impl JlcClient {
-    fn build_client(proxy: Option<&str>) -> Result<reqwest::Client, reqwest::Error> {
+    fn build_client(
+        proxy: Option<&str>,
+        headers: reqwest::header::HeaderMap,
+        timeout: Duration,
+    ) -> Result<reqwest::Client, reqwest::Error> {
         let mut builder = reqwest::Client::builder()
             .user_agent(USER_AGENT)
-            .timeout(Duration::from_secs(20))
+            .default_headers(headers)
+            .timeout(timeout)
             .connect_timeout(Duration::from_secs(10));
 
         if let Some(proxy_url) = proxy {
@@ -557,18 +2056,43 @@ impl JlcClient {
         builder.build()
     }
 
+    /// A client reused across conversions until the network settings change;
+    /// clients already handed out keep their settings.
+    pub fn shared() -> Self {
+        let slot = SHARED_CLIENT.get_or_init(|| Mutex::new(None));
+        let Ok(mut slot) = slot.lock() else {
+            return Self::new();
+        };
+        match slot.as_ref() {
+            Some(client) if client.is_current() => client.clone(),
+            _ => {
+                let client = Self::new();
+                *slot = Some(client.clone());
+                client
+            }
+        }
+    }
+
+    /// Whether the settings have not changed since this client was built.
+    pub fn is_current(&self) -> bool {
+        self.generation == NETWORK_GENERATION.load(Ordering::SeqCst)
+    }
+
     pub fn new() -> Self {
-        let settings = get_network_settings();
+        let (settings, generation) = network_snapshot();
+        let timeout = settings.request_timeout();
 
         let lcsc_proxy = if settings.lcsc_use_proxy {
             Some(settings.proxy_address.as_str())
         } else {
             None
         };
-        let lcsc_client = Self::build_client(lcsc_proxy).unwrap_or_else(|e| {
-            log::warn!("Failed to create LCSC client: {}", e);
-            reqwest::Client::new()
-        });
+        let lcsc_headers = settings.lcsc_client_headers().unwrap_or_default();
+        let lcsc_client = Self::build_client(lcsc_proxy, lcsc_headers, timeout)
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to create LCSC client: {}", e);
+                reqwest::Client::new()
+            });
 
         let easyeda_proxy = if settings.easyeda_use_proxy {
             Some(settings.proxy_address.as_str())
@@ -576,13 +2100,16 @@ impl JlcClient {
             None
         };
 
-        let easyeda_primary_client = Self::build_client(easyeda_proxy).unwrap_or_else(|e| {
-            log::warn!(
-                "Failed to create EasyEDA proxy client, fallback to direct: {}",
-                e
-            );
-            Self::build_client(None).unwrap_or_else(|_| reqwest::Client::new())
-        });
+        let auth_headers = settings.easyeda_client_headers().unwrap_or_default();
+        let easyeda_primary_client =
+            Self::build_client(easyeda_proxy, auth_headers.clone(), timeout).unwrap_or_else(|e| {
+                log::warn!(
+                    "Failed to create EasyEDA proxy client, fallback to direct: {}",
+                    e
+                );
+                Self::build_client(None, auth_headers.clone(), timeout)
+                    .unwrap_or_else(|_| reqwest::Client::new())
+            });
 
         let easyeda_fallback_proxy = if settings.easyeda_use_proxy {
             None
@@ -590,16 +2117,40 @@ impl JlcClient {
             Some(settings.proxy_address.as_str())
         };
         let easyeda_fallback_client =
-            Self::build_client(easyeda_fallback_proxy).unwrap_or_else(|e| {
-                log::warn!("Failed to create EasyEDA fallback client: {}", e);
-                Self::build_client(None).unwrap_or_else(|_| reqwest::Client::new())
-            });
+            Self::build_client(easyeda_fallback_proxy, auth_headers.clone(), timeout)
+                .unwrap_or_else(|e| {
+                    log::warn!("Failed to create EasyEDA fallback client: {}", e);
+                    Self::build_client(None, auth_headers.clone(), timeout)
+                        .unwrap_or_else(|_| reqwest::Client::new())
+                });
 
         Self {
             easyeda_primary_client,
             easyeda_fallback_client,
             lcsc_client,
+            has_easyeda_auth: settings.has_easyeda_auth(),
+            proxy_address: settings.proxy_address.trim().to_string(),
+            primary_via_proxy: settings.easyeda_use_proxy,
+            generation,
+            download_timeout: settings.download_timeout(),
+        }
+    }
+
+    /// Error for a request that failed on both EasyEDA clients. An unreachable
+    /// proxy is named explicitly, as it otherwise looks like EasyEDA is down.
+    fn fallback_error(&self, primary_err: &reqwest::Error, fallback_err: reqwest::Error) -> JlcError {
+        let (proxy_err, direct_err) = if self.primary_via_proxy {
+            (primary_err, &fallback_err)
+        } else {
+            (&fallback_err, primary_err)
+        };
+        if !self.proxy_address.is_empty() && proxy_err.is_connect() && direct_err.status().is_none() {
+            return JlcError::ApiError(format!(
+                "代理 {} 无法连接，直连 EasyEDA 也失败（{}）。请确认代理已启动，或在网络设置中调整代理",
+                self.proxy_address, direct_err
+            ));
         }
+        easyeda_status_error(fallback_err, self.has_easyeda_auth)
     }
 
     async fn easyeda_get_text_url(&self, url: &str) -> Result<String, JlcError> {
@@ -611,16 +2162,17 @@ impl JlcClient {
             .and_then(|r| r.error_for_status());
 
         match primary {
-            Ok(resp) => Ok(resp.text().await?),
+            Ok(resp) => read_text_guarded(resp).await,
             Err(primary_err) => {
                 log::warn!("EasyEDA primary request failed: {}", primary_err);
                 let fallback_resp = self
                     .easyeda_fallback_client
                     .get(url)
                     .send()
-                    .await?
-                    .error_for_status()?;
-                Ok(fallback_resp.text().await?)
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| self.fallback_error(&primary_err, e))?;
+                read_text_guarded(fallback_resp).await
             }
         }
     }
@@ -653,21 +2205,24 @@ impl JlcClient {
         let primary = self
             .easyeda_primary_client
             .get(url)
+            .timeout(self.download_timeout)
             .send()
             .await
             .and_then(|r| r.error_for_status());
 
         match primary {
-            Ok(resp) => Ok(resp.bytes().await?.to_vec()),
+            Ok(resp) => read_body_guarded(resp, MAX_BINARY_RESPONSE_BYTES).await,
             Err(primary_err) => {
                 log::warn!("EasyEDA primary request failed: {}", primary_err);
                 let fallback_resp = self
                     .easyeda_fallback_client
                     .get(url)
+                    .timeout(self.download_timeout)
                     .send()
-                    .await?
-                    .error_for_status()?;
-                Ok(fallback_resp.bytes().await?.to_vec())
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| self.fallback_error(&primary_err, e))?;
+                read_body_guarded(fallback_resp, MAX_BINARY_RESPONSE_BYTES).await
             }
         }
     }
@@ -705,7 +2260,7 @@ impl JlcClient {
                 .and_then(|r| r.error_for_status());
 
             let text = match primary {
-                Ok(resp) => resp.text().await?,
+                Ok(resp) => read_text_guarded(resp).await?,
                 Err(primary_err) => {
                     log::warn!("EasyEDA primary POST failed on {}: {}", base, primary_err);
                     match self
@@ -716,16 +2271,16 @@ impl JlcClient {
                         .await
                         .and_then(|r| r.error_for_status())
                     {
-                        Ok(resp) => resp.text().await?,
+                        Ok(resp) => read_text_guarded(resp).await?,
                         Err(e) => {
-                            last_err = Some(JlcError::RequestError(e));
+                            last_err = Some(self.fallback_error(&primary_err, e));
                             continue;
                         }
                     }
                 }
             };
 
-            match serde_json::from_str(&text) {
+            match parse_json_lenient(&text) {
                 Ok(v) => return Ok(v),
                 Err(e) => last_err = Some(JlcError::JsonError(e)),
             }
@@ -741,7 +2296,7 @@ impl JlcClient {
         let text = self
             .easyeda_get_text_pro_path(&format!("/api/devices/{}", device_uuid))
             .await?;
-        let json: serde_json::Value = serde_json::from_str(&text)?;
+        let json: serde_json::Value = parse_json_lenient(&text)?;
         Ok(json)
     }
 
@@ -749,7 +2304,7 @@ impl JlcClient {
         let path = format!("/api/products/{}/svgs", query);
         let text = self.easyeda_get_text_path(&path).await?;
         
-        let data: ComponentData = match serde_json::from_str(&text) {
+        let data: ComponentData = match parse_json_lenient(&text) {
             Ok(d) => d,
             Err(_) => {
                 return Err(JlcError::ApiError("Invalid response format".to_string()));
@@ -760,8 +2315,10 @@ impl JlcClient {
             return Ok(vec![]);
         }
         
-        let footprint_uuid = &data.result.last().unwrap().component_uuid;
-        let footprint_data = self.get_footprint_data(footprint_uuid).await?;
+        let Some(footprint_uuid) = data.footprint_uuids().pop() else {
+            return Ok(vec![]);
+        };
+        let footprint_data = self.get_footprint_data(&footprint_uuid).await?;
         
         let name = footprint_data.result.title.clone();
         
@@ -775,10 +2332,93 @@ impl JlcClient {
             price: None,
             stock: None,
             image_url: Some(format!("https://wmsc.lcsc.com/wmsc/upload/file/eec/image/{}.jpg", query)),
+            jlc_class: None,
         }])
     }
 
+    /// `SearchResult` for one searchByCodes item, enriched from the device
+    /// detail when the item only carries the code and uuid.
+    async fn code_item_result(&self, item: &serde_json::Value, id: String) -> SearchResult {
+        let device_uuid = item
+            .get("uuid")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        let mut name =
+            first_non_empty_str(item, &["display_title", "title", "name", "product_name"])
+                .unwrap_or_else(|| id.clone());
+        let mut package_value = extract_package_name(item);
+        let mut manufacturer_value = extract_manufacturer_name(item);
+        let mut brief_desc_value = extract_brief_desc(item);
+        let mut jlc_class = extract_jlc_class(item);
+
+        // For C-code queries, some responses only return code + uuid.
+        // Enrich with device detail so UI can show name and basic info.
+        if (!device_uuid.is_empty())
+            && (name == id
+                || package_value.is_none()
+                || manufacturer_value.is_none()
+                || brief_desc_value.is_none())
+        {
+            if let Ok(device_json) = self.get_pro_device_detail(&device_uuid).await {
+                let result = device_json.get("result").unwrap_or(&device_json);
+
+                if name == id {
+                    if let Some(detail_name) =
+                        first_non_empty_str(result, &["display_title", "title", "name"])
+                    {
+                        name = detail_name;
+                    }
+                }
+
+                if package_value.is_none() {
+                    package_value = extract_package_name(result);
+                }
+                if manufacturer_value.is_none() {
+                    manufacturer_value = extract_manufacturer_name(result);
+                }
+                if brief_desc_value.is_none() {
+                    brief_desc_value = extract_brief_desc(result);
+                }
+                if jlc_class.is_none() {
+                    jlc_class = extract_jlc_class(result);
+                }
+            }
+        }
+        let description = format!(
+            "封装: {} | 制造商: {} | 描述: {}",
+            package_value.clone().unwrap_or_else(|| "未知".to_string()),
+            manufacturer_value.clone().unwrap_or_else(|| "未知".to_string()),
+            brief_desc_value.unwrap_or_else(|| "未知".to_string())
+        );
+
+        SearchResult {
+            id,
+            name,
+            description,
+            package: package_value,
+            manufacturer: manufacturer_value,
+            category: None,
+            price: None,
+            stock: None,
+            image_url: None,
+            jlc_class,
+        }
+    }
+
     pub async fn search_easyeda_pro(&self, query: &str) -> Result<Vec<SearchResult>, JlcError> {
+        self.search_easyeda_pro_with(query, &mut |_| {}, &|| false).await
+    }
+
+    /// `on_result` sees each result once it is enriched; `is_cancelled` is
+    /// checked before every device-detail request.
+    async fn search_easyeda_pro_with(
+        &self,
+        query: &str,
+        on_result: &mut (dyn FnMut(&SearchResult) + Send),
+        is_cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Result<Vec<SearchResult>, JlcError> {
         let mut results = Vec::new();
         let mut seen = HashSet::new();
         let q = query.trim();
@@ -800,84 +2440,19 @@ impl JlcClient {
             {
                 if let Some(arr) = by_codes.get("result").and_then(|v| v.as_array()) {
                     for item in arr {
-                        let device_uuid = item
-                            .get("uuid")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .trim()
-                            .to_string();
-                        let id = item
-                            .get("product_code")
-                            .or_else(|| item.get("code"))
-                            .or_else(|| item.get("uuid"))
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .trim()
-                            .to_string();
-
+                        let id = code_item_id(item);
                         if id.is_empty() || seen.contains(&id) {
                             continue;
                         }
                         seen.insert(id.clone());
 
-                        let mut name = first_non_empty_str(
-                            item,
-                            &["display_title", "title", "name", "product_name"],
-                        )
-                        .unwrap_or_else(|| id.clone());
-                        let mut package_value = extract_package_name(item);
-                        let mut manufacturer_value = extract_manufacturer_name(item);
-                        let mut brief_desc_value = extract_brief_desc(item);
-
-                        // For C-code queries, some responses only return code + uuid.
-                        // Enrich with device detail so UI can show name and basic info.
-                        if (!device_uuid.is_empty())
-                            && (name == id
-                                || package_value.is_none()
-                                || manufacturer_value.is_none()
-                                || brief_desc_value.is_none())
-                        {
-                            if let Ok(device_json) = self.get_pro_device_detail(&device_uuid).await {
-                                let result = device_json.get("result").unwrap_or(&device_json);
-
-                                if name == id {
-                                    if let Some(detail_name) = first_non_empty_str(
-                                        result,
-                                        &["display_title", "title", "name"],
-                                    ) {
-                                        name = detail_name;
-                                    }
-                                }
-
-                                if package_value.is_none() {
-                                    package_value = extract_package_name(result);
-                                }
-                                if manufacturer_value.is_none() {
-                                    manufacturer_value = extract_manufacturer_name(result);
-                                }
-                                if brief_desc_value.is_none() {
-                                    brief_desc_value = extract_brief_desc(result);
-                                }
-                            }
+                        if is_cancelled() {
+                            return Err(search_cancelled());
+                        }
+                        results.push(self.code_item_result(item, id).await);
+                        if let Some(result) = results.last() {
+                            on_result(result);
                         }
-                        let description = format!(
-                            "封装: {} | 制造商: {} | 描述: {}",
-                            package_value.clone().unwrap_or_else(|| "未知".to_string()),
-                            manufacturer_value.clone().unwrap_or_else(|| "未知".to_string()),
-                            brief_desc_value.unwrap_or_else(|| "未知".to_string())
-                        );
-
-                        results.push(SearchResult {
-                            id,
-                            name,
-                            description,
-                            package: package_value,
-                            manufacturer: manufacturer_value,
-                            category: None,
-                            price: None,
-                            stock: None,
-                            image_url: None,
-                        });
                     }
                 }
             }
@@ -947,6 +2522,10 @@ impl JlcClient {
                         let mut package_value = extract_package_name(item);
                         let mut manufacturer_value = extract_manufacturer_name(item);
                         let mut brief_desc_value = extract_brief_desc(item);
+                        let mut jlc_class = extract_jlc_class(item);
+                        if is_cancelled() {
+                            return Err(search_cancelled());
+                        }
                         // Keep keyword search results consistent with C-code search:
                         // if list payload has little metadata, enrich by device detail.
                         if !device_uuid.is_empty()
@@ -965,6 +2544,9 @@ impl JlcClient {
                                 if brief_desc_value.is_none() {
                                     brief_desc_value = extract_brief_desc(result);
                                 }
+                                if jlc_class.is_none() {
+                                    jlc_class = extract_jlc_class(result);
+                                }
                             }
                         }
                         let description = format!(
@@ -984,7 +2566,11 @@ impl JlcClient {
                             price: None,
                             stock: None,
                             image_url: None,
+                            jlc_class,
                         });
+                        if let Some(result) = results.last() {
+                            on_result(result);
+                        }
                     }
                 }
             }
@@ -995,14 +2581,21 @@ impl JlcClient {
 
     pub async fn get_component_data(&self, component_id: &str) -> Result<ComponentData, JlcError> {
         let path = format!("/api/products/{}/svgs", component_id);
-        let text = self.easyeda_get_text_path(&path).await?;
-        let data: ComponentData = serde_json::from_str(&text)?;
+        let cached = cached_metadata(&path);
+        let text = match &cached {
+            Some(text) => text.clone(),
+            None => self.easyeda_get_text_path(&path).await?,
+        };
+        let data: ComponentData = parse_json_lenient(&text)?;
         if !data.success {
             return Err(JlcError::ApiError(format!(
                 "Failed to get component {} data",
                 component_id
             )));
         }
+        if cached.is_none() {
+            store_metadata(&path, text);
+        }
         Ok(data)
     }
 
@@ -1011,46 +2604,148 @@ impl JlcClient {
         footprint_uuid: &str,
     ) -> Result<FootprintApiResponse, JlcError> {
         let path = format!("/api/components/{}", footprint_uuid);
-        let text = self.easyeda_get_text_path(&path).await?;
-        let data: FootprintApiResponse = serde_json::from_str(&text)?;
+        let cached = cached_metadata(&path);
+        let text = match &cached {
+            Some(text) => text.clone(),
+            None => self.get_component_text(footprint_uuid).await?,
+        };
+        let data: FootprintApiResponse = parse_json_lenient(&text)?;
         if !data.success {
             return Err(JlcError::ApiError(format!(
                 "Failed to get footprint {} data",
                 footprint_uuid
             )));
         }
+        if cached.is_none() {
+            store_metadata(&path, text);
+        }
         Ok(data)
     }
 
+    /// Legacy `/api/components/{uuid}` text. When that endpoint cannot be
+    /// reached the Pro `/api/v2/components/{uuid}` is tried instead and its
+    /// response reshaped like a legacy one.
+    async fn get_component_text(&self, uuid: &str) -> Result<String, JlcError> {
+        let legacy_err = match self
+            .easyeda_get_text_path(&format!("/api/components/{}", uuid))
+            .await
+        {
+            Ok(text) => return Ok(text),
+            Err(e) => e,
+        };
+        let pro_text = match self
+            .easyeda_get_text_pro_path(&format!("/api/v2/components/{}", uuid))
+            .await
+        {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Pro component endpoint failed for {}: {}", uuid, e);
+                return Err(legacy_err);
+            }
+        };
+        pro_component_as_legacy(&pro_text)
+    }
+
     pub async fn get_symbol_data(&self, symbol_uuid: &str) -> Result<SymbolApiResponse, JlcError> {
         let path = format!("/api/components/{}", symbol_uuid);
-        let text = self.easyeda_get_text_path(&path).await?;
-        let data: SymbolApiResponse = serde_json::from_str(&text)?;
+        let cached = cached_metadata(&path);
+        let text = match &cached {
+            Some(text) => text.clone(),
+            None => self.get_component_text(symbol_uuid).await?,
+        };
+        let data: SymbolApiResponse = parse_json_lenient(&text).map_err(|e| {
+            JlcError::ParseError(format!("符号 {} 数据不完整，无法解析: {}", symbol_uuid, e))
+        })?;
         if !data.success {
             return Err(JlcError::ApiError(format!(
                 "Failed to get symbol {} data",
                 symbol_uuid
             )));
         }
+        if cached.is_none() {
+            store_metadata(&path, text);
+        }
         Ok(data)
     }
 
+    /// STEP model by uuid, or from `component_uuid` itself when it is a full URL.
+    pub async fn get_step_model(&self, component_uuid: &str) -> Result<Vec<u8>, JlcError> {
+        if let Some(content) = read_cached_model(component_uuid) {
+            return Ok(content);
+        }
+        let content = if is_model_url(component_uuid) {
+            self.easyeda_get_bytes_url(component_uuid).await?
+        } else {
+            let path = format!("/qAxj6KHrDKw4blvCG8QJPs7Y/{}", component_uuid);
+            self.easyeda_get_bytes_with_bases(&MODEL_BASE_URLS, &path).await?
+        };
+        if !content.is_empty() {
+            store_cached_model(component_uuid, &content);
+            Ok(content)
+        } else {
+            Err(JlcError::ApiError("Failed to download STEP model: empty response".to_string()))
+        }
+    }
+
+    /// Size of a STEP model from a HEAD request, so nothing is downloaded.
+    /// `Ok(None)` means the model may exist but its size is unknown: the
+    /// server answered without a Content-Length or refused HEAD altogether.
+    async fn step_model_size(&self, model_uuid: &str) -> Result<Option<u64>, JlcError> {
+        let urls: Vec<String> = if is_model_url(model_uuid) {
+            vec![model_uuid.to_string()]
+        } else {
+            MODEL_BASE_URLS
+                .iter()
+                .map(|base| format!("{}/qAxj6KHrDKw4blvCG8QJPs7Y/{}", base, model_uuid))
+                .collect()
+        };
+        let mut last_err: Option<JlcError> = None;
+        for url in urls {
+            let primary = self
+                .easyeda_primary_client
+                .head(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            let resp = match primary {
+                Ok(resp) => resp,
+                Err(primary_err) => match self
+                    .easyeda_fallback_client
+                    .head(&url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        let statuses = [primary_err.status(), e.status()];
+                        if statuses.into_iter().flatten().any(head_refused) {
+                            return Ok(None);
+                        }
+                        last_err = Some(self.fallback_error(&primary_err, e));
+                        continue;
+                    }
+                },
+            };
+            // Read the header itself: the body of a HEAD response is always empty.
+            return Ok(resp
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|len| *len > 0));
+        }
+        Err(last_err.unwrap_or_else(|| JlcError::ApiError("EasyEDA 请求失败".to_string())))
+    }
+
     pub async fn download_step_model(
         &self,
         component_uuid: &str,
         output_path: &str,
     ) -> Result<(), JlcError> {
-        let path = format!("/qAxj6KHrDKw4blvCG8QJPs7Y/{}", component_uuid);
-        let content = self
-            .easyeda_get_bytes_with_bases(&MODEL_BASE_URLS, &path)
-            .await?;
-        if !content.is_empty() {
-            let mut file = File::create(output_path)?;
-            file.write_all(&content)?;
-            Ok(())
-        } else {
-            Err(JlcError::ApiError("Failed to download STEP model: empty response".to_string()))
-        }
+        let content = self.get_step_model(component_uuid).await?;
+        write_file_atomic(Path::new(output_path), &content)?;
+        Ok(())
     }
 
     pub async fn get_wrl_model(&self, component_uuid: &str) -> Result<String, JlcError> {
@@ -1058,15 +2753,24 @@ impl JlcClient {
         self.easyeda_get_text_path(&path).await
     }
 
+    /// The part's 3D model UUID through the Pro API. The three-request chain
+    /// runs once per code per session; failures are not cached.
     pub async fn resolve_step_uuid_via_pro_api(
         &self,
         component_id: &str,
     ) -> Result<Option<String>, JlcError> {
-        let code = component_id.trim();
-        if !code.to_uppercase().starts_with('C') {
-            return Ok(None);
+        if let Some(uuid) = cached_pro_step_uuid(component_id) {
+            return Ok(uuid);
+        }
+        let code = component_id.trim().to_uppercase();
+        let uuid = self.fetch_step_uuid_via_pro_api(component_id.trim()).await?;
+        if let Ok(mut c) = response_cache().lock() {
+            c.step_uuids.insert(code, uuid.clone());
         }
+        Ok(uuid)
+    }
 
+    async fn fetch_step_uuid_via_pro_api(&self, code: &str) -> Result<Option<String>, JlcError> {
         let by_codes = self
             .easyeda_post_form_json("/api/v2/devices/searchByCodes", &[("codes[]", code.to_string())])
             .await?;
@@ -1085,7 +2789,7 @@ impl JlcClient {
         let device_text = self
             .easyeda_get_text_pro_path(&format!("/api/devices/{}", device_uuid))
             .await?;
-        let device_json: serde_json::Value = serde_json::from_str(&device_text)?;
+        let device_json: serde_json::Value = parse_json_lenient(&device_text)?;
 
         let model_uuid = device_json
             .get("result")
@@ -1101,7 +2805,7 @@ impl JlcClient {
         let model_text = self
             .easyeda_get_text_pro_path(&format!("/api/v2/components/{}", model_uuid))
             .await?;
-        let model_json: serde_json::Value = serde_json::from_str(&model_text)?;
+        let model_json: serde_json::Value = parse_json_lenient(&model_text)?;
 
         let direct_uuid = model_json
             .get("result")
@@ -1114,153 +2818,795 @@ impl JlcClient {
     }
 }
 
-pub async fn create_component(
-    component_id: &str,
-    output_dir: &str,
-    footprint_lib: &str,
-    symbol_lib: &str,
-    symbol_path: &str,
-    model_dir: &str,
-    models: Vec<String>,
-    create_footprint: bool,
-    create_symbol: bool,
-) -> Result<String, JlcError> {
-    let client = JlcClient::new();
+/// A Pro `/api/v2/components` response as legacy `/api/components` JSON. The
+/// Pro dataStr is either legacy-style JSON or an .elibu document, which is
+/// read like offline libraries.
+fn pro_component_as_legacy(text: &str) -> Result<String, JlcError> {
+    let json: serde_json::Value = parse_json_lenient(text)?;
+    let result = json.get("result").unwrap_or(&json);
+    let title =
+        first_non_empty_str(result, &["title", "display_title", "name"]).unwrap_or_default();
+    let data_str = match result.get("dataStr") {
+        Some(serde_json::Value::String(s)) => serde_json::from_str::<serde_json::Value>(s)
+            .unwrap_or_else(|_| serde_json::Value::String(s.clone())),
+        Some(v) => v.clone(),
+        None => return Err(JlcError::ParseError("Pro 元件数据缺少 dataStr".to_string())),
+    };
 
-    // Get component UUIDs from EasyEDA
-    let component_data = client.get_component_data(component_id).await?;
-    
-    if component_data.result.is_empty() {
-        return Err(JlcError::ApiError(format!(
-            "No results found for component {}",
-            component_id
-        )));
-    }
+    let data_str = match data_str {
+        serde_json::Value::String(document) => {
+            let mut bundle = OfflineBundle::default();
+            parse_elibu_content(&document, &mut bundle)?;
+            let lines = bundle
+                .symbol_data
+                .into_values()
+                .chain(bundle.footprint_data.into_values())
+                .next()
+                .ok_or_else(|| JlcError::ParseError("Pro 元件数据中没有图形".to_string()))?;
+            serde_json::json!({
+                "head": { "x": 0, "y": 0 },
+                "shape": lines.lines().collect::<Vec<_>>(),
+            })
+        }
+        v if v.get("shape").is_some() => v,
+        _ => return Err(JlcError::ParseError("无法识别的 Pro 元件数据格式".to_string())),
+    };
 
-    let footprint_uuid = &component_data.result.last().unwrap().component_uuid;
-    let symbol_uuids: Vec<String> = component_data.result[..component_data.result.len() - 1]
-        .iter()
-        .map(|r| r.component_uuid.clone())
-        .collect();
+    Ok(serde_json::json!({
+        "success": true,
+        "result": { "title": title, "dataStr": data_str },
+    })
+    .to_string())
+}
+
+/// Resolves the placeholders an output directory may contain: `{date}` (UTC,
+/// YYYY-MM-DD), `{lib}` (symbol library name) and `{id}` (component id).
+/// Without a component id `{id}` is left in place so batch conversions can
+/// resolve it per part.
+pub fn expand_output_dir(template: &str, component_id: Option<&str>, lib: &str) -> String {
+    let mut dir = template
+        .replace("{date}", &utc_date())
+        .replace("{lib}", &sanitize_footprint_name(lib));
+    if let Some(id) = component_id {
+        dir = dir.replace("{id}", &sanitize_footprint_name(id));
+    }
+    dir
+}
+
+fn utc_date() -> String {
+    utc_timestamp()[..10].to_string()
+}
+
+/// Current UTC time as `YYYY-MM-DDTHH:MM:SSZ`.
+fn utc_timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86_400) as i64;
+    // Days since 1970-01-01 to a civil date (proleptic Gregorian).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs % 86_400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+fn ensure_output_dir_writable(output_dir: &str) -> Result<(), JlcError> {
+    let dir = PathBuf::from(output_dir);
+    let describe = |e: std::io::Error| {
+        let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+            "没有写入权限"
+        } else {
+            "无法写入"
+        };
+        JlcError::ApiError(format!(
+            "输出目录{}: {}（{}）。请检查目录权限或选择其他输出目录",
+            hint,
+            dir.display(),
+            e
+        ))
+    };
+
+    fs::create_dir_all(&dir).map_err(describe)?;
+    let probe = dir.join(format!(".jlc2kicad_write_test_{}", std::process::id()));
+    File::create(&probe).map_err(describe)?;
+    fs::remove_file(&probe).ok();
+    Ok(())
+}
+
+/// Lists the footprint variants EasyEDA offers for a part; the default one is
+/// what conversions use when no variant is chosen.
+pub async fn list_footprint_variants(component_id: &str) -> Result<Vec<FootprintVariant>, JlcError> {
+    let client = JlcClient::shared();
+    let component_data = client.get_component_data(component_id).await?;
+    footprint_variants(&client, &component_data).await
+}
+
+/// Every footprint variant of a part with its preview. The previews come with
+/// the svgs response, which is cached like other metadata.
+pub async fn list_footprint_thumbnails(
+    component_id: &str,
+) -> Result<Vec<FootprintThumbnail>, JlcError> {
+    let client = JlcClient::shared();
+    let component_data = client.get_component_data(component_id).await?;
+    let variants = footprint_variants(&client, &component_data).await?;
+    Ok(variants
+        .into_iter()
+        .map(|variant| {
+            let svg = component_data
+                .result
+                .iter()
+                .find(|r| r.component_uuid == variant.uuid)
+                .and_then(|r| r.svg.clone())
+                .filter(|svg| svg.trim_start().starts_with("<svg"));
+            FootprintThumbnail { variant, svg }
+        })
+        .collect())
+}
+
+/// Shape lines of a part's default footprint and its symbols as EasyEDA
+/// serves them, for attaching to bug reports. Writes no files.
+pub async fn debug_dump(component_id: &str) -> Result<ComponentDump, JlcError> {
+    let id = component_id.trim().to_uppercase();
+    let client = JlcClient::shared();
+    let component_data = client.get_component_data(&id).await?;
+    if component_data.result.is_empty() {
+        return Err(JlcError::ApiError(format!("No results found for component {}", id)));
+    }
+
+    let footprint_uuid = select_footprint_uuid(&client, &component_data, "").await?;
+    let fp = client.get_footprint_data(&footprint_uuid).await?.result;
+    let head = &fp.data_str.head;
+    let footprint = ShapeDump {
+        uuid: footprint_uuid.clone(),
+        title: fp.title.clone(),
+        origin: (head.x, head.y),
+        unit: head.unit.clone(),
+        shape: fp.data_str.shape.iter().map(|line| strip_model_blob(line)).collect(),
+    };
+
+    let mut symbols = Vec::new();
+    for uuid in component_data.symbol_uuids() {
+        let symbol = client.get_symbol_data(&uuid).await?.result;
+        let head = &symbol.data_str.head;
+        symbols.push(ShapeDump {
+            uuid,
+            title: symbol.title.clone(),
+            origin: (head.x, head.y),
+            unit: head.unit.clone(),
+            shape: symbol.data_str.shape.clone(),
+        });
+    }
+
+    Ok(ComponentDump { id, footprint, symbols })
+}
+
+/// Drops the 3D outline geometry from an `SVGNODE` line, keeping the model
+/// attributes; any other line, graphic `SVGNODE`s included, is returned
+/// unchanged.
+fn strip_model_blob(line: &str) -> String {
+    let Some(raw) = line.strip_prefix("SVGNODE~") else {
+        return line.to_string();
+    };
+    let Ok(mut node) = serde_json::from_str::<serde_json::Value>(raw) else {
+        return line.to_string();
+    };
+    let is_outline = node
+        .get("attrs")
+        .and_then(|a| a.get("c_etype"))
+        .and_then(|e| e.as_str())
+        == Some("outline3D");
+    match node.as_object_mut() {
+        Some(fields) if is_outline => {
+            fields.remove("childNodes");
+            format!("SVGNODE~{}", node)
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// Merged metadata for one part, for a details view. Cached for the session.
+pub async fn get_component_details(component_id: &str) -> Result<ComponentDetails, JlcError> {
+    let id = component_id.trim().to_uppercase();
+    if let Some(details) = response_cache()
+        .lock()
+        .ok()
+        .and_then(|c| c.details.get(&id).cloned())
+    {
+        return Ok(details);
+    }
+
+    let client = JlcClient::shared();
+    let mut details = ComponentDetails {
+        id: id.clone(),
+        name: id.clone(),
+        ..Default::default()
+    };
+    let mut found = false;
+
+    if let Ok(component_data) = client.get_component_data(&id).await {
+        if let Some(uuid) = component_data.footprint_uuids().pop() {
+            if let Ok(fp) = client.get_footprint_data(&uuid).await {
+                found = true;
+                details.datasheet = fp
+                    .result
+                    .data_str
+                    .head
+                    .c_para
+                    .as_ref()
+                    .and_then(|c| c.link.clone())
+                    .filter(|l| !l.trim().is_empty());
+                details.footprint = Some(fp.result.title);
+            }
+        }
+    }
+
+    if let Ok(by_codes) = client
+        .easyeda_post_form_json("/api/v2/devices/searchByCodes", &[("codes[]", id.clone())])
+        .await
+    {
+        let device_uuid = by_codes
+            .get("result")
+            .and_then(|v| v.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|v| v.get("uuid"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        if let Some(device_uuid) = device_uuid {
+            if let Ok(device_json) = client.get_pro_device_detail(&device_uuid).await {
+                found = true;
+                let device = device_json.get("result").unwrap_or(&device_json);
+                if let Some(name) = first_non_empty_str(device, &["display_title", "title", "name"]) {
+                    details.name = name;
+                }
+                details.package = extract_package_name(device);
+                details.manufacturer = extract_manufacturer_name(device);
+                details.description = extract_brief_desc(device);
+                details.jlc_class = extract_jlc_class(device);
+                if details.datasheet.is_none() {
+                    details.datasheet = device
+                        .get("attributes")
+                        .and_then(|a| first_non_empty_str(a, &["Datasheet", "datasheet"]));
+                }
+            }
+        }
+    }
+
+    if let Some(result) = cached_search_result(&id) {
+        found = true;
+        details.package = details.package.or(result.package);
+        details.manufacturer = details.manufacturer.or(result.manufacturer);
+        details.stock = result.stock;
+        details.price = result.price;
+        details.jlc_class = details.jlc_class.or(result.jlc_class);
+    }
+
+    if !found {
+        return Err(JlcError::ApiError(format!("未找到元件 {}", id)));
+    }
+
+    details.model_uuid = client
+        .resolve_step_uuid_via_pro_api(&id)
+        .await
+        .ok()
+        .flatten();
+    details.has_3d_model = details.model_uuid.is_some();
+
+    if let Ok(mut c) = response_cache().lock() {
+        c.details.insert(id, details.clone());
+    }
+    Ok(details)
+}
+
+async fn footprint_variants(
+    client: &JlcClient,
+    component_data: &ComponentData,
+) -> Result<Vec<FootprintVariant>, JlcError> {
+    let uuids = component_data.footprint_uuids();
+    let mut variants = Vec::new();
+    for (idx, uuid) in uuids.iter().enumerate() {
+        let title = client
+            .get_footprint_data(uuid)
+            .await
+            .map(|d| d.result.title)
+            .unwrap_or_default();
+        variants.push(FootprintVariant {
+            uuid: uuid.clone(),
+            title,
+            is_default: idx + 1 == uuids.len(),
+        });
+    }
+    Ok(variants)
+}
+
+async fn select_footprint_uuid(
+    client: &JlcClient,
+    component_data: &ComponentData,
+    selector: &str,
+) -> Result<String, JlcError> {
+    let uuids = component_data.footprint_uuids();
+    let selector = selector.trim();
+    if selector.is_empty() {
+        return uuids
+            .last()
+            .cloned()
+            .ok_or_else(|| JlcError::ApiError("未找到封装".to_string()));
+    }
+    if let Some(uuid) = uuids.iter().find(|u| u.eq_ignore_ascii_case(selector)) {
+        return Ok(uuid.clone());
+    }
+    for variant in footprint_variants(client, component_data).await? {
+        if variant.title.eq_ignore_ascii_case(selector) {
+            return Ok(variant.uuid);
+        }
+    }
+    // An explicit UUID not listed by the svgs endpoint (e.g. an older revision).
+    if selector.len() >= 16 && selector.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(selector.to_string());
+    }
+    Err(JlcError::ApiError(format!("未找到封装变体 {}", selector)))
+}
+
+/// One independent part of a composite device, see `composite_parts`.
+struct CompositePart {
+    footprint_uuid: String,
+    symbol_uuids: Vec<String>,
+    /// Filled in once the footprint is generated or looked up.
+    footprint_name: String,
+    /// `lib:name` of that footprint, for the part's symbols.
+    footprint_reference: String,
+    pad_numbers: BTreeSet<String>,
+}
+
+/// Title of the symbol a unit belongs to: EasyEDA names the units of one
+/// symbol "Title.1", "Title.2", ...
+fn unit_base_title(title: &str) -> &str {
+    static UNIT_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let unit_re = UNIT_RE.get_or_init(|| regex::Regex::new(r"^(.+?)\.\d+$").unwrap());
+    let title = title.trim();
+    unit_re
+        .captures(title)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str())
+        .unwrap_or(title)
+}
+
+/// Splits a device that bundles several components (e.g. a relay module
+/// listing the relay and its driver) into its parts. A device is composite
+/// when its symbols fall into more than one title group and each group's
+/// symbols name, through their package uuid, one footprint of the device
+/// that no other group uses. Ordinary parts, multi-unit symbols, footprint
+/// variants of one symbol and groups without such a link give an empty list.
+async fn composite_parts(client: &JlcClient, component_data: &ComponentData) -> Vec<CompositePart> {
+    let mut symbols = Vec::new();
+    for uuid in component_data.symbol_uuids() {
+        // Unreadable symbols fail the normal conversion with a proper error.
+        let Ok(data) = client.get_symbol_data(&uuid).await else {
+            return Vec::new();
+        };
+        let package = data.result.package_detail.and_then(|p| p.uuid);
+        symbols.push((uuid, data.result.title, package));
+    }
+    pair_composite_parts(symbols, &component_data.footprint_uuids())
+}
+
+/// `composite_parts` on already read (uuid, title, package uuid) symbols.
+fn pair_composite_parts(
+    symbols: Vec<(String, String, Option<String>)>,
+    footprints: &[String],
+) -> Vec<CompositePart> {
+    let mut parts: Vec<(String, CompositePart)> = Vec::new();
+    for (uuid, title, package) in symbols {
+        let base = unit_base_title(&title).to_string();
+        let Some(footprint_uuid) = package.filter(|p| footprints.contains(p)) else {
+            return Vec::new();
+        };
+        match parts.iter_mut().find(|(title, _)| *title == base) {
+            Some((_, part)) if part.footprint_uuid == footprint_uuid => part.symbol_uuids.push(uuid),
+            // Units of one symbol drawn for different footprints.
+            Some(_) => return Vec::new(),
+            None => parts.push((
+                base,
+                CompositePart {
+                    footprint_uuid,
+                    symbol_uuids: vec![uuid],
+                    footprint_name: String::new(),
+                    footprint_reference: String::new(),
+                    pad_numbers: BTreeSet::new(),
+                },
+            )),
+        }
+    }
+    let footprints_used: BTreeSet<&str> =
+        parts.iter().map(|(_, p)| p.footprint_uuid.as_str()).collect();
+    if parts.len() < 2 || footprints_used.len() != parts.len() {
+        return Vec::new();
+    }
+    parts.into_iter().map(|(_, part)| part).collect()
+}
+
+/// What create_component made of one part.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreatedComponent {
+    /// Summary of the files written, for display.
+    pub message: String,
+    /// Problems worth listing in a batch report; the part was still converted.
+    pub warnings: Vec<String>,
+    /// Coordinate tables of the written footprints, when `geometry_report`
+    /// is set.
+    pub geometry: Vec<FootprintGeometry>,
+}
+
+pub async fn create_component(
+    component_id: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    symbol_lib: &str,
+    symbol_path: &str,
+    model_dir: &str,
+    models: Vec<String>,
+    create_footprint: bool,
+    create_symbol: bool,
+    options: &ConversionOptions,
+) -> Result<CreatedComponent, JlcError> {
+    options.validate()?;
+    let output_dir = &expand_output_dir(output_dir, Some(component_id), symbol_lib);
+    ensure_output_dir_writable(output_dir)?;
+
+    let journal = WriteJournal::default();
+    let mut options = ConversionOptions {
+        journal: options.rollback_on_failure.then(|| journal.clone()),
+        file_name_fallback: component_id.to_string(),
+        ..options.clone()
+    };
+    if options.passive_names && options.name_override(component_id).is_none() {
+        if let Some(name) = online_passive_name(component_id, &options).await {
+            options.name_overrides.insert(component_id.to_string(), name);
+        }
+    }
+    let options = &options;
+    let result = convert_component_online(
+        component_id,
+        output_dir,
+        footprint_lib,
+        symbol_lib,
+        symbol_path,
+        model_dir,
+        models,
+        create_footprint,
+        create_symbol,
+        options,
+    )
+    .await;
+    if result.is_err() && options.rollback_on_failure {
+        journal.rollback();
+    }
+    result
+}
+
+async fn convert_component_online(
+    component_id: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    symbol_lib: &str,
+    symbol_path: &str,
+    model_dir: &str,
+    models: Vec<String>,
+    create_footprint: bool,
+    create_symbol: bool,
+    options: &ConversionOptions,
+) -> Result<CreatedComponent, JlcError> {
+    let client = JlcClient::shared();
+
+    // Get component UUIDs from EasyEDA
+    let component_data = client.get_component_data(component_id).await?;
+    
+    if component_data.result.is_empty() {
+        return Err(JlcError::ApiError(format!(
+            "No results found for component {}",
+            component_id
+        )));
+    }
+
+    let footprint_uuid =
+        &select_footprint_uuid(&client, &component_data, &options.footprint_variant).await?;
+    let symbol_uuids = component_data.symbol_uuids();
+    // A picked variant means the caller wants that one footprint as a single part.
+    let mut composite =
+        if options.split_composite_devices && options.footprint_variant.trim().is_empty() {
+            composite_parts(&client, &component_data).await
+        } else {
+            Vec::new()
+        };
 
     let mut footprint_name = String::new();
+    let mut footprint_library = footprint_lib.to_string();
     let mut datasheet_link = String::new();
     let mut step_model_downloaded = false;
     let mut step_model_error: Option<String> = None;
+    let mut footprint_path: Option<PathBuf> = None;
+    let mut model_path: Option<PathBuf> = None;
+    let mut pad_numbers: Option<BTreeSet<String>> = None;
 
     // Download 3D model if requested, even without creating footprint
-    if !models.is_empty() && !create_footprint && !create_symbol {
+    if !models.is_empty()
+        && !create_footprint
+        && !create_symbol
+        && !options.model_reference_only
+    {
         // User only wants 3D model, need to get footprint data
         let fp_data = client.get_footprint_data(footprint_uuid).await?;
-        footprint_name = fp_data.result.title.replace(" ", "_")
-            .replace("/", "_")
-            .replace("(", "_")
-            .replace(")", "_");
-        
+        let c_para = fp_data.result.data_str.head.c_para.as_ref();
+        let manufacturer = c_para.and_then(|c| c.manufacturer.as_deref());
+        footprint_name = options.footprint_name(&fp_data.result.title, manufacturer);
+        footprint_library =
+            options.footprint_library(footprint_lib, &fp_data.result.title, manufacturer);
+
         // Download STEP model using the same chain as Python plugins:
         // searchByCodes -> devices/{uuid} -> components/{3DModelUuid} -> dataStr.model
         if models.contains(&"STEP".to_string()) {
-            let step_dir = PathBuf::from(output_dir)
-                .join(footprint_lib)
-                .join(model_dir);
+            let shared_dir = options.shared_model_dir();
+            let step_dir = shared_dir.clone().unwrap_or_else(|| {
+                PathBuf::from(output_dir).join(&footprint_library).join(model_dir)
+            });
             fs::create_dir_all(&step_dir)?;
             
-            let step_path = step_dir.join(format!("{}.step", footprint_name));
             let mut model_candidates: Vec<String> = Vec::new();
-            if let Ok(Some(uuid)) = client.resolve_step_uuid_via_pro_api(component_id).await {
-                model_candidates.push(uuid);
-            }
             if let Some(uuid) = extract_model_uuid_from_shape(&fp_data.result.data_str.shape) {
                 model_candidates.push(uuid);
             }
             model_candidates.push(footprint_uuid.to_string());
-            model_candidates.dedup();
+            // Keyed as in `create_footprint_internal`, so both find each other's copies.
+            let model_key = model_candidates[0].clone();
+            let shared_files = shared_dir
+                .as_ref()
+                .and_then(|dir| SharedModelManifest::files(dir, &model_key));
 
-            let mut last_error: Option<String> = None;
-            for model_uuid in model_candidates {
-                match client
-                    .download_step_model(&model_uuid, step_path.to_str().unwrap())
-                    .await
-                {
-                    Ok(_) => {
+            if let Some(files) = shared_files {
+                model_path = files.first().map(|f| step_dir.join(f));
+                step_model_downloaded = true;
+            } else {
+                match fetch_3d_models(&client, component_id, model_candidates, options).await {
+                    Ok(fetched) => {
+                        let mut names = Vec::new();
+                        for (ext, content) in fetched {
+                            let file_name = format!("{}.{}", footprint_name, ext);
+                            let step_path = step_dir.join(&file_name);
+                            // A shared folder already holding this file keeps its copy.
+                            if shared_dir.is_none() || !step_path.is_file() {
+                                write_output_file(&step_path, &content, options)?;
+                                log::info!("Downloaded 3D model to {:?}", step_path);
+                            }
+                            model_path.get_or_insert(step_path);
+                            names.push(file_name);
+                        }
+                        if let Some(dir) = &shared_dir {
+                            SharedModelManifest::record(dir, &model_key, names);
+                        }
                         step_model_downloaded = true;
-                        log::info!("Downloaded STEP model to {:?}", step_path);
-                        break;
                     }
-                    Err(e) => {
-                        last_error = Some(format!(
-                            "3D 模型下载失败（模型UUID: {}）: {}",
-                            model_uuid, e
-                        ));
+                    Err(e) if options.strict_3d => {
+                        return Err(JlcError::ApiError(format!("3D 模型下载失败: {}", e)))
                     }
+                    Err(e) => step_model_error = Some(e),
                 }
             }
-
-            if !step_model_downloaded {
-                step_model_error = last_error;
-            }
         }
     }
 
-    if !create_footprint
-        && !create_symbol
-        && models.contains(&"STEP".to_string())
-        && !step_model_downloaded
-    {
-        return Err(JlcError::ApiError(
-            step_model_error
-                .clone()
-                .unwrap_or_else(|| "3D 模型下载失败".to_string()),
-        ));
-    }
 
     // Create footprint
+    let mut fp_filters: Vec<String> = Vec::new();
+    let mut footprint_warnings: Vec<String> = Vec::new();
+    let mut geometry: Vec<FootprintGeometry> = Vec::new();
     if create_footprint {
-        let result = create_footprint_internal(
-            &client,
-            footprint_uuid,
-            component_id,
-            output_dir,
-            footprint_lib,
-            model_dir,
-            &models,
-        )
-        .await?;
-        footprint_name = result.0;
-        datasheet_link = result.1;
-        step_model_downloaded |= result.2;
+        let variant_uuids = if options.all_footprint_variants && composite.is_empty() {
+            component_data.footprint_uuids()
+        } else {
+            Vec::new()
+        };
+        if variant_uuids.len() > 1 {
+            for (idx, uuid) in variant_uuids.iter().enumerate() {
+                if uuid == footprint_uuid {
+                    continue;
+                }
+                let variant_options = ConversionOptions {
+                    footprint_suffix: format!("_V{}", idx + 1),
+                    ..options.clone()
+                };
+                let result = create_footprint_internal(
+                    &client,
+                    uuid,
+                    component_id,
+                    output_dir,
+                    footprint_lib,
+                    model_dir,
+                    &models,
+                    &variant_options,
+                )
+                .await;
+                // One broken variant leaves the part and its other variants alone.
+                match result {
+                    Ok(result) => {
+                        footprint_warnings.extend(
+                            result.warnings.iter().map(|w| format!("封装变体 V{}: {}", idx + 1, w)),
+                        );
+                        geometry.extend(result.geometry);
+                        fp_filters.push(result.name);
+                    }
+                    Err(e) => {
+                        log::warn!("{}: footprint variant {} failed: {}", component_id, uuid, e);
+                        footprint_warnings.push(format!("封装变体 V{} 转换失败: {}", idx + 1, e));
+                    }
+                }
+            }
+        }
+
+        let mut outputs = Vec::new();
+        if composite.is_empty() {
+            outputs.push(
+                create_footprint_internal(
+                    &client,
+                    footprint_uuid,
+                    component_id,
+                    output_dir,
+                    footprint_lib,
+                    model_dir,
+                    &models,
+                    options,
+                )
+                .await?,
+            );
+        }
+        for (n, part) in composite.iter_mut().enumerate() {
+            let result = create_footprint_internal(
+                &client,
+                &part.footprint_uuid,
+                component_id,
+                output_dir,
+                footprint_lib,
+                model_dir,
+                &models,
+                &options.for_composite_part(component_id, n),
+            )
+            .await?;
+            part.footprint_name = result.name.clone();
+            part.footprint_reference = footprint_reference(&result.library, &result.name);
+            part.pad_numbers = result.pad_numbers.clone();
+            outputs.push(result);
+        }
+        for output in &mut outputs {
+            footprint_warnings.append(&mut output.warnings);
+            geometry.extend(output.geometry.take());
+        }
+        // The first part of a composite device stands for it in the index.
+        let result = outputs.swap_remove(0);
+        footprint_name = result.name;
+        footprint_library = result.library;
+        datasheet_link = result.datasheet_link;
+        step_model_downloaded |= result.model_downloaded;
         if step_model_error.is_none() {
-            step_model_error = result.3;
+            step_model_error = result.model_error;
+        }
+        footprint_path = Some(result.path);
+        pad_numbers = Some(result.pad_numbers);
+        if result.model_path.is_some() {
+            model_path = result.model_path;
+        }
+        if !fp_filters.is_empty() {
+            fp_filters.insert(0, footprint_name.clone());
         }
     } else if create_symbol && footprint_name.is_empty() {
         // Still need to get footprint info for symbol
         let fp_data = client.get_footprint_data(footprint_uuid).await?;
-        footprint_name = fp_data.result.title.replace(" ", "_")
-            .replace("/", "_")
-            .replace("(", "_")
-            .replace(")", "_");
+        let c_para = fp_data.result.data_str.head.c_para.as_ref();
+        let manufacturer = c_para.and_then(|c| c.manufacturer.as_deref());
+        footprint_name = options.footprint_name(&fp_data.result.title, manufacturer);
+        footprint_library =
+            options.footprint_library(footprint_lib, &fp_data.result.title, manufacturer);
         datasheet_link = fp_data.result.data_str.head.c_para
             .and_then(|c| c.link)
             .unwrap_or_default();
+        for (n, part) in composite.iter_mut().enumerate() {
+            let fp_data = client.get_footprint_data(&part.footprint_uuid).await?;
+            let manufacturer = fp_data.result.data_str.head.c_para.as_ref();
+            let manufacturer = manufacturer.and_then(|c| c.manufacturer.as_deref());
+            let part_options = options.for_composite_part(component_id, n);
+            part.footprint_name = part_options.footprint_name(&fp_data.result.title, manufacturer);
+            let library =
+                part_options.footprint_library(footprint_lib, &fp_data.result.title, manufacturer);
+            part.footprint_reference = footprint_reference(&library, &part.footprint_name);
+        }
     }
 
     // Create symbol
-    if create_symbol && !symbol_uuids.is_empty() {
-        create_symbol_internal(
+    let mut symbol_files = Vec::new();
+    let mut pin_numbers: Option<BTreeSet<String>> = None;
+    let mut part_mismatches: Vec<String> = Vec::new();
+    if create_symbol && !composite.is_empty() {
+        let (files, part_pins) = create_composite_symbols(
+            &client,
+            &composite,
+            &datasheet_link,
+            component_id,
+            &PathBuf::from(output_dir).join(symbol_path),
+            symbol_lib,
+            options,
+        )
+        .await?;
+        symbol_files = files;
+        if create_footprint {
+            for (part, pins) in composite.iter().zip(&part_pins) {
+                if let Some(m) = pin_pad_mismatch(pins, &part.pad_numbers) {
+                    part_mismatches.push(format!("{}: {}", part.footprint_name, m));
+                }
+            }
+        }
+    } else if create_symbol && !symbol_uuids.is_empty() {
+        let (files, pins) = create_symbol_internal(
             &client,
             &symbol_uuids,
-            &footprint_name,
+            &footprint_reference(&footprint_library, &footprint_name),
             &datasheet_link,
+            &fp_filters,
             component_id,
             output_dir,
             symbol_lib,
             symbol_path,
+            options,
         )
         .await?;
+        symbol_files = files;
+        pin_numbers = Some(pins);
+    }
+    let mismatch = pin_numbers
+        .zip(pad_numbers)
+        .and_then(|(pins, pads)| pin_pad_mismatch(&pins, &pads))
+        .or_else(|| (!part_mismatches.is_empty()).then(|| part_mismatches.join("; ")));
+    if let Some(m) = &mismatch {
+        log::warn!("{}: {}", component_id, m);
+    }
+    let missing_datasheet = create_symbol
+        .then(|| options.missing_datasheet(component_id))
+        .flatten();
+
+    if options.write_index {
+        let meta = cached_search_result(component_id);
+        let symbol_file = symbol_files.into_iter().next();
+        let entry = PartIndexEntry {
+            id: component_id.to_string(),
+            name: meta
+                .as_ref()
+                .map(|m| m.name.clone())
+                .unwrap_or_else(|| footprint_name.clone()),
+            package: meta.as_ref().and_then(|m| m.package.clone()).unwrap_or_default(),
+            manufacturer: meta.as_ref().and_then(|m| m.manufacturer.clone()).unwrap_or_default(),
+            datasheet: datasheet_link.clone(),
+            footprint: footprint_path,
+            symbol: symbol_file,
+            model: model_path,
+        };
+        update_parts_index(Path::new(output_dir), &entry)?;
     }
 
     let model_status = if step_model_downloaded {
         "downloaded"
+    } else if !models.is_empty() && options.model_reference_only {
+        "referenced"
     } else if !models.is_empty() {
         "failed"
     } else {
@@ -1274,42 +3620,604 @@ pub async fn create_component(
         String::new()
     };
 
-    Ok(format!(
-        "Component {} created successfully!\nFootprint: {}\nSymbol: {}\n3D Model: {}{}",
+    let composite_line = if composite.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<&str> = composite.iter().map(|p| p.footprint_name.as_str()).collect();
+        format!("\nParts: {}", names.join(", "))
+    };
+
+    let message = format!(
+        "Component {} created successfully!\nFootprint: {}\nSymbol: {}\n3D Model: {}{}{}",
         component_id,
         if create_footprint { "created" } else { "skipped" },
         if create_symbol { "created" } else { "skipped" },
         model_status,
-        model_error_line
-    ))
+        composite_line,
+        model_error_line,
+    );
+    Ok(CreatedComponent {
+        message,
+        warnings: mismatch
+            .into_iter()
+            .chain(missing_datasheet)
+            .chain(footprint_warnings)
+            .collect(),
+        geometry,
+    })
 }
 
-async fn download_step_only_online(
+/// Converts one part entirely in memory; nothing is written to disk.
+pub async fn convert_to_bytes(
     component_id: &str,
-    model_name: &str,
-    output_dir: &str,
-    footprint_lib: &str,
-    model_dir: &str,
-) -> Result<(), JlcError> {
-    let client = JlcClient::new();
-    let step_uuid = client
-        .resolve_step_uuid_via_pro_api(component_id)
-        .await?
-        .ok_or_else(|| JlcError::ApiError("未获取到3D模型UUID".to_string()))?;
+    opts: &ArtifactOptions,
+) -> Result<ConvertedArtifacts, JlcError> {
+    opts.conversion.validate()?;
+    let opts = &ArtifactOptions {
+        conversion: ConversionOptions {
+            file_name_fallback: component_id.to_string(),
+            ..opts.conversion.clone()
+        },
+        ..opts.clone()
+    };
+    let client = JlcClient::shared();
+    let component_data = client.get_component_data(component_id).await?;
+    if component_data.result.is_empty() {
+        return Err(JlcError::ApiError(format!(
+            "No results found for component {}",
+            component_id
+        )));
+    }
 
-    let step_dir = PathBuf::from(output_dir).join(footprint_lib).join(model_dir);
-    fs::create_dir_all(&step_dir)?;
-    let preferred = sanitize_footprint_name(model_name);
-    let fallback = sanitize_footprint_name(component_id);
-    let file_base = if preferred.is_empty() { fallback } else { preferred };
-    let step_path = step_dir.join(format!("{}.step", file_base));
-    client
-        .download_step_model(&step_uuid, step_path.to_string_lossy().as_ref())
-        .await
-}
+    let footprint_uuid =
+        &select_footprint_uuid(&client, &component_data, &opts.conversion.footprint_variant)
+            .await?;
+    let symbol_uuids = component_data.symbol_uuids();
 
-fn get_symbol_data_by_uuid<'a>(bundle: &'a OfflineBundle, symbol_uuid: &str) -> Option<&'a String> {
-    if let Some(v) = bundle.symbol_data.get(symbol_uuid) {
+    let models: Vec<String> = if opts.model {
+        vec!["STEP".to_string()]
+    } else {
+        Vec::new()
+    };
+    let draft = build_footprint_draft(
+        &client,
+        footprint_uuid,
+        "",
+        &opts.footprint_lib,
+        &opts.model_dir,
+        &models,
+        &opts.conversion,
+    )
+    .await?;
+
+    let mut artifacts = ConvertedArtifacts {
+        footprint_name: draft.name.clone(),
+        footprint_lib: draft.library.clone(),
+        warnings: draft.info.warnings.clone(),
+        ..Default::default()
+    };
+
+    let referenced = if opts.model && opts.conversion.model_reference_only {
+        opts.conversion.referenced_models(&draft.name)
+    } else {
+        Vec::new()
+    };
+    if opts.model && referenced.is_empty() {
+        let candidates = draft.model_candidates.clone();
+        match fetch_3d_models(&client, component_id, candidates, &opts.conversion).await {
+            Ok(fetched) => {
+                artifacts.model_bytes = fetched
+                    .into_iter()
+                    .next()
+                    .map(|(ext, content)| (format!("{}.{}", draft.name, ext), content));
+            }
+            Err(e) if opts.conversion.strict_3d => {
+                return Err(JlcError::ApiError(format!("3D 模型下载失败: {}", e)))
+            }
+            Err(e) => artifacts.model_error = Some(e),
+        }
+    }
+
+    if opts.footprint {
+        let model_files = if referenced.is_empty() {
+            artifacts.model_bytes.as_slice()
+        } else {
+            referenced.as_slice()
+        };
+        artifacts.footprint_kicad_mod =
+            Some(finish_footprint(&draft, model_files, &opts.conversion));
+    }
+
+    if opts.symbol && !symbol_uuids.is_empty() {
+        artifacts.symbol_kicad_sym = Some(finish_symbol_lib(
+            build_symbol_lib(
+                &client,
+                &symbol_uuids,
+                &footprint_reference(&draft.library, &draft.name),
+                &draft.datasheet_link,
+                &[],
+                component_id,
+                &opts.conversion,
+            )
+            .await?,
+            &opts.conversion,
+        ));
+    }
+
+    Ok(artifacts)
+}
+
+/// Which editor `clipboard_text` converts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardKind {
+    Footprint,
+    Symbol,
+}
+
+/// A part's footprint or symbol as KiCad's editors put it on the clipboard,
+/// ready to paste with Ctrl+V. The footprint editor takes a bare `(footprint
+/// ...)`; the symbol editor takes one `(symbol ...)` without the
+/// `kicad_symbol_lib` wrapper, so only unit 1 of a multi-unit part is given.
+pub async fn clipboard_text(
+    component_id: &str,
+    kind: ClipboardKind,
+    conversion: &ConversionOptions,
+) -> Result<String, JlcError> {
+    // KiCad 5 has no S-expression symbols and the clipboard holds no models.
+    let mut conversion = conversion.clone();
+    if conversion.kicad_target() == 5 {
+        conversion.kicad_version = LATEST_KICAD_VERSION;
+    }
+    let opts = ArtifactOptions {
+        footprint: kind == ClipboardKind::Footprint,
+        symbol: kind == ClipboardKind::Symbol,
+        model: false,
+        conversion,
+        ..ArtifactOptions::default()
+    };
+    let artifacts = convert_to_bytes(component_id, &opts).await?;
+    let text = match kind {
+        ClipboardKind::Footprint => artifacts.footprint_kicad_mod,
+        ClipboardKind::Symbol => artifacts.symbol_kicad_sym.as_deref().and_then(clipboard_symbol),
+    };
+    text.ok_or_else(|| {
+        let what = if kind == ClipboardKind::Footprint { "封装" } else { "符号" };
+        JlcError::ApiError(format!("元件 {} 没有可复制的{}", component_id, what))
+    })
+}
+
+/// The first symbol of a library, unindented, as KiCad pastes it.
+fn clipboard_symbol(lib: &str) -> Option<String> {
+    let first = symbol_blocks(lib).into_iter().next()?;
+    Some(format!("{}\n", lib[first].trim()))
+}
+
+/// What `convert_to_archive` converts and where the `.zip` goes; the layout
+/// inside the archive matches a normal conversion into `output_dir`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveOptions {
+    pub output_dir: String,
+    /// Archive file name without `.zip`; empty uses the first part id.
+    pub archive_name: String,
+    pub footprint_lib: String,
+    pub symbol_lib: String,
+    pub symbol_path: String,
+    pub model_dir: String,
+    pub models: Vec<String>,
+    pub create_footprint: bool,
+    pub create_symbol: bool,
+    pub conversion: ConversionOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveResult {
+    pub archive_path: String,
+    pub report: ConversionReport,
+}
+
+/// Converts the parts into a scratch directory and packs everything written
+/// there into `output_dir/<archive_name>.zip`. Fails only when no part could
+/// be converted; the scratch directory is always removed.
+pub async fn convert_to_archive(
+    component_ids: &[String],
+    opts: &ArchiveOptions,
+) -> Result<ArchiveResult, JlcError> {
+    opts.conversion.validate()?;
+    let name = sanitize_footprint_name(opts.archive_name.trim());
+    let name = if name.is_empty() {
+        component_ids
+            .first()
+            .map(|id| sanitize_footprint_name(id))
+            .unwrap_or_else(|| "JLC2KiCad".to_string())
+    } else {
+        name
+    };
+    // `{id}` names the archive's directory after the archive itself.
+    let opts = &ArchiveOptions {
+        output_dir: expand_output_dir(&opts.output_dir, Some(&name), &opts.symbol_lib),
+        ..opts.clone()
+    };
+    ensure_output_dir_writable(&opts.output_dir)?;
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let scratch = std::env::temp_dir().join(format!(
+        "jlc2kicad-archive-{}-{}",
+        std::process::id(),
+        nanos
+    ));
+    fs::create_dir_all(&scratch)?;
+
+    let result = convert_into_archive(component_ids, opts, &scratch, &name).await;
+    fs::remove_dir_all(&scratch).ok();
+    result
+}
+
+async fn convert_into_archive(
+    component_ids: &[String],
+    opts: &ArchiveOptions,
+    scratch: &Path,
+    name: &str,
+) -> Result<ArchiveResult, JlcError> {
+    let scratch_dir = scratch.to_string_lossy().to_string();
+    let mut report = ConversionReport {
+        found: component_ids.to_vec(),
+        ..Default::default()
+    };
+    for component_id in component_ids {
+        match create_component(
+            component_id,
+            &scratch_dir,
+            &opts.footprint_lib,
+            &opts.symbol_lib,
+            &opts.symbol_path,
+            &opts.model_dir,
+            opts.models.clone(),
+            opts.create_footprint,
+            opts.create_symbol,
+            &opts.conversion,
+        )
+        .await
+        {
+            Ok(result) => {
+                for warning in result.warnings {
+                    report.warn(component_id, warning);
+                }
+                report.geometry.extend(result.geometry);
+                report.converted.push(component_id.clone());
+            }
+            Err(e) => report.fail(component_id, e.to_string()),
+        }
+    }
+    if report.converted.is_empty() {
+        report.summarize("打包失败", None);
+        return Err(JlcError::ApiError(report.message));
+    }
+
+    let archive_path = PathBuf::from(&opts.output_dir).join(format!("{}.zip", name));
+    zip_directory(scratch, &archive_path)?;
+    report.summarize(
+        "转换并打包完成",
+        Some(format!("压缩包: {}", archive_path.display())),
+    );
+    Ok(ArchiveResult {
+        archive_path: archive_path.to_string_lossy().to_string(),
+        report,
+    })
+}
+
+/// Zips every file under `dir` with paths relative to it. Files are streamed
+/// into a temporary archive that is renamed over `archive_path` when complete.
+fn zip_directory(dir: &Path, archive_path: &Path) -> Result<(), JlcError> {
+    let zip_error = |e: zip::result::ZipError| {
+        JlcError::ApiError(format!("无法写入压缩包 {}: {}", archive_path.display(), e))
+    };
+    let mut files = gather_input_files(dir)?;
+    files.sort();
+
+    let file_name = archive_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path =
+        archive_path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let write = || -> Result<(), JlcError> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(true);
+        for file in &files {
+            let entry = file
+                .strip_prefix(dir)
+                .unwrap_or(file)
+                .to_string_lossy()
+                .replace('\\', "/");
+            writer.start_file(entry, options).map_err(zip_error)?;
+            std::io::copy(&mut File::open(file)?, &mut writer)?;
+        }
+        writer.finish().map_err(zip_error)?.sync_all()?;
+        fs::rename(&tmp_path, archive_path)?;
+        Ok(())
+    };
+    let result = write();
+    if result.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+/// How `update_library` refreshes parts; footprints and models are only
+/// rewritten when `output_dir` is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryUpdateOptions {
+    pub output_dir: String,
+    pub artifacts: ArtifactOptions,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryUpdateReport {
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// "id: error" for parts that could not be refreshed; their symbols are kept.
+    pub failed: Vec<String>,
+    pub backup_path: Option<String>,
+}
+
+/// Re-converts every part found (by its LCSC property) in an existing
+/// `.kicad_sym` and replaces its symbols in place. Symbols without an LCSC id
+/// are left untouched, and the original file is kept as `.bak` when anything
+/// changes. Running it twice in a row changes nothing the second time.
+pub async fn update_library(
+    symbol_lib_path: &str,
+    opts: &LibraryUpdateOptions,
+) -> Result<LibraryUpdateReport, JlcError> {
+    opts.artifacts.conversion.validate()?;
+    let path = PathBuf::from(symbol_lib_path);
+    if path.extension().and_then(|e| e.to_str()) != Some("kicad_sym") {
+        return Err(JlcError::ApiError("只支持更新 .kicad_sym 符号库".to_string()));
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| JlcError::ApiError(format!("无法读取符号库 {}: {}", symbol_lib_path, e)))?;
+    let write_files = !opts.output_dir.trim().is_empty();
+    if write_files {
+        ensure_output_dir_writable(&opts.output_dir)?;
+    }
+    let artifact_opts = ArtifactOptions {
+        footprint: opts.artifacts.footprint && write_files,
+        model: opts.artifacts.model && write_files,
+        symbol: true,
+        ..opts.artifacts.clone()
+    };
+
+    // Symbol blocks grouped by part, in file order.
+    let mut parts: Vec<(String, Vec<std::ops::Range<usize>>)> = Vec::new();
+    for range in symbol_blocks(&content) {
+        let Some(id) = symbol_lcsc_id(&content[range.clone()]) else {
+            continue;
+        };
+        match parts.iter_mut().find(|(existing, _)| *existing == id) {
+            Some((_, ranges)) => ranges.push(range),
+            None => parts.push((id, vec![range])),
+        }
+    }
+    if parts.is_empty() {
+        return Err(JlcError::ApiError(format!(
+            "符号库 {} 中没有带 LCSC 编号的符号",
+            symbol_lib_path
+        )));
+    }
+
+    let mut report = LibraryUpdateReport::default();
+    let mut replacements: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    for (id, ranges) in &parts {
+        let artifacts = match convert_to_bytes(id, &artifact_opts).await {
+            Ok(artifacts) => artifacts,
+            Err(e) => {
+                report.failed.push(format!("{}: {}", id, e));
+                continue;
+            }
+        };
+        let new_lib = artifacts.symbol_kicad_sym.clone().unwrap_or_default();
+        let new_symbols: String = symbol_blocks(&new_lib)
+            .into_iter()
+            .map(|r| new_lib[r].to_string())
+            .collect();
+        if new_symbols.is_empty() {
+            report.failed.push(format!("{}: 未生成符号", id));
+            continue;
+        }
+        let old_symbols: String = ranges.iter().map(|r| &content[r.clone()]).collect();
+        let mut changed = old_symbols != new_symbols;
+        if write_files {
+            match write_updated_artifacts(&opts.output_dir, &artifact_opts, &artifacts) {
+                Ok(files_changed) => changed |= files_changed,
+                Err(e) => {
+                    report.failed.push(format!("{}: {}", id, e));
+                    continue;
+                }
+            }
+        }
+
+        replacements.push((ranges[0].clone(), new_symbols));
+        for range in &ranges[1..] {
+            replacements.push((range.clone(), String::new()));
+        }
+        if changed {
+            report.changed.push(id.clone());
+        } else {
+            report.unchanged.push(id.clone());
+        }
+    }
+
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut updated = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, text) in &replacements {
+        updated.push_str(&content[last..range.start]);
+        updated.push_str(text);
+        last = range.end;
+    }
+    updated.push_str(&content[last..]);
+
+    if updated != content {
+        let backup = path.with_extension("kicad_sym.bak");
+        fs::copy(&path, &backup)?;
+        write_file_atomic(&path, updated.as_bytes())?;
+        report.backup_path = Some(backup.to_string_lossy().to_string());
+    }
+    Ok(report)
+}
+
+/// Writes a refreshed footprint and model; true when either differs from disk.
+fn write_updated_artifacts(
+    output_dir: &str,
+    opts: &ArtifactOptions,
+    artifacts: &ConvertedArtifacts,
+) -> Result<bool, JlcError> {
+    let mut changed = false;
+    let mut write_if_changed = |path: PathBuf, content: &[u8]| -> Result<(), JlcError> {
+        if fs::read(&path).ok().as_deref() != Some(content) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_file_atomic(&path, content)?;
+            changed = true;
+        }
+        Ok(())
+    };
+
+    if let Some(footprint) = &artifacts.footprint_kicad_mod {
+        let dir = PathBuf::from(output_dir).join(&artifacts.footprint_lib);
+        write_if_changed(
+            dir.join(format!("{}.kicad_mod", artifacts.footprint_name)),
+            footprint.as_bytes(),
+        )?;
+    }
+    if let Some((file_name, content)) = &artifacts.model_bytes {
+        if !opts.conversion.embeds_3d_model() {
+            let dir = PathBuf::from(output_dir)
+                .join(&artifacts.footprint_lib)
+                .join(&opts.model_dir);
+            write_if_changed(dir.join(file_name), content)?;
+        }
+    }
+    Ok(changed)
+}
+
+/// Byte ranges of the top-level `(symbol ...)` blocks in a `.kicad_sym`,
+/// each including its indentation and trailing newline.
+fn symbol_blocks(content: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut start: Option<usize> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if in_string {
+            match c {
+                b'\\' => i += 1,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                b'"' => in_string = true,
+                b'(' => {
+                    depth += 1;
+                    if depth == 2 && content[i..].starts_with("(symbol ") {
+                        let line_start = content[..i].rfind('\n').map_or(0, |p| p + 1);
+                        start = Some(if content[line_start..i].trim().is_empty() {
+                            line_start
+                        } else {
+                            i
+                        });
+                    }
+                }
+                b')' => {
+                    if depth == 2 {
+                        if let Some(s) = start.take() {
+                            let mut end = i + 1;
+                            if bytes.get(end) == Some(&b'\n') {
+                                end += 1;
+                            }
+                            blocks.push(s..end);
+                        }
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    blocks
+}
+
+fn symbol_lcsc_id(block: &str) -> Option<String> {
+    static LCSC_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let lcsc_re = LCSC_RE.get_or_init(|| {
+        regex::Regex::new(r#"\(property\s+"LCSC"\s+"\s*([Cc]\d+)\s*""#).unwrap()
+    });
+    lcsc_re.captures(block).map(|c| c[1].to_uppercase())
+}
+
+/// Fetches the 3D models, in `preferred_3d_format`, of a part converted from a
+/// local library, which rarely carries any, naming them after `model_name`.
+async fn download_models_only_online(
+    component_id: &str,
+    model_name: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    model_dir: &str,
+    options: &ConversionOptions,
+) -> Result<(), JlcError> {
+    let client = JlcClient::shared();
+    let step_uuid = client
+        .resolve_step_uuid_via_pro_api(component_id)
+        .await?
+        .ok_or_else(|| JlcError::ApiError("未获取到3D模型UUID".to_string()))?;
+
+    let shared_dir = options.shared_model_dir();
+    if let Some(dir) = &shared_dir {
+        if SharedModelManifest::files(dir, &step_uuid).is_some() {
+            return Ok(());
+        }
+    }
+    let step_dir = shared_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(output_dir).join(footprint_lib).join(model_dir));
+    fs::create_dir_all(&step_dir)?;
+    let preferred = sanitize_footprint_name(model_name);
+    let fallback = sanitize_footprint_name(component_id);
+    let file_base = if preferred.is_empty() { fallback } else { preferred };
+    let fetched = fetch_3d_models(&client, component_id, vec![step_uuid.clone()], options)
+        .await
+        .map_err(JlcError::ApiError)?;
+    let mut names = Vec::new();
+    for (ext, content) in fetched {
+        let file_name = format!("{}.{}", file_base, ext);
+        let model_path = step_dir.join(&file_name);
+        // A shared folder already holding this file keeps its copy.
+        if shared_dir.is_none() || !model_path.is_file() {
+            write_output_file(&model_path, &content, options)?;
+        }
+        names.push(file_name);
+    }
+    if let Some(dir) = &shared_dir {
+        SharedModelManifest::record(dir, &step_uuid, names);
+    }
+    Ok(())
+}
+
+fn get_symbol_data_by_uuid<'a>(bundle: &'a OfflineBundle, symbol_uuid: &str) -> Option<&'a String> {
+    if let Some(v) = bundle.symbol_data.get(symbol_uuid) {
         return Some(v);
     }
     let short = uuid_first_part(symbol_uuid);
@@ -1343,7 +4251,7 @@ fn get_footprint_title_by_uuid(bundle: &OfflineBundle, footprint_uuid: &str) ->
 }
 
 pub async fn search_components(query: &str) -> Result<Vec<SearchResult>, JlcError> {
-    let client = JlcClient::new();
+    let client = JlcClient::shared();
     match client.search_components(query).await {
         Ok(results) if !results.is_empty() => Ok(results),
         Ok(_) | Err(_) => search_lcsc(query).await,
@@ -1351,19 +4259,71 @@ pub async fn search_components(query: &str) -> Result<Vec<SearchResult>, JlcErro
 }
 
 pub async fn search_easyeda(query: &str) -> Result<Vec<SearchResult>, JlcError> {
+    search_easyeda_with(query, &mut |_| {}, &|| false).await
+}
+
+static SEARCH_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Stops the running `search_easyeda_streaming` call, if any.
+pub fn cancel_search() {
+    SEARCH_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+fn search_cancelled() -> JlcError {
+    JlcError::ApiError("搜索已取消".to_string())
+}
+
+/// Like `search_easyeda`, but hands every result to `on_result` as soon as it
+/// is ready. Starting another streaming search cancels this one.
+pub async fn search_easyeda_streaming<F>(
+    query: &str,
+    mut on_result: F,
+) -> Result<Vec<SearchResult>, JlcError>
+where
+    F: FnMut(&SearchResult) + Send,
+{
+    let generation = SEARCH_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let is_cancelled = move || SEARCH_GENERATION.load(Ordering::SeqCst) != generation;
+    search_easyeda_with(query, &mut on_result, &is_cancelled).await
+}
+
+async fn search_easyeda_with(
+    query: &str,
+    on_result: &mut (dyn FnMut(&SearchResult) + Send),
+    is_cancelled: &(dyn Fn() -> bool + Sync),
+) -> Result<Vec<SearchResult>, JlcError> {
     let trimmed = query.trim();
     if trimmed.is_empty() {
         return Err(JlcError::ApiError("请输入搜索关键字".to_string()));
     }
+    if let Some(results) = cached_search(trimmed) {
+        for result in &results {
+            on_result(result);
+        }
+        return Ok(results);
+    }
+
+    let results = search_easyeda_uncached(trimmed, on_result, is_cancelled).await?;
+    store_search(trimmed, &results);
+    Ok(results)
+}
 
-    let client = JlcClient::new();
-    match client.search_easyeda_pro(trimmed).await {
+async fn search_easyeda_uncached(
+    trimmed: &str,
+    on_result: &mut (dyn FnMut(&SearchResult) + Send),
+    is_cancelled: &(dyn Fn() -> bool + Sync),
+) -> Result<Vec<SearchResult>, JlcError> {
+    let client = JlcClient::shared();
+    match client.search_easyeda_pro_with(trimmed, on_result, is_cancelled).await {
         Ok(results) if !results.is_empty() => Ok(results),
         Ok(_) => {
             // Fallback to legacy endpoint for C-code lookups.
             if trimmed.to_uppercase().starts_with('C') {
                 if let Ok(results) = client.search_components(trimmed).await {
                     if !results.is_empty() {
+                        for result in &results {
+                            on_result(result);
+                        }
                         return Ok(results);
                     }
                 }
@@ -1375,6 +4335,9 @@ pub async fn search_easyeda(query: &str) -> Result<Vec<SearchResult>, JlcError>
             if trimmed.to_uppercase().starts_with('C') {
                 if let Ok(results) = client.search_components(trimmed).await {
                     if !results.is_empty() {
+                        for result in &results {
+                            on_result(result);
+                        }
                         return Ok(results);
                     }
                 }
@@ -1388,12 +4351,81 @@ pub async fn search_easyeda(query: &str) -> Result<Vec<SearchResult>, JlcError>
     }
 }
 
+/// Codes sent per searchByCodes request by `search_codes`.
+const CODE_SEARCH_BATCH: usize = 50;
+
+/// Outcome of `search_codes`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CodeSearchReport {
+    pub results: Vec<SearchResult>,
+    /// Plausible codes EasyEDA returned nothing for.
+    pub not_found: Vec<String>,
+    /// Inputs that are not LCSC codes and were never looked up.
+    pub skipped: Vec<String>,
+}
+
+/// Looks up many C-codes with batched searchByCodes requests, e.g. to check a
+/// BOM before converting it. `on_progress` gets (codes done, total) per batch.
+pub async fn search_codes<F>(
+    codes: &[String],
+    mut on_progress: F,
+) -> Result<CodeSearchReport, JlcError>
+where
+    F: FnMut(usize, usize) + Send,
+{
+    let mut report = CodeSearchReport::default();
+    let mut wanted: Vec<String> = Vec::new();
+    for code in codes {
+        let code = code.trim().to_uppercase();
+        if code.is_empty() || wanted.contains(&code) {
+            continue;
+        }
+        if is_plausible_component_id(&code) {
+            wanted.push(code);
+        } else {
+            report.skipped.push(code);
+        }
+    }
+
+    let client = JlcClient::shared();
+    let mut done = 0;
+    for batch in wanted.chunks(CODE_SEARCH_BATCH) {
+        let form: Vec<(&str, String)> = batch.iter().map(|c| ("codes[]", c.clone())).collect();
+        let by_codes = client
+            .easyeda_post_form_json("/api/v2/devices/searchByCodes", &form)
+            .await?;
+        let items = by_codes
+            .get("result")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut found = HashSet::new();
+        for item in &items {
+            let id = code_item_id(item).to_uppercase();
+            if !batch.contains(&id) || !found.insert(id.clone()) {
+                continue;
+            }
+            let result = client.code_item_result(item, id).await;
+            store_search(&result.id, std::slice::from_ref(&result));
+            report.results.push(result);
+        }
+        report
+            .not_found
+            .extend(batch.iter().filter(|c| !found.contains(*c)).cloned());
+
+        done += batch.len();
+        on_progress(done, wanted.len());
+    }
+    Ok(report)
+}
+
 pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
-    let client = JlcClient::new();
+    let client = JlcClient::shared();
 
     // 1) Same method as python plugin easyeda_lib_loader.py:
     // POST /api/v2/devices/search with uid/path = "lcsc"
-    if let Ok(found) = client
+    let easyeda_found = client
         .easyeda_post_form_json(
             "/api/v2/devices/search",
             &[
@@ -1405,8 +4437,11 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
                 ("path", "lcsc".to_string()),
             ],
         )
-        .await
-    {
+        .await;
+    // Set when a source answers with 429 or a captcha page, so the user learns
+    // they are throttled rather than that nothing matched.
+    let mut rate_limited = matches!(easyeda_found, Err(JlcError::HtmlResponse(_)));
+    if let Ok(found) = easyeda_found {
         if found
             .get("success")
             .and_then(|v| v.as_bool())
@@ -1456,6 +4491,7 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
                                 price: None,
                                 stock: None,
                                 image_url: None,
+                                jlc_class: extract_jlc_class(item),
                             });
                         }
                     }
@@ -1474,18 +4510,18 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
         .lcsc_client
         .get("https://wwwapi.lcsc.com/v1/search/global-search")
         .query(&[("keyword", query)])
-        .header(
-            reqwest::header::USER_AGENT,
-            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36",
-        )
-        .header(reqwest::header::REFERER, "https://www.lcsc.com/")
         .send()
         .await;
 
     if let Ok(resp) = public_resp {
+        rate_limited |= resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS;
         if resp.status().is_success() {
-            let text = resp.text().await?;
-            if let Ok(data) = serde_json::from_str::<serde_json::Value>(&text) {
+            let text = read_text_guarded(resp).await.unwrap_or_else(|e| {
+                rate_limited |= matches!(e, JlcError::HtmlResponse(_));
+                log::warn!("LCSC search response rejected: {}", e);
+                String::new()
+            });
+            if let Ok(data) = parse_json_lenient::<serde_json::Value>(&text) {
                 let list = data
                     .get("productSearchResultVO")
                     .and_then(|v| v.get("productList"))
@@ -1556,6 +4592,7 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
                         price: None,
                         stock: None,
                         image_url: Some(format!("https://wmsc.lcsc.com/wmsc/upload/file/eec/image/{}.jpg", id)),
+                        jlc_class: extract_jlc_class(product),
                     });
                 }
 
@@ -1571,18 +4608,18 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
         .lcsc_client
         .get("https://wmsc.lcsc.com/wmsc/product/detail")
         .query(&[("search", query)])
-        .header(
-            reqwest::header::USER_AGENT,
-            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36",
-        )
-        .header(reqwest::header::REFERER, "https://www.lcsc.com/")
         .send()
         .await;
 
     if let Ok(resp) = legacy_resp {
+        rate_limited |= resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS;
         if resp.status().is_success() {
-            let text = resp.text().await?;
-            if let Ok(data) = serde_json::from_str::<serde_json::Value>(&text) {
+            let text = read_text_guarded(resp).await.unwrap_or_else(|e| {
+                rate_limited |= matches!(e, JlcError::HtmlResponse(_));
+                log::warn!("LCSC search response rejected: {}", e);
+                String::new()
+            });
+            if let Ok(data) = parse_json_lenient::<serde_json::Value>(&text) {
                 let mut results = Vec::new();
                 if let Some(products) = data.get("products").and_then(|p| p.as_array()) {
                     for product in products.iter().take(20) {
@@ -1630,6 +4667,7 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
                             price: None,
                             stock: None,
                             image_url: Some(format!("https://wmsc.lcsc.com/wmsc/upload/file/eec/image/{}.jpg", id)),
+                            jlc_class: None,
                         });
                     }
                 }
@@ -1641,6 +4679,11 @@ pub async fn search_lcsc(query: &str) -> Result<Vec<SearchResult>, JlcError> {
         }
     }
 
+    if rate_limited {
+        return Err(JlcError::ApiError(
+            "立创商城暂时限制了访问频率（返回了验证码/限流页面），请稍后再试或配置代理".to_string(),
+        ));
+    }
     Err(JlcError::ApiError(
         "立创商城公开搜索受限；官方API需申请 key/secret 并签名调用（详见 LCSC API 文档）。请使用 EasyEDA、配置官方API，或改用本地文件".to_string(),
     ))
@@ -1669,21 +4712,23 @@ pub async fn import_local_model_for_component(
         ));
     }
 
-    let client = JlcClient::new();
+    let client = JlcClient::shared();
     let component_data = client.get_component_data(component_id).await?;
     if component_data.result.is_empty() {
         return Err(JlcError::ApiError(format!("未找到元件 {}", component_id)));
     }
 
-    let footprint_uuid = &component_data.result.last().unwrap().component_uuid;
-    let fp_data = client.get_footprint_data(footprint_uuid).await?;
+    let Some(footprint_uuid) = component_data.footprint_uuids().pop() else {
+        return Err(JlcError::ApiError(format!("未找到元件 {} 的封装", component_id)));
+    };
+    let fp_data = client.get_footprint_data(&footprint_uuid).await?;
     let footprint_name = sanitize_footprint_name(&fp_data.result.title);
 
     let normalized_ext = if ext == "stp" { "step" } else { &ext };
     let dest_dir = PathBuf::from(output_dir).join(footprint_lib).join(model_dir);
     fs::create_dir_all(&dest_dir)?;
     let dest_path = dest_dir.join(format!("{}.{}", footprint_name, normalized_ext));
-    fs::copy(&src_path, &dest_path)?;
+    write_file_atomic(&dest_path, &fs::read(&src_path)?)?;
 
     // If footprint already exists, inject/replace model reference automatically.
     let footprint_path = PathBuf::from(output_dir)
@@ -1713,7 +4758,7 @@ pub async fn import_local_model_for_component(
             } else {
                 content.push_str(&model_line);
             }
-            fs::write(&footprint_path, content)?;
+            write_file_atomic(&footprint_path, content.as_bytes())?;
         }
     }
 
@@ -1729,6 +4774,17 @@ fn component_id_regex() -> &'static regex::Regex {
     RE.get_or_init(|| regex::Regex::new(r"\bC\d{3,}\b").unwrap())
 }
 
+/// LCSC codes have no leading zero and at most 9 digits, so "C0402"/"C0G"
+/// style package and dielectric codes are rejected.
+fn is_plausible_component_id(id: &str) -> bool {
+    let Some(digits) = id.strip_prefix('C') else {
+        return false;
+    };
+    (3..=9).contains(&digits.len())
+        && !digits.starts_with('0')
+        && digits.chars().all(|c| c.is_ascii_digit())
+}
+
 fn looks_like_hex_uuid(value: &str) -> bool {
     let s = value.trim();
     if s.len() == 32 {
@@ -1768,10 +4824,40 @@ fn normalize_component_token(value: &str) -> Option<String> {
     None
 }
 
+/// Standalone C-codes rejected by `is_plausible_component_id`, e.g. package
+/// codes like "C0402". Reference designators like "C12" are everywhere in a
+/// BOM or schematic, so they are left out rather than reported.
+fn implausible_component_ids(content: &str, skipped: &mut BTreeSet<String>) {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r"\bC\d+\b").unwrap());
+    let is_designator = |digits: &str| digits.len() < 3 && !digits.starts_with('0');
+    for m in re.find_iter(content) {
+        if !is_plausible_component_id(m.as_str()) && !is_designator(&m.as_str()[1..]) {
+            skipped.insert(m.as_str().to_string());
+        }
+    }
+}
+
 fn extract_component_ids_from_text(content: &str, ids: &mut HashSet<String>) {
+    // Value and package tokens carry digits ("50V", "X7R"); words like a
+    // URL's "html" or an empty rest after a full stop do not.
+    let is_value_token = |token: &str| token.chars().any(|c| c.is_ascii_digit());
     for m in component_id_regex().find_iter(content) {
+        // Glued to a value or package token ("50V-C104", "C1206.X7R"), it is
+        // part of a description rather than a standalone code.
+        let before = content[..m.start()]
+            .strip_suffix(['.', '-'])
+            .and_then(|s| s.rsplit(|c: char| !c.is_ascii_alphanumeric()).next());
+        let after = content[m.end()..]
+            .strip_prefix(['.', '-'])
+            .and_then(|s| s.split(|c: char| !c.is_ascii_alphanumeric()).next());
+        if [before, after].into_iter().flatten().any(is_value_token) {
+            continue;
+        }
         if let Some(id) = normalize_component_token(m.as_str()) {
-            ids.insert(id);
+            if is_plausible_component_id(&id) {
+                ids.insert(id);
+            }
         }
     }
 }
@@ -1906,7 +4992,7 @@ fn extract_component_ids_from_elibz(path: &Path) -> Result<HashSet<String>, JlcE
         }
     }
     if found {
-        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
+        if let Ok(json) = parse_json_lenient::<serde_json::Value>(&content) {
             extract_component_ids_from_json_value(&json, &mut ids);
         }
     }
@@ -1920,7 +5006,7 @@ fn extract_data_str_from_component_blob(content: &str) -> Option<String> {
         return None;
     }
 
-    if let Ok(v) = serde_json::from_str::<serde_json::Value>(trimmed) {
+    if let Ok(v) = parse_json_lenient::<serde_json::Value>(trimmed) {
         if let Some(ds) = v.get("dataStr") {
             if let Some(s) = ds.as_str() {
                 return Some(s.to_string());
@@ -1962,7 +5048,7 @@ fn parse_elibz_components(path: &Path) -> Result<BTreeMap<String, SearchResult>,
         return Ok(out);
     }
 
-    let json: serde_json::Value = serde_json::from_str(&content)?;
+    let json: serde_json::Value = parse_json_lenient(&content)?;
     let mut footprint_titles: BTreeMap<String, String> = BTreeMap::new();
     if let Some(footprints) = json.get("footprints").and_then(|v| v.as_object()) {
         for (uuid, fp) in footprints {
@@ -1974,7 +5060,7 @@ fn parse_elibz_components(path: &Path) -> Result<BTreeMap<String, SearchResult>,
     if let Ok(mut fp_manifest) = archive.by_name("footprint.json") {
         let mut fp_content = String::new();
         fp_manifest.read_to_string(&mut fp_content)?;
-        if let Ok(fp_json) = serde_json::from_str::<serde_json::Value>(&fp_content) {
+        if let Ok(fp_json) = parse_json_lenient::<serde_json::Value>(&fp_content) {
             if let Some(obj) = fp_json.as_object() {
                 for (uuid, fp) in obj {
                     if let Some(title) = first_non_empty_str(
@@ -2046,6 +5132,7 @@ fn parse_elibz_components(path: &Path) -> Result<BTreeMap<String, SearchResult>,
                 price: None,
                 stock: None,
                 image_url: None,
+                jlc_class: None,
             },
         );
     }
@@ -2053,7 +5140,7 @@ fn parse_elibz_components(path: &Path) -> Result<BTreeMap<String, SearchResult>,
     Ok(out)
 }
 
-fn extract_component_ids_from_file(path: &Path) -> HashSet<String> {
+fn extract_component_ids_from_file(path: &Path, skipped: &mut BTreeSet<String>) -> HashSet<String> {
     let mut ids = HashSet::new();
     let ext = path
         .extension()
@@ -2064,15 +5151,17 @@ fn extract_component_ids_from_file(path: &Path) -> HashSet<String> {
     match ext.as_str() {
         "json" => {
             if let Ok(content) = fs::read_to_string(path) {
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
+                if let Ok(json) = parse_json_lenient::<serde_json::Value>(&content) {
                     extract_component_ids_from_json_value(&json, &mut ids);
                 }
                 extract_component_ids_from_text(&content, &mut ids);
+                implausible_component_ids(&content, skipped);
             }
         }
         "txt" | "csv" | "tsv" | "list" | "eda" | "lcsc" => {
             if let Ok(content) = fs::read_to_string(path) {
                 extract_component_ids_from_text(&content, &mut ids);
+                implausible_component_ids(&content, skipped);
             }
             if ids.is_empty() {
                 if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
@@ -2093,10 +5182,21 @@ fn extract_component_ids_from_file(path: &Path) -> HashSet<String> {
     ids
 }
 
+struct LocalComponents {
+    map: BTreeMap<String, SearchResult>,
+    /// Id-like tokens that were not plausible ids.
+    skipped: Vec<String>,
+}
+
 fn collect_local_component_map(path: &Path) -> Result<BTreeMap<String, SearchResult>, JlcError> {
+    Ok(collect_local_components(path)?.map)
+}
+
+fn collect_local_components(path: &Path) -> Result<LocalComponents, JlcError> {
     let files = gather_input_files(path)?;
     let mut map: BTreeMap<String, SearchResult> = BTreeMap::new();
     let mut ids = HashSet::new();
+    let mut skipped = BTreeSet::new();
 
     for file in files {
         let ext = file
@@ -2123,7 +5223,7 @@ fn collect_local_component_map(path: &Path) -> Result<BTreeMap<String, SearchRes
             continue;
         }
 
-        let found = extract_component_ids_from_file(&file);
+        let found = extract_component_ids_from_file(&file, &mut skipped);
         for id in found {
             ids.insert(id.clone());
             map.entry(id.clone()).or_insert(SearchResult {
@@ -2136,6 +5236,7 @@ fn collect_local_component_map(path: &Path) -> Result<BTreeMap<String, SearchRes
                 price: None,
                 stock: None,
                 image_url: None,
+                jlc_class: None,
             });
         }
     }
@@ -2153,19 +5254,20 @@ fn collect_local_component_map(path: &Path) -> Result<BTreeMap<String, SearchRes
         ));
     }
 
-    Ok(map)
-}
-
-fn collect_component_ids_from_path(path: &Path) -> Result<HashSet<String>, JlcError> {
-    let map = collect_local_component_map(path)?;
-    Ok(map.keys().cloned().collect())
+    Ok(LocalComponents {
+        map,
+        skipped: skipped.into_iter().collect(),
+    })
 }
 
 #[derive(Debug, Clone)]
 struct OfflineDevice {
     id: String,
     name: String,
+    /// Default footprint, the first of `footprint_uuids`.
     footprint_uuid: Option<String>,
+    /// Every footprint variant the device lists.
+    footprint_uuids: Vec<String>,
     symbol_uuids: Vec<String>,
     model_title: Option<String>,
 }
@@ -2186,6 +5288,44 @@ fn split_uuid_first(value: Option<&str>) -> Option<String> {
         .filter(|s| !s.is_empty())
 }
 
+/// All uuids of a device attribute that lists several footprints or symbols,
+/// either as an array or as a comma/semicolon separated string.
+fn split_uuid_list(value: Option<&serde_json::Value>) -> Vec<String> {
+    let entries: Vec<&str> = match value {
+        Some(serde_json::Value::String(s)) => s.split([',', ';', '\n']).collect(),
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let mut uuids: Vec<String> = Vec::new();
+    for uuid in entries.into_iter().filter_map(|e| split_uuid_first(Some(e))) {
+        if !uuids.contains(&uuid) {
+            uuids.push(uuid);
+        }
+    }
+    uuids
+}
+
+/// Footprint to convert for an offline device: the variant matching
+/// `selector` by uuid or title, otherwise the device default.
+fn offline_footprint_uuid(
+    bundle: &OfflineBundle,
+    device: &OfflineDevice,
+    selector: &str,
+) -> Option<String> {
+    let selector = selector.trim();
+    if !selector.is_empty() {
+        let matched = device.footprint_uuids.iter().find(|u| {
+            u.eq_ignore_ascii_case(selector)
+                || get_footprint_title_by_uuid(bundle, u)
+                    .is_some_and(|t| t.eq_ignore_ascii_case(selector))
+        });
+        if let Some(uuid) = matched {
+            return Some(uuid.clone());
+        }
+    }
+    device.footprint_uuid.clone()
+}
+
 #[derive(Debug, Clone, Default)]
 struct ElibuSymbolPin {
     x: f64,
@@ -2220,15 +5360,138 @@ fn json_str(v: Option<&serde_json::Value>) -> Option<String> {
     }
 }
 
-fn elibu_pin_type_to_code(pin_type: &str) -> &'static str {
-    match pin_type.to_lowercase().as_str() {
-        "input" => "1",
-        "output" => "2",
-        "bidirectional" => "3",
-        "power" | "power_in" => "4",
-        _ => "0",
-    }
-}
+/// "corner:<round ratio>:<chamfer ratio>:<corner bits>" for a Pro pad with
+/// rounded or chamfered corners, empty otherwise. Radius and chamfer are
+/// lengths like the pad size; corner bits follow KiCad (1 top-left,
+/// 2 top-right, 4 bottom-left, 8 bottom-right).
+fn elibu_pad_corners(pad: &serde_json::Value, short_side: f64) -> String {
+    if short_side <= 0.0 {
+        return String::new();
+    }
+    let radius = json_num(pad.get("cornerRadius"))
+        .or_else(|| json_num(pad.get("radius")))
+        .unwrap_or(0.0);
+    let chamfer = json_num(pad.get("chamfer")).unwrap_or(0.0);
+    let corner_bits = match pad.get("chamferCorners") {
+        Some(serde_json::Value::Number(n)) => n.as_u64().unwrap_or(0) & 0xF,
+        Some(serde_json::Value::Array(names)) => names
+            .iter()
+            .filter_map(|n| n.as_str())
+            .map(|n| match n.to_lowercase().replace(['_', '-'], "").as_str() {
+                "tl" | "topleft" => 1,
+                "tr" | "topright" => 2,
+                "bl" | "bottomleft" => 4,
+                "br" | "bottomright" => 8,
+                _ => 0,
+            })
+            .fold(0, |bits, bit| bits | bit),
+        // A chamfer without corner flags applies to all four corners.
+        _ if chamfer > 0.0 => 0xF,
+        _ => 0,
+    };
+
+    let round_ratio = (radius / short_side).clamp(0.0, 0.5);
+    let chamfer_ratio = if corner_bits == 0 {
+        0.0
+    } else {
+        (chamfer / short_side).clamp(0.0, 0.5)
+    };
+    if round_ratio <= 0.0 && chamfer_ratio <= 0.0 {
+        return String::new();
+    }
+    format!(
+        "corner:{}:{}:{}",
+        format_number(round_ratio, 4),
+        format_number(chamfer_ratio, 4),
+        corner_bits
+    )
+}
+
+impl ElibuSymbolPin {
+    /// The pin as a classic `P~` shape line, which parse_symbol_pin reads.
+    fn shape_line(&self) -> String {
+        let pin_num = if self.pin_num.is_empty() { "0" } else { &self.pin_num };
+        format!(
+            "P~1~{}~{}~{}~{}~{}~0~0~0~0~0~0~0~{}",
+            elibu_pin_type_to_code(&self.pin_type),
+            pin_num,
+            self.x,
+            self.y,
+            self.rotation,
+            self.pin_name
+        )
+    }
+
+    /// A pin given as a JSON object, with the .elibu attribute names either on
+    /// the object itself, under `attrs`, or in camel case.
+    fn from_object(value: &serde_json::Value) -> Option<Self> {
+        let kind = json_str(value.get("type")).unwrap_or_else(|| "PIN".to_string());
+        if !kind.eq_ignore_ascii_case("PIN") {
+            return None;
+        }
+        let attr = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|k| {
+                    json_str(value.get(*k))
+                        .or_else(|| json_str(value.get("attrs").and_then(|a| a.get(*k))))
+                })
+                .unwrap_or_default()
+        };
+        Some(Self {
+            x: json_num(value.get("x"))?,
+            y: json_num(value.get("y"))?,
+            rotation: json_num(value.get("rotation")).unwrap_or(0.0),
+            pin_num: attr(&["Pin Number", "pinNumber", "number"]),
+            pin_name: attr(&["Pin Name", "pinName", "name"]),
+            pin_type: attr(&["Pin Type", "pinType", "electric"]),
+        })
+    }
+}
+
+/// Symbol shapes are mostly strings, but EasyEDA Pro sends pins as objects;
+/// those become `P~` lines and any other object is dropped.
+fn deserialize_symbol_shape<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let items = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(items
+        .iter()
+        .filter_map(|item| match item {
+            serde_json::Value::String(line) => Some(line.clone()),
+            serde_json::Value::Object(_) => {
+                ElibuSymbolPin::from_object(item).map(|pin| pin.shape_line())
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+fn elibu_pin_type_to_code(pin_type: &str) -> &'static str {
+    match pin_type.to_lowercase().as_str() {
+        "input" => "1",
+        "output" => "2",
+        "bidirectional" => "3",
+        "power" | "power_in" => "4",
+        _ => "0",
+    }
+}
+
+/// Like `extract_polyline_numbers`, but keeps `ARC`/`CARC` markers so arc
+/// segments survive into the TRACK line.
+fn polyline_path_tokens(path: &serde_json::Value) -> Vec<String> {
+    let Some(arr) = path.as_array() else {
+        return Vec::new();
+    };
+    arr.iter()
+        .filter_map(|item| match item {
+            serde_json::Value::Number(n) => n.as_f64().map(|v| v.to_string()),
+            serde_json::Value::String(s) if s == "ARC" || s == "CARC" => Some(s.clone()),
+            serde_json::Value::String(s) => s.parse::<f64>().ok().map(|v| v.to_string()),
+            _ => None,
+        })
+        .collect()
+}
 
 fn extract_polyline_numbers(path: &serde_json::Value) -> Vec<f64> {
     let mut out = Vec::new();
@@ -2263,21 +5526,7 @@ fn flush_elibu_doc(acc: &mut ElibuDocAccumulator, bundle: &mut OfflineBundle) {
 
     if acc.doc_type.eq_ignore_ascii_case("SYMBOL") {
         for pin in acc.pins.values() {
-            let pin_num = if pin.pin_num.is_empty() {
-                "0".to_string()
-            } else {
-                pin.pin_num.clone()
-            };
-            let line = format!(
-                "P~1~{}~{}~{}~{}~{}~0~0~0~0~0~0~0~{}",
-                elibu_pin_type_to_code(&pin.pin_type),
-                pin_num,
-                pin.x,
-                pin.y,
-                pin.rotation,
-                pin.pin_name
-            );
-            acc.lines.push(line);
+            acc.lines.push(pin.shape_line());
         }
         if !acc.lines.is_empty() {
             bundle
@@ -2316,7 +5565,7 @@ fn parse_elibu_content(content: &str, bundle: &mut OfflineBundle) -> Result<(),
             continue;
         }
 
-        let header: serde_json::Value = match serde_json::from_str(left) {
+        let header: serde_json::Value = match parse_json_lenient(left) {
             Ok(v) => v,
             Err(_) => continue,
         };
@@ -2443,14 +5692,25 @@ fn parse_elibu_content(content: &str, bundle: &mut OfflineBundle) -> Result<(),
                     let sy = json_num(default_pad.get("height")).unwrap_or(1.0);
                     let mut layer = json_str(payload.get("layerId")).unwrap_or_else(|| "1".to_string());
                     let mut drill = 0.0_f64;
+                    let mut slot_length = 0.0_f64;
+                    let mut plated = "Y";
                     if let Some(hole) = payload.get("hole") {
                         if !hole.is_null() {
-                            drill = json_num(hole.get("radius"))
-                                .or_else(|| json_num(hole.get("diameter")).map(|d| d / 2.0))
-                                .or_else(|| json_num(hole.get("width")).map(|d| d / 2.0))
+                            let hole_w = json_num(hole.get("width"))
+                                .or_else(|| json_num(hole.get("diameter")))
+                                .or_else(|| json_num(hole.get("radius")).map(|r| r * 2.0))
                                 .unwrap_or(0.0);
+                            let hole_h = json_num(hole.get("height")).unwrap_or(hole_w);
+                            drill = hole_w.min(hole_h) / 2.0;
+                            if hole_w.max(hole_h) > hole_w.min(hole_h) {
+                                slot_length = hole_w.max(hole_h);
+                            }
                             if drill > 0.0 {
                                 layer = "11".to_string();
+                                // parse_pad decides plated vs mechanical, as for online pads.
+                                if payload.get("plated").and_then(|v| v.as_bool()) == Some(false) {
+                                    plated = "N";
+                                }
                             }
                         }
                     }
@@ -2459,9 +5719,31 @@ fn parse_elibu_content(content: &str, bundle: &mut OfflineBundle) -> Result<(),
                     let rot = json_num(payload.get("padAngle"))
                         .or_else(|| json_num(payload.get("relativeAngle")))
                         .unwrap_or(0.0);
+                    let net = json_str(payload.get("net"))
+                        .unwrap_or_default()
+                        .replace('~', "_");
+                    let corners = if shape.eq_ignore_ascii_case("RECT") {
+                        elibu_pad_corners(default_pad, sx.min(sy))
+                    } else {
+                        String::new()
+                    };
+                    // Same field order as EasyEDA PAD shapes: ..., id, holeLength, holePoints,
+                    // plated; corner shaping, which EasyEDA Std lacks, goes after them.
                     acc.lines.push(format!(
-                        "PAD~{}~{}~{}~{}~{}~{}~0~{}~{}~0~{}",
-                        shape, x, y, sx, sy, layer, pad_num, drill, rot
+                        "PAD~{}~{}~{}~{}~{}~{}~{}~{}~{}~0~{}~0~{}~0~{}~{}",
+                        shape,
+                        x,
+                        y,
+                        sx,
+                        sy,
+                        layer,
+                        net,
+                        pad_num,
+                        drill,
+                        rot,
+                        slot_length,
+                        plated,
+                        corners
                     ));
                 }
                 "POLY" | "FILL" => {
@@ -2482,23 +5764,15 @@ fn parse_elibu_content(content: &str, bundle: &mut OfflineBundle) -> Result<(),
                                 }
                             }
                         }
-                        let points = extract_polyline_numbers(path);
-                        if points.len() >= 4 {
-                            let pt = points
-                                .chunks(2)
-                                .filter_map(|xy| {
-                                    if xy.len() == 2 {
-                                        Some(format!("{} {}", xy[0], xy[1]))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect::<Vec<_>>()
-                                .join(" ");
-                            if !pt.is_empty() {
-                                acc.lines
-                                    .push(format!("TRACK~{}~{}~0~{}", width, layer, pt));
-                            }
+                        let tokens = polyline_path_tokens(path);
+                        let numbers = tokens.iter().filter(|t| t.parse::<f64>().is_ok()).count();
+                        if numbers >= 4 {
+                            acc.lines.push(format!(
+                                "TRACK~{}~{}~0~{}",
+                                width,
+                                layer,
+                                tokens.join(" ")
+                            ));
                         }
                     }
                 }
@@ -2536,7 +5810,7 @@ fn load_offline_bundle_from_elibz(path: &Path) -> Result<OfflineBundle, JlcError
     }
 
     {
-        let json: serde_json::Value = serde_json::from_str(&content)?;
+        let json: serde_json::Value = parse_json_lenient(&content)?;
 
         if let Some(footprints) = json.get("footprints").and_then(|v| v.as_object()) {
             for (uuid, fp) in footprints {
@@ -2570,9 +5844,9 @@ fn load_offline_bundle_from_elibz(path: &Path) -> Result<OfflineBundle, JlcError
                 }
 
                 let attrs = dev.get("attributes").unwrap_or(dev);
-                let footprint_uuid = split_uuid_first(attrs.get("Footprint").and_then(|v| v.as_str()));
-                let symbol_uuid = split_uuid_first(attrs.get("Symbol").and_then(|v| v.as_str()));
-                let symbol_uuids = symbol_uuid.into_iter().collect();
+                let footprint_uuids = split_uuid_list(attrs.get("Footprint"));
+                let footprint_uuid = footprint_uuids.first().cloned();
+                let symbol_uuids = split_uuid_list(attrs.get("Symbol"));
 
                 let name = first_non_empty_str(
                     dev,
@@ -2588,6 +5862,7 @@ fn load_offline_bundle_from_elibz(path: &Path) -> Result<OfflineBundle, JlcError
                         id,
                         name,
                         footprint_uuid,
+                        footprint_uuids,
                         symbol_uuids,
                         model_title,
                     },
@@ -2679,6 +5954,17 @@ fn load_offline_bundle(path: &Path) -> Result<Option<OfflineBundle>, JlcError> {
     }
 }
 
+/// Whether a local dataStr's head declares millimetre coordinates.
+fn local_data_str_is_metric(ds: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(ds)
+        .ok()
+        .map(|v| {
+            let unit = v.get("head").and_then(|h| h.get("unit")).and_then(|u| u.as_str());
+            is_metric_unit(unit)
+        })
+        .unwrap_or(false)
+}
+
 fn parse_local_data_str(ds: &str) -> Option<(Vec<String>, f64, f64)> {
     if let Ok(v) = serde_json::from_str::<serde_json::Value>(ds) {
         let shape = v
@@ -2735,6 +6021,30 @@ fn index_local_models(path: &Path) -> Result<BTreeMap<String, PathBuf>, JlcError
     Ok(map)
 }
 
+/// Adds the models found under `dirs` to `index`, folder by folder; a name
+/// already indexed keeps the file it was first found as.
+fn add_model_search_dirs(index: &mut BTreeMap<String, PathBuf>, dirs: &[String]) {
+    for dir in dirs.iter().map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        match index_local_models(Path::new(dir)) {
+            Ok(models) => {
+                for (name, path) in models {
+                    index.entry(name).or_insert(path);
+                }
+            }
+            Err(e) => log::warn!("Skipping 3D model folder {}: {}", dir, e),
+        }
+    }
+}
+
+/// What `create_footprint_from_offline` wrote.
+struct OfflineFootprint {
+    pad_numbers: BTreeSet<String>,
+    /// Primitives left out of the footprint.
+    warnings: Vec<String>,
+    geometry: Option<FootprintGeometry>,
+}
+
+/// Writes one footprint from offline data.
 fn create_footprint_from_offline(
     device: &OfflineDevice,
     footprint_name_hint: Option<&str>,
@@ -2744,31 +6054,39 @@ fn create_footprint_from_offline(
     model_dir: &str,
     models: &[String],
     model_index: &BTreeMap<String, PathBuf>,
-) -> Result<bool, JlcError> {
+    options: &ConversionOptions,
+) -> Result<OfflineFootprint, JlcError> {
     let (shape, origin_x, origin_y) = parse_local_data_str(footprint_ds)
         .ok_or_else(|| JlcError::ParseError("无法解析本地封装 dataStr".to_string()))?;
+    options.check_shape_kinds(&shape, &FOOTPRINT_SHAPE_KINDS)?;
+    let options = &ConversionOptions {
+        file_name_fallback: device.id.clone(),
+        ..options.clone()
+    };
 
-    let footprint_name = sanitize_footprint_name(
-        footprint_name_hint
-            .filter(|s| !s.trim().is_empty())
-            .unwrap_or(&device.name),
-    );
+    let manufacturer = symbol_ds_manufacturer(footprint_ds);
+    let title = footprint_name_hint
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(&device.name);
+    let footprint_name = options.footprint_name(title, manufacturer.as_deref());
+    let footprint_lib = &options.footprint_library(footprint_lib, title, manufacturer.as_deref());
     let mut footprint_info = FootprintInfo {
         footprint_name: footprint_name.clone(),
         output_dir: output_dir.to_string(),
         footprint_lib: footprint_lib.to_string(),
         model_dir: model_dir.to_string(),
+        model_base_variable: options.model_base_variable.trim().to_string(),
+        min_silk_width: options.min_silk_width.max(0.0),
+        simplify_tolerance: options.simplify_tolerance(),
+        shared_model_dir: options.shared_model_dir.trim().to_string(),
         origin: (origin_x, origin_y),
         models: models.to_vec(),
+        metric: local_data_str_is_metric(footprint_ds),
+        geometry: options.geometry_report.then(Vec::new),
         ..Default::default()
     };
 
-    let mut kicad_mod_content = String::new();
-    kicad_mod_content.push_str("(kicad_mod (version 20220214)\n");
-    kicad_mod_content.push_str(&format!(
-        "  (footprint {} (identifier {}) (user {})\n",
-        footprint_name, footprint_name, footprint_name
-    ));
+    let mut kicad_mod_content = footprint_header(&footprint_name, options);
 
     for line in &shape {
         let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
@@ -2777,9 +6095,12 @@ fn create_footprint_from_offline(
         }
         let model = parts[0];
         let args: Vec<&str> = parts[1..].to_vec();
+        // Pads and regions keep empty fields so the net stays in place.
+        let fields: Vec<&str> = line.split('~').skip(1).collect();
+        let emitted_from = kicad_mod_content.len();
         match model {
             "PAD" => {
-                if let Some(s) = parse_pad(&args, &mut footprint_info) {
+                if let Some(s) = parse_pad(&fields, &mut footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
@@ -2789,12 +6110,12 @@ fn create_footprint_from_offline(
                 }
             }
             "CIRCLE" => {
-                if let Some(s) = parse_circle(&args) {
+                if let Some(s) = parse_circle(&args, &mut footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
             "ARC" => {
-                if let Some(s) = parse_arc(&args) {
+                if let Some(s) = parse_arc(&args, &mut footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
@@ -2804,26 +6125,41 @@ fn create_footprint_from_offline(
                 }
             }
             "HOLE" => {
-                if let Some(s) = parse_hole(&args) {
+                if let Some(s) = parse_hole(&args, &footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
             "SOLIDREGION" => {
-                if let Some(s) = parse_solid_region(&args) {
+                if let Some(s) = parse_solid_region(&fields, &mut footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
             "TEXT" => {
-                if let Some(s) = parse_text(&args) {
+                if let Some(s) = parse_text(&fields, &mut footprint_info) {
+                    kicad_mod_content.push_str(&s);
+                }
+            }
+            "DIMENSION" => {
+                if let Some(s) = parse_dimension(&args, &footprint_info) {
+                    kicad_mod_content.push_str(&s);
+                }
+            }
+            "SVGNODE" => {
+                if let Some(s) = parse_svg_node(&args, &mut footprint_info) {
                     kicad_mod_content.push_str(&s);
                 }
             }
             _ => {}
         }
+        let emitted = &kicad_mod_content[emitted_from..];
+        record_geometry(&mut footprint_info, model, &args, &fields, emitted);
     }
 
-    let mut model_copied = false;
-    if models.contains(&"STEP".to_string()) {
+    if models.contains(&"STEP".to_string()) && options.model_reference_only {
+        for (file_name, _) in options.referenced_models(&footprint_name) {
+            kicad_mod_content.push_str(&footprint_model_line(&footprint_info, &file_name));
+        }
+    } else if models.contains(&"STEP".to_string()) {
         let mut candidate_keys = vec![device.id.to_lowercase(), footprint_name.to_lowercase()];
         if let Some(mt) = &device.model_title {
             candidate_keys.push(mt.to_lowercase());
@@ -2836,15 +6172,20 @@ fn create_footprint_from_offline(
                     .unwrap_or("step")
                     .to_lowercase();
                 let ext = if ext == "stp" { "step" } else { ext.as_str() };
-                let model_out_dir = PathBuf::from(output_dir).join(footprint_lib).join(model_dir);
+                let model_out_dir = options.shared_model_dir().unwrap_or_else(|| {
+                    PathBuf::from(output_dir).join(footprint_lib).join(model_dir)
+                });
                 fs::create_dir_all(&model_out_dir)?;
                 let dst_model = model_out_dir.join(format!("{}.{}", footprint_name, ext));
-                fs::copy(src_model, &dst_model)?;
+                // A shared folder already holding this model keeps its copy.
+                let needs_copy = options.shared_model_dir().is_none() || !dst_model.is_file();
+                if needs_copy {
+                    write_output_file(&dst_model, &fs::read(src_model)?, options)?;
+                }
                 kicad_mod_content.push_str(&format!(
-                    "  (model {}/{}.{} (at (xyz 0 0 0)) (rotate (xyz 0 0 0)))\n",
-                    model_dir, footprint_name, ext
+                    "  (model {} (at (xyz 0 0 0)) (rotate (xyz 0 0 0)))\n",
+                    model_reference(&footprint_info, &format!("{}.{}", footprint_name, ext))
                 ));
-                model_copied = true;
                 break;
             }
         }
@@ -2852,27 +6193,40 @@ fn create_footprint_from_offline(
 
     let center_x = (footprint_info.min_x + footprint_info.max_x) / 2.0;
     let center_y = (footprint_info.min_y + footprint_info.max_y) / 2.0;
-    kicad_mod_content.push_str(&format!(
-        "  (fp_text reference REF** (at {} {}) (layer F.SilkS)\n    (effects (font (size 1 1)))\n  )\n",
-        center_x, footprint_info.min_y - 2.0
-    ));
-    kicad_mod_content.push_str(&format!(
-        "  (fp_text value {} (at {} {}) (layer F.Fab)\n    (effects (font (size 1 1)))\n  )\n",
-        footprint_name, center_x, footprint_info.max_y + 2.0
-    ));
+    if options.pad_one_marker {
+        if let Some(marker) = pad_one_marker(&kicad_mod_content, (center_x, center_y)) {
+            kicad_mod_content.push_str(&marker);
+        }
+    }
+    kicad_mod_content.push_str(&reference_value_texts(&footprint_info, &footprint_name));
     kicad_mod_content.push_str(&format!(
         "  (fp_text user ${{REFERENCE}} (at {} {}) (layer F.Fab)\n    (effects (font (size 0.5 0.5)))\n  )\n",
         center_x, center_y
     ));
-    kicad_mod_content.push_str("  )\n)\n");
+    let origin = (footprint_info.mm(origin_x), footprint_info.mm(origin_y));
+    kicad_mod_content = translate_footprint(&kicad_mod_content, origin);
+    if options.sort_pads_and_pins {
+        kicad_mod_content = sort_footprint_pads(&kicad_mod_content);
+    }
+    if options.mirror_to_back {
+        kicad_mod_content = mirror_footprint_to_back(&kicad_mod_content);
+    }
+    if options.kicad_target() == 5 {
+        kicad_mod_content = kicad_mod_to_legacy(&kicad_mod_content);
+    }
+    kicad_mod_content = round_coordinates(&kicad_mod_content, options.coordinate_decimals());
+    kicad_mod_content.push_str(")\n");
 
     let output_path = PathBuf::from(output_dir).join(footprint_lib);
     fs::create_dir_all(&output_path)?;
     let file_path = output_path.join(format!("{}.kicad_mod", footprint_name));
-    let mut file = File::create(file_path)?;
-    file.write_all(kicad_mod_content.as_bytes())?;
+    write_output_file(&file_path, kicad_mod_content.as_bytes(), options)?;
 
-    Ok(model_copied)
+    Ok(OfflineFootprint {
+        pad_numbers: footprint_pad_numbers(&kicad_mod_content),
+        geometry: footprint_geometry(&footprint_info, &device.id, &footprint_name),
+        warnings: footprint_info.warnings,
+    })
 }
 
 fn symbol_prefix_from_ds(ds: &str) -> String {
@@ -2889,19 +6243,82 @@ fn symbol_prefix_from_ds(ds: &str) -> String {
         .unwrap_or_else(|| "U".to_string())
 }
 
+/// `head.c_para.Manufacturer` of an offline symbol or footprint dataStr.
+fn symbol_ds_manufacturer(data_str: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(data_str)
+        .ok()
+        .and_then(|v| {
+            v.pointer("/head/c_para/Manufacturer")
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+        })
+        .filter(|m| !m.trim().is_empty())
+}
+
+/// Manufacturer a local part's names are prefixed with: its first symbol's,
+/// falling back to its footprint's, so symbol and footprint always agree.
+fn offline_part_manufacturer(bundle: &OfflineBundle, device: &OfflineDevice) -> Option<String> {
+    device
+        .symbol_uuids
+        .iter()
+        .filter_map(|uuid| get_symbol_data_by_uuid(bundle, uuid))
+        .chain(device.footprint_uuid.as_ref().and_then(|u| bundle.footprint_data.get(u)))
+        .find_map(|ds| symbol_ds_manufacturer(ds))
+}
+
+/// `lib:name` of the footprint `create_footprint_from_offline` writes for
+/// `device`, for its symbols' Footprint property; empty for a device without
+/// a footprint, such as a power flag.
+fn offline_footprint_reference(
+    bundle: &OfflineBundle,
+    device: &OfflineDevice,
+    footprint_lib: &str,
+    options: &ConversionOptions,
+) -> String {
+    let Some(uuid) = offline_footprint_uuid(bundle, device, &options.footprint_variant) else {
+        return String::new();
+    };
+    let title = get_footprint_title_by_uuid(bundle, &uuid)
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| device.name.clone());
+    let options = ConversionOptions {
+        file_name_fallback: device.id.clone(),
+        ..options.clone()
+    };
+    footprint_reference(
+        &options.footprint_library(footprint_lib, &title, None),
+        &options.footprint_name(&title, None),
+    )
+}
+
+/// Writes the symbol library for `devices`, taking the ready-made symbol
+/// blocks in `online_symbols` for parts the bundle has no symbol data for.
 fn create_symbols_from_offline(
     devices: &[OfflineDevice],
+    online_symbols: &BTreeMap<String, String>,
     bundle: &OfflineBundle,
-    output_dir: &str,
-    symbol_lib: &str,
-    symbol_path: &str,
-) -> Result<usize, JlcError> {
-    let mut lib_content = String::new();
-    lib_content.push_str("(kicad_symbol_lib (version 20210201) (generator JLC2KiCad)\n");
-    let mut created = 0usize;
+    opts: &LocalConvertOptions,
+) -> Result<BTreeMap<String, BTreeSet<String>>, JlcError> {
+    let options = &opts.conversion;
+    let output_path = PathBuf::from(&opts.output_dir).join(&opts.symbol_path);
+    let mut lib = SymbolLibWriter::new(&output_path, &opts.symbol_lib, options)?;
+    // Pin numbers by part id, for every part that got a symbol.
+    let mut created: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
 
     for device in devices {
-        for (idx, symbol_uuid) in device.symbol_uuids.iter().enumerate() {
+        if let Some(symbols) = online_symbols.get(&device.id) {
+            created.insert(device.id.clone(), symbol_pin_numbers(symbols));
+            lib.push(symbols)?;
+            continue;
+        }
+        let options = &ConversionOptions {
+            part_manufacturer: offline_part_manufacturer(bundle, device),
+            ..options.clone()
+        };
+        let footprint = offline_footprint_reference(bundle, device, &opts.footprint_lib, options);
+        let mut units: Vec<SymbolUnit> = Vec::new();
+        let symbol_uuids = options.ordered_units(&device.id, &device.symbol_uuids);
+        for (idx, symbol_uuid) in symbol_uuids.into_iter().enumerate() {
             let Some(ds) = get_symbol_data_by_uuid(bundle, symbol_uuid) else {
                 continue;
             };
@@ -2914,19 +6331,16 @@ fn create_symbols_from_offline(
                 .get(symbol_uuid)
                 .cloned()
                 .unwrap_or_else(|| device.name.clone());
-            let component_name = title
-                .replace(" ", "_")
-                .replace(".", "_")
-                .replace("/", "{slash}")
-                .replace("\\", "{backslash}")
-                .replace("<", "{lt}")
-                .replace(">", "{gt}")
-                .replace(":", "{colon}")
-                .replace('"', "{dblquote}");
-            let sym_name = if idx == 0 {
-                format!("{}_{}", component_name, device.id)
-            } else {
-                format!("{}_{}_{}", component_name, device.id, idx)
+            let component_name = format!(
+                "{}{}",
+                options.name_prefix(None),
+                sanitize_symbol_name(&title)
+            );
+            let sym_name = match options.name_override(&device.id) {
+                Some(name) if idx == 0 => name.to_string(),
+                Some(name) => format!("{}_{}", name, idx),
+                None if idx == 0 => format!("{}_{}", component_name, device.id),
+                None => format!("{}_{}_{}", component_name, device.id, idx),
             };
             let prefix = bundle
                 .symbol_prefix
@@ -2934,352 +6348,4384 @@ fn create_symbols_from_offline(
                 .cloned()
                 .unwrap_or_else(|| symbol_prefix_from_ds(ds));
 
-            lib_content.push_str(&format!(
-                "  (symbol \"{}\" (pin_names hide) (pin_numbers hide) (in_bom yes) (on_board yes)\n",
-                sym_name
-            ));
-            lib_content.push_str(&format!(
-                "    (property \"Reference\" \"{}\" (id 0) (at 0 1.27 0)\n      (effects (font (size 1.27 1.27)))\n    )\n",
-                prefix
-            ));
-            lib_content.push_str(&format!(
-                "    (property \"Value\" \"{}\" (id 1) (at 0 -2.54 0)\n      (effects (font (size 1.27 1.27)))\n    )\n",
-                title
-            ));
-            lib_content.push_str(&format!(
-                "    (property \"LCSC\" \"{}\" (id 5) (at 0 0 0)\n      (effects (font (size 1.27 1.27)) hide)\n    )\n",
-                device.id
+            let metric = local_data_str_is_metric(ds);
+            let (body, pins) = symbol_unit_graphics(&shape, origin_x, origin_y, metric, options);
+            let power_net = (device.symbol_uuids.len() == 1)
+                .then(|| power_symbol_net(&prefix, None, &body, &pins, &title))
+                .flatten();
+            let body = if power_net.is_some() { power_symbol_body(&body) } else { body };
+            let (reference_at, value_at) = symbol_label_positions(&body);
+
+            let mut header = String::new();
+            header.push_str(&format!(
+                "  (symbol \"{}\" {}{} (pin_numbers hide) (in_bom yes) (on_board yes)\n",
+                sym_name,
+                if power_net.is_some() { "(power) " } else { "" },
+                options.pin_names_attr()
             ));
-
-            for line in &shape {
-                let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
-                if parts.is_empty() {
-                    continue;
+            let mut properties = SymbolProperties::new(options);
+            match &power_net {
+                Some(net) => {
+                    properties.add("Reference", "#PWR", reference_at, HIDDEN_PROPERTY_EFFECTS);
+                    properties.add("Value", net, value_at, PROPERTY_EFFECTS);
                 }
-                let model = parts[0];
-                let args: Vec<&str> = parts[1..].to_vec();
-                match model {
-                    "P" => {
-                        if let Some(s) = parse_symbol_pin(&args, origin_x, origin_y) {
-                            lib_content.push_str(&s);
-                        }
-                    }
-                    "R" => {
-                        if let Some(s) = parse_symbol_rect(&args, origin_x, origin_y) {
-                            lib_content.push_str(&s);
-                        }
-                    }
-                    "E" => {
-                        if let Some(s) = parse_symbol_circle(&args, origin_x, origin_y) {
-                            lib_content.push_str(&s);
-                        }
-                    }
-                    "T" => {
-                        if let Some(s) = parse_symbol_text(&args, origin_x, origin_y) {
-                            lib_content.push_str(&s);
-                        }
-                    }
-                    "PL" | "PG" => {
-                        if let Some(s) = parse_symbol_poly(&args, origin_x, origin_y) {
-                            lib_content.push_str(&s);
-                        }
-                    }
-                    _ => {}
+                None => {
+                    properties.add("Reference", &prefix, reference_at, PROPERTY_EFFECTS);
+                    properties.add("Value", &title, value_at, PROPERTY_EFFECTS);
                 }
             }
+            properties.add("Footprint", &footprint, (0.0, -10.16), HIDDEN_ITALIC_PROPERTY_EFFECTS);
+            let datasheet = options.datasheet_override(&device.id).unwrap_or_default();
+            properties.add("Datasheet", datasheet, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+            properties.add("LCSC", &device.id, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+            header.push_str(&properties.text);
 
-            lib_content.push_str("  )\n");
-            created += 1;
+            units.push(SymbolUnit { name: sym_name, header, body, pins });
+        }
+        if !units.is_empty() {
+            let symbols = emit_symbol_units(&units, options.demorgan);
+            created.insert(device.id.clone(), symbol_pin_numbers(&symbols));
+            lib.push(&symbols)?;
         }
     }
 
-    lib_content.push_str(")\n");
-    let output_path = PathBuf::from(output_dir).join(symbol_path);
-    fs::create_dir_all(&output_path)?;
-    let file_path = output_path.join(format!("{}.kicad_sym", symbol_lib));
-    let mut file = File::create(file_path)?;
-    file.write_all(lib_content.as_bytes())?;
+    lib.finish(&output_path, &opts.symbol_lib, options)?;
     Ok(created)
 }
 
-pub async fn load_local_folder(path: &str) -> Result<Vec<SearchResult>, JlcError> {
-    let source = Path::new(path);
-    let map = collect_local_component_map(source)?;
-    Ok(map.into_values().collect())
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KicadInstallation {
+    pub found: bool,
+    pub version: Option<String>,
+    pub model_dir: Option<String>,
+    pub model_base_variable: Option<String>,
+    pub output_dir: Option<String>,
 }
 
-pub async fn convert_local_folder(
-    path: &str,
-    output_dir: &str,
-    footprint_lib: &str,
-    symbol_lib: &str,
-    symbol_path: &str,
-    model_dir: &str,
-    models: Vec<String>,
-    create_footprint: bool,
-    create_symbol: bool,
-) -> Result<String, JlcError> {
-    let source_path = Path::new(path);
-    let bundle_kind = detect_local_bundle_kind(source_path);
-
-    if let Some(bundle) = load_offline_bundle(source_path)? {
-        let offline_can_export_footprint = !bundle.footprint_data.is_empty();
-        let offline_can_export_symbol = !bundle.symbol_data.is_empty();
-        let need_offline_data = (create_footprint && !offline_can_export_footprint)
-            || (create_symbol && !offline_can_export_symbol);
-
-        if need_offline_data {
-            // New elibz2 bundles may only include device2.json + .elibu.
-            // In this case keep local-ID discovery, then fall back to online conversion path.
-            let component_ids = collect_component_ids_from_path(source_path)?;
-            let mut success = 0usize;
-            let mut failed: Vec<String> = Vec::new();
-
-            for component_id in component_ids {
-                match create_component(
-                    &component_id,
-                    output_dir,
-                    footprint_lib,
-                    symbol_lib,
-                    symbol_path,
-                    model_dir,
-                    models.clone(),
-                    create_footprint,
-                    create_symbol,
-                )
-                .await
-                {
-                    Ok(_) => success += 1,
-                    Err(e) => failed.push(format!("{}: {}", component_id, e)),
-                }
-            }
-
-            if failed.is_empty() {
-                return Ok(format!(
-                    "本地转换完成（检测到 elibz2，已使用在线补全），成功 {} 个元件",
-                    success
-                ));
-            } else {
-                return Ok(format!(
-                    "本地转换完成（检测到 elibz2，已使用在线补全），成功 {} 个，失败 {} 个\n{}",
-                    success,
-                    failed.len(),
-                    failed.join("\n")
-                ));
-            }
+const KICAD_MAJOR_VERSIONS: [u32; 5] = [9, 8, 7, 6, 5];
+
+fn kicad_stock_model_dirs(major: u32) -> Vec<PathBuf> {
+    let mut dirs_out = Vec::new();
+    if cfg!(target_os = "windows") {
+        for root in ["C:\\Program Files\\KiCad", "C:\\Program Files (x86)\\KiCad"] {
+            dirs_out.push(
+                PathBuf::from(root)
+                    .join(format!("{}.0", major))
+                    .join("share")
+                    .join("kicad")
+                    .join("3dmodels"),
+            );
         }
+    } else if cfg!(target_os = "macos") {
+        dirs_out.push(PathBuf::from(
+            "/Applications/KiCad/KiCad.app/Contents/SharedSupport/3dmodels",
+        ));
+    } else {
+        for root in ["/usr/share/kicad", "/usr/local/share/kicad"] {
+            let base = PathBuf::from(root);
+            dirs_out.push(base.join("3dmodels"));
+            dirs_out.push(base.join("modules").join("packages3d"));
+        }
+    }
+    dirs_out
+}
 
-        let component_ids = collect_component_ids_from_path(source_path)?;
-        let model_index = index_local_models(source_path).unwrap_or_default();
-        let mut success = 0usize;
-        let mut failed: Vec<String> = Vec::new();
-        let mut selected_devices: Vec<OfflineDevice> = Vec::new();
+/// Best-effort lookup of a local KiCad install: environment variables first,
+/// then the per-version user config dir, then the usual install prefixes.
+pub fn detect_kicad() -> KicadInstallation {
+    let mut version: Option<u32> = None;
+    let mut model_dir: Option<PathBuf> = None;
 
-        for component_id in component_ids {
-            let Some(device) = bundle.devices.get(&component_id).cloned() else {
-                failed.push(format!("{}: 本地库缺少 device 元数据", component_id));
-                continue;
-            };
-            let model_name = device
-                .footprint_uuid
-                .as_ref()
-                .and_then(|u| get_footprint_title_by_uuid(&bundle, u))
-                .unwrap_or_else(|| device.name.clone());
-            selected_devices.push(device.clone());
-
-            if create_footprint {
-                if let Some(fp_uuid) = &device.footprint_uuid {
-                    if let Some(ds) = bundle.footprint_data.get(fp_uuid) {
-                        match create_footprint_from_offline(
-                            &device,
-                            device
-                                .footprint_uuid
-                                .as_ref()
-                                .and_then(|u| get_footprint_title_by_uuid(&bundle, u))
-                                .as_deref(),
-                            ds,
-                            output_dir,
-                            footprint_lib,
-                            model_dir,
-                            &models,
-                            &model_index,
-                        ) {
-                            Ok(_) => {
-                                // Local libraries usually do not include 3D models.
-                                // If STEP is requested, fetch it online directly.
-                                if models.contains(&"STEP".to_string()) {
-                                    match download_step_only_online(
-                                        &component_id,
-                                        &model_name,
-                                        output_dir,
-                                        footprint_lib,
-                                        model_dir,
-                                    )
-                                    .await
-                                    {
-                                        Ok(_) => success += 1,
-                                        Err(e) => failed.push(format!(
-                                            "{}: 封装已导出，但在线拉取3D失败: {}",
-                                            component_id, e
-                                        )),
-                                    }
-                                } else {
-                                    success += 1;
-                                }
-                            }
-                            Err(e) => failed.push(format!("{}: {}", component_id, e)),
-                        }
-                    } else {
-                        failed.push(format!("{}: 本地库缺少封装数据 {}", component_id, fp_uuid));
-                    }
-                } else {
-                    failed.push(format!("{}: 本地库未提供封装UUID", component_id));
-                }
-            } else if models.contains(&"STEP".to_string()) && !create_symbol {
-                // 3D-only mode: always fetch online (do not search local files).
-                match download_step_only_online(
-                    &component_id,
-                    &model_name,
-                    output_dir,
-                    footprint_lib,
-                    model_dir,
-                )
-                .await
-                {
-                    Ok(_) => success += 1,
-                    Err(e) => failed.push(format!("{}: 在线拉取3D失败: {}", component_id, e)),
-                }
-            } else {
-                success += 1;
+    for major in KICAD_MAJOR_VERSIONS {
+        let var = if major == 5 {
+            "KISYS3DMOD".to_string()
+        } else {
+            format!("KICAD{}_3DMODEL_DIR", major)
+        };
+        if let Ok(value) = std::env::var(&var) {
+            if !value.trim().is_empty() {
+                version = Some(major);
+                model_dir = Some(PathBuf::from(value.trim()));
+                break;
             }
         }
+    }
 
-        if create_symbol {
-            match create_symbols_from_offline(
-                &selected_devices,
-                &bundle,
-                output_dir,
-                symbol_lib,
-                symbol_path,
-            ) {
-                Ok(0) => failed.push("符号导出失败: 本地库未解析到可用符号数据".to_string()),
-                Ok(_) => {}
-                Err(e) => failed.push(format!("符号导出失败: {}", e)),
-            }
-        }
-
-        if failed.is_empty() {
-            if create_symbol {
-                let symbol_file = PathBuf::from(output_dir)
-                    .join(symbol_path)
-                    .join(format!("{}.kicad_sym", symbol_lib));
-                return Ok(format!(
-                    "本地离线转换完成（{}），成功 {} 个元件\n器件库文件: {}",
-                    bundle_kind,
-                    success,
-                    symbol_file.display()
-                ));
-            }
-            return Ok(format!("本地离线转换完成（{}），成功 {} 个元件", bundle_kind, success));
-        } else {
-            return Ok(format!(
-                "本地离线转换完成（{}），成功 {} 个，失败 {} 个\n{}",
-                bundle_kind,
-                success,
-                failed.len(),
-                failed.join("\n")
-            ));
+    if version.is_none() {
+        if let Some(config) = dirs::config_dir() {
+            version = KICAD_MAJOR_VERSIONS
+                .into_iter()
+                .find(|major| config.join("kicad").join(format!("{}.0", major)).is_dir());
         }
     }
 
-    let component_ids = collect_component_ids_from_path(Path::new(path))?;
-
-    let mut success = 0usize;
-    let mut failed: Vec<String> = Vec::new();
-
-    for component_id in component_ids {
-        match create_component(
-            &component_id,
-            output_dir,
-            footprint_lib,
-            symbol_lib,
-            symbol_path,
-            model_dir,
-            models.clone(),
-            create_footprint,
-            create_symbol,
-        )
-        .await
-        {
-            Ok(_) => success += 1,
-            Err(e) => failed.push(format!("{}: {}", component_id, e)),
+    let stock_candidates: Vec<(u32, PathBuf)> = match version {
+        Some(major) => kicad_stock_model_dirs(major)
+            .into_iter()
+            .map(|p| (major, p))
+            .collect(),
+        None => KICAD_MAJOR_VERSIONS
+            .into_iter()
+            .flat_map(|major| kicad_stock_model_dirs(major).into_iter().map(move |p| (major, p)))
+            .collect(),
+    };
+    if model_dir.is_none() {
+        if let Some((major, dir)) = stock_candidates.into_iter().find(|(_, p)| p.is_dir()) {
+            // A shared install prefix doesn't tell the version; keep a detected one.
+            version.get_or_insert(major);
+            model_dir = Some(dir);
         }
     }
 
-    if failed.is_empty() {
-        Ok(format!("本地转换完成，成功 {} 个元件", success))
+    let Some(major) = version else {
+        return KicadInstallation::default();
+    };
+
+    // KiCad 7+ ships a per-user KICADn_3RD_PARTY directory meant for add-on libraries.
+    let (model_base_variable, output_dir) = if major >= 7 {
+        let third_party = std::env::var(format!("KICAD{}_3RD_PARTY", major))
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                dirs::document_dir().map(|d| {
+                    d.join("KiCad")
+                        .join(format!("{}.0", major))
+                        .join("3rdparty")
+                })
+            });
+        (
+            Some(format!("${{KICAD{}_3RD_PARTY}}/JLC2KiCad_lib", major)),
+            third_party.map(|p| p.join("JLC2KiCad_lib").to_string_lossy().to_string()),
+        )
     } else {
-        Ok(format!(
-            "本地转换完成，成功 {} 个，失败 {} 个\n{}",
-            success,
-            failed.len(),
-            failed.join("\n")
-        ))
+        (None, None)
+    };
+
+    KicadInstallation {
+        found: true,
+        version: Some(format!("{}.0", major)),
+        model_dir: model_dir.map(|p| p.to_string_lossy().to_string()),
+        model_base_variable,
+        output_dir,
     }
 }
 
-async fn create_footprint_internal(
-    client: &JlcClient,
-    footprint_uuid: &str,
-    component_id: &str,
-    output_dir: &str,
-    footprint_lib: &str,
-    model_dir: &str,
-    models: &[String],
-) -> Result<(String, String, bool, Option<String>), JlcError> {
-    let data = client.get_footprint_data(footprint_uuid).await?;
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComponentIdList {
+    pub ids: Vec<String>,
+    pub unmatched_lines: Vec<String>,
+}
 
-    let title = &data.result.title;
-    let footprint_name = title
-        .replace(" ", "_")
-        .replace("/", "_")
-        .replace("(", "_")
-        .replace(")", "_");
+/// Split a pasted blob (e.g. a spreadsheet column) into component ids, keeping
+/// the original order and remembering the lines that yielded no id.
+pub fn parse_component_id_list(text: &str) -> ComponentIdList {
+    let mut list = ComponentIdList::default();
+    let mut seen = HashSet::new();
 
-    let shape = &data.result.data_str.shape;
-    let (origin_x, origin_y) = (data.result.data_str.head.x, data.result.data_str.head.y);
-    let datasheet_link = data
-        .result
-        .data_str
-        .head
-        .c_para
-        .and_then(|c| c.link)
-        .unwrap_or_default();
+    for raw_line in text.lines() {
+        let line = raw_line.trim();
+        if line.is_empty() {
+            continue;
+        }
 
-    let mut footprint_info = FootprintInfo {
-        footprint_name: footprint_name.clone(),
+        let mut line_ids = HashSet::new();
+        extract_component_ids_from_text(&line.to_uppercase(), &mut line_ids);
+        if line_ids.is_empty() {
+            list.unmatched_lines.push(line.to_string());
+            continue;
+        }
+
+        let mut line_ids: Vec<String> = line_ids.into_iter().collect();
+        line_ids.sort();
+        for id in line_ids {
+            if seen.insert(id.clone()) {
+                list.ids.push(id);
+            }
+        }
+    }
+
+    list
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SchematicParts {
+    pub ids: Vec<String>,
+    pub sheets: Vec<String>,
+    pub missing_sheets: Vec<String>,
+}
+
+/// Collect LCSC codes from the symbol properties of a `.kicad_sch`, following
+/// hierarchical sheets. Ids keep the order they first appear in.
+pub fn parse_kicad_schematic(path: &str) -> Result<SchematicParts, JlcError> {
+    let root = PathBuf::from(path);
+    let content = fs::read_to_string(&root)
+        .map_err(|e| JlcError::ApiError(format!("无法读取原理图 {}: {}", path, e)))?;
+
+    let mut parts = SchematicParts::default();
+    let mut seen_ids = HashSet::new();
+    let mut visited = HashSet::new();
+    visited.insert(root.canonicalize().unwrap_or_else(|_| root.clone()));
+    parts.sheets.push(root.to_string_lossy().to_string());
+    collect_schematic_parts(&root, &content, &mut parts, &mut seen_ids, &mut visited);
+    Ok(parts)
+}
+
+fn collect_schematic_parts(
+    sheet_path: &Path,
+    content: &str,
+    parts: &mut SchematicParts,
+    seen_ids: &mut HashSet<String>,
+    visited: &mut HashSet<PathBuf>,
+) {
+    static PROPERTY_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static PLACED_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let property_re = PROPERTY_RE.get_or_init(|| {
+        regex::Regex::new(r#"\(property\s+"([^"]*)"\s+"([^"]*)""#).unwrap()
+    });
+    // Placed parts and sheets only: the symbol definitions cached under
+    // `lib_symbols` carry the same properties but are not on the schematic.
+    let placed_re =
+        PLACED_RE.get_or_init(|| regex::Regex::new(r"\((?:symbol\s+\(lib_id|sheet)\s").unwrap());
+    let placed: Vec<&str> = placed_re
+        .find_iter(content)
+        .map(|m| &content[m.start()..sexpr_end(content, m.start())])
+        .collect();
+
+    let mut sub_sheets: Vec<PathBuf> = Vec::new();
+    for cap in placed.iter().flat_map(|block| property_re.captures_iter(block)) {
+        let name = cap[1].trim().to_lowercase();
+        let value = cap[2].trim();
+        match name.as_str() {
+            "lcsc" | "lcsc part" | "lcsc part #" | "lcsc_part" => {
+                let mut ids = HashSet::new();
+                extract_component_ids_from_text(&value.to_uppercase(), &mut ids);
+                let mut ids: Vec<String> = ids.into_iter().collect();
+                ids.sort();
+                for id in ids {
+                    if seen_ids.insert(id.clone()) {
+                        parts.ids.push(id);
+                    }
+                }
+            }
+            // "Sheetfile" since KiCad 7, "Sheet file" in KiCad 6.
+            "sheetfile" | "sheet file" if !value.is_empty() => {
+                let base = sheet_path.parent().unwrap_or_else(|| Path::new("."));
+                sub_sheets.push(base.join(value));
+            }
+            _ => {}
+        }
+    }
+
+    for sub in sub_sheets {
+        let key = sub.canonicalize().unwrap_or_else(|_| sub.clone());
+        if !visited.insert(key) {
+            continue;
+        }
+        match fs::read_to_string(&sub) {
+            Ok(sub_content) => {
+                parts.sheets.push(sub.to_string_lossy().to_string());
+                collect_schematic_parts(&sub, &sub_content, parts, seen_ids, visited);
+            }
+            Err(_) => parts.missing_sheets.push(sub.to_string_lossy().to_string()),
+        }
+    }
+}
+
+/// End (exclusive) of the s-expression opening at `start`, skipping
+/// parentheses inside quoted strings; the end of `content` when unbalanced.
+fn sexpr_end(content: &str, start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, b) in content.bytes().enumerate().skip(start) {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    content.len()
+}
+
+pub async fn load_local_folder(path: &str) -> Result<Vec<SearchResult>, JlcError> {
+    let source = Path::new(path);
+    let map = collect_local_component_map(source)?;
+    Ok(map.into_values().collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedComponent {
+    pub id: String,
+    pub reason: String,
+}
+
+/// Per-id outcome of a batch conversion, for the UI to break down.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConversionReport {
+    /// Every id discovered in the input, in conversion order.
+    pub found: Vec<String>,
+    pub converted: Vec<String>,
+    pub failed: Vec<FailedComponent>,
+    /// Id-like tokens that were not plausible ids and were never attempted.
+    pub skipped: Vec<String>,
+    /// Converted parts with a problem short of failure, e.g. a missing 3D model.
+    pub warnings: Vec<FailedComponent>,
+    /// Parts left alone because a resumed run had already converted them.
+    pub resumed: Vec<String>,
+    /// Coordinate tables of the written footprints, when `geometry_report`
+    /// is set.
+    pub geometry: Vec<FootprintGeometry>,
+    /// Symbol library files and footprint library folders written, when
+    /// `kicad_cli_check` is set; what `check_with_kicad_cli` loads.
+    pub written_libraries: Vec<PathBuf>,
+    /// Human-readable summary, as shown in the progress log.
+    pub message: String,
+}
+
+impl ConversionReport {
+    fn fail(&mut self, id: &str, reason: impl Into<String>) {
+        self.failed.push(FailedComponent {
+            id: id.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    fn warn(&mut self, id: &str, reason: impl Into<String>) {
+        self.warnings.push(FailedComponent {
+            id: id.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    fn summarize(&mut self, title: &str, extra: Option<String>) {
+        let mut message = if self.failed.is_empty() {
+            format!("{}，成功 {} 个元件", title, self.converted.len())
+        } else {
+            let reasons: Vec<String> = self
+                .failed
+                .iter()
+                .map(|f| format!("{}: {}", f.id, f.reason))
+                .collect();
+            format!(
+                "{}，成功 {} 个，失败 {} 个\n{}",
+                title,
+                self.converted.len(),
+                self.failed.len(),
+                reasons.join("\n")
+            )
+        };
+        if !self.resumed.is_empty() {
+            message.push_str(&format!("\n已跳过上次已完成的 {} 个元件", self.resumed.len()));
+        }
+        if let Some(extra) = extra.filter(|_| self.failed.is_empty()) {
+            message.push_str(&format!("\n{}", extra));
+        }
+        if !self.warnings.is_empty() {
+            let warnings: Vec<String> = self
+                .warnings
+                .iter()
+                .map(|w| format!("{}: {}", w.id, w.reason))
+                .collect();
+            message.push_str(&format!("\n警告:\n{}", warnings.join("\n")));
+        }
+        if !self.skipped.is_empty() {
+            message.push_str(&format!("\n已跳过无效编号: {}", self.skipped.join(", ")));
+        }
+        self.message = message;
+    }
+}
+
+const PROGRESS_STATE_FILE: &str = ".jlc2kicad_progress.json";
+
+/// Per-part outcome of earlier runs into one output directory, kept for
+/// `ConversionOptions::resume`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProgressState {
+    /// Part id to `"converted"` or `"failed"`.
+    parts: BTreeMap<String, String>,
+}
+
+impl ProgressState {
+    fn load(output_dir: &str) -> Self {
+        fs::read(Path::new(output_dir).join(PROGRESS_STATE_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn is_converted(&self, id: &str) -> bool {
+        self.parts.get(id).is_some_and(|status| status == "converted")
+    }
+
+    /// Merges the outcome of `report` into the state file. Progress is only
+    /// bookkeeping, so a failed write is logged rather than returned.
+    fn record(output_dir: &str, report: &ConversionReport) {
+        let mut state = Self::load(output_dir);
+        for id in &report.converted {
+            state.parts.insert(id.clone(), "converted".to_string());
+        }
+        for failed in &report.failed {
+            state.parts.insert(failed.id.clone(), "failed".to_string());
+        }
+        let path = Path::new(output_dir).join(PROGRESS_STATE_FILE);
+        let written = serde_json::to_vec_pretty(&state)
+            .map_err(std::io::Error::from)
+            .and_then(|data| write_file_atomic(&path, &data));
+        if let Err(e) = written {
+            log::warn!("Failed to save conversion progress {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Where and what to write when converting parts from a local source.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalConvertOptions {
+    pub output_dir: String,
+    pub footprint_lib: String,
+    pub symbol_lib: String,
+    pub symbol_path: String,
+    pub model_dir: String,
+    pub models: Vec<String>,
+    pub create_footprint: bool,
+    pub create_symbol: bool,
+    pub conversion: ConversionOptions,
+}
+
+pub async fn convert_local_folder(
+    path: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    symbol_lib: &str,
+    symbol_path: &str,
+    model_dir: &str,
+    models: Vec<String>,
+    create_footprint: bool,
+    create_symbol: bool,
+    options: &ConversionOptions,
+) -> Result<ConversionReport, JlcError> {
+    options.validate()?;
+    let output_dir = &expand_output_dir(output_dir, None, symbol_lib);
+    if !output_dir.contains("{id}") {
+        ensure_output_dir_writable(output_dir)?;
+    }
+    let source_path = Path::new(path);
+    let bundle_kind = detect_local_bundle_kind(source_path);
+    let local = collect_local_components(source_path)?;
+    let report = ConversionReport {
+        found: local.map.keys().cloned().collect(),
+        skipped: local.skipped,
+        ..Default::default()
+    };
+    let opts = LocalConvertOptions {
         output_dir: output_dir.to_string(),
         footprint_lib: footprint_lib.to_string(),
+        symbol_lib: symbol_lib.to_string(),
+        symbol_path: symbol_path.to_string(),
         model_dir: model_dir.to_string(),
-        origin: (origin_x, origin_y),
-        models: models.iter().map(|s| s.clone()).collect(),
-        ..Default::default()
+        models,
+        create_footprint,
+        create_symbol,
+        conversion: ConversionOptions {
+            written: options.kicad_cli_check.then(WrittenLibraries::default),
+            ..options.clone()
+        },
+    };
+
+    let mut report = match load_offline_bundle(source_path)? {
+        Some(bundle) => {
+            let mut model_index = index_local_models(source_path).unwrap_or_default();
+            add_model_search_dirs(&mut model_index, &options.model_search_dirs);
+            convert_bundle_parts(&bundle, &model_index, &bundle_kind, report, &opts).await
+        }
+        None => convert_online_ids(report, "本地转换完成", &opts).await,
+    };
+    if let Some(written) = &opts.conversion.written {
+        report.written_libraries = written.libraries();
+    }
+    Ok(report)
+}
+
+/// `kicad-cli` from PATH or a default KiCad install location.
+pub fn find_kicad_cli() -> Option<PathBuf> {
+    let exe = if cfg!(target_os = "windows") { "kicad-cli.exe" } else { "kicad-cli" };
+    let mut candidates: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(exe)).collect())
+        .unwrap_or_default();
+    if cfg!(target_os = "windows") {
+        for major in KICAD_MAJOR_VERSIONS {
+            candidates.push(PathBuf::from(format!(
+                "C:\\Program Files\\KiCad\\{}.0\\bin\\{}",
+                major, exe
+            )));
+        }
+    } else if cfg!(target_os = "macos") {
+        candidates.push(PathBuf::from("/Applications/KiCad/KiCad.app/Contents/MacOS/kicad-cli"));
+    }
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+/// What one kicad-cli run returned.
+pub struct CliOutput {
+    pub success: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// With `kicad_cli_check` set, has kicad-cli upgrade a scratch copy of every
+/// library in `report.written_libraries`, which fails for files KiCad cannot
+/// load. `run` starts kicad-cli (the app goes through its shell plugin) and
+/// `cli` is where it was found, see `find_kicad_cli`. Rejected libraries
+/// become warnings; a missing kicad-cli only skips this.
+pub async fn check_with_kicad_cli<F, Fut>(
+    options: &ConversionOptions,
+    report: &mut ConversionReport,
+    cli: Option<PathBuf>,
+    mut run: F,
+) where
+    F: FnMut(PathBuf, Vec<std::ffi::OsString>) -> Fut,
+    Fut: std::future::Future<Output = Result<CliOutput, String>>,
+{
+    if !options.kicad_cli_check {
+        return;
+    }
+    let Some(cli) = cli else {
+        log::info!("kicad-cli not found, skipping library check");
+        report.message.push_str("\n未找到 kicad-cli，已跳过 KiCad 加载校验");
+        return;
+    };
+    let libraries: Vec<(&str, PathBuf)> = report
+        .written_libraries
+        .iter()
+        // A rolled back part may have taken its library with it.
+        .filter(|path| path.exists())
+        .map(|path| {
+            let kind = if path.is_dir() { "fp" } else { "sym" };
+            (kind, path.clone())
+        })
+        .collect();
+
+    let scratch = std::env::temp_dir().join(format!("jlc2kicad_cli_check_{}", std::process::id()));
+    let mut rejected = Vec::new();
+    for (n, (kind, path)) in libraries.iter().enumerate() {
+        let out = match *kind {
+            "sym" => scratch.join(format!("{}.kicad_sym", n)),
+            _ => scratch.join(format!("{}.pretty", n)),
+        };
+        let args = vec![
+            (*kind).into(),
+            "upgrade".into(),
+            path.into(),
+            "--output".into(),
+            out.into(),
+        ];
+        match run(cli.clone(), args).await {
+            Ok(run) if run.success => {}
+            Ok(run) => {
+                let stderr = String::from_utf8_lossy(&run.stderr);
+                let stdout = String::from_utf8_lossy(&run.stdout);
+                let detail = stderr
+                    .lines()
+                    .chain(stdout.lines())
+                    .map(str::trim)
+                    .find(|l| !l.is_empty())
+                    .unwrap_or("无输出")
+                    .to_string();
+                rejected.push(format!("{}: {}", path.display(), detail));
+            }
+            Err(e) => {
+                rejected.push(format!("无法运行 {}: {}", cli.display(), e));
+                break;
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&scratch);
+
+    if rejected.is_empty() {
+        report.message.push_str(&format!(
+            "\nKiCad 加载校验通过（{} 个库）",
+            libraries.len()
+        ));
+        return;
+    }
+    report.message.push_str(&format!("\nKiCad 加载校验未通过:\n{}", rejected.join("\n")));
+    for reason in rejected {
+        report.warn("kicad-cli", reason);
+    }
+}
+
+/// Converts `report.found` online, as local sources without offline data do.
+async fn convert_online_ids(
+    mut report: ConversionReport,
+    title: &str,
+    opts: &LocalConvertOptions,
+) -> ConversionReport {
+    let resume = opts.conversion.resume;
+    for component_id in report.found.clone() {
+        let part_dir = expand_output_dir(&opts.output_dir, Some(&component_id), &opts.symbol_lib);
+        if resume && ProgressState::load(&part_dir).is_converted(&component_id) {
+            report.resumed.push(component_id);
+            continue;
+        }
+        let mut part = ConversionReport::default();
+        match create_component(
+            &component_id,
+            &opts.output_dir,
+            &opts.footprint_lib,
+            &opts.symbol_lib,
+            &opts.symbol_path,
+            &opts.model_dir,
+            opts.models.clone(),
+            opts.create_footprint,
+            opts.create_symbol,
+            &opts.conversion,
+        )
+        .await
+        {
+            Ok(result) => {
+                for warning in result.warnings {
+                    part.warn(&component_id, warning);
+                }
+                part.geometry.extend(result.geometry);
+                part.converted.push(component_id);
+            }
+            Err(e) => part.fail(&component_id, e.to_string()),
+        }
+        if resume {
+            ProgressState::record(&part_dir, &part);
+        }
+        report.converted.extend(part.converted);
+        report.failed.extend(part.failed);
+        report.warnings.extend(part.warnings);
+        report.geometry.extend(part.geometry);
+    }
+    report.summarize(title, None);
+    report
+}
+
+/// Converts `report.found` from a parsed bundle, one part at a time when the
+/// output directory still holds an `{id}` placeholder.
+async fn convert_bundle_parts(
+    bundle: &OfflineBundle,
+    model_index: &BTreeMap<String, PathBuf>,
+    bundle_kind: &str,
+    mut report: ConversionReport,
+    opts: &LocalConvertOptions,
+) -> ConversionReport {
+    if !opts.output_dir.contains("{id}") {
+        return convert_from_bundle(bundle, model_index, bundle_kind, report, opts).await;
+    }
+
+    for component_id in report.found.clone() {
+        let part_opts = LocalConvertOptions {
+            output_dir: expand_output_dir(&opts.output_dir, Some(&component_id), &opts.symbol_lib),
+            ..opts.clone()
+        };
+        if let Err(e) = ensure_output_dir_writable(&part_opts.output_dir) {
+            report.fail(&component_id, e.to_string());
+            continue;
+        }
+        let part = ConversionReport {
+            found: vec![component_id],
+            ..Default::default()
+        };
+        let part = convert_from_bundle(bundle, model_index, bundle_kind, part, &part_opts).await;
+        report.converted.extend(part.converted);
+        report.failed.extend(part.failed);
+        report.warnings.extend(part.warnings);
+        report.geometry.extend(part.geometry);
+        report.resumed.extend(part.resumed);
+    }
+    report.summarize(&format!("本地离线转换完成（{}）", bundle_kind), None);
+    report
+}
+
+/// Converts `report.found` from an already parsed elibz bundle.
+async fn convert_from_bundle(
+    bundle: &OfflineBundle,
+    model_index: &BTreeMap<String, PathBuf>,
+    bundle_kind: &str,
+    mut report: ConversionReport,
+    opts: &LocalConvertOptions,
+) -> ConversionReport {
+    let passive_opts;
+    let opts = if opts.conversion.passive_names {
+        passive_opts = LocalConvertOptions {
+            conversion: offline_passive_names(bundle, &report.found, &opts.conversion),
+            ..opts.clone()
+        };
+        &passive_opts
+    } else {
+        opts
+    };
+    let (output_dir, footprint_lib, model_dir) =
+        (opts.output_dir.as_str(), opts.footprint_lib.as_str(), opts.model_dir.as_str());
+    let (create_footprint, create_symbol) = (opts.create_footprint, opts.create_symbol);
+    let models = &opts.models;
+    let options = &opts.conversion;
+
+    // New elibz2 bundles may only include device2.json + .elibu, and some
+    // bundles carry footprints only. Whatever the bundle lacks entirely is
+    // fetched online per part; the rest still comes from the bundle.
+    let footprints_online = create_footprint && bundle.footprint_data.is_empty();
+    let symbols_online = create_symbol && bundle.symbol_data.is_empty();
+
+    let mut selected_devices: Vec<OfflineDevice> = Vec::new();
+    // Parts whose footprint has to come from EasyEDA; their symbols stay offline.
+    let mut online_footprints: Vec<String> = Vec::new();
+    let mut pad_numbers: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    // Per part, so a failed part's footprint files can be undone.
+    let mut journals: BTreeMap<String, WriteJournal> = BTreeMap::new();
+    let progress = if options.resume {
+        ProgressState::load(output_dir)
+    } else {
+        ProgressState::default()
+    };
+
+    for component_id in report.found.clone() {
+        let Some(device) = bundle.devices.get(&component_id).cloned() else {
+            report.fail(&component_id, "本地库缺少 device 元数据");
+            continue;
+        };
+        if progress.is_converted(&component_id) {
+            // The symbol library is rewritten as a whole, so it still needs
+            // the part; its footprint and 3D model are already on disk.
+            selected_devices.push(device);
+            report.resumed.push(component_id);
+            continue;
+        }
+        let footprint_uuid = offline_footprint_uuid(bundle, &device, &options.footprint_variant);
+        let model_name = footprint_uuid
+            .as_ref()
+            .and_then(|u| get_footprint_title_by_uuid(bundle, u))
+            .unwrap_or_else(|| device.name.clone());
+        selected_devices.push(device.clone());
+        let journal = WriteJournal::default();
+        let part_options = ConversionOptions {
+            part_manufacturer: offline_part_manufacturer(bundle, &device),
+            journal: (options.rollback_on_failure || options.strict_3d).then(|| journal.clone()),
+            ..options.clone()
+        };
+        let part_footprint_lib = &part_options.footprint_library(footprint_lib, &model_name, None);
+        journals.insert(component_id.clone(), journal.clone());
+
+        if create_footprint && options.all_footprint_variants && !footprints_online {
+            create_offline_footprint_variants(
+                bundle,
+                &device,
+                footprint_uuid.as_deref(),
+                model_index,
+                opts,
+                &part_options,
+                &mut report,
+            );
+        }
+
+        if create_footprint {
+            if let Some(fp_uuid) = &footprint_uuid {
+                if let Some(ds) = bundle.footprint_data.get(fp_uuid) {
+                    match create_footprint_from_offline(
+                        &device,
+                        get_footprint_title_by_uuid(bundle, fp_uuid).as_deref(),
+                        ds,
+                        output_dir,
+                        footprint_lib,
+                        model_dir,
+                        models,
+                        model_index,
+                        &part_options,
+                    ) {
+                        Ok(footprint) => {
+                            pad_numbers.insert(component_id.clone(), footprint.pad_numbers);
+                            for warning in footprint.warnings {
+                                report.warn(&component_id, warning);
+                            }
+                            report.geometry.extend(footprint.geometry);
+                            // Local libraries usually do not include 3D models.
+                            // If STEP is requested, fetch it online directly.
+                            if models.contains(&"STEP".to_string()) && !options.model_reference_only {
+                                match download_models_only_online(
+                                    &component_id,
+                                    &model_name,
+                                    output_dir,
+                                    part_footprint_lib,
+                                    model_dir,
+                                    &part_options,
+                                )
+                                .await
+                                {
+                                    Ok(_) => report.converted.push(component_id),
+                                    Err(e) if options.strict_3d => {
+                                        // The footprint went out before its
+                                        // model failed; take it back.
+                                        journal.rollback();
+                                        report.fail(&component_id, format!("在线拉取3D失败: {}", e));
+                                    }
+                                    Err(e) => {
+                                        report.warn(&component_id, format!("在线拉取3D失败: {}", e));
+                                        report.converted.push(component_id);
+                                    }
+                                }
+                            } else {
+                                report.converted.push(component_id);
+                            }
+                        }
+                        Err(e) => report.fail(&component_id, e.to_string()),
+                    }
+                } else if options.online_fallback || footprints_online {
+                    online_footprints.push(component_id);
+                } else {
+                    report.fail(&component_id, format!("本地库缺少封装数据 {}", fp_uuid));
+                }
+            } else if options.online_fallback || footprints_online {
+                online_footprints.push(component_id);
+            } else {
+                report.fail(&component_id, "本地库未提供封装UUID");
+            }
+        } else if models.contains(&"STEP".to_string())
+            && !create_symbol
+            && !options.model_reference_only
+        {
+            // 3D-only mode: always fetch online (do not search local files).
+            match download_models_only_online(
+                &component_id,
+                &model_name,
+                output_dir,
+                part_footprint_lib,
+                model_dir,
+                &part_options,
+            )
+            .await
+            {
+                Ok(_) => report.converted.push(component_id),
+                Err(e) if options.strict_3d => {
+                    report.fail(&component_id, format!("在线拉取3D失败: {}", e))
+                }
+                Err(e) => {
+                    report.warn(&component_id, format!("在线拉取3D失败: {}", e));
+                    report.converted.push(component_id);
+                }
+            }
+        } else {
+            report.converted.push(component_id);
+        }
+        // Saved after every part, as for online ids, so an interrupted run
+        // resumes after the last part it finished.
+        if options.resume {
+            ProgressState::record(output_dir, &report);
+        }
+    }
+
+    for component_id in online_footprints {
+        match create_component(
+            &component_id,
+            output_dir,
+            footprint_lib,
+            &opts.symbol_lib,
+            &opts.symbol_path,
+            model_dir,
+            models.clone(),
+            true,
+            false,
+            options,
+        )
+        .await
+        {
+            Ok(result) => {
+                report.warn(&component_id, "本地库缺少封装数据，已在线转换封装");
+                for warning in result.warnings {
+                    report.warn(&component_id, warning);
+                }
+                report.geometry.extend(result.geometry);
+                report.converted.push(component_id);
+            }
+            Err(e) => report.fail(&component_id, format!("本地库缺少封装数据，在线转换失败: {}", e)),
+        }
+        if options.resume {
+            ProgressState::record(output_dir, &report);
+        }
+    }
+    // A part whose footprint failed gets no symbol pointing at it either.
+    selected_devices.retain(|device| !report.failed.iter().any(|f| f.id == device.id));
+
+    // Strict mode fails parts whose symbols use element kinds we can't convert.
+    if create_symbol && options.strict_parse {
+        let mut kept = Vec::new();
+        for device in selected_devices {
+            let shape: Vec<String> = device
+                .symbol_uuids
+                .iter()
+                .filter_map(|uuid| get_symbol_data_by_uuid(bundle, uuid))
+                .filter_map(|ds| parse_local_data_str(ds))
+                .flat_map(|(shape, _, _)| shape)
+                .collect();
+            match options.check_shape_kinds(&shape, &SYMBOL_SHAPE_KINDS) {
+                Ok(()) => kept.push(device),
+                Err(e) => {
+                    report.converted.retain(|id| *id != device.id);
+                    report.fail(&device.id, format!("符号导出失败: {}", e));
+                }
+            }
+        }
+        selected_devices = kept;
+    }
+
+    if create_symbol {
+        for device in &selected_devices {
+            if let Some(warning) = options.missing_datasheet(&device.id) {
+                report.warn(&device.id, warning);
+            }
+        }
+        // Parts without any symbol data in the bundle get theirs from EasyEDA.
+        let mut online_symbols: BTreeMap<String, String> = BTreeMap::new();
+        if symbols_online || options.online_fallback {
+            for device in &selected_devices {
+                let offline = device
+                    .symbol_uuids
+                    .iter()
+                    .any(|uuid| get_symbol_data_by_uuid(bundle, uuid).is_some());
+                if offline {
+                    continue;
+                }
+                // Named as the footprint this run wrote for the part.
+                let part_options = ConversionOptions {
+                    part_manufacturer: offline_part_manufacturer(bundle, device),
+                    ..options.clone()
+                };
+                let footprint =
+                    offline_footprint_reference(bundle, device, footprint_lib, &part_options);
+                match online_symbol_blocks(&device.id, &footprint, options).await {
+                    Ok(symbols) => {
+                        report.warn(&device.id, "本地库缺少符号数据，已在线转换符号");
+                        online_symbols.insert(device.id.clone(), symbols);
+                    }
+                    Err(e) => {
+                        if let Some(pos) = report.converted.iter().position(|id| *id == device.id) {
+                            report.converted.remove(pos);
+                            report.fail(
+                                &device.id,
+                                format!("本地库缺少符号数据，在线转换失败: {}", e),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        let symbol_error = match create_symbols_from_offline(
+            &selected_devices,
+            &online_symbols,
+            bundle,
+            opts,
+        ) {
+            Ok(pins) if pins.is_empty() => {
+                Some("符号导出失败: 本地库未解析到可用符号数据".to_string())
+            }
+            Ok(pins) => {
+                for (id, pins) in &pins {
+                    let mismatch = pad_numbers
+                        .get(id)
+                        .and_then(|pads| pin_pad_mismatch(pins, pads));
+                    if let Some(m) = mismatch {
+                        report.warn(id, m);
+                    }
+                }
+                None
+            }
+            Err(e) => Some(format!("符号导出失败: {}", e)),
+        };
+        // The library is written in one go, so every part loses its symbol.
+        if let Some(reason) = symbol_error {
+            for device in &selected_devices {
+                if let Some(pos) = report.converted.iter().position(|id| *id == device.id) {
+                    report.converted.remove(pos);
+                    report.fail(&device.id, reason.clone());
+                }
+            }
+        }
+    }
+
+    if options.rollback_on_failure {
+        for failed in &report.failed {
+            if let Some(journal) = journals.get(&failed.id) {
+                journal.rollback();
+            }
+        }
+    }
+    if options.resume {
+        ProgressState::record(output_dir, &report);
+    }
+
+    let symbol_file = create_symbol.then(|| {
+        let dir = PathBuf::from(output_dir).join(&opts.symbol_path);
+        if options.symbol_per_part {
+            format!("器件库目录: {}", dir.display())
+        } else {
+            let file = dir.join(symbol_lib_file_name(&opts.symbol_lib, options));
+            format!("器件库文件: {}", file.display())
+        }
+    });
+    report.summarize(&format!("本地离线转换完成（{}）", bundle_kind), symbol_file);
+    report
+}
+
+/// Writes every footprint variant of `device` other than `primary`, suffixed
+/// `_V<n>` like the online variants, with `options` in place of the
+/// conversion options of `opts`. A variant that fails is skipped and, like
+/// any variant warning, noted in `report`.
+fn create_offline_footprint_variants(
+    bundle: &OfflineBundle,
+    device: &OfflineDevice,
+    primary: Option<&str>,
+    model_index: &BTreeMap<String, PathBuf>,
+    opts: &LocalConvertOptions,
+    options: &ConversionOptions,
+    report: &mut ConversionReport,
+) {
+    if device.footprint_uuids.len() < 2 {
+        return;
+    }
+    for (idx, uuid) in device.footprint_uuids.iter().enumerate() {
+        if Some(uuid.as_str()) == primary {
+            continue;
+        }
+        let Some(ds) = bundle.footprint_data.get(uuid) else {
+            report.warn(
+                &device.id,
+                format!("封装变体 V{} 转换失败: 本地库缺少封装变体数据 {}", idx + 1, uuid),
+            );
+            continue;
+        };
+        let variant_options = ConversionOptions {
+            footprint_suffix: format!("_V{}", idx + 1),
+            ..options.clone()
+        };
+        let result = create_footprint_from_offline(
+            device,
+            get_footprint_title_by_uuid(bundle, uuid).as_deref(),
+            ds,
+            &opts.output_dir,
+            &opts.footprint_lib,
+            &opts.model_dir,
+            &opts.models,
+            model_index,
+            &variant_options,
+        );
+        match result {
+            Ok(footprint) => {
+                for warning in footprint.warnings {
+                    report.warn(&device.id, format!("封装变体 V{}: {}", idx + 1, warning));
+                }
+                report.geometry.extend(footprint.geometry);
+            }
+            Err(e) => report.warn(&device.id, format!("封装变体 V{} 转换失败: {}", idx + 1, e)),
+        }
+    }
+}
+
+/// A local source parsed once by `open_local_bundle`, reused by later
+/// conversions until its files change.
+struct LoadedBundle {
+    path: PathBuf,
+    stamp: Vec<(PathBuf, u64, Option<std::time::SystemTime>)>,
+    kind: String,
+    bundle: Option<std::sync::Arc<OfflineBundle>>,
+    model_index: std::sync::Arc<BTreeMap<String, PathBuf>>,
+    components: BTreeMap<String, SearchResult>,
+    skipped: Vec<String>,
+}
+
+static LOADED_BUNDLES: OnceLock<Mutex<BTreeMap<u64, LoadedBundle>>> = OnceLock::new();
+static NEXT_BUNDLE_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+fn loaded_bundles() -> &'static Mutex<BTreeMap<u64, LoadedBundle>> {
+    LOADED_BUNDLES.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Size and modification time of every input file, to notice edits.
+fn source_stamp(path: &Path) -> Result<Vec<(PathBuf, u64, Option<std::time::SystemTime>)>, JlcError> {
+    let mut files = gather_input_files(path)?;
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|f| {
+            let meta = fs::metadata(&f).ok();
+            let len = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+            let modified = meta.and_then(|m| m.modified().ok());
+            (f, len, modified)
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleHandle {
+    pub handle: u64,
+    pub kind: String,
+    pub components: Vec<SearchResult>,
+}
+
+/// Parses a local file or folder once and keeps it in memory; the returned
+/// handle is passed to `convert_local_bundle` instead of re-reading the files.
+pub fn open_local_bundle(path: &str) -> Result<BundleHandle, JlcError> {
+    let source = Path::new(path);
+    let stamp = source_stamp(source)?;
+    let local = collect_local_components(source)?;
+    let loaded = LoadedBundle {
+        path: source.to_path_buf(),
+        stamp,
+        kind: detect_local_bundle_kind(source),
+        bundle: load_offline_bundle(source)?.map(std::sync::Arc::new),
+        model_index: std::sync::Arc::new(index_local_models(source).unwrap_or_default()),
+        components: local.map,
+        skipped: local.skipped,
+    };
+
+    let handle = NEXT_BUNDLE_HANDLE.fetch_add(1, Ordering::SeqCst);
+    let result = BundleHandle {
+        handle,
+        kind: loaded.kind.clone(),
+        components: loaded.components.values().cloned().collect(),
+    };
+    loaded_bundles()
+        .lock()
+        .map_err(|_| JlcError::ApiError("本地库缓存不可用".to_string()))?
+        .insert(handle, loaded);
+    Ok(result)
+}
+
+pub fn close_local_bundle(handle: u64) {
+    if let Ok(mut bundles) = loaded_bundles().lock() {
+        bundles.remove(&handle);
+    }
+}
+
+/// Converts `component_ids` (all parts when empty) from a handle returned by
+/// `open_local_bundle`. A handle whose files changed on disk is dropped and
+/// must be opened again.
+pub async fn convert_local_bundle(
+    handle: u64,
+    component_ids: &[String],
+    opts: &LocalConvertOptions,
+) -> Result<ConversionReport, JlcError> {
+    opts.conversion.validate()?;
+    let opts = &LocalConvertOptions {
+        output_dir: expand_output_dir(&opts.output_dir, None, &opts.symbol_lib),
+        conversion: ConversionOptions {
+            written: opts.conversion.kicad_cli_check.then(WrittenLibraries::default),
+            ..opts.conversion.clone()
+        },
+        ..opts.clone()
+    };
+    if !opts.output_dir.contains("{id}") {
+        ensure_output_dir_writable(&opts.output_dir)?;
+    }
+
+    let (bundle, model_index, kind, mut report) = {
+        let mut bundles = loaded_bundles()
+            .lock()
+            .map_err(|_| JlcError::ApiError("本地库缓存不可用".to_string()))?;
+        let loaded = bundles
+            .get(&handle)
+            .ok_or_else(|| JlcError::ApiError("本地库未加载或已关闭，请重新加载".to_string()))?;
+        if source_stamp(&loaded.path).ok().as_ref() != Some(&loaded.stamp) {
+            bundles.remove(&handle);
+            return Err(JlcError::ApiError("本地库文件已更改，请重新加载".to_string()));
+        }
+
+        let mut report = ConversionReport {
+            skipped: loaded.skipped.clone(),
+            ..Default::default()
+        };
+        if component_ids.is_empty() {
+            report.found = loaded.components.keys().cloned().collect();
+        } else {
+            for id in component_ids {
+                if loaded.components.contains_key(id) {
+                    report.found.push(id.clone());
+                } else {
+                    report.fail(id, "不在已加载的本地库中");
+                }
+            }
+        }
+        (
+            loaded.bundle.clone(),
+            loaded.model_index.clone(),
+            loaded.kind.clone(),
+            report,
+        )
+    };
+
+    let model_index = if opts.conversion.model_search_dirs.is_empty() {
+        model_index
+    } else {
+        let mut index = (*model_index).clone();
+        add_model_search_dirs(&mut index, &opts.conversion.model_search_dirs);
+        std::sync::Arc::new(index)
+    };
+
+    report = match bundle {
+        Some(bundle) => convert_bundle_parts(&bundle, &model_index, &kind, report, opts).await,
+        None => convert_online_ids(report, "本地转换完成", opts).await,
+    };
+    if let Some(written) = &opts.conversion.written {
+        report.written_libraries = written.libraries();
+    }
+    Ok(report)
+}
+
+/// How one part of a local source would be converted.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedComponent {
+    pub id: String,
+    pub name: String,
+    /// "offline" when every requested output comes from local data, else "online".
+    pub source: String,
+    pub offline_footprint: bool,
+    pub offline_symbol: bool,
+    pub local_model: bool,
+}
+
+/// Summary of a local source before converting it, see `plan_conversion`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConversionPlan {
+    pub kind: String,
+    pub total: usize,
+    pub offline: usize,
+    pub needs_network: usize,
+    pub offline_footprints: usize,
+    pub offline_symbols: usize,
+    pub local_models: usize,
+    pub components: Vec<PlannedComponent>,
+    pub skipped: Vec<String>,
+    pub message: String,
+}
+
+/// Inspects a local file or folder and reports which parts can be converted
+/// offline and which need the network, without writing anything.
+pub fn plan_conversion(path: &str, opts: &LocalConvertOptions) -> Result<ConversionPlan, JlcError> {
+    let source = Path::new(path);
+    let local = collect_local_components(source)?;
+    let bundle = load_offline_bundle(source)?.unwrap_or_default();
+    let mut model_index = index_local_models(source).unwrap_or_default();
+    add_model_search_dirs(&mut model_index, &opts.conversion.model_search_dirs);
+    let wants_step = opts.models.contains(&"STEP".to_string());
+    // As in convert_from_bundle, a kind the bundle lacks entirely is fetched
+    // online per part while the other kind still comes from the bundle.
+    let footprints_online = opts.create_footprint && bundle.footprint_data.is_empty();
+    let symbols_online = opts.create_symbol && bundle.symbol_data.is_empty();
+
+    let mut plan = ConversionPlan {
+        kind: detect_local_bundle_kind(source),
+        skipped: local.skipped,
+        ..Default::default()
+    };
+    for (id, part) in local.map {
+        let device = bundle.devices.get(&id);
+        let footprint_uuid = device
+            .and_then(|d| offline_footprint_uuid(&bundle, d, &opts.conversion.footprint_variant));
+        let footprint_title = footprint_uuid
+            .as_ref()
+            .and_then(|u| get_footprint_title_by_uuid(&bundle, u));
+        let offline_footprint = !footprints_online
+            && footprint_uuid.is_some_and(|u| bundle.footprint_data.contains_key(&u));
+        let offline_symbol = !symbols_online
+            && device.is_some_and(|d| {
+                d.symbol_uuids.iter().any(|u| get_symbol_data_by_uuid(&bundle, u).is_some())
+            });
+        let local_model = [Some(&id), footprint_title.as_ref(), device.and_then(|d| d.model_title.as_ref())]
+            .into_iter()
+            .flatten()
+            .any(|key| model_index.contains_key(&key.to_lowercase()));
+
+        // Offline conversions still fetch STEP models online.
+        let offline = device.is_some()
+            && (!opts.create_footprint || offline_footprint)
+            && (!opts.create_symbol || offline_symbol)
+            && (!wants_step || opts.conversion.model_reference_only);
+
+        plan.offline += offline as usize;
+        plan.offline_footprints += offline_footprint as usize;
+        plan.offline_symbols += offline_symbol as usize;
+        plan.local_models += local_model as usize;
+        plan.components.push(PlannedComponent {
+            id,
+            name: part.name,
+            source: if offline { "offline" } else { "online" }.to_string(),
+            offline_footprint,
+            offline_symbol,
+            local_model,
+        });
+    }
+    plan.total = plan.components.len();
+    plan.needs_network = plan.total - plan.offline;
+    plan.message = format!(
+        "共 {} 个元件：{} 个可完全离线转换，{} 个需要联网（离线封装 {} 个，离线符号 {} 个，本地3D模型 {} 个）",
+        plan.total,
+        plan.offline,
+        plan.needs_network,
+        plan.offline_footprints,
+        plan.offline_symbols,
+        plan.local_models
+    );
+    Ok(plan)
+}
+
+/// Expected network use of one part, see `estimate_download`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PartDownloadEstimate {
+    pub id: String,
+    pub found: bool,
+    /// Requests converting the part would make; cached metadata is not counted.
+    pub requests: usize,
+    pub has_model: bool,
+    /// STEP size from Content-Length or the model cache, when known.
+    pub model_bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Summary of an online batch before converting it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DownloadEstimate {
+    pub parts: Vec<PartDownloadEstimate>,
+    pub total_requests: usize,
+    /// Sum of the known model sizes.
+    pub model_bytes: u64,
+    /// Models whose server did not report a size; not part of `model_bytes`.
+    pub unknown_model_sizes: usize,
+    pub message: String,
+}
+
+/// Estimates what converting `ids` online would download. Only metadata is
+/// fetched (and cached for the conversion); 3D models are sized with HEAD
+/// requests or taken from the model cache. `on_progress` gets (done, total).
+pub async fn estimate_download<F>(
+    ids: &[String],
+    models: &[String],
+    create_footprint: bool,
+    create_symbol: bool,
+    options: &ConversionOptions,
+    mut on_progress: F,
+) -> Result<DownloadEstimate, JlcError>
+where
+    F: FnMut(usize, usize) + Send,
+{
+    let client = JlcClient::shared();
+    let wants_step = models.contains(&"STEP".to_string()) && !options.model_reference_only;
+    let mut estimate = DownloadEstimate::default();
+    for (done, id) in ids.iter().enumerate() {
+        let id = id.trim().to_uppercase();
+        let mut part = PartDownloadEstimate {
+            id: id.clone(),
+            ..Default::default()
+        };
+        let wanted = (create_footprint, create_symbol, wants_step);
+        if let Err(e) = estimate_part(&client, &mut part, wanted, options).await {
+            part.error = Some(e.to_string());
+        }
+        estimate.total_requests += part.requests;
+        match part.model_bytes {
+            Some(bytes) => estimate.model_bytes += bytes,
+            None if part.has_model => estimate.unknown_model_sizes += 1,
+            None => {}
+        }
+        estimate.parts.push(part);
+        on_progress(done + 1, ids.len());
+    }
+
+    let found = estimate.parts.iter().filter(|p| p.found).count();
+    estimate.message = format!(
+        "共 {} 个元件（找到 {} 个），预计 {} 次请求，3D 模型约 {:.1} MB",
+        estimate.parts.len(),
+        found,
+        estimate.total_requests,
+        estimate.model_bytes as f64 / (1024.0 * 1024.0)
+    );
+    if estimate.unknown_model_sizes > 0 {
+        estimate.message.push_str(&format!(
+            "，另有 {} 个模型大小未知",
+            estimate.unknown_model_sizes
+        ));
+    }
+    Ok(estimate)
+}
+
+/// Fills in one part of `estimate_download`; `wanted` is (footprint, symbol,
+/// STEP model).
+async fn estimate_part(
+    client: &JlcClient,
+    part: &mut PartDownloadEstimate,
+    wanted: (bool, bool, bool),
+    options: &ConversionOptions,
+) -> Result<(), JlcError> {
+    let (create_footprint, create_symbol, wants_step) = wanted;
+    let uncached = |path: &str| cached_metadata(path).is_none() as usize;
+
+    part.requests += uncached(&format!("/api/products/{}/svgs", part.id));
+    let component_data = client.get_component_data(&part.id).await?;
+    if component_data.result.is_empty() {
+        return Ok(());
+    }
+    part.found = true;
+    let footprint_uuid =
+        select_footprint_uuid(client, &component_data, &options.footprint_variant).await?;
+    if create_symbol {
+        for uuid in component_data.symbol_uuids() {
+            part.requests += uncached(&format!("/api/components/{}", uuid));
+        }
+    }
+    if !(create_footprint || create_symbol || wants_step) {
+        return Ok(());
+    }
+    part.requests += uncached(&format!("/api/components/{}", footprint_uuid));
+    if !wants_step {
+        return Ok(());
+    }
+
+    // Same candidates as fetch_step_model: the Pro lookup, then the uuid in
+    // the footprint shape, then the footprint itself. An uncached Pro lookup
+    // is only counted; its answer is left to the conversion.
+    let fp_data = client.get_footprint_data(&footprint_uuid).await?;
+    let mut candidates = Vec::new();
+    match cached_pro_step_uuid(&part.id) {
+        Some(uuid) => candidates.extend(uuid),
+        None => part.requests += PRO_LOOKUP_REQUESTS,
+    }
+    candidates.extend(extract_model_uuid_from_shape(&fp_data.result.data_str.shape));
+    candidates.push(footprint_uuid);
+    candidates.dedup();
+    for uuid in candidates {
+        let cached_size = model_cache_path(&uuid)
+            .and_then(|path| fs::metadata(path).ok())
+            .map(|m| m.len())
+            .filter(|len| *len > 0);
+        if cached_size.is_some() {
+            part.has_model = true;
+            part.model_bytes = cached_size;
+            return Ok(());
+        }
+        part.requests += 1;
+        // An error means no model there (e.g. 404); the next candidate may have one.
+        if let Ok(size) = client.step_model_size(&uuid).await {
+            part.has_model = true;
+            part.model_bytes = size;
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Whether a HEAD answer of `status` only means the server takes no HEAD
+/// requests (405, 501), which says nothing about the file itself; a 404
+/// does.
+fn head_refused(status: reqwest::StatusCode) -> bool {
+    matches!(
+        status,
+        reqwest::StatusCode::METHOD_NOT_ALLOWED | reqwest::StatusCode::NOT_IMPLEMENTED
+    )
+}
+
+/// Requests of the Pro API model lookup, see `resolve_step_uuid_via_pro_api`.
+const PRO_LOOKUP_REQUESTS: usize = 3;
+
+/// The Pro API answer for `component_id` when it needs no request: codes that
+/// are not LCSC codes have none, others only once looked up.
+fn cached_pro_step_uuid(component_id: &str) -> Option<Option<String>> {
+    let code = component_id.trim().to_uppercase();
+    if !code.starts_with('C') {
+        return Some(None);
+    }
+    response_cache()
+        .lock()
+        .ok()
+        .and_then(|c| c.step_uuids.get(&code).cloned())
+}
+
+/// Online footprint generated in memory, still missing its 3D model line.
+struct FootprintDraft {
+    name: String,
+    /// Library the footprint goes to, see `ConversionOptions::footprint_library`.
+    library: String,
+    datasheet_link: String,
+    info: FootprintInfo,
+    body: String,
+    model_candidates: Vec<String>,
+}
+
+struct FootprintOutput {
+    name: String,
+    library: String,
+    datasheet_link: String,
+    path: PathBuf,
+    /// First separate 3D file; `None` when skipped, failed or embedded.
+    model_path: Option<PathBuf>,
+    model_downloaded: bool,
+    model_error: Option<String>,
+    pad_numbers: BTreeSet<String>,
+    warnings: Vec<String>,
+    geometry: Option<FootprintGeometry>,
+}
+
+async fn create_footprint_internal(
+    client: &JlcClient,
+    footprint_uuid: &str,
+    component_id: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    model_dir: &str,
+    models: &[String],
+    options: &ConversionOptions,
+) -> Result<FootprintOutput, JlcError> {
+    let draft = build_footprint_draft(
+        client,
+        footprint_uuid,
+        output_dir,
+        footprint_lib,
+        model_dir,
+        models,
+        options,
+    )
+    .await?;
+
+    let mut step_model_downloaded = false;
+    let mut step_model_error: Option<String> = None;
+    let mut model_files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut model_path: Option<PathBuf> = None;
+
+    let shared_dir = options.shared_model_dir();
+    // The SVGNODE model uuid when there is one, else the footprint's own.
+    let model_key = draft.model_candidates.first().cloned().unwrap_or_default();
+    let shared_files = shared_dir
+        .as_ref()
+        .and_then(|dir| SharedModelManifest::files(dir, &model_key));
+    if models.contains(&"STEP".to_string()) && options.model_reference_only {
+        model_files = options.referenced_models(&draft.name);
+    } else if let (Some(dir), Some(files)) = (&shared_dir, shared_files) {
+        model_path = files.first().map(|f| dir.join(f));
+        model_files = files.into_iter().map(|f| (f, Vec::new())).collect();
+        step_model_downloaded = true;
+    } else if models.contains(&"STEP".to_string()) {
+        match fetch_3d_models(client, component_id, draft.model_candidates.clone(), options).await {
+            Ok(fetched) => {
+                for (ext, content) in fetched {
+                    let file_name = format!("{}.{}", draft.name, ext);
+                    if !options.embeds_3d_model() {
+                        let step_dir = shared_dir.clone().unwrap_or_else(|| {
+                            PathBuf::from(output_dir).join(&draft.library).join(model_dir)
+                        });
+                        fs::create_dir_all(&step_dir)?;
+                        let step_path = step_dir.join(&file_name);
+                        write_output_file(&step_path, &content, options)?;
+                        model_path.get_or_insert(step_path);
+                    }
+                    model_files.push((file_name, content));
+                }
+                if let Some(dir) = &shared_dir {
+                    let names = model_files.iter().map(|(name, _)| name.clone()).collect();
+                    SharedModelManifest::record(dir, &model_key, names);
+                }
+                step_model_downloaded = true;
+            }
+            Err(e) => step_model_error = Some(e),
+        }
+    }
+    // Checked before anything is written, so a strict failure leaves no
+    // footprint without its model behind.
+    if options.strict_3d {
+        if let Some(e) = &step_model_error {
+            return Err(JlcError::ApiError(format!("3D 模型下载失败: {}", e)));
+        }
+    }
+
+    let kicad_mod_content = finish_footprint(&draft, &model_files, options);
+
+    // Write to file
+    let output_path = PathBuf::from(output_dir).join(&draft.library);
+    fs::create_dir_all(&output_path)?;
+    let file_path = output_path.join(format!("{}.kicad_mod", draft.name));
+    write_output_file(&file_path, kicad_mod_content.as_bytes(), options)?;
+
+    Ok(FootprintOutput {
+        geometry: footprint_geometry(&draft.info, component_id, &draft.name),
+        name: draft.name,
+        library: draft.library,
+        datasheet_link: draft.datasheet_link,
+        path: file_path,
+        model_path,
+        model_downloaded: step_model_downloaded,
+        model_error: step_model_error,
+        pad_numbers: footprint_pad_numbers(&kicad_mod_content),
+        warnings: draft.info.warnings,
+    })
+}
+
+async fn build_footprint_draft(
+    client: &JlcClient,
+    footprint_uuid: &str,
+    output_dir: &str,
+    footprint_lib: &str,
+    model_dir: &str,
+    models: &[String],
+    options: &ConversionOptions,
+) -> Result<FootprintDraft, JlcError> {
+    let data = client.get_footprint_data(footprint_uuid).await?;
+
+    let manufacturer = data
+        .result
+        .data_str
+        .head
+        .c_para
+        .as_ref()
+        .and_then(|c| c.manufacturer.clone());
+    let footprint_name = options.footprint_name(&data.result.title, manufacturer.as_deref());
+    let footprint_lib =
+        &options.footprint_library(footprint_lib, &data.result.title, manufacturer.as_deref());
+
+    let shape = &data.result.data_str.shape;
+    options.check_shape_kinds(shape, &FOOTPRINT_SHAPE_KINDS)?;
+    let (origin_x, origin_y) = (data.result.data_str.head.x, data.result.data_str.head.y);
+    let metric = is_metric_unit(data.result.data_str.head.unit.as_deref());
+    let datasheet_link = data
+        .result
+        .data_str
+        .head
+        .c_para
+        .and_then(|c| c.link)
+        .unwrap_or_default();
+
+    let mut footprint_info = FootprintInfo {
+        footprint_name: footprint_name.clone(),
+        output_dir: output_dir.to_string(),
+        footprint_lib: footprint_lib.to_string(),
+        model_dir: model_dir.to_string(),
+        model_base_variable: options.model_base_variable.trim().to_string(),
+        min_silk_width: options.min_silk_width.max(0.0),
+        simplify_tolerance: options.simplify_tolerance(),
+        shared_model_dir: options.shared_model_dir.trim().to_string(),
+        origin: (origin_x, origin_y),
+        models: models.iter().map(|s| s.clone()).collect(),
+        metric,
+        geometry: options.geometry_report.then(Vec::new),
+        ..Default::default()
+    };
+    let mut svg_model_uuid: Option<String> = None;
+
+    // Generate KiCad footprint header
+    let mut kicad_mod_content = footprint_header(&footprint_name, options);
+
+    // Parse shape and generate footprint elements
+    for line in shape {
+        let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
+        if parts.is_empty() {
+            continue;
+        }
+
+        let model = parts[0];
+        let args: Vec<&str> = parts[1..].to_vec();
+        // Pads and regions keep empty fields so the net stays in place.
+        let fields: Vec<&str> = line.split('~').skip(1).collect();
+        let emitted_from = kicad_mod_content.len();
+
+        match model {
+            "PAD" => {
+                if let Some(pad_str) = parse_pad(&fields, &mut footprint_info) {
+                    kicad_mod_content.push_str(&pad_str);
+                }
+            }
+            "TRACK" => {
+                if let Some(track_str) = parse_track(&args, &mut footprint_info) {
+                    kicad_mod_content.push_str(&track_str);
+                }
+            }
+            "CIRCLE" => {
+                if let Some(circle_str) = parse_circle(&args, &mut footprint_info) {
+                    kicad_mod_content.push_str(&circle_str);
+                }
+            }
+            "ARC" => {
+                if let Some(arc_str) = parse_arc(&args, &mut footprint_info) {
+                    kicad_mod_content.push_str(&arc_str);
+                }
+            }
+            "RECT" => {
+                if let Some(rect_str) = parse_rect(&args, &mut footprint_info) {
+                    kicad_mod_content.push_str(&rect_str);
+                }
+            }
+            "HOLE" => {
+                if let Some(hole_str) = parse_hole(&args, &footprint_info) {
+                    kicad_mod_content.push_str(&hole_str);
+                }
+            }
+            "SOLIDREGION" => {
+                if let Some(solid_str) = parse_solid_region(&fields, &mut footprint_info) {
+                    kicad_mod_content.push_str(&solid_str);
+                }
+            }
+            "TEXT" => {
+                if let Some(text_str) = parse_text(&fields, &mut footprint_info) {
+                    kicad_mod_content.push_str(&text_str);
+                }
+            }
+            "DIMENSION" => {
+                if let Some(dim_str) = parse_dimension(&args, &footprint_info) {
+                    kicad_mod_content.push_str(&dim_str);
+                }
+            }
+            "SVGNODE" => {
+                if let Ok(json_data) = serde_json::from_str::<serde_json::Value>(args[0]) {
+                    if let Some(uuid) = json_data
+                        .get("attrs")
+                        .and_then(|a| a.get("uuid"))
+                        .and_then(|u| u.as_str())
+                    {
+                        svg_model_uuid = Some(uuid.to_string());
+                    }
+                }
+                if let Some(svg_str) = parse_svg_node(&args, &mut footprint_info) {
+                    kicad_mod_content.push_str(&svg_str);
+                }
+            }
+            _ => {}
+        }
+        let emitted = &kicad_mod_content[emitted_from..];
+        record_geometry(&mut footprint_info, model, &args, &fields, emitted);
+    }
+
+    let mut model_candidates: Vec<String> = Vec::new();
+    if let Some(uuid) = svg_model_uuid {
+        model_candidates.push(uuid);
+    }
+    model_candidates.push(footprint_uuid.to_string());
+
+    Ok(FootprintDraft {
+        name: footprint_name,
+        library: footprint_lib.to_string(),
+        datasheet_link,
+        info: footprint_info,
+        body: kicad_mod_content,
+        model_candidates,
+    })
+}
+
+/// `models` are 3D file names and contents in preference order. Only the
+/// first is referenced by path or embedded, depending on the options; KiCad
+/// would draw every (model ...) line, so the other format stays on disk only.
+fn finish_footprint(
+    draft: &FootprintDraft,
+    models: &[(String, Vec<u8>)],
+    options: &ConversionOptions,
+) -> String {
+    let footprint_info = &draft.info;
+    let mut kicad_mod_content = draft.body.clone();
+    let mut embedded_files = String::new();
+    if let Some((file_name, content)) = models.first() {
+        if options.embeds_3d_model() {
+            match embedded_file_entry(file_name, content) {
+                Ok(entry) => {
+                    kicad_mod_content.push_str(&format!(
+                        "  (model \"kicad-embed://{}\" (at (xyz 0 0 0)) (rotate (xyz 0 0 0)))\n",
+                        file_name
+                    ));
+                    embedded_files.push_str(&entry);
+                }
+                Err(e) => {
+                    log::warn!("Failed to embed {}: {}", file_name, e);
+                    kicad_mod_content.push_str(&footprint_model_line(footprint_info, file_name));
+                }
+            }
+        } else {
+            kicad_mod_content.push_str(&footprint_model_line(footprint_info, file_name));
+        }
+    }
+
+    // Add reference, value text
+    let center_x = (footprint_info.min_x + footprint_info.max_x) / 2.0;
+    let center_y = (footprint_info.min_y + footprint_info.max_y) / 2.0;
+    if options.pad_one_marker {
+        if let Some(marker) = pad_one_marker(&kicad_mod_content, (center_x, center_y)) {
+            kicad_mod_content.push_str(&marker);
+        }
+    }
+
+    kicad_mod_content.push_str(&reference_value_texts(footprint_info, &draft.name));
+    kicad_mod_content.push_str(&format!(
+        "  (fp_text user ${{REFERENCE}} (at {} {}) (layer F.Fab)\n    (effects (font (size 0.5 0.5)))\n  )\n",
+        center_x, center_y
+    ));
+    let (origin_x, origin_y) = footprint_info.origin;
+    let origin = (footprint_info.mm(origin_x), footprint_info.mm(origin_y));
+    kicad_mod_content = translate_footprint(&kicad_mod_content, origin);
+
+    if options.sort_pads_and_pins {
+        kicad_mod_content = sort_footprint_pads(&kicad_mod_content);
+    }
+    if options.mirror_to_back {
+        kicad_mod_content = mirror_footprint_to_back(&kicad_mod_content);
+    }
+    if options.kicad_target() == 5 {
+        kicad_mod_content = kicad_mod_to_legacy(&kicad_mod_content);
+    }
+    kicad_mod_content = round_coordinates(&kicad_mod_content, options.coordinate_decimals());
+    if !embedded_files.is_empty() {
+        kicad_mod_content.push_str(&format!("  (embedded_files\n{}  )\n", embedded_files));
+    }
+
+    // Close footprint
+    kicad_mod_content.push_str(")\n");
+    kicad_mod_content
+}
+
+/// `(file ...)` entry of an `(embedded_files ...)` block as KiCad 9 writes it:
+/// zstd-compressed, base64-encoded data with a MurmurHash3 checksum of the raw bytes.
+fn embedded_file_entry(file_name: &str, content: &[u8]) -> std::io::Result<String> {
+    use base64::Engine;
+
+    const EMBED_HASH_SEED: u32 = 0xABBA2345;
+    let compressed = zstd::encode_all(content, 3)?;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(compressed);
+    let hash = murmur3::murmur3_x64_128(&mut std::io::Cursor::new(content), EMBED_HASH_SEED)?;
+
+    let mut entry = format!(
+        "    (file\n      (name \"{}\")\n      (type model)\n      (data |",
+        file_name
+    );
+    for (i, chunk) in encoded.as_bytes().chunks(76).enumerate() {
+        if i > 0 {
+            entry.push_str("\n        ");
+        }
+        entry.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+    }
+    entry.push_str(&format!(
+        "|)\n      (checksum \"{:016x}{:016x}\")\n    )\n",
+        hash as u64,
+        (hash >> 64) as u64
+    ));
+    Ok(entry)
+}
+
+fn footprint_model_line(info: &FootprintInfo, file_name: &str) -> String {
+    format!(
+        "  (model {} (at (xyz 0 0 0)) (rotate (xyz 0 0 0)))\n",
+        model_reference(info, file_name)
+    )
+}
+
+/// Fetches STEP data, trying the Pro API model UUID first and then `candidates`
+/// in order. The error is the last failure, ready to show to the user.
+async fn fetch_step_model(
+    client: &JlcClient,
+    component_id: &str,
+    candidates: Vec<String>,
+) -> Result<Vec<u8>, String> {
+    let mut model_candidates: Vec<String> = Vec::new();
+    if let Ok(Some(uuid)) = client.resolve_step_uuid_via_pro_api(component_id).await {
+        model_candidates.push(uuid);
+    }
+    model_candidates.extend(candidates);
+    model_candidates.dedup();
+
+    let mut last_error = "3D 模型下载失败".to_string();
+    for uuid in model_candidates {
+        match client.get_step_model(&uuid).await {
+            Ok(content) => return Ok(content),
+            Err(e) => {
+                last_error = format!("3D 模型下载失败（模型UUID: {}）: {}", uuid, e);
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Downloads 3D models in `preferred_3d_format` order as (extension, data).
+/// Stops at the first success unless both formats were asked for; the error
+/// is the last failure when nothing could be fetched.
+async fn fetch_3d_models(
+    client: &JlcClient,
+    component_id: &str,
+    candidates: Vec<String>,
+    options: &ConversionOptions,
+) -> Result<Vec<(&'static str, Vec<u8>)>, String> {
+    let mut fetched = Vec::new();
+    let mut last_error = "3D 模型下载失败".to_string();
+    for ext in options.preferred_3d_format.extensions() {
+        let result = if ext == "wrl" {
+            fetch_wrl_model(client, &candidates).await
+        } else {
+            fetch_step_model(client, component_id, candidates.clone()).await
+        };
+        match result {
+            Ok(content) => {
+                fetched.push((ext, content));
+                if options.preferred_3d_format != ModelFormat::Both {
+                    break;
+                }
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    if fetched.is_empty() {
+        Err(last_error)
+    } else {
+        Ok(fetched)
+    }
+}
+
+/// Fetches the EasyEDA OBJ model of the first working candidate as VRML.
+async fn fetch_wrl_model(client: &JlcClient, candidates: &[String]) -> Result<Vec<u8>, String> {
+    let mut last_error = "WRL 模型下载失败".to_string();
+    // Direct model URLs point at STEP files, not the OBJ-like WRL source.
+    for uuid in candidates.iter().filter(|c| !is_model_url(c)) {
+        match client.get_wrl_model(uuid).await {
+            Ok(text) => match obj_to_wrl(&text) {
+                Some(wrl) => return Ok(wrl.into_bytes()),
+                None => {
+                    last_error = format!("WRL 模型数据无效（模型UUID: {}）", uuid);
+                }
+            },
+            Err(e) => {
+                last_error = format!("WRL 模型下载失败（模型UUID: {}）: {}", uuid, e);
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Converts EasyEDA's OBJ-like model text (mm, `usemtl` groups) to VRML 2.0
+/// in KiCad's 0.1 inch units, one shape per material.
+fn obj_to_wrl(text: &str) -> Option<String> {
+    #[derive(Default)]
+    struct Material {
+        diffuse: String,
+        specular: String,
+        transparency: String,
+    }
+
+    let mut materials: BTreeMap<String, Material> = BTreeMap::new();
+    let mut current: Option<String> = None;
+    let mut vertices: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("newmtl") => {
+                let id = fields.next().unwrap_or_default().to_string();
+                materials.insert(id.clone(), Material::default());
+                current = Some(id);
+            }
+            Some("endmtl") => current = None,
+            Some(key @ ("Kd" | "Ks" | "d")) => {
+                let Some(material) = current.as_ref().and_then(|id| materials.get_mut(id)) else {
+                    continue;
+                };
+                let value = fields.collect::<Vec<_>>().join(" ");
+                match key {
+                    "Kd" => material.diffuse = value,
+                    "Ks" => material.specular = value,
+                    _ => material.transparency = value,
+                }
+            }
+            Some("v") => {
+                let coords: Vec<String> = fields
+                    .filter_map(|c| c.parse::<f64>().ok())
+                    .map(|c| format_number(c / 2.54, 4))
+                    .collect();
+                vertices.push(coords.join(" "));
+            }
+            _ => {}
+        }
+    }
+
+    let mut wrl = String::from("#VRML V2.0 utf8\n#created by JLC2KiCad\n");
+    let mut shapes = 0;
+    for group in text.split("usemtl").skip(1) {
+        let mut lines = group.lines();
+        let material_id = lines.next().unwrap_or_default().trim();
+        let material = materials.get(material_id);
+
+        // Faces index the global vertex list; each shape gets its own points.
+        let mut index_map: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut points: Vec<&str> = Vec::new();
+        let mut coord_index = String::new();
+        for line in lines.map(str::trim).filter(|l| l.starts_with("f ")) {
+            for field in line.split_whitespace().skip(1) {
+                let Some(vertex) = field
+                    .split('/')
+                    .next()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .filter(|v| *v >= 1 && *v <= vertices.len())
+                else {
+                    continue;
+                };
+                let index = *index_map.entry(vertex).or_insert_with(|| {
+                    points.push(&vertices[vertex - 1]);
+                    points.len() - 1
+                });
+                coord_index.push_str(&format!("{},", index));
+            }
+            coord_index.push_str("-1,");
+        }
+        if points.is_empty() {
+            continue;
+        }
+
+        let color = |value: Option<&String>, default: &str| {
+            value.filter(|v| !v.is_empty()).cloned().unwrap_or_else(|| default.to_string())
+        };
+        wrl.push_str(&format!(
+            "Shape {{\n  appearance Appearance {{\n    material Material {{\n      \
+             diffuseColor {}\n      specularColor {}\n      ambientIntensity 0.2\n      \
+             transparency {}\n      shininess 0.5\n    }}\n  }}\n  \
+             geometry IndexedFaceSet {{\n    ccw TRUE\n    solid FALSE\n    \
+             coord Coordinate {{\n      point [{}]\n    }}\n    coordIndex [{}]\n  }}\n}}\n",
+            color(material.map(|m| &m.diffuse), "0.8 0.8 0.8"),
+            color(material.map(|m| &m.specular), "0 0 0"),
+            color(material.map(|m| &m.transparency), "0"),
+            points.join(", "),
+            coord_index
+        ));
+        shapes += 1;
+    }
+    (shapes > 0).then_some(wrl)
+}
+
+fn footprint_header(name: &str, options: &ConversionOptions) -> String {
+    match options.kicad_target() {
+        5 => format!("(module {} (layer F.Cu) (tedit 0)\n", name),
+        _ => {
+            let format_version = match options.kicad_target() {
+                6 => "20211014",
+                7 => "20221018",
+                8 => "20240108",
+                _ => "20241229",
+            };
+            format!(
+                "(footprint \"{}\" (version {}) (generator JLC2KiCad){} (layer \"F.Cu\")\n",
+                name,
+                format_version,
+                generator_version(options)
+            )
+        }
+    }
+}
+
+/// Rewrite the generated footprint body in KiCad 5 syntax: three-point arcs
+/// become centre/angle arcs, rectangles become lines or polygons and footprint
+/// zones, which v5 cannot load, are dropped.
+fn kicad_mod_to_legacy(content: &str) -> String {
+    static ARC_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static RECT_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static CIRCLE_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let arc_re = ARC_RE.get_or_init(|| {
+        regex::Regex::new(
+            r"^\s*\(fp_arc \(start (\S+) (\S+)\) \(mid (\S+) (\S+)\) \(end (\S+) (\S+)\) \(layer (\S+)\) \(width (\S+)\)\)",
+        )
+        .unwrap()
+    });
+    let rect_re = RECT_RE.get_or_init(|| {
+        regex::Regex::new(
+            r"^\s*\(fp_rect \(start (\S+) (\S+)\) \(end (\S+) (\S+)\) \(layer (\S+)\) \(width (\S+)\)( \(fill solid\))?\)",
+        )
+        .unwrap()
+    });
+    let circle_re = CIRCLE_RE.get_or_init(|| {
+        regex::Regex::new(
+            r"^\s*\(fp_circle \(center (\S+) (\S+)\) \(end (\S+) (\S+)\) \(layer (\S+)\) \(width \S+\) \(fill solid\)\)",
+        )
+        .unwrap()
+    });
+    let num = |v: &str| v.parse::<f64>().unwrap_or(0.0);
+
+    let mut out = String::with_capacity(content.len());
+    let mut in_zone = false;
+    for line in content.lines() {
+        if in_zone {
+            in_zone = line != "  )";
+            continue;
+        }
+        if line.starts_with("  (zone ") {
+            log::warn!("KiCad 5 footprints cannot hold zones; copper region dropped");
+            in_zone = true;
+            continue;
+        }
+
+        if let Some(c) = arc_re.captures(line) {
+            let start = (num(&c[1]), num(&c[2]));
+            let mid = (num(&c[3]), num(&c[4]));
+            let end = (num(&c[5]), num(&c[6]));
+            match three_point_arc(start, mid, end) {
+                Some((center, angle)) => out.push_str(&format!(
+                    "  (fp_arc (start {} {}) (end {} {}) (angle {}) (layer {}) (width {}))\n",
+                    center.0, center.1, start.0, start.1, angle, &c[7], &c[8]
+                )),
+                None => out.push_str(&format!(
+                    "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+                    start.0, start.1, end.0, end.1, &c[7], &c[8]
+                )),
+            }
+        } else if let Some(c) = rect_re.captures(line) {
+            let (x1, y1, x2, y2) = (&c[1], &c[2], &c[3], &c[4]);
+            let corners = [(x1, y1), (x2, y1), (x2, y2), (x1, y2)];
+            if c.get(7).is_some() {
+                let pts: Vec<String> = corners.iter().map(|(x, y)| format!("(xy {} {})", x, y)).collect();
+                out.push_str(&format!(
+                    "  (fp_poly (pts {}) (layer {}) (width {}))\n",
+                    pts.join(" "),
+                    &c[5],
+                    &c[6]
+                ));
+            } else {
+                for i in 0..4 {
+                    let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                    out.push_str(&format!(
+                        "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+                        a.0, a.1, b.0, b.1, &c[5], &c[6]
+                    ));
+                }
+            }
+        } else if let Some(c) = circle_re.captures(line) {
+            // No fill in v5: a circle at half the radius stroked as wide as the radius.
+            let (cx, cy) = (num(&c[1]), num(&c[2]));
+            let radius = (num(&c[3]) - cx).hypot(num(&c[4]) - cy);
+            out.push_str(&format!(
+                "  (fp_circle (center {} {}) (end {} {}) (layer {}) (width {}))\n",
+                cx,
+                cy,
+                cx + radius / 2.0,
+                cy,
+                &c[5],
+                radius
+            ));
+        } else if line.starts_with("  (fp_poly ") {
+            // v5 polygons are always filled.
+            out.push_str(&line.replace(" (fill solid)", ""));
+            out.push('\n');
+        } else {
+            out.push_str(&line.replace("${REFERENCE}", "%R"));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Centre and KiCad 5 sweep angle (degrees, clockwise on screen) of the arc
+/// from `start` through `mid` to `end`; `None` when the points are collinear.
+fn three_point_arc(
+    start: (f64, f64),
+    mid: (f64, f64),
+    end: (f64, f64),
+) -> Option<((f64, f64), f64)> {
+    let d = 2.0 * (start.0 * (mid.1 - end.1) + mid.0 * (end.1 - start.1) + end.0 * (start.1 - mid.1));
+    if d.abs() < 1e-9 {
+        return None;
+    }
+    let sq = |p: (f64, f64)| p.0 * p.0 + p.1 * p.1;
+    let cx = (sq(start) * (mid.1 - end.1) + sq(mid) * (end.1 - start.1) + sq(end) * (start.1 - mid.1)) / d;
+    let cy = (sq(start) * (end.0 - mid.0) + sq(mid) * (start.0 - end.0) + sq(end) * (mid.0 - start.0)) / d;
+    let angle_of = |p: (f64, f64)| (p.1 - cy).atan2(p.0 - cx);
+    let turn = std::f64::consts::TAU;
+    let a_start = angle_of(start);
+    let to_end = (angle_of(end) - a_start).rem_euclid(turn);
+    let to_mid = (angle_of(mid) - a_start).rem_euclid(turn);
+    // With y pointing down, increasing atan2 is clockwise, as KiCad 5 expects.
+    let sweep = if to_mid <= to_end { to_end } else { to_end - turn };
+    Some(((cx, cy), sweep.to_degrees()))
+}
+
+/// Package family from a footprint name: chip size for passives (0402, 0603...),
+/// otherwise the leading letters of the name (SOT, QFN, SOIC...).
+fn package_family(footprint_name: &str) -> String {
+    const CHIP_SIZES: [&str; 10] = [
+        "01005", "0201", "0402", "0603", "0805", "1206", "1210", "1812", "2010", "2512",
+    ];
+    let upper = footprint_name.to_uppercase();
+    let tokens: Vec<&str> = upper
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    for token in &tokens {
+        let digits = token.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        if CHIP_SIZES.contains(&digits) {
+            return digits.to_string();
+        }
+    }
+
+    tokens
+        .first()
+        .map(|t| {
+            t.chars()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect::<String>()
+        })
+        .filter(|family| family.len() >= 2)
+        .unwrap_or_else(|| "Other".to_string())
+}
+
+/// "R_0402_10k" style name from the designator prefix, chip size and value
+/// of a resistor, capacitor or inductor. Without a prefix the first value
+/// field present decides the kind. `None` for other parts and for passives
+/// without a usable value.
+fn passive_name(c_para: &SymbolCPara, footprint_title: &str) -> Option<String> {
+    let fields = [
+        ("R", c_para.resistance.as_deref()),
+        ("C", c_para.capacitance.as_deref()),
+        ("L", c_para.inductance.as_deref()),
+    ];
+    let prefix = c_para.pre.replace('?', "").trim().to_uppercase();
+    let (kind, value) = if prefix.is_empty() {
+        fields.into_iter().find(|(_, value)| value.is_some())?
+    } else {
+        fields.into_iter().find(|(kind, _)| *kind == prefix)?
+    };
+
+    // "10kΩ ±1%" -> "10k", "4.7µF" -> "4.7uF"
+    let mut value: String = value?
+        .split_whitespace()
+        .next()?
+        .replace(['µ', 'μ'], "u")
+        .chars()
+        .filter(|c| !matches!(c, '\u{3a9}' | '\u{2126}'))
+        .collect();
+    if value.to_ascii_lowercase().ends_with("ohm") {
+        value.truncate(value.len() - 3);
+    }
+    let value = sanitize_footprint_name(&value);
+    if value.is_empty() {
+        return None;
+    }
+    let package = package_family(footprint_title);
+    let name = if package == "Other" {
+        format!("{}_{}", kind, value)
+    } else {
+        format!("{}_{}_{}", kind, package, value)
+    };
+    invalid_name_reason(&name).is_none().then_some(name)
+}
+
+/// `passive_name` of an online part; `None` when it is no passive or its data
+/// cannot be fetched, which the conversion itself then reports.
+async fn online_passive_name(component_id: &str, options: &ConversionOptions) -> Option<String> {
+    let client = JlcClient::shared();
+    let component_data = client.get_component_data(component_id).await.ok()?;
+    let symbol_uuid = component_data.symbol_uuids().into_iter().next()?;
+    let symbol = client.get_symbol_data(&symbol_uuid).await.ok()?;
+    let footprint_uuid =
+        select_footprint_uuid(&client, &component_data, &options.footprint_variant)
+            .await
+            .ok()?;
+    let footprint = client.get_footprint_data(&footprint_uuid).await.ok()?;
+    passive_name(&symbol.result.data_str.head.c_para, &footprint.result.title)
+}
+
+/// `options` with a `passive_names` name override for every passive among
+/// the offline `ids` that has none yet.
+fn offline_passive_names(
+    bundle: &OfflineBundle,
+    ids: &[String],
+    options: &ConversionOptions,
+) -> ConversionOptions {
+    let mut options = options.clone();
+    for id in ids {
+        let Some(device) = bundle.devices.get(id) else {
+            continue;
+        };
+        if options.name_override(id).is_some() {
+            continue;
+        }
+        let c_para = device
+            .symbol_uuids
+            .first()
+            .and_then(|uuid| get_symbol_data_by_uuid(bundle, uuid))
+            .and_then(|ds| serde_json::from_str::<serde_json::Value>(ds).ok())
+            .and_then(|v| v.pointer("/head/c_para").cloned())
+            .and_then(|c| serde_json::from_value::<SymbolCPara>(c).ok());
+        let title = offline_footprint_uuid(bundle, device, &options.footprint_variant)
+            .and_then(|uuid| get_footprint_title_by_uuid(bundle, &uuid))
+            .unwrap_or_else(|| device.name.clone());
+        if let Some(name) = c_para.and_then(|c| passive_name(&c, &title)) {
+            options.name_overrides.insert(id.clone(), name);
+        }
+    }
+    options
+}
+
+fn model_reference(info: &FootprintInfo, file_name: &str) -> String {
+    if !info.shared_model_dir.is_empty() {
+        return shared_model_reference(&info.shared_model_dir, file_name);
+    }
+    if info.model_base_variable.is_empty() {
+        format!("{}/{}", info.model_dir, file_name)
+    } else {
+        format!(
+            "{}/{}/{}/{}",
+            info.model_base_variable.trim_end_matches('/'),
+            info.footprint_lib,
+            info.model_dir,
+            file_name
+        )
+    }
+}
+
+/// Reference to a file in the shared model folder. When the folder lies in a
+/// KiCad 3D model or third-party directory set in the environment, the path
+/// is written through that variable (e.g. `${KICAD9_3DMODEL_DIR}/x.step`) so
+/// it resolves on every machine of the team; otherwise it stays absolute.
+fn shared_model_reference(dir: &str, file_name: &str) -> String {
+    let normalize = |p: &str| p.replace('\\', "/").trim_end_matches('/').to_string();
+    let dir = normalize(dir);
+    let mut best: Option<(String, String)> = None;
+    for major in KICAD_MAJOR_VERSIONS {
+        let vars = if major == 5 {
+            vec!["KISYS3DMOD".to_string()]
+        } else {
+            vec![format!("KICAD{}_3DMODEL_DIR", major), format!("KICAD{}_3RD_PARTY", major)]
+        };
+        for var in vars {
+            let Ok(value) = std::env::var(&var) else {
+                continue;
+            };
+            let value = normalize(value.trim());
+            if value.is_empty() {
+                continue;
+            }
+            let rest = match dir.strip_prefix(&value) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                _ => continue,
+            };
+            // The most specific variable wins.
+            if best.as_ref().is_none_or(|(_, r)| rest.len() < r.len()) {
+                best = Some((var, rest.to_string()));
+            }
+        }
+    }
+    match best {
+        Some((var, rest)) => format!("${{{}}}{}/{}", var, rest, file_name),
+        None => format!("{}/{}", dir, file_name),
+    }
+}
+
+/// Maps EasyEDA model uuids to the files already in a shared model folder,
+/// so parts using the same model don't download or copy it again.
+const SHARED_MODEL_MANIFEST: &str = ".jlc2kicad_models.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SharedModelManifest {
+    models: BTreeMap<String, Vec<String>>,
+}
+
+impl SharedModelManifest {
+    fn load(dir: &Path) -> Self {
+        fs::read(dir.join(SHARED_MODEL_MANIFEST))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Files stored for `model_key`, provided they are all still there.
+    fn files(dir: &Path, model_key: &str) -> Option<Vec<String>> {
+        let files = Self::load(dir).models.remove(model_key)?;
+        (!files.is_empty() && files.iter().all(|f| dir.join(f).is_file())).then_some(files)
+    }
+
+    /// Like the progress state, a failed write is only logged.
+    fn record(dir: &Path, model_key: &str, files: Vec<String>) {
+        let mut manifest = Self::load(dir);
+        manifest.models.insert(model_key.to_string(), files);
+        let path = dir.join(SHARED_MODEL_MANIFEST);
+        let written = serde_json::to_vec_pretty(&manifest)
+            .map_err(std::io::Error::from)
+            .and_then(|data| write_file_atomic(&path, &data));
+        if let Err(e) = written {
+            log::warn!("Failed to save shared model list {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// KiCad's inner copper layers; EasyEDA numbers them from 21 (Inner1).
+const INNER_COPPER_LAYERS: [&str; 30] = [
+    "In1.Cu", "In2.Cu", "In3.Cu", "In4.Cu", "In5.Cu", "In6.Cu", "In7.Cu", "In8.Cu", "In9.Cu",
+    "In10.Cu", "In11.Cu", "In12.Cu", "In13.Cu", "In14.Cu", "In15.Cu", "In16.Cu", "In17.Cu",
+    "In18.Cu", "In19.Cu", "In20.Cu", "In21.Cu", "In22.Cu", "In23.Cu", "In24.Cu", "In25.Cu",
+    "In26.Cu", "In27.Cu", "In28.Cu", "In29.Cu", "In30.Cu",
+];
+
+/// KiCad name of EasyEDA inner copper layer `layer_id`; `None` for other
+/// layers and for inner layers past KiCad's 30.
+fn inner_copper_layer(layer_id: &str) -> Option<&'static str> {
+    let inner = layer_id.trim().parse::<usize>().ok()?.checked_sub(21)?;
+    INNER_COPPER_LAYERS.get(inner).copied()
+}
+
+/// KiCad layer of a `kind` primitive on EasyEDA layer `layer_id`, as
+/// `layer_map`. EasyEDA's Inner31 and Inner32 (ids 51 and 52) have no KiCad
+/// layer, so primitives there are left out with a warning in `info`.
+fn primitive_layer(layer_id: &str, kind: &str, info: &mut FootprintInfo) -> Option<&'static str> {
+    let inner = layer_id.trim().parse::<usize>().ok().and_then(|id| id.checked_sub(20));
+    if let Some(n) = inner.filter(|n| (INNER_COPPER_LAYERS.len() + 1..=32).contains(n)) {
+        info.warnings.push(format!("{}位于 Inner{} 层，KiCad 没有对应的内层，已跳过", kind, n));
+        return None;
+    }
+    Some(layer_map(layer_id))
+}
+
+fn layer_map(layer_id: &str) -> &'static str {
+    if let Some(layer) = inner_copper_layer(layer_id) {
+        return layer;
+    }
+    match layer_id {
+        "1" => "F.Cu",
+        "2" => "B.Cu",
+        "3" => "F.SilkS",
+        "4" => "B.SilkS",
+        "5" => "F.Paste",
+        "6" => "B.Paste",
+        "7" => "F.Mask",
+        "8" => "B.Mask",
+        "10" => "Edge.Cuts",
+        "11" => "F.Fab",
+        "12" => "F.Fab",
+        "99" => "F.Fab",
+        "100" => "F.Fab",
+        "101" => "F.Fab",
+        _ => "F.SilkS",
+    }
+}
+
+fn parse_pad(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    // args: [shape, x, y, size_x, size_y, layer, net, pad_num, drill, ..., rotation,
+    //        ..., slot_length, ..., plated]
+    if args.len() < 9 {
+        return None;
+    }
+
+    let shape = args[0];
+    let x = info.mm(args[1].parse().unwrap_or(0.0));
+    let y = info.mm(args[2].parse().unwrap_or(0.0));
+    let mut size_x = info.mm(args[3].parse().unwrap_or(1.0));
+    let mut size_y = info.mm(args[4].parse().unwrap_or(1.0));
+    let layer_ids: Vec<&str> = args[5]
+        .split([',', ' '])
+        .filter(|id| !id.is_empty())
+        .collect();
+    let multi_layer = layer_ids.contains(&"11");
+    let net = info
+        .net_code(args[6])
+        .map(|code| format!(" (net {} \"{}\")", code, args[6].trim().replace('"', "\\\"")));
+    let pad_num = args[7];
+    let drill_diameter = info.mm(args[8].parse::<f64>().unwrap_or(0.0)) * 2.0;
+    let rotation = args
+        .get(10)
+        .and_then(|s| s.parse::<f64>().ok())
+        .unwrap_or(0.0)
+        .rem_euclid(360.0);
+    // KiCad rotates the unrotated size; some EasyEDA pads at 90/270 degrees
+    // already store the rotated size, which the outline polygon reveals.
+    let quarter_turn = (rotation - 90.0).abs() < 0.01 || (rotation - 270.0).abs() < 0.01;
+    if quarter_turn && (size_x - size_y).abs() > 0.001 {
+        let outline = args[8..args.len().min(11)].iter().find(|a| a.contains(' '));
+        if let Some((w, h)) = outline.and_then(|o| pad_outline_extent(o, info.metric)) {
+            if (w - size_x).abs() < 0.01 && (h - size_y).abs() < 0.01 {
+                std::mem::swap(&mut size_x, &mut size_y);
+            }
+        }
+    }
+    let slot_length = info.mm(args.get(12).and_then(|s| s.parse().ok()).unwrap_or(0.0));
+    let plated = args.get(14) != Some(&"N");
+
+    // Update footprint bounds
+    info.max_x = info.max_x.max(x);
+    info.min_x = info.min_x.min(x);
+    info.max_y = info.max_y.max(y);
+    info.min_y = info.min_y.min(y);
+
+    // The slot runs along the pad's long side.
+    let hole_size = if slot_length <= drill_diameter {
+        (drill_diameter, drill_diameter)
+    } else if size_x >= size_y {
+        (slot_length, drill_diameter)
+    } else {
+        (drill_diameter, slot_length)
+    };
+    let pad_type = if !multi_layer {
+        "smd"
+    } else {
+        drilled_pad_type(plated, (size_x, size_y), hole_size)
+    };
+    if pad_type == "np_thru_hole" {
+        size_x = size_x.max(hole_size.0);
+        size_y = size_y.max(hole_size.1);
+    }
+
+    let corner_shaping = if shape == "RECT" {
+        args.get(15).and_then(|c| pad_corner_shaping(c, rotation))
+    } else {
+        None
+    };
+    let ki_shape = match shape {
+        "OVAL" => "oval",
+        "RECT" if corner_shaping.is_some() => "roundrect",
+        "RECT" => "rect",
+        "ELLIPSE" => "circle",
+        "CIRCLE" => "circle",
+        _ => "oval",
+    };
+
+    let layers = pad_layers(&layer_ids);
+
+    let drill = if pad_type == "smd" || drill_diameter <= 0.0 {
+        String::new()
+    } else if slot_length > drill_diameter {
+        format!(" (drill oval {} {})", hole_size.0, hole_size.1)
+    } else {
+        format!(" (drill {})", drill_diameter)
+    };
+    // Mechanical holes carry no number.
+    let pad_num = if pad_type == "np_thru_hole" { "" } else { pad_num };
+
+    // A ranged number stacks one pad per number on the same copper; shared
+    // numbers on separate pads are left as they are.
+    let mut result = String::new();
+    for number in expand_pad_numbers(pad_num) {
+        result.push_str(&format!(
+            "  (pad \"{}\" {} {} (at {} {} {}) (size {} {}){} (layers {}){}{})\n",
+            number.replace('"', "\\\""),
+            pad_type,
+            ki_shape,
+            x,
+            y,
+            rotation,
+            size_x,
+            size_y,
+            drill,
+            layers,
+            corner_shaping.as_deref().unwrap_or(""),
+            net.as_deref().unwrap_or("")
+        ));
+    }
+    Some(result)
+}
+
+/// Numbers of the pads in a generated footprint; unnumbered pads are left out.
+fn footprint_pad_numbers(kicad_mod: &str) -> BTreeSet<String> {
+    static PAD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let pad_re = PAD_RE.get_or_init(|| regex::Regex::new(r#"(?m)^\s*\(pad "([^"]+)""#).unwrap());
+    pad_re.captures_iter(kicad_mod).map(|c| c[1].to_string()).collect()
+}
+
+/// Numbers of the pins in generated symbols, across all units.
+fn symbol_pin_numbers(kicad_sym: &str) -> BTreeSet<String> {
+    static NUMBER_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let number_re = NUMBER_RE.get_or_init(|| regex::Regex::new(r#"\(number "([^"]+)""#).unwrap());
+    number_re.captures_iter(kicad_sym).map(|c| c[1].to_string()).collect()
+}
+
+/// Pins without a pad and pads without a pin, described for the report;
+/// `None` when they match or either side has nothing to compare.
+fn pin_pad_mismatch(pins: &BTreeSet<String>, pads: &BTreeSet<String>) -> Option<String> {
+    if pins.is_empty() || pads.is_empty() {
+        return None;
+    }
+    let missing = |from: &BTreeSet<String>, other: &BTreeSet<String>| {
+        from.difference(other).cloned().collect::<Vec<_>>().join(", ")
+    };
+    let (lonely_pins, lonely_pads) = (missing(pins, pads), missing(pads, pins));
+    let mut problems = Vec::new();
+    if !lonely_pins.is_empty() {
+        problems.push(format!("引脚 {} 没有对应焊盘", lonely_pins));
+    }
+    if !lonely_pads.is_empty() {
+        problems.push(format!("焊盘 {} 没有对应引脚", lonely_pads));
+    }
+    (!problems.is_empty()).then(|| format!("符号引脚与封装焊盘不一致: {}", problems.join("；")))
+}
+
+/// KiCad `(layers ...)` of a pad on the given EasyEDA layer ids: every copper
+/// layer for multi-layer (11) pads, otherwise each listed side with its paste
+/// and mask plus any inner layers (21 = In1.Cu onwards). No recognised layer
+/// means the top side.
+fn pad_layers(layer_ids: &[&str]) -> String {
+    if layer_ids.contains(&"11") {
+        return "*.Cu *.Mask".to_string();
+    }
+    let mut layers: Vec<String> = Vec::new();
+    if layer_ids.contains(&"1") {
+        layers.extend(["F.Cu", "F.Paste", "F.Mask"].map(String::from));
+    }
+    if layer_ids.contains(&"2") {
+        layers.extend(["B.Cu", "B.Paste", "B.Mask"].map(String::from));
+    }
+    layers.extend(layer_ids.iter().filter_map(|id| inner_copper_layer(id)).map(String::from));
+    if layers.is_empty() {
+        return "F.Cu F.Paste F.Mask".to_string();
+    }
+    layers.join(" ")
+}
+
+/// KiCad roundrect/chamfer attributes from a "corner:<round>:<chamfer>:<bits>"
+/// pad field, as written by the EasyEDA Pro converter. The bits name corners
+/// as seen on the board; KiCad names them before the pad's `rotation`.
+fn pad_corner_shaping(field: &str, rotation: f64) -> Option<String> {
+    const CORNERS: [(u32, &str); 4] = [
+        (1, "top_left"),
+        (2, "top_right"),
+        (4, "bottom_left"),
+        (8, "bottom_right"),
+    ];
+    let mut values = field.strip_prefix("corner:")?.split(':');
+    let round_ratio: f64 = values.next()?.parse().ok()?;
+    let chamfer_ratio: f64 = values.next().and_then(|v| v.parse().ok()).unwrap_or(0.0);
+    let bits: u32 = values.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let bits = unrotate_corner_bits(bits, rotation);
+
+    let mut attrs = format!(" (roundrect_rratio {})", round_ratio.clamp(0.0, 0.5));
+    let corners: Vec<&str> = CORNERS
+        .iter()
+        .filter(|(bit, _)| bits & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if chamfer_ratio > 0.0 && !corners.is_empty() {
+        attrs.push_str(&format!(
+            " (chamfer_ratio {}) (chamfer {})",
+            chamfer_ratio.clamp(0.0, 0.5),
+            corners.join(" ")
+        ));
+    }
+    Some(attrs)
+}
+
+/// Corner bits of a pad turned by `rotation`, as the unrotated pad's. A
+/// counter-clockwise quarter turn moves each corner to the next one in
+/// top-left, bottom-left, bottom-right, top-right order; off-axis angles keep
+/// the bits as they are.
+fn unrotate_corner_bits(bits: u32, rotation: f64) -> u32 {
+    const CYCLE: [u32; 4] = [1, 4, 8, 2];
+    let turns = rotation / 90.0;
+    if (turns - turns.round()).abs() > 0.01 / 90.0 {
+        return bits;
+    }
+    let turns = (turns.round() as i64).rem_euclid(4) as usize;
+    CYCLE
+        .iter()
+        .enumerate()
+        .filter(|(_, bit)| bits & *bit != 0)
+        .fold(0, |local, (i, _)| local | CYCLE[(i + 4 - turns) % 4])
+}
+
+/// Width and height in mm of the bounding box of a pad outline "x1 y1 x2 y2 ...".
+fn pad_outline_extent(points: &str, metric: bool) -> Option<(f64, f64)> {
+    let values: Vec<f64> = points
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    if values.len() < 6 {
+        return None;
+    }
+    let (xs, ys): (Vec<f64>, Vec<f64>) = values
+        .chunks(2)
+        .filter(|c| c.len() == 2)
+        .map(|c| (c[0], c[1]))
+        .unzip();
+    let extent = |v: &[f64]| {
+        v.iter().cloned().fold(f64::MIN, f64::max) - v.iter().cloned().fold(f64::MAX, f64::min)
+    };
+    Some((to_mm(extent(&xs), metric), to_mm(extent(&ys), metric)))
+}
+
+/// Expand pad number ranges such as "1-4" or lists such as "1,2" into single
+/// numbers. Anything else (including names like "A-1") is kept verbatim.
+fn expand_pad_numbers(pad_num: &str) -> Vec<String> {
+    const MAX_RANGE: u32 = 64;
+    let trimmed = pad_num.trim();
+    let mut numbers = Vec::new();
+    for part in trimmed.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let range = part.split_once('-').and_then(|(a, b)| {
+            let start = a.trim().parse::<u32>().ok()?;
+            let end = b.trim().parse::<u32>().ok()?;
+            (start < end && end - start < MAX_RANGE).then_some((start, end))
+        });
+        match range {
+            Some((start, end)) => numbers.extend((start..=end).map(|n| n.to_string())),
+            None if trimmed.contains(',') && part.parse::<u32>().is_ok() => {
+                numbers.push(part.to_string())
+            }
+            None => return vec![trimmed.to_string()],
+        }
+    }
+    if numbers.is_empty() {
+        numbers.push(trimmed.to_string());
+    }
+    numbers
+}
+
+fn parse_track(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    if args.len() < 4 {
+        return None;
+    }
+
+    let layer = primitive_layer(args[1], "走线", info)?;
+    // A zero-width track is a hairline in EasyEDA, not a region.
+    let width = stroke_width(info.mm(args[0].parse().unwrap_or(0.2)), layer, info);
+    let tokens: Vec<&str> = args[3].split(' ').filter(|s| !s.is_empty()).collect();
+    let num = |i: usize| tokens.get(i).and_then(|t| t.parse::<f64>().ok());
+
+    let mut result = String::new();
+    let mut last: Option<(f64, f64)> = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        // Pro-derived tracks keep `ARC <sweep degrees> x y` segments.
+        if tokens[i] == "ARC" || tokens[i] == "CARC" {
+            let (Some(angle), Some(x), Some(y), Some(start)) =
+                (num(i + 1), num(i + 2), num(i + 3), last)
+            else {
+                break;
+            };
+            let end = (info.mm(x), info.mm(y));
+            match arc_center(start, end, angle) {
+                Some(center) => {
+                    let mid = rotate_about(start, center, angle.to_radians() / 2.0);
+                    for (px, py) in arc_points(start, end, angle) {
+                        info.max_x = info.max_x.max(px);
+                        info.min_x = info.min_x.min(px);
+                        info.max_y = info.max_y.max(py);
+                        info.min_y = info.min_y.min(py);
+                    }
+                    result.push_str(&format!(
+                        "  (fp_arc (start {} {}) (mid {} {}) (end {} {}) (layer {}) (width {}))\n",
+                        start.0, start.1, mid.0, mid.1, end.0, end.1, layer, width
+                    ));
+                }
+                None => result.push_str(&track_segment(start, end, layer, width, info)),
+            }
+            last = Some(end);
+            i += 4;
+            continue;
+        }
+
+        let (Some(x), Some(y)) = (num(i), num(i + 1)) else {
+            // Markers such as `L` carry no coordinates.
+            i += 1;
+            continue;
+        };
+        let point = (info.mm(x), info.mm(y));
+        if let Some(start) = last {
+            result.push_str(&track_segment(start, point, layer, width, info));
+        }
+        last = Some(point);
+        i += 2;
+    }
+
+    if result.is_empty() {
+        return None;
+    }
+    Some(result)
+}
+
+fn track_segment(
+    start: (f64, f64),
+    end: (f64, f64),
+    layer: &str,
+    width: f64,
+    info: &mut FootprintInfo,
+) -> String {
+    info.max_x = info.max_x.max(start.0).max(end.0);
+    info.min_x = info.min_x.min(start.0).min(end.0);
+    info.max_y = info.max_y.max(start.1).max(end.1);
+    info.min_y = info.min_y.min(start.1).min(end.1);
+
+    format!(
+        "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+        start.0, start.1, end.0, end.1, layer, width
+    )
+}
+
+fn parse_circle(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    if args.len() < 4 {
+        return None;
+    }
+
+    let cx = info.mm(args[0].parse().unwrap_or(0.0));
+    let cy = info.mm(args[1].parse().unwrap_or(0.0));
+    let r = info.mm(args[2].parse().unwrap_or(0.0));
+    let raw_width = info.mm(args[3].parse().unwrap_or(0.2));
+    let layer = primitive_layer(args.get(4).unwrap_or(&"3"), "圆", info)?;
+
+    // Skip circles on pad layer
+    if args.get(4).map(|s| *s == "100").unwrap_or(false) {
+        return None;
+    }
+
+    // A filled circle on copper is a round pad (test points and the like);
+    // it has no number since EasyEDA did not give it one.
+    if raw_width <= 0.0 && matches!(layer, "F.Cu" | "B.Cu") {
+        info.max_x = info.max_x.max(cx + r);
+        info.min_x = info.min_x.min(cx - r);
+        info.max_y = info.max_y.max(cy + r);
+        info.min_y = info.min_y.min(cy - r);
+        let layers = pad_layers(&[args[4]]);
+        return Some(format!(
+            "  (pad \"\" smd circle (at {} {}) (size {} {}) (layers {}))\n",
+            cx,
+            cy,
+            r * 2.0,
+            r * 2.0,
+            layers
+        ));
+    }
+
+    // Zero-width circles are filled dots (pin-1 markers and the like).
+    if raw_width <= 0.0 {
+        return Some(format!(
+            "  (fp_circle (center {} {}) (end {} {}) (layer {}) (width {}) (fill solid))\n",
+            cx, cy, cx + r, cy, layer, stroke_width(raw_width, layer, info)
+        ));
+    }
+
+    Some(format!(
+        "  (fp_circle (center {} {}) (end {} {}) (layer {}) (width {}))\n",
+        cx, cy, cx + r, cy, layer, stroke_width(raw_width, layer, info)
+    ))
+}
+
+/// ARC~width~layer~net~path~...: `path` is an SVG "M x y A rx ry rot large sweep x y".
+fn parse_arc(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    if args.len() < 3 {
+        return None;
+    }
+
+    let layer = primitive_layer(args[1], "圆弧", info)?;
+    let width = stroke_width(info.mm(args[0].parse().unwrap_or(0.2)), layer, info);
+    let path = args[2..].iter().find(|a| a.trim_start().starts_with('M'))?;
+    let spaced = path.replace(',', " ").replace('M', " M ").replace('A', " A ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let num = |i: usize| tokens.get(i).and_then(|t| t.parse::<f64>().ok());
+
+    // Every "A" continues from where the previous one ended.
+    let mut start = (info.mm(num(1)?), info.mm(num(2)?));
+    let mut arcs = Vec::new();
+    for a in (0..tokens.len()).filter(|i| tokens[*i] == "A") {
+        let radius = info.mm(num(a + 1)?);
+        let large_arc = num(a + 4)? != 0.0;
+        let sweep = num(a + 5)? != 0.0;
+        let end = (info.mm(num(a + 6)?), info.mm(num(a + 7)?));
+        if let Some(geometry) = svg_arc_geometry(start, end, radius, large_arc, sweep) {
+            arcs.push((start, end, geometry));
+        }
+        start = end;
+    }
+    let &(_, _, (center, radius, _)) = arcs.first()?;
+
+    // A path turning (nearly) all the way round is a circle; as an fp_arc it
+    // would start and end on the same point, which KiCad rejects.
+    let total_sweep: f64 = arcs.iter().map(|(_, _, (_, _, delta))| delta).sum();
+    if total_sweep.abs().to_degrees() >= FULL_CIRCLE_SWEEP_DEG {
+        info.max_x = info.max_x.max(center.0 + radius);
+        info.min_x = info.min_x.min(center.0 - radius);
+        info.max_y = info.max_y.max(center.1 + radius);
+        info.min_y = info.min_y.min(center.1 - radius);
+        return Some(format!(
+            "  (fp_circle (center {} {}) (end {} {}) (layer {}) (width {}))\n",
+            center.0,
+            center.1,
+            center.0 + radius,
+            center.1,
+            layer,
+            width
+        ));
+    }
+
+    let mut result = String::new();
+    for (start, end, ((cx, cy), radius, delta)) in arcs {
+        let a = (start.1 - cy).atan2(start.0 - cx) + delta / 2.0;
+        let mid = (cx + radius * a.cos(), cy + radius * a.sin());
+        for (x, y) in [start, mid, end] {
+            info.max_x = info.max_x.max(x);
+            info.min_x = info.min_x.min(x);
+            info.max_y = info.max_y.max(y);
+            info.min_y = info.min_y.min(y);
+        }
+        result.push_str(&format!(
+            "  (fp_arc (start {} {}) (mid {} {}) (end {} {}) (layer {}) (width {}))\n",
+            start.0, start.1, mid.0, mid.1, end.0, end.1, layer, width
+        ));
+    }
+    Some(result)
+}
+
+/// Arcs sweeping at least this far are written as full circles; the gap left
+/// by EasyEDA's rounding of a 360° arc stays well under this.
+const FULL_CIRCLE_SWEEP_DEG: f64 = 359.5;
+
+/// Center, radius and signed sweep in radians of an SVG circular arc, from
+/// the SVG endpoint-to-center conversion; `None` when start and end meet.
+/// `sweep` set means increasing angle, which is clockwise on screen since
+/// both EasyEDA and KiCad point y down.
+fn svg_arc_geometry(
+    start: (f64, f64),
+    end: (f64, f64),
+    radius: f64,
+    large_arc: bool,
+    sweep: bool,
+) -> Option<((f64, f64), f64, f64)> {
+    let (hx, hy) = ((start.0 - end.0) / 2.0, (start.1 - end.1) / 2.0);
+    let half_chord_sq = hx * hx + hy * hy;
+    if half_chord_sq < 1e-12 {
+        return None;
+    }
+    // A radius too small to span the chord is scaled up, as SVG does.
+    let radius = radius.abs().max(half_chord_sq.sqrt());
+    let sign = if large_arc != sweep { 1.0 } else { -1.0 };
+    let coef = sign * ((radius * radius - half_chord_sq).max(0.0) / half_chord_sq).sqrt();
+    let cx = coef * hy + (start.0 + end.0) / 2.0;
+    let cy = -coef * hx + (start.1 + end.1) / 2.0;
+
+    let a0 = (start.1 - cy).atan2(start.0 - cx);
+    let a1 = (end.1 - cy).atan2(end.0 - cx);
+    let mut delta = a1 - a0;
+    if sweep && delta < 0.0 {
+        delta += std::f64::consts::TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= std::f64::consts::TAU;
+    }
+    Some(((cx, cy), radius, delta))
+}
+
+fn parse_rect(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    if args.len() < 8 {
+        return None;
+    }
+
+    let x1 = info.mm(args[0].parse().unwrap_or(0.0));
+    let y1 = info.mm(args[1].parse().unwrap_or(0.0));
+    let dx = info.mm(args[2].parse().unwrap_or(0.0));
+    let dy = info.mm(args[3].parse().unwrap_or(0.0));
+    let x2 = x1 + dx;
+    let y2 = y1 + dy;
+    let layer = primitive_layer(args.get(4).unwrap_or(&"3"), "矩形", info)?;
+    let width = info.mm(args.get(7).unwrap_or(&"0").parse().unwrap_or(0.2));
+    // Corner radius, when the RECT carries one after the stroke width.
+    let radius = args
+        .get(8)
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|r| r.is_finite() && *r > 0.0)
+        .map(|r| info.mm(r))
+        .unwrap_or(0.0);
+
+    info.max_x = info.max_x.max(x1).max(x2);
+    info.min_x = info.min_x.min(x1).min(x2);
+    info.max_y = info.max_y.max(y1).max(y2);
+    info.min_y = info.min_y.min(y1).min(y2);
+
+    if width == 0.0 {
+        // Zero-width rects are filled regions.
+        Some(format!(
+            "  (fp_rect (start {} {}) (end {} {}) (layer {}) (width {}) (fill solid))\n",
+            x1, y1, x2, y2, layer, stroke_width(0.0, layer, info)
+        ))
+    } else if radius > 0.0 {
+        let width = stroke_width(width, layer, info);
+        Some(rounded_rect_outline(
+            (x1.min(x2), y1.min(y2)),
+            (x1.max(x2), y1.max(y2)),
+            radius,
+            layer,
+            width,
+        ))
+    } else {
+        let width = stroke_width(width, layer, info);
+        Some(format!(
+            "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+            x1, y1, x2, y1, layer, width
+        ))
+    }
+}
+
+/// Rectangle outline with arc corners; the radius is capped at half the
+/// shorter side.
+fn rounded_rect_outline(
+    min: (f64, f64),
+    max: (f64, f64),
+    radius: f64,
+    layer: &str,
+    width: f64,
+) -> String {
+    let r = radius.min((max.0 - min.0) / 2.0).min((max.1 - min.1) / 2.0);
+    let (l, t, rt, b) = (min.0 + r, min.1 + r, max.0 - r, max.1 - r);
+    let mut out = String::new();
+    for (sx, sy, ex, ey) in [
+        (l, min.1, rt, min.1),
+        (max.0, t, max.0, b),
+        (rt, max.1, l, max.1),
+        (min.0, b, min.0, t),
+    ] {
+        if (sx - ex).abs() > 1e-9 || (sy - ey).abs() > 1e-9 {
+            out.push_str(&format!(
+                "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+                sx, sy, ex, ey, layer, width
+            ));
+        }
+    }
+
+    // Corner centers with the outward direction of each corner.
+    let d = r * std::f64::consts::FRAC_1_SQRT_2;
+    for (cx, cy, dx, dy) in [
+        (l, t, -1.0, -1.0),
+        (rt, t, 1.0, -1.0),
+        (rt, b, 1.0, 1.0),
+        (l, b, -1.0, 1.0),
+    ] {
+        out.push_str(&format!(
+            "  (fp_arc (start {} {}) (mid {} {}) (end {} {}) (layer {}) (width {}))\n",
+            cx + dx * r,
+            cy,
+            cx + dx * d,
+            cy + dy * d,
+            cx,
+            cy + dy * r,
+            layer,
+            width
+        ));
+    }
+    out
+}
+
+fn default_stroke_width(layer: &str) -> f64 {
+    match layer {
+        "F.SilkS" | "B.SilkS" => 0.12,
+        "F.Fab" | "B.Fab" => 0.1,
+        "Edge.Cuts" | "F.CrtYd" | "B.CrtYd" => 0.05,
+        _ => 0.2,
+    }
+}
+
+fn stroke_width(width: f64, layer: &str, info: &FootprintInfo) -> f64 {
+    let width = if width > 0.0 { width } else { default_stroke_width(layer) };
+    if layer.ends_with(".SilkS") && info.min_silk_width > 0.0 {
+        width.max(info.min_silk_width)
+    } else {
+        width
+    }
+}
+
+/// Pad type of a drilled pad, for online PADs and offline elibu pads alike: a
+/// hole marked unplated, or one with no copper ring around it, is mechanical.
+fn drilled_pad_type(plated: bool, pad_size: (f64, f64), hole_size: (f64, f64)) -> &'static str {
+    let no_copper = pad_size.0 <= hole_size.0 + 1e-6 && pad_size.1 <= hole_size.1 + 1e-6;
+    if !plated || no_copper {
+        "np_thru_hole"
+    } else {
+        "thru_hole"
+    }
+}
+
+/// A bare HOLE, written exactly like the mechanical pad `parse_pad` emits for
+/// the same hole.
+fn parse_hole(args: &[&str], info: &FootprintInfo) -> Option<String> {
+    if args.len() < 3 {
+        return None;
+    }
+
+    let x = info.mm(args[0].parse().unwrap_or(0.0));
+    let y = info.mm(args[1].parse().unwrap_or(0.0));
+    let r = info.mm(args[2].parse().unwrap_or(0.0)) * 2.0;
+
+    Some(format!(
+        "  (pad \"\" np_thru_hole circle (at {} {} 0) (size {} {}) (drill {}) (layers *.Cu *.Mask))\n",
+        x, y, r, r, r
+    ))
+}
+
+/// Notes the anchor of the primitive parsed from one shape line: its source
+/// coordinates and the first point of what it `emitted`. Lines that produced
+/// nothing, or have no single anchor (dimensions, 3D outlines), are skipped.
+fn record_geometry(
+    info: &mut FootprintInfo,
+    model: &str,
+    args: &[&str],
+    fields: &[&str],
+    emitted: &str,
+) {
+    static COORD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let Some(rows) = info.geometry.as_mut() else {
+        return;
+    };
+    let source_text = match model {
+        "PAD" | "TEXT" => fields.get(1..3).map(|f| f.join(" ")),
+        "CIRCLE" | "HOLE" | "RECT" => args.get(..2).map(|a| a.join(" ")),
+        "TRACK" => args.get(3).map(|a| a.to_string()),
+        "ARC" => args
+            .iter()
+            .skip(2)
+            .find(|a| a.trim_start().starts_with('M'))
+            .map(|a| a.to_string()),
+        "SOLIDREGION" => fields.get(2).map(|f| f.to_string()),
+        _ => None,
+    };
+    // The first two numbers, skipping SVG path commands.
+    let source = source_text.and_then(|text| {
+        let mut nums = text
+            .split(|c: char| c.is_whitespace() || c == ',' || c.is_ascii_alphabetic())
+            .filter_map(|t| t.parse::<f64>().ok());
+        Some((nums.next()?, nums.next()?))
+    });
+    let coord_re = COORD_RE.get_or_init(|| {
+        regex::Regex::new(r"\((?:at|start|center|xy) (-?[0-9.eE+-]+) (-?[0-9.eE+-]+)").unwrap()
+    });
+    let converted = coord_re.captures(emitted).and_then(|c| {
+        Some((c[1].parse::<f64>().ok()?, c[2].parse::<f64>().ok()?))
+    });
+    if let (Some(source), Some(converted)) = (source, converted) {
+        rows.push(GeometryRow { kind: model.to_string(), source, converted });
+    }
+}
+
+/// The table of `info.geometry` for footprint `name` of part `id`, with
+/// converted points shifted by the footprint origin as in the written file.
+fn footprint_geometry(info: &FootprintInfo, id: &str, name: &str) -> Option<FootprintGeometry> {
+    let rows = info.geometry.as_ref()?;
+    let origin = (info.mm(info.origin.0), info.mm(info.origin.1));
+    Some(FootprintGeometry {
+        id: id.to_string(),
+        footprint: name.to_string(),
+        source_unit: if info.metric { "mm" } else { "10 mil" }.to_string(),
+        rows: rows
+            .iter()
+            .map(|row| GeometryRow {
+                converted: (row.converted.0 - origin.0, row.converted.1 - origin.1),
+                ..row.clone()
+            })
+            .collect(),
+    })
+}
+
+/// Moves every coordinate of a generated footprint by `-offset`, so the
+/// EasyEDA reference point (the dataStr head `x`/`y`, in mm) becomes KiCad's
+/// footprint origin. Pads, graphics, zones and texts all store absolute
+/// positions, so one shift keeps them in place relative to each other;
+/// sizes, widths and rotations are left alone.
+fn translate_footprint(content: &str, offset: (f64, f64)) -> String {
+    static COORD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    if offset.0 == 0.0 && offset.1 == 0.0 {
+        return content.to_string();
+    }
+    let coord_re = COORD_RE.get_or_init(|| {
+        regex::Regex::new(r"\((at|start|mid|end|center|xy) (-?[0-9.eE+-]+) (-?[0-9.eE+-]+)")
+            .unwrap()
+    });
+    coord_re
+        .replace_all(content, |caps: &regex::Captures| {
+            let x = caps[2].parse::<f64>().unwrap_or(0.0) - offset.0;
+            let y = caps[3].parse::<f64>().unwrap_or(0.0) - offset.1;
+            format!("({} {} {}", &caps[1], x, y)
+        })
+        .into_owned()
+}
+
+fn mirror_footprint_to_back(content: &str) -> String {
+    // Mirror around the Y axis, the same way KiCad flips a footprint to the
+    // bottom side: x is negated, F.* and B.* layers swap and text is mirrored.
+    static LAYER_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static COORD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static EFFECTS_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static MODEL_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let layer_re = LAYER_RE.get_or_init(|| {
+        regex::Regex::new(r"\b([FB])\.(Cu|SilkS|Paste|Mask|Fab|CrtYd|Adhes)\b").unwrap()
+    });
+    let coord_re = COORD_RE.get_or_init(|| {
+        regex::Regex::new(r"\((at|start|mid|end|center|xy) (-?[0-9.eE+-]+) (-?[0-9.eE+-]+)(?: (-?[0-9.eE+-]+))?\)")
+            .unwrap()
+    });
+    let effects_re = EFFECTS_RE.get_or_init(|| {
+        regex::Regex::new(r"\(effects (\(font(?: \([^()]*\))*\))( \(justify mirror\))?\)").unwrap()
+    });
+    let model_re = MODEL_RE.get_or_init(|| {
+        regex::Regex::new(r"\((at|offset|rotate) \(xyz (\S+) (\S+) (\S+)\)\)").unwrap()
+    });
+    let negate = |v: f64| if v == 0.0 { 0.0 } else { -v };
+
+    let swapped = layer_re.replace_all(content, |caps: &regex::Captures| {
+        let side = if &caps[1] == "F" { "B" } else { "F" };
+        format!("{}.{}", side, &caps[2])
+    });
+
+    let mirrored = coord_re.replace_all(&swapped, |caps: &regex::Captures| {
+        let x = negate(caps[2].parse().unwrap_or(0.0));
+        match caps.get(4) {
+            Some(rot) => {
+                let rot: f64 = rot.as_str().parse().unwrap_or(0.0);
+                let rot = (360.0 - rot).rem_euclid(360.0);
+                format!("({} {} {} {})", &caps[1], x, &caps[3], rot)
+            }
+            None => format!("({} {} {})", &caps[1], x, &caps[3]),
+        }
+    });
+
+    // The 3D model turns over with the footprint: half a turn about the y
+    // axis negates x and z of its offset and its x and z rotation.
+    let flipped = model_re.replace_all(&mirrored, |caps: &regex::Captures| {
+        let xyz: Vec<f64> = (2..5).map(|i| caps[i].parse().unwrap_or(0.0)).collect();
+        let (x, y, z) = if &caps[1] == "rotate" {
+            (negate(xyz[0]), (xyz[1] + 180.0).rem_euclid(360.0), negate(xyz[2]))
+        } else {
+            (negate(xyz[0]), xyz[1], negate(xyz[2]))
+        };
+        format!("({} (xyz {} {} {}))", &caps[1], x, y, z)
+    });
+
+    // Flipping sides mirrors front text and turns back text readable again.
+    effects_re
+        .replace_all(&flipped, |caps: &regex::Captures| match caps.get(2) {
+            Some(_) => format!("(effects {})", &caps[1]),
+            None => format!("(effects {} (justify mirror))", &caps[1]),
+        })
+        .into_owned()
+}
+
+fn parse_solid_region(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    // args: [layer, net, svg path, type, ...]; only solid copper regions become
+    // zones, so a ground pour keeps its net. Silkscreen regions (usually logos)
+    // become filled polygons; other regions are dropped.
+    let layer = match *args.first()? {
+        "1" => "F.Cu",
+        "2" => "B.Cu",
+        silk @ ("3" | "4") => {
+            let outlines = parse_svg_polyline_path(args.get(2)?);
+            return Some(graphic_polygons(&outlines, layer_map(silk), info));
+        }
+        _ => return Some(String::new()),
+    };
+    if args.get(3).is_some_and(|t| !t.is_empty() && *t != "solid") {
+        return Some(String::new());
+    }
+    let net_name = args.get(1).map(|n| n.trim()).unwrap_or("");
+    let net_code = info.net_code(net_name).unwrap_or(0);
+
+    let mut result = String::new();
+    for outline in parse_svg_polyline_path(args.get(2)?) {
+        if outline.len() < 3 {
+            continue;
+        }
+        let pts: Vec<String> = outline
+            .iter()
+            .map(|(x, y)| format!("(xy {} {})", info.mm(*x), info.mm(*y)))
+            .collect();
+        result.push_str(&format!(
+            "  (zone (net {}) (net_name \"{}\") (layer {}) (hatch edge 0.508)\n    (connect_pads yes (clearance 0))\n    (min_thickness 0.254)\n    (fill yes (thermal_gap 0.508) (thermal_bridge_width 0.508))\n    (polygon\n      (pts {})\n    )\n  )\n",
+            net_code,
+            net_name.replace('"', "\\\""),
+            layer,
+            pts.join(" ")
+        ));
+    }
+    Some(result)
+}
+
+/// Designator (`P`) and name (`N`) texts only record their placement for the
+/// reference and value fields; other texts become user text.
+fn parse_text(fields: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    // fields: [type, x, y, stroke_width, rotation, mirror, layer, net, font_size, text,
+    //          path, display, ...]
+    if fields.len() < 10 {
+        return None;
+    }
+
+    let size = info.mm(fields[8].parse().unwrap_or(0.0));
+    let size = if size > 0.0 { size } else { 1.0 };
+    let thickness = info.mm(fields[3].parse().unwrap_or(0.0));
+    let layer = primitive_layer(fields[6], "文字", info)?;
+    let placement = TextPlacement {
+        x: info.mm(fields[1].parse().unwrap_or(0.0)),
+        y: info.mm(fields[2].parse().unwrap_or(0.0)),
+        rotation: readable_text_rotation(fields[4].parse::<f64>().unwrap_or(0.0)),
+        size,
+        thickness: if thickness > 0.0 { thickness } else { size * 0.15 },
+        hidden: fields.get(11).is_some_and(|d| *d == "none"),
+        mirrored: layer.starts_with("B."),
+    };
+
+    match fields[0] {
+        "P" => {
+            info.reference_text = Some(placement);
+            None
+        }
+        "N" => {
+            info.value_text = Some(placement);
+            None
+        }
+        _ => {
+            let text = fields[9].trim();
+            if text.is_empty() || placement.hidden {
+                return None;
+            }
+            Some(fp_text_line(
+                "user",
+                &format!("\"{}\"", text.replace('"', "\\\"")),
+                layer,
+                &placement,
+            ))
+        }
+    }
+}
+
+/// Reference and value fp_text, placed like EasyEDA's designator and name text
+/// when the footprint had them, otherwise above and below the footprint.
+/// Texts EasyEDA put on the bottom side go to the back layers, mirrored.
+fn reference_value_texts(info: &FootprintInfo, name: &str) -> String {
+    let center_x = (info.min_x + info.max_x) / 2.0;
+    let computed = |y: f64| TextPlacement {
+        x: center_x,
+        y,
+        rotation: 0.0,
+        size: 1.0,
+        thickness: 0.15,
+        hidden: false,
+        mirrored: false,
+    };
+    let reference = info
+        .reference_text
+        .clone()
+        .unwrap_or_else(|| computed(info.min_y - 2.0));
+    let value = info
+        .value_text
+        .clone()
+        .unwrap_or_else(|| computed(info.max_y + 2.0));
+    let side = |text: &TextPlacement| if text.mirrored { "B" } else { "F" };
+    format!(
+        "{}{}",
+        fp_text_line("reference", "REF**", &format!("{}.SilkS", side(&reference)), &reference),
+        fp_text_line("value", name, &format!("{}.Fab", side(&value)), &value)
+    )
+}
+
+fn fp_text_line(kind: &str, text: &str, layer: &str, placement: &TextPlacement) -> String {
+    let rotation = if placement.rotation.abs() > 0.001 {
+        format!(" {}", placement.rotation)
+    } else {
+        String::new()
+    };
+    format!(
+        "  (fp_text {} {} (at {} {}{}) (layer {}){}\n    \
+         (effects (font (size {} {}) (thickness {})){})\n  )\n",
+        kind,
+        text,
+        placement.x,
+        placement.y,
+        rotation,
+        layer,
+        if placement.hidden { " hide" } else { "" },
+        placement.size,
+        placement.size,
+        placement.thickness,
+        if placement.mirrored { " (justify mirror)" } else { "" }
+    )
+}
+
+/// Folds a text angle into KiCad's readable half-turn. Text is centred on its
+/// position, so at 180° it covers the same spot as at 0°, only upside down:
+/// angles over 90° up to 270° lose 180°, giving 0/90 for right angles.
+/// Angles within 0.01° of a right angle are snapped to it first.
+fn readable_text_rotation(degrees: f64) -> f64 {
+    let mut angle = degrees.rem_euclid(360.0);
+    let right_angle = (angle / 90.0).round() * 90.0;
+    if (angle - right_angle).abs() < 0.01 {
+        angle = right_angle;
+    }
+    if angle > 90.0 && angle <= 270.0 {
+        angle -= 180.0;
+    }
+    angle.rem_euclid(360.0)
+}
+
+fn parse_svg_polyline_path(path: &str) -> Vec<Vec<(f64, f64)>> {
+    // Straight segments (M/L/H/V/Z) are taken as they are, C/Q curves are
+    // flattened and A arcs are sampled along their circle; numbers belonging
+    // to other commands are skipped.
+    const CURVE_STEPS: usize = 8;
+    // Largest angle between two samples of an arc.
+    const ARC_STEP: f64 = std::f64::consts::PI / 16.0;
+    let mut spaced = String::with_capacity(path.len() * 2);
+    for ch in path.chars() {
+        if ch.is_ascii_alphabetic() && ch != 'e' && ch != 'E' {
+            spaced.push(' ');
+            spaced.push(ch);
+            spaced.push(' ');
+        } else if ch == ',' {
+            spaced.push(' ');
+        } else {
+            spaced.push(ch);
+        }
+    }
+
+    let mut subpaths: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut current: Vec<(f64, f64)> = Vec::new();
+    let mut cmd = 'M';
+    let mut pending: Vec<f64> = Vec::new();
+    let mut pos = (0.0_f64, 0.0_f64);
+
+    for token in spaced.split_whitespace() {
+        let mut chars = token.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphabetic() {
+                cmd = c;
+                pending.clear();
+                if c == 'Z' || c == 'z' {
+                    if let Some(first) = current.first().copied() {
+                        current.push(first);
+                        pos = first;
+                    }
+                }
+                continue;
+            }
+        }
+
+        let Ok(v) = token.parse::<f64>() else {
+            continue;
+        };
+        pending.push(v);
+
+        match cmd {
+            'M' | 'm' | 'L' | 'l' if pending.len() == 2 => {
+                let relative = cmd.is_ascii_lowercase();
+                let pt = if relative {
+                    (pos.0 + pending[0], pos.1 + pending[1])
+                } else {
+                    (pending[0], pending[1])
+                };
+                if cmd == 'M' || cmd == 'm' {
+                    if current.len() >= 2 {
+                        subpaths.push(std::mem::take(&mut current));
+                    } else {
+                        current.clear();
+                    }
+                    // Subsequent pairs after a moveto are implicit linetos.
+                    cmd = if relative { 'l' } else { 'L' };
+                }
+                current.push(pt);
+                pos = pt;
+                pending.clear();
+            }
+            'H' | 'h' | 'V' | 'v' => {
+                let pt = match cmd {
+                    'H' => (v, pos.1),
+                    'h' => (pos.0 + v, pos.1),
+                    'V' => (pos.0, v),
+                    _ => (pos.0, pos.1 + v),
+                };
+                current.push(pt);
+                pos = pt;
+                pending.clear();
+            }
+            'C' | 'c' | 'Q' | 'q'
+                if pending.len() == 6 || (pending.len() == 4 && matches!(cmd, 'Q' | 'q')) =>
+            {
+                let base = if cmd.is_ascii_lowercase() { pos } else { (0.0, 0.0) };
+                let ctrl: Vec<(f64, f64)> = pending
+                    .chunks(2)
+                    .map(|xy| (base.0 + xy[0], base.1 + xy[1]))
+                    .collect();
+                if current.is_empty() {
+                    current.push(pos);
+                }
+                current.extend(flatten_bezier(pos, &ctrl, CURVE_STEPS));
+                pos = *ctrl.last().unwrap_or(&pos);
+                pending.clear();
+            }
+            'A' | 'a' if pending.len() == 7 => {
+                let pt = if cmd == 'a' {
+                    (pos.0 + pending[5], pos.1 + pending[6])
+                } else {
+                    (pending[5], pending[6])
+                };
+                if current.is_empty() {
+                    current.push(pos);
+                }
+                // Elliptical arcs are taken as circular ones of radius rx.
+                let (large_arc, sweep) = (pending[3] != 0.0, pending[4] != 0.0);
+                if let Some((center, radius, delta)) =
+                    svg_arc_geometry(pos, pt, pending[0], large_arc, sweep)
+                {
+                    let a0 = (pos.1 - center.1).atan2(pos.0 - center.0);
+                    let steps = ((delta.abs() / ARC_STEP).ceil() as usize).max(1);
+                    current.extend((1..steps).map(|i| {
+                        let a = a0 + delta * i as f64 / steps as f64;
+                        (center.0 + radius * a.cos(), center.1 + radius * a.sin())
+                    }));
+                }
+                current.push(pt);
+                pos = pt;
+                pending.clear();
+            }
+            _ => {}
+        }
+    }
+
+    if current.len() >= 2 {
+        subpaths.push(current);
+    }
+    subpaths
+}
+
+/// Douglas–Peucker: drops points closer than `tolerance` to the line between
+/// the points kept around them. The first and last points always stay.
+fn simplify_polyline(points: &[(f64, f64)], tolerance: f64) -> Vec<(f64, f64)> {
+    if points.len() < 3 || tolerance <= 0.0 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let (a, b) = (points[first], points[last]);
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx.hypot(dy);
+        let distance = |p: (f64, f64)| {
+            if length < 1e-12 {
+                (p.0 - a.0).hypot(p.1 - a.1)
+            } else {
+                ((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / length
+            }
+        };
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance(points[i])))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, _)) = farthest.filter(|(_, d)| *d > tolerance) {
+            keep[i] = true;
+            spans.push((first, i));
+            spans.push((i, last));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(p, k)| k.then_some(*p))
+        .collect()
+}
+
+/// Filled polygons for graphics such as logos, in mm. Each outline is
+/// simplified, with the tolerance doubled until it fits `MAX_POLYGON_POINTS`;
+/// graphics over `MAX_GRAPHIC_SOURCE_POINTS` are skipped with a warning.
+fn graphic_polygons(outlines: &[Vec<(f64, f64)>], layer: &str, info: &mut FootprintInfo) -> String {
+    let source_points: usize = outlines.iter().map(|o| o.len()).sum();
+    if source_points > MAX_GRAPHIC_SOURCE_POINTS {
+        log::warn!(
+            "Skipping {} graphic with {} points (limit {})",
+            layer,
+            source_points,
+            MAX_GRAPHIC_SOURCE_POINTS
+        );
+        info.warnings.push(format!(
+            "{} 上的图形有 {} 个点，超过 {} 的上限，已跳过",
+            layer, source_points, MAX_GRAPHIC_SOURCE_POINTS
+        ));
+        return String::new();
+    }
+
+    let mut result = String::new();
+    for outline in outlines {
+        let outline: Vec<(f64, f64)> =
+            outline.iter().map(|(x, y)| (info.mm(*x), info.mm(*y))).collect();
+        let mut tolerance = info.simplify_tolerance;
+        let mut simplified = simplify_polyline(&outline, tolerance);
+        while simplified.len() > MAX_POLYGON_POINTS {
+            tolerance = if tolerance > 0.0 { tolerance * 2.0 } else { DEFAULT_SIMPLIFY_TOLERANCE };
+            simplified = simplify_polyline(&outline, tolerance);
+        }
+        if simplified.len() < 3 {
+            continue;
+        }
+        let pts: Vec<String> =
+            simplified.iter().map(|(x, y)| format!("(xy {} {})", x, y)).collect();
+        result.push_str(&format!(
+            "  (fp_poly (pts {}) (layer {}) (width 0) (fill solid))\n",
+            pts.join(" "),
+            layer
+        ));
+    }
+    result
+}
+
+/// Graphics drawn by an SVGNODE (imported images and logos) as filled
+/// polygons; the 3D model outline node is skipped.
+fn parse_svg_node(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
+    fn collect_paths(node: &serde_json::Value, paths: &mut Vec<String>) {
+        if let Some(d) = node.get("attrs").and_then(|a| a.get("d")).and_then(|d| d.as_str()) {
+            paths.push(d.to_string());
+        }
+        for child in node.get("childNodes").and_then(|c| c.as_array()).into_iter().flatten() {
+            collect_paths(child, paths);
+        }
+    }
+
+    let node: serde_json::Value = serde_json::from_str(args.first()?).ok()?;
+    let attrs = node.get("attrs")?;
+    if attrs.get("c_etype").and_then(|e| e.as_str()) == Some("outline3D") {
+        return None;
+    }
+    let layer = primitive_layer(
+        attrs.get("layerid").and_then(|l| l.as_str()).unwrap_or("3"),
+        "图形",
+        info,
+    )?;
+    let mut paths = Vec::new();
+    collect_paths(&node, &mut paths);
+    let outlines: Vec<Vec<(f64, f64)>> = paths
+        .iter()
+        .flat_map(|d| parse_svg_polyline_path(d))
+        .filter(|o| o.len() >= 3)
+        .collect();
+    Some(graphic_polygons(&outlines, layer, info))
+}
+
+/// Points along a quadratic (one control point) or cubic (two) Bézier from
+/// `start`; `ctrl` ends with the end point, which is the last point returned.
+fn flatten_bezier(start: (f64, f64), ctrl: &[(f64, f64)], steps: usize) -> Vec<(f64, f64)> {
+    (1..=steps)
+        .map(|step| {
+            let t = step as f64 / steps as f64;
+            let u = 1.0 - t;
+            let weights: Vec<f64> = if ctrl.len() == 3 {
+                vec![u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t]
+            } else {
+                vec![u * u, 2.0 * u * t, t * t]
+            };
+            std::iter::once(start)
+                .chain(ctrl.iter().copied())
+                .zip(weights)
+                .fold((0.0, 0.0), |acc, (p, w)| (acc.0 + p.0 * w, acc.1 + p.1 * w))
+        })
+        .collect()
+}
+
+fn parse_dimension(args: &[&str], info: &FootprintInfo) -> Option<String> {
+    // args: [layer, ..., svg path, ...]; the path carries the dimension
+    // and arrow strokes. Only document/mechanical layers map unambiguously.
+    let layer = match *args.first()? {
+        "12" => "Dwgs.User",
+        "15" => "Cmts.User",
+        _ => return None,
     };
-    let mut svg_model_uuid: Option<String> = None;
-    let mut step_model_downloaded = false;
-    let mut step_model_error: Option<String> = None;
+    let path = args.iter().find(|a| a.trim_start().starts_with('M'))?;
 
-    let mut kicad_mod_content = String::new();
+    let mut result = String::new();
+    let mut longest = 0.0_f64;
+    let mut label_at = (0.0_f64, 0.0_f64);
+    for subpath in parse_svg_polyline_path(path) {
+        for seg in subpath.windows(2) {
+            let (x1, y1) = (info.mm(seg[0].0), info.mm(seg[0].1));
+            let (x2, y2) = (info.mm(seg[1].0), info.mm(seg[1].1));
+            let len = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            if len == 0.0 {
+                continue;
+            }
+            if len > longest {
+                longest = len;
+                label_at = ((x1 + x2) / 2.0, (y1 + y2) / 2.0 - 0.5);
+            }
+            result.push_str(&format!(
+                "  (fp_line (start {} {}) (end {} {}) (layer {}) (width 0.1))\n",
+                x1, y1, x2, y2, layer
+            ));
+        }
+    }
 
-    // Generate KiCad footprint header
-    kicad_mod_content.push_str(&format!(
-        "(kicad_mod (version {})\n",
-        "20220214"
+    if longest == 0.0 {
+        return None;
+    }
+    result.push_str(&format!(
+        "  (fp_text user {:.2}mm (at {} {}) (layer {})\n    (effects (font (size 0.5 0.5)))\n  )\n",
+        longest, label_at.0, label_at.1, layer
     ));
-    kicad_mod_content.push_str(&format!("  (footprint {} (identifier {}) (user {})\n",
-        footprint_name, footprint_name, footprint_name));
+    Some(result)
+}
 
-    // Parse shape and generate footprint elements
+async fn create_symbol_internal(
+    client: &JlcClient,
+    symbol_uuids: &[String],
+    footprint_name: &str,
+    datasheet_link: &str,
+    fp_filters: &[String],
+    component_id: &str,
+    output_dir: &str,
+    symbol_lib: &str,
+    symbol_path: &str,
+    options: &ConversionOptions,
+) -> Result<(Vec<PathBuf>, BTreeSet<String>), JlcError> {
+    let lib_content = build_symbol_lib(
+        client,
+        symbol_uuids,
+        footprint_name,
+        datasheet_link,
+        fp_filters,
+        component_id,
+        options,
+    )
+    .await?;
+
+    let output_path = PathBuf::from(output_dir).join(symbol_path);
+    let files = write_symbol_lib(&output_path, symbol_lib, &lib_content, options)?;
+    Ok((files, symbol_pin_numbers(&lib_content)))
+}
+
+/// Writes the symbols of a composite device into one library, each part's
+/// symbols pointing at that part's footprint. Returns the written files and
+/// the pin numbers of every part.
+async fn create_composite_symbols(
+    client: &JlcClient,
+    parts: &[CompositePart],
+    datasheet_link: &str,
+    component_id: &str,
+    output_path: &Path,
+    symbol_lib: &str,
+    options: &ConversionOptions,
+) -> Result<(Vec<PathBuf>, Vec<BTreeSet<String>>), JlcError> {
+    let mut lib_content = symbol_lib_header(options);
+    let mut pins = Vec::new();
+    for (n, part) in parts.iter().enumerate() {
+        let part_lib = build_symbol_lib(
+            client,
+            &part.symbol_uuids,
+            &part.footprint_reference,
+            datasheet_link,
+            &[],
+            component_id,
+            &options.for_composite_part(component_id, n),
+        )
+        .await?;
+        pins.push(symbol_pin_numbers(&part_lib));
+        for block in symbol_blocks(&part_lib) {
+            lib_content.push_str(&part_lib[block]);
+        }
+    }
+    lib_content.push_str(")\n");
+    let files = write_symbol_lib(output_path, symbol_lib, &lib_content, options)?;
+    Ok((files, pins))
+}
+
+/// Top-level symbol blocks of a part built from EasyEDA, for merging into a
+/// library otherwise converted from a local bundle.
+async fn online_symbol_blocks(
+    component_id: &str,
+    footprint_reference: &str,
+    options: &ConversionOptions,
+) -> Result<String, JlcError> {
+    let client = JlcClient::shared();
+    let symbol_uuids = client.get_component_data(component_id).await?.symbol_uuids();
+    if symbol_uuids.is_empty() {
+        return Err(JlcError::ApiError(format!("在线数据中没有 {} 的符号", component_id)));
+    }
+    let lib =
+        build_symbol_lib(&client, &symbol_uuids, footprint_reference, "", &[], component_id, options)
+            .await?;
+    Ok(symbol_blocks(&lib).into_iter().map(|block| &lib[block]).collect())
+}
+
+async fn build_symbol_lib(
+    client: &JlcClient,
+    symbol_uuids: &[String],
+    footprint_name: &str,
+    datasheet_link: &str,
+    fp_filters: &[String],
+    component_id: &str,
+    options: &ConversionOptions,
+) -> Result<String, JlcError> {
+    let datasheet_link = options.datasheet_override(component_id).unwrap_or(datasheet_link);
+    let mut lib_content = String::new();
+    lib_content.push_str(&symbol_lib_header(options));
+    let fetched_at = (options.annotate_generator && !options.deterministic).then(utc_timestamp);
+    let mut part_prefix: Option<String> = None;
+    let mut units: Vec<SymbolUnit> = Vec::new();
+    // Only known when the part was found through a search this session.
+    let search_result = cached_search_result(component_id);
+    let jlc_class = search_result.as_ref().and_then(|r| r.jlc_class.clone());
+    let category = search_result.and_then(|r| r.category);
+
+    let ordered_uuids = options.ordered_units(component_id, symbol_uuids);
+    for (idx, symbol_uuid) in ordered_uuids.into_iter().enumerate() {
+        let data = client.get_symbol_data(symbol_uuid).await?;
+        
+        let title = &data.result.title;
+        let component_name = format!(
+            "{}{}",
+            options.name_prefix(data.result.data_str.head.c_para.manufacturer.as_deref()),
+            sanitize_symbol_name(title)
+        );
+
+        // Like the offline path, prefer the unit's own prefix; units without one
+        // reuse the part's prefix so all gates share a single designator.
+        let unit_prefix = data.result.data_str.head.c_para.pre.replace('?', "");
+        let prefix = if !unit_prefix.trim().is_empty() {
+            unit_prefix.trim().to_string()
+        } else if let Some(p) = &part_prefix {
+            p.clone()
+        } else {
+            let package_prefix = data
+                .result
+                .package_detail
+                .as_ref()
+                .and_then(|p| p.data_str.head.c_para.as_ref())
+                .map(|c| c.pre.replace('?', ""))
+                .unwrap_or_default();
+            if package_prefix.trim().is_empty() {
+                "U".to_string()
+            } else {
+                package_prefix.trim().to_string()
+            }
+        };
+        part_prefix.get_or_insert_with(|| prefix.clone());
+
+        let shape = &data.result.data_str.shape;
+        options.check_shape_kinds(shape, &SYMBOL_SHAPE_KINDS)?;
+        let (origin_x, origin_y) = (data.result.data_str.head.x, data.result.data_str.head.y);
+
+        let sym_name = match options.name_override(component_id) {
+            Some(name) if idx == 0 => name.to_string(),
+            Some(name) => format!("{}_{}", name, idx),
+            None if idx == 0 => format!("{}_0", component_name),
+            None => component_name.clone(),
+        };
+
+        let metric = is_metric_unit(data.result.data_str.head.unit.as_deref());
+        let (body, pins) = symbol_unit_graphics(shape, origin_x, origin_y, metric, options);
+        let power_net = (symbol_uuids.len() == 1)
+            .then(|| power_symbol_net(&prefix, category.as_deref(), &body, &pins, title))
+            .flatten();
+        let body = if power_net.is_some() { power_symbol_body(&body) } else { body };
+        let (reference_at, value_at) = symbol_label_positions(&body);
+
+        let mut header = String::new();
+        header.push_str(&format!(
+            "  (symbol \"{}\" {}{} (pin_numbers hide) (in_bom yes) (on_board yes)\n",
+            sym_name,
+            if power_net.is_some() { "(power) " } else { "" },
+            options.pin_names_attr()
+        ));
+
+        let mut properties = SymbolProperties::new(options);
+        match &power_net {
+            Some(net) => {
+                properties.add("Reference", "#PWR", reference_at, HIDDEN_PROPERTY_EFFECTS);
+                properties.add("Value", net, value_at, PROPERTY_EFFECTS);
+            }
+            None => {
+                properties.add("Reference", &prefix, reference_at, PROPERTY_EFFECTS);
+                properties.add("Value", title, value_at, PROPERTY_EFFECTS);
+            }
+        }
+        properties.add("Footprint", footprint_name, (0.0, -10.16), HIDDEN_ITALIC_PROPERTY_EFFECTS);
+        properties.add(
+            "Datasheet",
+            datasheet_link,
+            (-2.286, 0.127),
+            "(font (size 1.27 1.27)) (justify left) hide",
+        );
+        properties.add("ki_keywords", component_id, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+        properties.add("LCSC", component_id, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+        if let Some(fetched_at) = fetched_at.as_deref() {
+            properties.add("EasyEDA Fetched", fetched_at, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+        }
+        if !fp_filters.is_empty() {
+            let filters = fp_filters.join(" ");
+            properties.add("ki_fp_filters", &filters, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+        }
+        if let Some(class) = jlc_class.as_deref() {
+            properties.add("JLC Class", class, (0.0, 0.0), HIDDEN_PROPERTY_EFFECTS);
+        }
+        header.push_str(&properties.text);
+
+        units.push(SymbolUnit { name: sym_name, header, body, pins });
+    }
+
+    lib_content.push_str(&emit_symbol_units(&units, options.demorgan));
+    lib_content.push_str(")\n");
+
+    Ok(lib_content)
+}
+
+const PROPERTY_EFFECTS: &str = "(font (size 1.27 1.27))";
+const HIDDEN_PROPERTY_EFFECTS: &str = "(font (size 1.27 1.27)) hide";
+const HIDDEN_ITALIC_PROPERTY_EFFECTS: &str = "(font (size 1.27 1.27) italic) hide";
+
+/// The `(property ...)` entries of one symbol. KiCad 6 and 7 number them,
+/// with 0-3 kept for Reference, Value, Footprint and Datasheet and custom
+/// fields counting on from 4 so no two share an id; KiCad 8 dropped the ids.
+struct SymbolProperties {
+    text: String,
+    numbered: bool,
+    next_custom_id: usize,
+}
+
+impl SymbolProperties {
+    fn new(options: &ConversionOptions) -> Self {
+        Self {
+            text: String::new(),
+            numbered: options.kicad_target() < 8,
+            next_custom_id: 4,
+        }
+    }
+
+    fn add(&mut self, name: &str, value: &str, at: (f64, f64), effects: &str) {
+        let id = match name {
+            "Reference" => 0,
+            "Value" => 1,
+            "Footprint" => 2,
+            "Datasheet" => 3,
+            _ => {
+                self.next_custom_id += 1;
+                self.next_custom_id - 1
+            }
+        };
+        let id = if self.numbered {
+            format!(" (id {})", id)
+        } else {
+            String::new()
+        };
+        self.text.push_str(&format!(
+            "    (property \"{}\" \"{}\"{} (at {} {} 0)\n      (effects {})\n    )\n",
+            name,
+            value.replace('"', "\\\""),
+            id,
+            at.0,
+            at.1,
+            effects
+        ));
+    }
+}
+
+fn symbol_lib_header(options: &ConversionOptions) -> String {
+    let format_version = match options.kicad_target() {
+        7 => "20220914",
+        8 => "20231120",
+        9 => "20241209",
+        // KiCad 5 output is generated as 6 and converted in finish_symbol_lib.
+        _ => "20211014",
+    };
+    format!(
+        "(kicad_symbol_lib (version {}) (generator JLC2KiCad){}\n",
+        format_version,
+        generator_version(options)
+    )
+}
+
+/// ` (generator_version "...")` for library headers when `annotate_generator`
+/// is set; KiCad only knows the token from version 8.
+fn generator_version(options: &ConversionOptions) -> String {
+    if options.annotate_generator && options.kicad_target() >= 8 {
+        format!(" (generator_version \"{}\")", env!("CARGO_PKG_VERSION"))
+    } else {
+        String::new()
+    }
+}
+
+fn symbol_lib_file_name(symbol_lib: &str, options: &ConversionOptions) -> String {
+    if options.kicad_target() == 5 {
+        format!("{}.lib", symbol_lib)
+    } else {
+        format!("{}.kicad_sym", symbol_lib)
+    }
+}
+
+/// Writes a generated library under `output_path`: as `<symbol_lib>` or, with
+/// `symbol_per_part`, as one standalone library per top-level symbol. Returns
+/// the files written.
+fn write_symbol_lib(
+    output_path: &Path,
+    symbol_lib: &str,
+    lib_content: &str,
+    options: &ConversionOptions,
+) -> Result<Vec<PathBuf>, JlcError> {
+    fs::create_dir_all(output_path)?;
+    let blocks = symbol_blocks(lib_content);
+    let libraries: Vec<(String, String)> = match blocks.first() {
+        Some(first) if options.symbol_per_part => {
+            let header = &lib_content[..first.start];
+            blocks
+                .iter()
+                .map(|block| {
+                    let text = &lib_content[block.clone()];
+                    let name = text.split('"').nth(1).unwrap_or(symbol_lib);
+                    let name = if options.ascii_file_names {
+                        ascii_file_name(name, &options.file_name_fallback)
+                    } else {
+                        name.to_string()
+                    };
+                    (name, format!("{}{})\n", header, text))
+                })
+                .collect()
+        }
+        _ => vec![(symbol_lib.to_string(), lib_content.to_string())],
+    };
+
+    let mut written = Vec::new();
+    for (name, content) in libraries {
+        let file_path = output_path.join(symbol_lib_file_name(&name, options));
+        write_output_file(&file_path, finish_symbol_lib(content, options).as_bytes(), options)?;
+        written.push(file_path);
+    }
+    Ok(written)
+}
+
+/// Temporary file of a streamed write, deleted when dropped unless `keep`
+/// was called after renaming it into place.
+struct TmpFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TmpFile {
+    fn keep(mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A symbol library written as its symbols are converted, so large local
+/// bundles never hold the whole library in memory. Per-part files and KiCad 5
+/// `.lib` output need the complete text and are still buffered. A streamed
+/// library dropped before `finish` leaves no temporary file behind.
+enum SymbolLibWriter {
+    Stream {
+        // Declared before `tmp` so the file is closed before it is deleted.
+        writer: BufWriter<File>,
+        path: PathBuf,
+        tmp: TmpFile,
+        decimals: usize,
+    },
+    Buffer(String),
+}
+
+impl SymbolLibWriter {
+    fn new(
+        output_path: &Path,
+        symbol_lib: &str,
+        options: &ConversionOptions,
+    ) -> Result<Self, JlcError> {
+        let header = symbol_lib_header(options);
+        if options.symbol_per_part || options.kicad_target() == 5 {
+            return Ok(Self::Buffer(header));
+        }
+        fs::create_dir_all(output_path)?;
+        let path = output_path.join(symbol_lib_file_name(symbol_lib, options));
+        let tmp_path = atomic_tmp_path(&path);
+        let mut lib = Self::Stream {
+            writer: BufWriter::new(File::create(&tmp_path)?),
+            path,
+            tmp: TmpFile { path: tmp_path, keep: false },
+            decimals: options.coordinate_decimals(),
+        };
+        lib.push(&header)?;
+        Ok(lib)
+    }
+
+    /// Appends whole symbol blocks (or the header). Rounding works number by
+    /// number, so rounding each piece matches rounding the finished file.
+    fn push(&mut self, text: &str) -> Result<(), JlcError> {
+        match self {
+            Self::Stream { writer, decimals, .. } => {
+                writer.write_all(round_coordinates(text, *decimals).as_bytes())?;
+            }
+            Self::Buffer(content) => content.push_str(text),
+        }
+        Ok(())
+    }
+
+    fn finish(
+        self,
+        output_path: &Path,
+        symbol_lib: &str,
+        options: &ConversionOptions,
+    ) -> Result<Vec<PathBuf>, JlcError> {
+        match self {
+            Self::Stream { mut writer, path, tmp, .. } => {
+                writer.write_all(b")\n")?;
+                writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                record_write(&path, options);
+                fs::rename(&tmp.path, &path)?;
+                tmp.keep();
+                Ok(vec![path])
+            }
+            Self::Buffer(mut content) => {
+                content.push_str(")\n");
+                write_symbol_lib(output_path, symbol_lib, &content, options)
+            }
+        }
+    }
+}
+
+fn finish_symbol_lib(lib_content: String, options: &ConversionOptions) -> String {
+    let lib_content = round_coordinates(&lib_content, options.coordinate_decimals());
+    if options.kicad_target() == 5 {
+        kicad_sym_to_legacy_lib(&lib_content)
+    } else {
+        lib_content
+    }
+}
+
+fn mm_to_mil(v: &str) -> i64 {
+    (v.parse::<f64>().unwrap_or(0.0) / 0.0254).round() as i64
+}
+
+/// Rewrite the generated `.kicad_sym` text as a KiCad 5 `.lib`. Only the
+/// constructs this converter emits are understood.
+fn kicad_sym_to_legacy_lib(content: &str) -> String {
+    static SYMBOL_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static PROPERTY_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static PIN_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static NAME_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static RECT_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static CIRCLE_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static TEXT_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static XY_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static FILL_RE: OnceLock<regex::Regex> = OnceLock::new();
+    static UNIT_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let symbol_re = SYMBOL_RE.get_or_init(|| regex::Regex::new(r#"^\s*\(symbol "([^"]*)""#).unwrap());
+    let unit_re = UNIT_RE.get_or_init(|| regex::Regex::new(r"_(\d+)_(\d+)$").unwrap());
+    let property_re = PROPERTY_RE
+        .get_or_init(|| regex::Regex::new(r#"^\s*\(property "([^"]*)" "([^"]*)""#).unwrap());
+    let pin_re = PIN_RE.get_or_init(|| {
+        regex::Regex::new(r"^\s*\(pin (\S+) (\S+) \(at (\S+) (\S+) (\S+)\) \(length (\S+)\)").unwrap()
+    });
+    let name_re =
+        NAME_RE.get_or_init(|| regex::Regex::new(r#"^\s*\((name|number) "([^"]*)""#).unwrap());
+    let rect_re = RECT_RE.get_or_init(|| {
+        regex::Regex::new(r"^\s*\(rectangle \(start (\S+) (\S+)\) \(end (\S+) (\S+)\)").unwrap()
+    });
+    let circle_re = CIRCLE_RE.get_or_init(|| {
+        regex::Regex::new(r"^\s*\(circle \(center (\S+) (\S+)\) \(radius (\S+)\)").unwrap()
+    });
+    let text_re = TEXT_RE
+        .get_or_init(|| regex::Regex::new(r#"^\s*\(text "([^"]*)" \(at (\S+) (\S+) (\S+)\)"#).unwrap());
+    let xy_re = XY_RE.get_or_init(|| regex::Regex::new(r"\(xy (\S+) (\S+)\)").unwrap());
+    let fill_re = FILL_RE.get_or_init(|| regex::Regex::new(r"\(fill \(type (\w+)\)\)").unwrap());
+
+    let legacy_fill = |line: &str| match fill_re.captures(line).map(|c| c[1].to_string()) {
+        Some(f) if f == "outline" => "F",
+        Some(f) if f == "background" => "f",
+        _ => "N",
+    };
+
+    let mut out = String::from("EESchema-LIBRARY Version 2.4\n#encoding utf-8\n");
+    let mut name = String::new();
+    let mut fields: Vec<String> = Vec::new();
+    let mut draw: Vec<String> = Vec::new();
+    // Unit 0 draws in every unit; sub-symbols NAME_<unit>_<style> set both.
+    let mut unit = 0;
+    let mut unit_count = 1;
+    let mut convert = 1;
+    let mut pending_pin: Option<(String, String, String, String, String, String, bool)> = None;
+    let mut pin_name = String::new();
+    let mut power = false;
+
+    let flush = |out: &mut String,
+                 name: &str,
+                 fields: &[String],
+                 draw: &[String],
+                 units: usize,
+                 power: bool| {
+        if name.is_empty() {
+            return;
+        }
+        let reference = fields.first().cloned().unwrap_or_else(|| "U".to_string());
+        out.push_str(&format!("#\n# {}\n#\n", name));
+        out.push_str(&format!(
+            "DEF {} {} 0 40 Y Y {} F {}\n",
+            name,
+            reference,
+            units,
+            if power { "P" } else { "N" }
+        ));
+        let positions = ["0 50", "0 -100", "0 -400", "0 -500"];
+        for (idx, value) in fields.iter().enumerate().take(4) {
+            let value = if idx == 1 && value.is_empty() { name } else { value.as_str() };
+            let visible = if idx < 2 { "V" } else { "I" };
+            out.push_str(&format!(
+                "F{} \"{}\" {} 50 H {} C CNN\n",
+                idx, value, positions[idx], visible
+            ));
+        }
+        out.push_str("DRAW\n");
+        for d in draw {
+            out.push_str(d);
+        }
+        out.push_str("ENDDRAW\nENDDEF\n");
+    };
+
+    for line in content.lines() {
+        if let Some(cap) = symbol_re.captures(line) {
+            let sym = cap[1].to_string();
+            if line.starts_with("  (symbol") {
+                flush(&mut out, &name, &fields, &draw, unit_count, power);
+                name = sym.replace(' ', "_");
+                power = line.contains("(power)");
+                fields = vec![String::new(); 4];
+                draw.clear();
+                unit = 0;
+                unit_count = 1;
+                convert = 1;
+            } else if let Some(cap) = unit_re.captures(&sym) {
+                // Style 2 is the de Morgan body; 0 means shared, as in v5.
+                unit = cap[1].parse().unwrap_or(0);
+                convert = cap[2].parse().unwrap_or(1);
+                unit_count = unit_count.max(unit);
+            }
+        } else if let Some(cap) = property_re.captures(line) {
+            let idx = match &cap[1] {
+                "Reference" => 0,
+                "Value" => 1,
+                "Footprint" => 2,
+                "Datasheet" => 3,
+                _ => continue,
+            };
+            if let Some(f) = fields.get_mut(idx) {
+                *f = cap[2].to_string();
+            }
+        } else if let Some(cap) = pin_re.captures(line) {
+            pending_pin = Some((
+                cap[1].to_string(),
+                cap[2].to_string(),
+                cap[3].to_string(),
+                cap[4].to_string(),
+                cap[5].to_string(),
+                cap[6].to_string(),
+                line.contains(") hide"),
+            ));
+        } else if let Some(cap) = name_re.captures(line) {
+            if &cap[1] == "name" {
+                pin_name = cap[2].to_string();
+            } else if let Some((etype, shape, x, y, rot, length, hidden)) = pending_pin.take() {
+                let orient = match rot.parse::<i64>().unwrap_or(0).rem_euclid(360) {
+                    90 => "U",
+                    180 => "L",
+                    270 => "D",
+                    _ => "R",
+                };
+                let etype = match etype.as_str() {
+                    "input" => "I",
+                    "output" => "O",
+                    "bidirectional" => "B",
+                    "power_in" => "W",
+                    "passive" => "P",
+                    _ => "U",
+                };
+                let shape = match shape.as_str() {
+                    "inverted" => "I",
+                    "clock" => "C",
+                    "inverted_clock" => "IC",
+                    _ => "",
+                };
+                // Invisible pins carry an N in front of the shape letters.
+                let shape = match (hidden, shape) {
+                    (true, shape) => format!(" N{}", shape),
+                    (false, "") => String::new(),
+                    (false, shape) => format!(" {}", shape),
+                };
+                let pin_label = if pin_name.is_empty() { "~" } else { pin_name.as_str() };
+                draw.push(format!(
+                    "X {} {} {} {} {} {} 50 50 {} {} {}{}\n",
+                    pin_label.replace(' ', "_"),
+                    &cap[2],
+                    mm_to_mil(&x),
+                    mm_to_mil(&y),
+                    mm_to_mil(&length),
+                    orient,
+                    unit,
+                    convert,
+                    etype,
+                    shape
+                ));
+                pin_name.clear();
+            }
+        } else if let Some(cap) = rect_re.captures(line) {
+            draw.push(format!(
+                "S {} {} {} {} {} {} 0 {}\n",
+                mm_to_mil(&cap[1]),
+                mm_to_mil(&cap[2]),
+                mm_to_mil(&cap[3]),
+                mm_to_mil(&cap[4]),
+                unit,
+                convert,
+                legacy_fill(line)
+            ));
+        } else if let Some(cap) = circle_re.captures(line) {
+            draw.push(format!(
+                "C {} {} {} {} {} 0 {}\n",
+                mm_to_mil(&cap[1]),
+                mm_to_mil(&cap[2]),
+                mm_to_mil(&cap[3]),
+                unit,
+                convert,
+                legacy_fill(line)
+            ));
+        } else if line.trim_start().starts_with("(polyline") {
+            let pts: Vec<String> = xy_re
+                .captures_iter(line)
+                .map(|c| format!("{} {}", mm_to_mil(&c[1]), mm_to_mil(&c[2])))
+                .collect();
+            if pts.len() >= 2 {
+                draw.push(format!(
+                    "P {} {} {} 0 {} {}\n",
+                    pts.len(),
+                    unit,
+                    convert,
+                    pts.join(" "),
+                    legacy_fill(line)
+                ));
+            }
+        } else if let Some(cap) = text_re.captures(line) {
+            draw.push(format!(
+                "T {} {} {} 50 0 {} {} {} Normal 0 C C\n",
+                cap[4].parse::<i64>().unwrap_or(0),
+                mm_to_mil(&cap[2]),
+                mm_to_mil(&cap[3]),
+                unit,
+                convert,
+                cap[1].replace(' ', "~")
+            ));
+        }
+    }
+    flush(&mut out, &name, &fields, &draw, unit_count, power);
+    out.push_str("#\n#End Library\n");
+    out
+}
+
+struct SymbolUnit {
+    name: String,
+    header: String,
+    body: String,
+    pins: Vec<String>,
+}
+
+fn symbol_unit_graphics(
+    shape: &[String],
+    origin_x: f64,
+    origin_y: f64,
+    metric: bool,
+    options: &ConversionOptions,
+) -> (String, Vec<String>) {
+    let pin_text_size = options.pin_text_size();
+    let (origin_x, origin_y) = symbol_shape_origin(shape, origin_x, origin_y);
+    let mut body = String::new();
+    let mut pins: Vec<String> = Vec::new();
     for line in shape {
         let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
         if parts.is_empty() {
@@ -3290,581 +10736,1986 @@ async fn create_footprint_internal(
         let args: Vec<&str> = parts[1..].to_vec();
 
         match model {
-            "PAD" => {
-                if let Some(pad_str) = parse_pad(&args, &mut footprint_info) {
-                    kicad_mod_content.push_str(&pad_str);
+            "P" => {
+                if let Some(pin_str) =
+                    parse_symbol_pin(&args, origin_x, origin_y, metric, pin_text_size)
+                {
+                    body.push_str(&pin_str);
+                    pins.push(args[2].to_string());
                 }
             }
-            "TRACK" => {
-                if let Some(track_str) = parse_track(&args, &mut footprint_info) {
-                    kicad_mod_content.push_str(&track_str);
+            "R" => {
+                if let Some(rect_str) = parse_symbol_rect(&args, origin_x, origin_y, metric) {
+                    body.push_str(&rect_str);
                 }
             }
-            "CIRCLE" => {
-                if let Some(circle_str) = parse_circle(&args) {
-                    kicad_mod_content.push_str(&circle_str);
+            "E" => {
+                if let Some(circle_str) = parse_symbol_circle(&args, origin_x, origin_y, metric) {
+                    body.push_str(&circle_str);
                 }
             }
-            "ARC" => {
-                if let Some(arc_str) = parse_arc(&args) {
-                    kicad_mod_content.push_str(&arc_str);
+            "T" => {
+                if let Some(text_str) = parse_symbol_text(&args, origin_x, origin_y, metric) {
+                    body.push_str(&text_str);
                 }
             }
-            "RECT" => {
-                if let Some(rect_str) = parse_rect(&args, &mut footprint_info) {
-                    kicad_mod_content.push_str(&rect_str);
+            "PL" | "PG" => {
+                let filled = model == "PG";
+                if let Some(poly_str) = parse_symbol_poly(&args, origin_x, origin_y, metric, filled)
+                {
+                    body.push_str(&poly_str);
                 }
             }
-            "HOLE" => {
-                if let Some(hole_str) = parse_hole(&args) {
-                    kicad_mod_content.push_str(&hole_str);
-                }
+            "A" => {
+                // Arc - simplified
             }
-            "SOLIDREGION" => {
-                if let Some(solid_str) = parse_solid_region(&args) {
-                    kicad_mod_content.push_str(&solid_str);
-                }
+            _ => {}
+        }
+    }
+    pins.sort();
+    if options.sort_pads_and_pins {
+        body = sort_symbol_pins(&body);
+    }
+    (body, pins)
+}
+
+/// Net of a single-pin symbol that is an EasyEDA power or net flag, told
+/// apart by a `#` designator or a power/net-flag part `category`: the pin
+/// name, or the title when the pin has none. `None` for ordinary symbols,
+/// single-pin parts with a power-input pin (e.g. a battery clip) included.
+fn power_symbol_net(
+    prefix: &str,
+    category: Option<&str>,
+    body: &str,
+    pins: &[String],
+    title: &str,
+) -> Option<String> {
+    static NAME_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let flag_category = category.is_some_and(|c| {
+        let c = c.to_lowercase();
+        ["power symbol", "power flag", "power port", "net flag", "netflag", "电源符号", "网络标签"]
+            .iter()
+            .any(|k| c.contains(k))
+    });
+    if pins.len() != 1 || !(prefix.starts_with('#') || flag_category) {
+        return None;
+    }
+    let name_re = NAME_RE.get_or_init(|| regex::Regex::new(r#"\(name "([^"]*)""#).unwrap());
+    let pin_name = name_re
+        .captures(body)
+        .map(|c| c[1].trim().to_string())
+        .filter(|n| !n.is_empty() && n != "~");
+    Some(pin_name.unwrap_or_else(|| title.to_string()))
+}
+
+/// Makes the only pin of a power symbol the hidden power input KiCad
+/// connects the net through.
+fn power_symbol_body(body: &str) -> String {
+    static PIN_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let pin_re = PIN_RE.get_or_init(|| {
+        regex::Regex::new(r"\(pin \S+ (\S+) (\(at [^)]*\)) \(length ([^)]*)\)").unwrap()
+    });
+    pin_re
+        .replace(body, "(pin power_in $1 $2 (length $3) hide")
+        .into_owned()
+}
+
+/// Compares pad/pin numbers so "2" < "10" and "A2" < "A10".
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let chunks = |s: &str| -> Vec<(bool, String)> {
+        let mut out: Vec<(bool, String)> = Vec::new();
+        for c in s.chars() {
+            let digit = c.is_ascii_digit();
+            match out.last_mut() {
+                Some((d, chunk)) if *d == digit => chunk.push(c),
+                _ => out.push((digit, c.to_string())),
             }
-            "TEXT" => {
-                if let Some(text_str) = parse_text(&args) {
-                    kicad_mod_content.push_str(&text_str);
-                }
+        }
+        out
+    };
+    let (a, b) = (chunks(a), chunks(b));
+    for ((a_digit, a), (b_digit, b)) in a.iter().zip(&b) {
+        let ordering = if *a_digit && *b_digit {
+            let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+            a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+        } else {
+            a.cmp(b)
+        };
+        if ordering != std::cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Puts the items `number` picks out back in natural order of their number;
+/// each sorted item takes the slot of an original one, so everything else
+/// stays where it was.
+fn sort_numbered_items(items: Vec<String>, number: impl Fn(&str) -> Option<String>) -> String {
+    let mut numbered: Vec<(String, String)> = items
+        .iter()
+        .filter_map(|item| number(item).map(|n| (n, item.clone())))
+        .collect();
+    numbered.sort_by(|a, b| natural_cmp(&a.0, &b.0).then_with(|| a.1.cmp(&b.1)));
+    let mut sorted = numbered.into_iter().map(|(_, item)| item);
+    items
+        .into_iter()
+        .map(|item| {
+            if number(&item).is_some() {
+                sorted.next().unwrap_or(item)
+            } else {
+                item
             }
-            "SVGNODE" => {
-                if let Ok(json_data) = serde_json::from_str::<serde_json::Value>(args[0]) {
-                    if let Some(uuid) = json_data
-                        .get("attrs")
-                        .and_then(|a| a.get("uuid"))
-                        .and_then(|u| u.as_str())
-                    {
-                        svg_model_uuid = Some(uuid.to_string());
-                    }
-                }
+        })
+        .collect()
+}
+
+/// Filled silkscreen dot just outside pad "1" (or "A1"), on the side facing
+/// away from `center`; `None` when the footprint has no such pad.
+fn pad_one_marker(kicad_mod: &str, center: (f64, f64)) -> Option<String> {
+    const MARKER_RADIUS: f64 = 0.15;
+    const MARKER_GAP: f64 = 0.3;
+    static PAD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let pad_re = PAD_RE.get_or_init(|| {
+        regex::Regex::new(
+            r#"(?m)^  \(pad "([^"]*)" \S+ \S+ \(at (\S+) (\S+)(?: \S+)?\) \(size (\S+) (\S+)\)"#,
+        )
+        .unwrap()
+    });
+    let pads: Vec<(String, f64, f64, f64)> = pad_re
+        .captures_iter(kicad_mod)
+        .filter_map(|c| {
+            let x = c[2].parse().ok()?;
+            let y = c[3].parse().ok()?;
+            let w: f64 = c[4].parse().ok()?;
+            let h: f64 = c[5].parse().ok()?;
+            Some((c[1].to_string(), x, y, w.max(h)))
+        })
+        .collect();
+    let (_, x, y, extent) = ["1", "A1"]
+        .iter()
+        .find_map(|number| pads.iter().find(|(n, ..)| n.eq_ignore_ascii_case(number)))?;
+    let (dx, dy) = (x - center.0, y - center.1);
+    let offset = extent / 2.0 + MARKER_GAP + MARKER_RADIUS;
+    let (mx, my) = if dx.abs() < 1e-6 && dy.abs() < 1e-6 {
+        (x - offset, *y)
+    } else if dx.abs() >= dy.abs() {
+        (x + offset * dx.signum(), *y)
+    } else {
+        (*x, y + offset * dy.signum())
+    };
+    Some(format!(
+        "  (fp_circle (center {} {}) (end {} {}) (layer F.SilkS) (width {}) (fill solid))\n",
+        mx,
+        my,
+        mx + MARKER_RADIUS,
+        my,
+        MARKER_RADIUS
+    ))
+}
+
+fn sort_footprint_pads(content: &str) -> String {
+    static PAD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let pad_re = PAD_RE.get_or_init(|| regex::Regex::new(r#"^  \(pad "([^"]*)""#).unwrap());
+    let lines = content.split_inclusive('\n').map(str::to_string).collect();
+    sort_numbered_items(lines, |line| pad_re.captures(line).map(|c| c[1].to_string()))
+}
+
+fn sort_symbol_pins(body: &str) -> String {
+    static NUMBER_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let number_re = NUMBER_RE.get_or_init(|| regex::Regex::new(r#"\(number "([^"]*)""#).unwrap());
+    // A pin spans from its "(pin" line to the closing ")" at the same indent.
+    let mut items: Vec<String> = Vec::new();
+    let mut in_pin = false;
+    for line in body.split_inclusive('\n') {
+        if in_pin {
+            if let Some(pin) = items.last_mut() {
+                pin.push_str(line);
             }
-            _ => {}
+            in_pin = line.trim_end() != "    )";
+        } else {
+            in_pin = line.starts_with("    (pin ");
+            items.push(line.to_string());
         }
     }
+    sort_numbered_items(items, |item| {
+        item.starts_with("    (pin ")
+            .then(|| number_re.captures(item).map(|c| c[1].to_string()))
+            .flatten()
+    })
+}
 
-    if models.contains(&"STEP".to_string()) {
-        let step_dir = PathBuf::from(output_dir).join(footprint_lib).join(model_dir);
-        fs::create_dir_all(&step_dir)?;
-        let step_path = step_dir.join(format!("{}.step", footprint_name));
+/// Reference and Value positions just above and below everything drawn in a
+/// unit, so the labels never land inside a large body. Empty units keep the
+/// old fixed spots.
+fn symbol_label_positions(body: &str) -> ((f64, f64), (f64, f64)) {
+    static COORD_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let coord_re = COORD_RE.get_or_init(|| {
+        regex::Regex::new(r"\((?:at|start|mid|end|center|xy) (-?[\d.]+) (-?[\d.]+)").unwrap()
+    });
+
+    let mut bounds: Option<(f64, f64, f64, f64)> = None;
+    for cap in coord_re.captures_iter(body) {
+        let (Ok(x), Ok(y)) = (cap[1].parse::<f64>(), cap[2].parse::<f64>()) else {
+            continue;
+        };
+        bounds = Some(match bounds {
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
 
-        let mut model_candidates: Vec<String> = Vec::new();
-        if let Ok(Some(uuid)) = client.resolve_step_uuid_via_pro_api(component_id).await {
-            model_candidates.push(uuid);
+    match bounds {
+        Some((min_x, min_y, max_x, max_y)) => {
+            let center_x = (min_x + max_x) / 2.0;
+            ((center_x, max_y + 1.27), (center_x, min_y - 1.27))
         }
-        if let Some(uuid) = svg_model_uuid {
-            model_candidates.push(uuid);
+        None => ((0.0, 1.27), (0.0, -2.54)),
+    }
+}
+
+/// The origin to subtract from a symbol's shapes. Most symbols store absolute
+/// canvas coordinates around `head.x/y`, but some are already relative to it;
+/// whichever of the head origin and (0, 0) lies closer to the pins and body
+/// rectangles is taken as their reference.
+fn symbol_shape_origin(shape: &[String], origin_x: f64, origin_y: f64) -> (f64, f64) {
+    if origin_x == 0.0 && origin_y == 0.0 {
+        return (origin_x, origin_y);
+    }
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for line in shape {
+        let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
+        let xy = match parts.first() {
+            Some(&"P") => (parts.get(4), parts.get(5)),
+            Some(&"R") | Some(&"E") => (parts.get(1), parts.get(2)),
+            _ => continue,
+        };
+        if let (Some(Ok(x)), Some(Ok(y))) = (xy.0.map(|v| v.parse()), xy.1.map(|v| v.parse())) {
+            points.push((x, y));
         }
-        model_candidates.push(footprint_uuid.to_string());
-        model_candidates.dedup();
+    }
+    if points.is_empty() {
+        return (origin_x, origin_y);
+    }
 
-        for uuid in model_candidates {
-            match client.download_step_model(&uuid, step_path.to_str().unwrap()).await {
-                Ok(_) => {
-                    step_model_downloaded = true;
-                    let path_name = format!("{}/{}.step", model_dir, footprint_name);
-                    kicad_mod_content.push_str(&format!(
-                        "  (model {} (at (xyz 0 0 0)) (rotate (xyz 0 0 0)))\n",
-                        path_name
-                    ));
-                    break;
-                }
-                Err(e) => {
-                    step_model_error = Some(format!(
-                        "3D 模型下载失败（模型UUID: {}）: {}",
-                        uuid, e
-                    ));
-                }
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let to_origin = (cx - origin_x).hypot(cy - origin_y);
+    let to_zero = cx.hypot(cy);
+    if to_zero < to_origin {
+        (0.0, 0.0)
+    } else {
+        (origin_x, origin_y)
+    }
+}
+
+/// Pin numbers of a unit body with where each pin sits, sorted by number.
+fn pin_layout(body: &str) -> Vec<(String, String)> {
+    static PIN_RE: OnceLock<regex::Regex> = OnceLock::new();
+    let pin_re = PIN_RE.get_or_init(|| {
+        regex::Regex::new(r#"\(pin \S+ \S+ \(at ([^)]*)\)(?s:.*?)\(number "([^"]*)""#).unwrap()
+    });
+    let mut layout: Vec<(String, String)> = pin_re
+        .captures_iter(body)
+        .map(|c| (c[2].to_string(), c[1].to_string()))
+        .collect();
+    layout.sort();
+    layout
+}
+
+fn demorgan_alternates(units: &[SymbolUnit]) -> Vec<Option<usize>> {
+    // EasyEDA has no body-style flag. A unit repeating another unit's pins,
+    // same numbers at the same spots, cannot be a separate gate, so it is that
+    // gate's alternate body.
+    let layouts: Vec<Vec<(String, String)>> = units.iter().map(|u| pin_layout(&u.body)).collect();
+    let mut alternate_of: Vec<Option<usize>> = vec![None; units.len()];
+    let mut paired = vec![false; units.len()];
+    for j in 0..units.len() {
+        if units[j].pins.is_empty() {
+            continue;
+        }
+        if let Some(i) = (0..j).find(|&i| {
+            alternate_of[i].is_none()
+                && !paired[i]
+                && units[i].pins == units[j].pins
+                && layouts[i] == layouts[j]
+        }) {
+            alternate_of[j] = Some(i);
+            paired[i] = true;
+        }
+    }
+    alternate_of
+}
+
+fn emit_symbol_units(units: &[SymbolUnit], demorgan: bool) -> String {
+    let alternate_of = if demorgan {
+        demorgan_alternates(units)
+    } else {
+        vec![None; units.len()]
+    };
+
+    let mut out = String::new();
+    for (i, unit) in units.iter().enumerate() {
+        if alternate_of[i].is_some() {
+            continue;
+        }
+        out.push_str(&unit.header);
+        match alternate_of.iter().position(|a| *a == Some(i)) {
+            Some(j) => {
+                out.push_str(&format!("    (symbol \"{}_1_1\"\n{}    )\n", unit.name, unit.body));
+                out.push_str(&format!(
+                    "    (symbol \"{}_1_2\"\n{}    )\n",
+                    unit.name, units[j].body
+                ));
             }
+            None => out.push_str(&unit.body),
         }
+        out.push_str("  )\n");
     }
+    out
+}
 
-    // Add reference, value text
-    let center_x = (footprint_info.min_x + footprint_info.max_x) / 2.0;
-    let center_y = (footprint_info.min_y + footprint_info.max_y) / 2.0;
+fn parse_symbol_pin(
+    args: &[&str],
+    origin_x: f64,
+    origin_y: f64,
+    metric: bool,
+    text_size: f64,
+) -> Option<String> {
+    if args.len() < 14 {
+        return None;
+    }
 
-    kicad_mod_content.push_str(&format!(
-        "  (fp_text reference REF** (at {} {}) (layer F.SilkS)\n    (effects (font (size 1 1)))\n  )\n",
-        center_x, footprint_info.min_y - 2.0
-    ));
-    kicad_mod_content.push_str(&format!(
-        "  (fp_text value {} (at {} {}) (layer F.Fab)\n    (effects (font (size 1 1)))\n  )\n",
-        footprint_name, center_x, footprint_info.max_y + 2.0
-    ));
-    kicad_mod_content.push_str(&format!(
-        "  (fp_text user ${{REFERENCE}} (at {} {}) (layer F.Fab)\n    (effects (font (size 0.5 0.5)))\n  )\n",
-        center_x, center_y
-    ));
+    let electrical_type = match args[1] {
+        "0" => "unspecified",
+        "1" => "input",
+        "2" => "output",
+        "3" => "bidirectional",
+        "4" => "power_in",
+        _ => "unspecified",
+    };
 
-    // Close footprint and root node
-    kicad_mod_content.push_str("  )\n");
-    kicad_mod_content.push_str(")\n");
+    let pin_num = args[2];
+    let x = to_mm(args[3].parse::<f64>().unwrap_or(0.0) - origin_x, metric);
+    let y = -to_mm(args[4].parse::<f64>().unwrap_or(0.0) - origin_y, metric);
+    let rotation: i32 = args.get(5).and_then(|s| s.parse().ok()).unwrap_or(0);
+    let rotation = (rotation + 180) % 360;
+    let pin_name = args.get(13).unwrap_or(&"");
 
-    // Write to file
-    let output_path = PathBuf::from(output_dir).join(footprint_lib);
-    fs::create_dir_all(&output_path)?;
-    let file_path = output_path.join(format!("{}.kicad_mod", footprint_name));
-    let mut file = File::create(file_path)?;
-    file.write_all(kicad_mod_content.as_bytes())?;
+    // Segments after the pin settings: dot position, path, name, number,
+    // inverted dot (show~x~y), clock (show~path).
+    let joined = args.join("~");
+    let segments: Vec<&str> = joined.split("^^").collect();
+    let shown = |idx: usize| {
+        segments
+            .get(idx)
+            .and_then(|seg| seg.split('~').next())
+            .map(|flag| flag == "1" || flag == "show")
+            .unwrap_or(false)
+    };
+    let pin_shape = match (shown(5), shown(6)) {
+        (true, true) => "inverted_clock",
+        (true, false) => "inverted",
+        (false, true) => "clock",
+        (false, false) => "line",
+    };
 
-    Ok((footprint_name, datasheet_link, step_model_downloaded, step_model_error))
+    let length = 2.54;
+
+    Some(format!(
+        "    (pin {} {} (at {} {} {}) (length {})\n      (name \"{}\" (effects (font (size {} {}))))\n      (number \"{}\" (effects (font (size {} {}))))\n    )\n",
+        electrical_type,
+        pin_shape,
+        x,
+        y,
+        rotation,
+        length,
+        pin_name,
+        text_size,
+        text_size,
+        pin_num,
+        text_size,
+        text_size
+    ))
 }
 
-fn layer_map(layer_id: &str) -> &'static str {
-    match layer_id {
-        "1" => "F.Cu",
-        "2" => "B.Cu",
-        "3" => "F.SilkS",
-        "4" => "B.SilkS",
-        "5" => "F.Paste",
-        "6" => "B.Paste",
-        "7" => "F.Mask",
-        "8" => "B.Mask",
-        "10" => "Edge.Cuts",
-        "11" => "F.Fab",
-        "12" => "F.Fab",
-        "99" => "F.Fab",
-        "100" => "F.Fab",
-        "101" => "F.Fab",
-        _ => "F.SilkS",
+fn symbol_fill_type(fill_color: Option<&str>, stroke_color: Option<&str>) -> &'static str {
+    // EasyEDA stores a fill colour: "none" is outline-only, the stroke colour
+    // means a solid shape, anything else is a body background.
+    let Some(fill) = fill_color
+        .map(|f| f.trim())
+        .filter(|f| f.starts_with('#') || f.eq_ignore_ascii_case("none"))
+    else {
+        // Older/derived records carry no fill field; keep the historical default.
+        return "background";
+    };
+    if fill.eq_ignore_ascii_case("none") {
+        "none"
+    } else if stroke_color
+        .map(|s| s.trim().eq_ignore_ascii_case(fill))
+        .unwrap_or(false)
+    {
+        "outline"
+    } else {
+        "background"
     }
 }
 
-fn parse_pad(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
-    // args: [shape, x, y, size_x, size_y, layer, ..., pad_num, drill, ..., rotation, ...]
-    if args.len() < 9 {
+fn parse_symbol_rect(args: &[&str], origin_x: f64, origin_y: f64, metric: bool) -> Option<String> {
+    if args.len() < 6 {
         return None;
     }
 
-    let shape = args[0];
-    let x = mil2mm(args[1].parse().unwrap_or(0.0));
-    let y = mil2mm(args[2].parse().unwrap_or(0.0));
-    let size_x = mil2mm(args[3].parse().unwrap_or(1.0));
-    let size_y = mil2mm(args[4].parse().unwrap_or(1.0));
-    let layer = args[5];
-    let pad_num = args[7];
-    let drill_diameter = mil2mm(args[8].parse::<f64>().unwrap_or(0.0)) * 2.0;
-    let rotation: f64 = args.get(10).and_then(|s| s.parse().ok()).unwrap_or(0.0);
+    let x1 = to_mm(args[0].parse::<f64>().unwrap_or(0.0) - origin_x, metric);
+    let y1 = -to_mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_y, metric);
+    let width = to_mm(args[4].parse::<f64>().unwrap_or(0.0), metric);
+    let length = to_mm(args[5].parse::<f64>().unwrap_or(0.0), metric);
+    let x2 = x1 + width;
+    let y2 = y1 - length;
+    // args: [x, y, rx, ry, width, height, stroke_color, stroke_width, stroke_style, fill_color, ...]
+    let fill = symbol_fill_type(args.get(9).copied(), args.get(6).copied());
 
-    // Update footprint bounds
-    info.max_x = info.max_x.max(x);
-    info.min_x = info.min_x.min(x);
-    info.max_y = info.max_y.max(y);
-    info.min_y = info.min_y.min(y);
+    Some(format!(
+        "    (rectangle (start {} {}) (end {} {}) (stroke (width 0) (type default)) (fill (type {})))\n",
+        x1, y1, x2, y2, fill
+    ))
+}
 
-    let pad_type = if layer == "11" {
-        "thru_hole"
-    } else {
-        "smd"
-    };
+fn parse_symbol_circle(args: &[&str], origin_x: f64, origin_y: f64, metric: bool) -> Option<String> {
+    if args.len() < 3 {
+        return None;
+    }
 
-    let ki_shape = match shape {
-        "OVAL" => "oval",
-        "RECT" => "rect",
-        "ELLIPSE" => "circle",
-        "CIRCLE" => "circle",
-        _ => "oval",
-    };
+    let x = to_mm(args[0].parse::<f64>().unwrap_or(0.0) - origin_x, metric);
+    let y = -to_mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_y, metric);
+    let r = to_mm(args[2].parse::<f64>().unwrap_or(0.0), metric);
+    // args: [cx, cy, rx, ry, stroke_color, stroke_width, stroke_style, fill_color, ...]
+    let fill = symbol_fill_type(args.get(7).copied(), args.get(4).copied());
 
-    let layers = if layer == "11" {
-        "*.Cu *.Mask"
-    } else if layer == "1" {
-        "F.Cu F.Paste F.Mask"
-    } else {
-        "B.Cu B.Paste B.Mask"
-    };
+    Some(format!(
+        "    (circle (center {} {}) (radius {}) (stroke (width 0) (type default)) (fill (type {})))\n",
+        x, y, r, fill
+    ))
+}
 
-    let drill = if pad_type == "thru_hole" && drill_diameter > 0.0 {
-        format!(" (drill {})", drill_diameter)
-    } else {
-        String::new()
-    };
+fn parse_symbol_text(args: &[&str], origin_x: f64, origin_y: f64, metric: bool) -> Option<String> {
+    if args.len() < 12 {
+        return None;
+    }
+
+    let x = to_mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_x, metric);
+    let y = -to_mm(args[2].parse::<f64>().unwrap_or(0.0) - origin_y, metric);
+    let rotation: f64 = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(0.0);
+    // Symbol text angles are in tenths of a degree.
+    let rotation = readable_text_rotation(rotation) * 10.0;
+    let text = args.get(11).unwrap_or(&"");
 
     Some(format!(
-        "  (pad {} {} {} (at {} {} {}) (size {} {}){} (layers {}))\n",
-        pad_num, pad_type, ki_shape, x, y, rotation, size_x, size_y, drill, layers
+        "    (text \"{}\" (at {} {} {}) (effects (font (size 1.27 1.27))))\n",
+        text, x, y, rotation
     ))
 }
 
-fn parse_track(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
-    if args.len() < 4 {
+/// Center of the arc sweeping `sweep_deg` from `start` to `end`; `None` for a
+/// degenerate arc.
+fn arc_center(start: (f64, f64), end: (f64, f64), sweep_deg: f64) -> Option<(f64, f64)> {
+    let theta = sweep_deg.to_radians();
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let d = (dx * dx + dy * dy).sqrt();
+    if theta.abs() < 1e-9 || d < 1e-9 {
         return None;
     }
 
-    let width = mil2mm(args[0].parse().unwrap_or(0.2));
-    let layer = layer_map(args[1]);
-    let points_str = args[3];
-    let points: Vec<f64> = points_str
-        .split(' ')
-        .filter(|s| !s.is_empty())
-        .filter_map(|s| s.parse().ok())
-        .map(|v| mil2mm(v))
+    // Center sits on the chord's normal, offset by (d/2) / tan(theta/2).
+    let h = (d / 2.0) / (theta / 2.0).tan();
+    Some((
+        (start.0 + end.0) / 2.0 - dy / d * h,
+        (start.1 + end.1) / 2.0 + dx / d * h,
+    ))
+}
+
+fn rotate_about(point: (f64, f64), center: (f64, f64), angle: f64) -> (f64, f64) {
+    let (sin, cos) = angle.sin_cos();
+    let (x, y) = (point.0 - center.0, point.1 - center.1);
+    (center.0 + x * cos - y * sin, center.1 + x * sin + y * cos)
+}
+
+fn arc_points(start: (f64, f64), end: (f64, f64), sweep_deg: f64) -> Vec<(f64, f64)> {
+    let theta = sweep_deg.to_radians();
+    let Some((cx, cy)) = arc_center(start, end, sweep_deg) else {
+        return vec![end];
+    };
+    let radius = ((start.0 - cx).powi(2) + (start.1 - cy).powi(2)).sqrt();
+    let a0 = (start.1 - cy).atan2(start.0 - cx);
+    let steps = ((theta.abs() / (std::f64::consts::PI / 18.0)).ceil() as usize).max(1);
+
+    let mut pts: Vec<(f64, f64)> = (1..steps)
+        .map(|i| {
+            let a = a0 + theta * i as f64 / steps as f64;
+            (cx + radius * a.cos(), cy + radius * a.sin())
+        })
+        .collect();
+    pts.push(end);
+    pts
+}
+
+/// Flatten an EasyEDA polyline path into point runs. Plain paths are number
+/// pairs; Pro-derived ones embed `M`/`L`/`ARC`/`CARC`/`C`/`CIRCLE` tokens.
+fn flatten_symbol_path(path: &str) -> Vec<Vec<(f64, f64)>> {
+    let tokens: Vec<&str> = path
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
         .collect();
+    let num = |i: usize| tokens.get(i).and_then(|t| t.parse::<f64>().ok());
+
+    let mut runs: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut current: Vec<(f64, f64)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            "L" => i += 1,
+            "M" => {
+                if current.len() >= 2 {
+                    runs.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                i += 1;
+            }
+            "ARC" | "CARC" => match (num(i + 1), num(i + 2), num(i + 3), current.last()) {
+                (Some(angle), Some(x), Some(y), Some(&start)) => {
+                    current.extend(arc_points(start, (x, y), angle));
+                    i += 4;
+                }
+                _ => break,
+            },
+            "C" => match (
+                num(i + 1),
+                num(i + 2),
+                num(i + 3),
+                num(i + 4),
+                num(i + 5),
+                num(i + 6),
+                current.last(),
+            ) {
+                (Some(x1), Some(y1), Some(x2), Some(y2), Some(x), Some(y), Some(&p0)) => {
+                    for step in 1..=8 {
+                        let t = step as f64 / 8.0;
+                        let u = 1.0 - t;
+                        current.push((
+                            u * u * u * p0.0 + 3.0 * u * u * t * x1 + 3.0 * u * t * t * x2 + t * t * t * x,
+                            u * u * u * p0.1 + 3.0 * u * u * t * y1 + 3.0 * u * t * t * y2 + t * t * t * y,
+                        ));
+                    }
+                    i += 7;
+                }
+                _ => break,
+            },
+            "CIRCLE" => match (num(i + 1), num(i + 2), num(i + 3)) {
+                (Some(cx), Some(cy), Some(r)) => {
+                    if current.len() >= 2 {
+                        runs.push(std::mem::take(&mut current));
+                    }
+                    let start = (cx + r.abs(), cy);
+                    let mut circle = vec![start];
+                    circle.extend(arc_points(start, (cx - r.abs(), cy), 180.0));
+                    circle.extend(arc_points((cx - r.abs(), cy), start, 180.0));
+                    runs.push(circle);
+                    current.clear();
+                    i += 4;
+                }
+                _ => break,
+            },
+            _ => match (num(i), num(i + 1)) {
+                (Some(x), Some(y)) => {
+                    current.push((x, y));
+                    i += 2;
+                }
+                // Unknown command or dangling coordinate: keep what parsed so far.
+                _ => break,
+            },
+        }
+    }
+    if current.len() >= 2 {
+        runs.push(current);
+    }
+    runs
+}
 
-    if points.len() < 4 {
+/// PL is an open polyline; PG is a closed polygon filled with the stroke
+/// colour, as the solid triangle of a diode.
+fn parse_symbol_poly(
+    args: &[&str],
+    origin_x: f64,
+    origin_y: f64,
+    metric: bool,
+    filled: bool,
+) -> Option<String> {
+    if args.is_empty() {
         return None;
     }
 
+    let fill = if filled { "outline" } else { "none" };
     let mut result = String::new();
-    for i in (0..points.len() - 2).step_by(2) {
-        let x1 = points[i];
-        let y1 = points[i + 1];
-        let x2 = points[i + 2];
-        let y2 = points[i + 3];
-
-        // Update bounds
-        info.max_x = info.max_x.max(x1).max(x2);
-        info.min_x = info.min_x.min(x1).min(x2);
-        info.max_y = info.max_y.max(y1).max(y2);
-        info.min_y = info.min_y.min(y1).min(y2);
-
+    for mut run in flatten_symbol_path(args[0]) {
+        if filled && run.first() != run.last() {
+            run.push(run[0]);
+        }
+        let mut pts_str = String::new();
+        for (px, py) in run {
+            let x = to_mm(px - origin_x, metric);
+            let y = -to_mm(py - origin_y, metric);
+            pts_str.push_str(&format!("(xy {} {}) ", x, y));
+        }
         result.push_str(&format!(
-            "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
-            x1, y1, x2, y2, layer, width
+            "    (polyline (pts {}) (stroke (width 0) (type default)) (fill (type {})))\n",
+            pts_str, fill
         ));
     }
 
-    Some(result)
+    if result.is_empty() {
+        None
+    } else {
+        Some(result)
+    }
 }
 
-fn parse_circle(args: &[&str]) -> Option<String> {
-    if args.len() < 4 {
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol_unit(name: &str, pins: &[(&str, f64, f64)]) -> SymbolUnit {
+        let body = pins
+            .iter()
+            .map(|(number, x, y)| {
+                format!(
+                    "    (pin input line (at {} {} 0) (length 2.54)\n      (name \"~\" (effects (font (size 1 1))))\n      (number \"{}\" (effects (font (size 1 1))))\n    )\n",
+                    x, y, number
+                )
+            })
+            .collect();
+        SymbolUnit {
+            name: name.to_string(),
+            header: String::new(),
+            body,
+            pins: pins.iter().map(|(number, ..)| number.to_string()).collect(),
+        }
     }
 
-    let cx = mil2mm(args[0].parse().unwrap_or(0.0));
-    let cy = mil2mm(args[1].parse().unwrap_or(0.0));
-    let r = mil2mm(args[2].parse().unwrap_or(0.0));
-    let width = mil2mm(args[3].parse().unwrap_or(0.2));
-    let layer = layer_map(args.get(4).unwrap_or(&"3"));
+    #[test]
+    fn response_cache_drops_the_oldest_entries_past_its_cap() {
+        let mut cache = BoundedCache::default();
+        for n in 0..MAX_CACHED_RESPONSES + 10 {
+            cache.insert(format!("C{}", n), n);
+        }
+        assert_eq!(cache.len(), MAX_CACHED_RESPONSES);
+        assert!(cache.get("C0").is_none());
+        assert_eq!(
+            cache.get(&format!("C{}", MAX_CACHED_RESPONSES + 9)),
+            Some(&(MAX_CACHED_RESPONSES + 9))
+        );
+    }
 
-    // Skip circles on pad layer
-    if args.get(4).map(|s| *s == "100").unwrap_or(false) {
-        return None;
+    #[test]
+    fn schematic_import_skips_cached_library_symbols() {
+        let schematic = r#"(kicad_sch (version 20231120)
+  (lib_symbols
+    (symbol "Lib:R_C25744" (property "LCSC" "C25744" (at 0 0 0)))
+  )
+  (symbol
+    (lib_id "Lib:R_C25804") (at 10 10 0)
+    (property "LCSC" "C25804" (at 0 0 0))
+  )
+)"#;
+        let mut parts = SchematicParts::default();
+        collect_schematic_parts(
+            Path::new("top.kicad_sch"),
+            schematic,
+            &mut parts,
+            &mut HashSet::new(),
+            &mut HashSet::new(),
+        );
+        assert_eq!(parts.ids, vec!["C25804".to_string()]);
     }
 
-    Some(format!(
-        "  (fp_circle (center {} {}) (end {} {}) (layer {}) (width {}))\n",
-        cx, cy, cx + r, cy, layer, width
-    ))
-}
+    #[test]
+    fn legacy_lib_keeps_units_of_multi_unit_symbols() {
+        let sym = r#"(kicad_symbol_lib (version 20211014) (generator JLC2KiCad)
+  (symbol "DUAL" (in_bom yes) (on_board yes)
+    (property "Reference" "U" (id 0) (at 0 0 0)
+      (effects (font (size 1.27 1.27)))
+    )
+    (symbol "DUAL_1_1"
+    (rectangle (start -2.54 2.54) (end 2.54 -2.54) (stroke (width 0) (type default)) (fill (type background)))
+    (pin input line (at -5.08 0 0) (length 2.54)
+      (name "A" (effects (font (size 1 1))))
+      (number "1" (effects (font (size 1 1))))
+    )
+    )
+    (symbol "DUAL_2_1"
+    (pin input line (at -5.08 0 0) (length 2.54)
+      (name "B" (effects (font (size 1 1))))
+      (number "2" (effects (font (size 1 1))))
+    )
+    )
+  )
+)
+"#;
+        let lib = kicad_sym_to_legacy_lib(sym);
+        assert!(lib.contains("DEF DUAL U 0 40 Y Y 2 F N\n"), "{}", lib);
+        assert!(lib.contains("S -100 100 100 -100 1 1 0 f\n"), "{}", lib);
+        assert!(lib.contains("X A 1 -200 0 100 R 50 50 1 1 I\n"), "{}", lib);
+        assert!(lib.contains("X B 2 -200 0 100 R 50 50 2 1 I\n"), "{}", lib);
+    }
+
+    #[test]
+    fn journal_rollback_restores_and_removes_written_files() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("existing.kicad_sym");
+        let created = dir.join("created.kicad_mod");
+        fs::write(&existing, "old").unwrap();
+        let journal = WriteJournal::default();
+        let options = ConversionOptions {
+            journal: Some(journal.clone()),
+            ..Default::default()
+        };
 
-fn parse_arc(args: &[&str]) -> Option<String> {
-    if args.len() < 4 {
-        return None;
+        write_output_file(&existing, b"new", &options).unwrap();
+        write_output_file(&created, b"footprint", &options).unwrap();
+        // The failure after the footprint write undoes both files.
+        journal.rollback();
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old");
+        assert!(!created.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rounding_leaves_numbers_in_strings_alone() {
+        let text = "(property \"Value\" \"4.700000 k 0.125W\") (at 1.2700000000000002 -0.30000000000000004 90)\n";
+        assert_eq!(
+            round_coordinates(text, 4),
+            "(property \"Value\" \"4.700000 k 0.125W\") (at 1.27 -0.3 90)\n"
+        );
     }
 
-    let _layer = layer_map(args.get(1).unwrap_or(&"3"));
-    let _width = mil2mm(args[0].parse().unwrap_or(0.2));
+    #[test]
+    fn offline_symbols_name_their_footprint_with_the_part_prefix() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-offline-fp-{}", std::process::id()));
+        let data_str = |manufacturer: &str| {
+            serde_json::json!({
+                "head": { "x": 0, "y": 0, "c_para": { "pre": "U?", "Manufacturer": manufacturer } },
+                "shape": ["R~0~0~~~10~10~#000~1~0~none~gge1~0"],
+            })
+            .to_string()
+        };
+        let device = OfflineDevice {
+            id: "C1".to_string(),
+            name: "LM358".to_string(),
+            footprint_uuid: Some("fp".to_string()),
+            footprint_uuids: vec!["fp".to_string()],
+            symbol_uuids: vec!["sym".to_string()],
+            model_title: None,
+        };
+        let bundle = OfflineBundle {
+            symbol_data: BTreeMap::from([("sym".to_string(), data_str("TI"))]),
+            footprint_data: BTreeMap::from([("fp".to_string(), data_str("Other"))]),
+            symbol_titles: BTreeMap::from([("sym".to_string(), "LM358".to_string())]),
+            footprint_titles: BTreeMap::from([("fp".to_string(), "SOIC-8".to_string())]),
+            ..Default::default()
+        };
+        let options = ConversionOptions {
+            prefix_manufacturer: true,
+            ..Default::default()
+        };
 
-    Some(String::new())
-}
+        create_symbols_from_offline(
+            &[device],
+            &BTreeMap::new(),
+            &bundle,
+            &LocalConvertOptions {
+                output_dir: dir.to_string_lossy().into_owned(),
+                footprint_lib: "footprint.pretty".to_string(),
+                symbol_lib: "lib".to_string(),
+                symbol_path: "symbol".to_string(),
+                conversion: options,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lib = fs::read_to_string(dir.join("symbol").join("lib.kicad_sym")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(lib.contains("(symbol \"TI_LM358_C1\""), "{}", lib);
+        assert!(
+            lib.contains("(property \"Footprint\" \"footprint:TI_SOIC-8\""),
+            "{}",
+            lib
+        );
+    }
 
-fn parse_rect(args: &[&str], info: &mut FootprintInfo) -> Option<String> {
-    if args.len() < 8 {
-        return None;
+    #[test]
+    fn both_model_formats_reference_only_the_step_file() {
+        let options = ConversionOptions {
+            preferred_3d_format: ModelFormat::Both,
+            ..Default::default()
+        };
+        let draft = FootprintDraft {
+            name: "SOIC-8".to_string(),
+            library: "footprint".to_string(),
+            datasheet_link: String::new(),
+            info: FootprintInfo::default(),
+            body: String::new(),
+            model_candidates: Vec::new(),
+        };
+        let models = vec![
+            ("SOIC-8.step".to_string(), Vec::new()),
+            ("SOIC-8.wrl".to_string(), Vec::new()),
+        ];
+
+        let footprint = finish_footprint(&draft, &models, &options);
+        assert_eq!(footprint.matches("(model ").count(), 1, "{}", footprint);
+        assert!(footprint.contains("SOIC-8.step"));
+        assert_eq!(options.referenced_models("SOIC-8").len(), 1);
+    }
+
+    #[test]
+    fn bottom_designator_goes_to_the_back_silkscreen_mirrored() {
+        let mut info = FootprintInfo::default();
+        let fields = ["P", "10", "20", "0.6", "0", "", "4", "", "6", "U1", "", ""];
+        assert_eq!(parse_text(&fields, &mut info), None);
+
+        let texts = reference_value_texts(&info, "SOIC-8");
+        let reference = texts.lines().next().unwrap();
+        assert!(reference.contains("(layer B.SilkS)"), "{}", texts);
+        assert!(texts.contains("(justify mirror)"), "{}", texts);
+        assert!(
+            texts.contains("(fp_text value SOIC-8") && texts.contains("(layer F.Fab)"),
+            "{}",
+            texts
+        );
     }
 
-    let x1 = mil2mm(args[0].parse().unwrap_or(0.0));
-    let y1 = mil2mm(args[1].parse().unwrap_or(0.0));
-    let dx = mil2mm(args[2].parse().unwrap_or(0.0));
-    let dy = mil2mm(args[3].parse().unwrap_or(0.0));
-    let x2 = x1 + dx;
-    let y2 = y1 + dy;
-    let layer = layer_map(args.get(4).unwrap_or(&"3"));
-    let width = mil2mm(args.get(7).unwrap_or(&"0").parse().unwrap_or(0.2));
+    #[test]
+    fn component_ids_in_urls_and_sentences_are_found() {
+        let mut ids = HashSet::new();
+        extract_component_ids_from_text(
+            "https://jlcpcb.com/partdetail/product-detail/C19702.html, use C25804. \
+             C0G C0402 50V-C104 C1206.X7R",
+            &mut ids,
+        );
+        let mut ids: Vec<_> = ids.into_iter().collect();
+        ids.sort();
+        assert_eq!(ids, ["C19702", "C25804"]);
+    }
+
+    #[test]
+    fn designators_are_not_reported_as_skipped_codes() {
+        let mut skipped = BTreeSet::new();
+        implausible_component_ids("C1 C12 C0402 C1234567890 C2040", &mut skipped);
+        assert_eq!(
+            skipped.into_iter().collect::<Vec<_>>(),
+            ["C0402", "C1234567890"]
+        );
+    }
 
-    info.max_x = info.max_x.max(x1).max(x2);
-    info.min_x = info.min_x.min(x1).min(x2);
-    info.max_y = info.max_y.max(y1).max(y2);
-    info.min_y = info.min_y.min(y1).min(y2);
+    #[test]
+    fn chamfered_corners_turn_with_the_pad() {
+        // Top-left on the board is the unrotated pad's top-right after a
+        // quarter turn, and its bottom-left after three.
+        let shaping = pad_corner_shaping("corner:0:0.25:1", 90.0).unwrap();
+        assert!(shaping.ends_with("(chamfer top_right)"), "{}", shaping);
+        let shaping = pad_corner_shaping("corner:0:0.25:1", 270.0).unwrap();
+        assert!(shaping.ends_with("(chamfer bottom_left)"), "{}", shaping);
+        assert_eq!(unrotate_corner_bits(1 | 2, 180.0), 4 | 8);
+        assert_eq!(unrotate_corner_bits(2, 0.0), 2);
+        assert_eq!(unrotate_corner_bits(2, 45.0), 2);
+    }
+
+    #[test]
+    fn legacy_footprint_matches_golden_file() {
+        let footprint = include_str!("../tests/fixtures/legacy_footprint.kicad_mod");
+        let expected = include_str!("../tests/fixtures/legacy_footprint.v5.kicad_mod");
+        assert_eq!(kicad_mod_to_legacy(footprint), expected);
+    }
+
+    #[test]
+    fn parts_with_failed_footprints_get_no_symbol() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-failed-fp-{}", std::process::id()));
+        let symbol = serde_json::json!({
+            "head": { "x": 0, "y": 0, "c_para": { "pre": "U?" } },
+            "shape": ["R~0~0~~~10~10~#000~1~0~none~gge1~0"],
+        })
+        .to_string();
+        let device = |id: &str, footprint: &str| OfflineDevice {
+            id: id.to_string(),
+            name: id.to_string(),
+            footprint_uuid: Some(footprint.to_string()),
+            footprint_uuids: vec![footprint.to_string()],
+            symbol_uuids: vec!["sym".to_string()],
+            model_title: None,
+        };
+        let bundle = OfflineBundle {
+            devices: BTreeMap::from([
+                ("C100".to_string(), device("C100", "good")),
+                ("C200".to_string(), device("C200", "broken")),
+            ]),
+            symbol_data: BTreeMap::from([("sym".to_string(), symbol)]),
+            footprint_data: BTreeMap::from([
+                (
+                    "good".to_string(),
+                    r#"{"head":{"x":0,"y":0},"shape":["TRACK~1~3~~0 0 10 0~gge1~0"]}"#.to_string(),
+                ),
+                ("broken".to_string(), "garbage".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let opts = LocalConvertOptions {
+            output_dir: dir.to_string_lossy().into_owned(),
+            footprint_lib: "footprint".to_string(),
+            symbol_lib: "lib".to_string(),
+            symbol_path: "symbol".to_string(),
+            create_footprint: true,
+            create_symbol: true,
+            ..Default::default()
+        };
+        let report = ConversionReport {
+            found: vec!["C100".to_string(), "C200".to_string()],
+            ..Default::default()
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let report = runtime.block_on(convert_from_bundle(
+            &bundle,
+            &BTreeMap::new(),
+            "elibz",
+            report,
+            &opts,
+        ));
+        let lib = fs::read_to_string(dir.join("symbol").join("lib.kicad_sym")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
 
-    if width == 0.0 {
-        Some(format!(
-            "  (fp_rect (start {} {}) (end {} {}) (layer {}))\n",
-            x1, y1, x2, y2, layer
-        ))
-    } else {
-        Some(format!(
-            "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
-            x1, y1, x2, y1, layer, width
-        ))
+        assert_eq!(report.converted, ["C100"]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(lib.contains("_C100\""), "{}", lib);
+        assert!(!lib.contains("C200"), "{}", lib);
     }
-}
 
-fn parse_hole(args: &[&str]) -> Option<String> {
-    if args.len() < 3 {
-        return None;
+    #[test]
+    fn drilled_pads_without_copper_or_plating_are_mechanical() {
+        assert_eq!(drilled_pad_type(true, (1.6, 1.6), (0.8, 0.8)), "thru_hole");
+        assert_eq!(
+            drilled_pad_type(false, (1.6, 1.6), (0.8, 0.8)),
+            "np_thru_hole"
+        );
+        assert_eq!(
+            drilled_pad_type(true, (0.8, 0.8), (0.8, 0.8)),
+            "np_thru_hole"
+        );
+        // Copper left on one axis is still a ring, as around a long slot.
+        assert_eq!(drilled_pad_type(true, (1.0, 2.0), (0.6, 2.0)), "thru_hole");
+    }
+
+    #[test]
+    fn shuffled_pads_and_pins_come_out_in_natural_order() {
+        assert_eq!(natural_cmp("2", "10"), std::cmp::Ordering::Less);
+        assert_eq!(natural_cmp("A10", "A2"), std::cmp::Ordering::Greater);
+        assert_eq!(natural_cmp("B1", "A10"), std::cmp::Ordering::Greater);
+
+        let pad = |number: &str| format!("  (pad \"{}\" smd rect (at 0 0) (size 1 1))\n", number);
+        let line = "  (fp_line (start 0 0) (end 1 0))\n";
+        let footprint = format!("{}{}{}{}", pad("10"), line, pad("2"), pad("1"));
+        assert_eq!(
+            sort_footprint_pads(&footprint),
+            format!("{}{}{}{}", pad("1"), line, pad("2"), pad("10"))
+        );
+
+        let pin = |number: &str| {
+            format!(
+                "    (pin passive line (at 0 0 0) (length 2.54)\n      (number \"{}\")\n    )\n",
+                number
+            )
+        };
+        let body = format!("{}    (rectangle)\n{}{}", pin("B2"), pin("A10"), pin("A2"));
+        assert_eq!(
+            sort_symbol_pins(&body),
+            format!("{}    (rectangle)\n{}{}", pin("A2"), pin("A10"), pin("B2"))
+        );
     }
 
-    let x = mil2mm(args[0].parse().unwrap_or(0.0));
-    let y = mil2mm(args[1].parse().unwrap_or(0.0));
-    let r = mil2mm(args[2].parse().unwrap_or(0.0)) * 2.0;
+    #[test]
+    fn symbol_per_part_writes_one_library_per_symbol() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-per-part-{}", std::process::id()));
+        let options = ConversionOptions {
+            symbol_per_part: true,
+            ..Default::default()
+        };
+        let content = format!(
+            "{}  (symbol \"R_10k\"\n    (property \"Reference\" \"R\")\n  )\n  (symbol \"C_100n\"\n    (property \"Reference\" \"C\")\n  )\n)\n",
+            symbol_lib_header(&options)
+        );
 
-    Some(format!(
-        "  (pad \"\" np_thru_hole circle (at {} {}) (size {} {}) (drill {}))\n",
-        x, y, r, r, r
-    ))
-}
+        let written = write_symbol_lib(&dir, "lib", &content, &options).unwrap();
+        let names: Vec<_> = written
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        let resistor = fs::read_to_string(dir.join("R_10k.kicad_sym")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names, ["R_10k.kicad_sym", "C_100n.kicad_sym"]);
+        assert!(resistor.starts_with("(kicad_symbol_lib"), "{}", resistor);
+        assert!(
+            resistor.contains("(symbol \"R_10k\"") && !resistor.contains("C_100n"),
+            "{}",
+            resistor
+        );
+        assert_eq!(
+            resistor.matches('(').count(),
+            resistor.matches(')').count(),
+            "{}",
+            resistor
+        );
+    }
 
-fn parse_solid_region(_args: &[&str]) -> Option<String> {
-    Some(String::new())
-}
+    #[test]
+    fn translation_moves_positions_but_not_sizes() {
+        let content = "  (pad \"1\" smd rect (at 2 3 90) (size 1.5 0.5) (layers F.Cu))\n\
+                       \x20 (fp_line (start 1 1) (end 4 1) (layer F.SilkS) (width 0.12))\n\
+                       \x20 (fp_poly (pts (xy 0 0) (xy 1 2)) (layer F.Cu) (width 0))\n\
+                       \x20 (model x.step (offset (xyz 0 0 0)) (rotate (xyz 0 0 90)))\n";
+        assert_eq!(
+            translate_footprint(content, (1.0, 2.0)),
+            "  (pad \"1\" smd rect (at 1 1 90) (size 1.5 0.5) (layers F.Cu))\n\
+             \x20 (fp_line (start 0 -1) (end 3 -1) (layer F.SilkS) (width 0.12))\n\
+             \x20 (fp_poly (pts (xy -1 -2) (xy 0 0)) (layer F.Cu) (width 0))\n\
+             \x20 (model x.step (offset (xyz 0 0 0)) (rotate (xyz 0 0 90)))\n"
+        );
+        assert_eq!(translate_footprint(content, (0.0, 0.0)), content);
+    }
 
-fn parse_text(args: &[&str]) -> Option<String> {
-    if args.len() < 12 {
-        return None;
+    #[test]
+    fn download_timeout_is_never_shorter_than_the_request_timeout() {
+        let settings = |request_timeout_secs, download_timeout_secs| NetworkSettings {
+            request_timeout_secs,
+            download_timeout_secs,
+            ..Default::default()
+        };
+        let secs = |s: u64| Duration::from_secs(s);
+        assert_eq!(settings(20, 60).download_timeout(), secs(60));
+        assert_eq!(settings(90, 30).download_timeout(), secs(90));
+        // 0 means the default, for both timeouts.
+        assert_eq!(
+            settings(0, 0).request_timeout(),
+            secs(DEFAULT_REQUEST_TIMEOUT_SECS)
+        );
+        assert_eq!(
+            settings(0, 0).download_timeout(),
+            secs(DEFAULT_DOWNLOAD_TIMEOUT_SECS)
+        );
+        assert_eq!(settings(300, 0).download_timeout(), secs(300));
     }
 
-    let x = mil2mm(args[1].parse().unwrap_or(0.0));
-    let y = mil2mm(args[2].parse().unwrap_or(0.0));
-    let text = args.get(11).unwrap_or(&"");
+    #[test]
+    fn datasheet_overrides_match_ids_and_warn_when_missing() {
+        let options = ConversionOptions {
+            datasheet_overrides: BTreeMap::from([(
+                " c2040 ".to_string(),
+                " https://example.com/rp2040.pdf ".to_string(),
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.datasheet_override("C2040"),
+            Some("https://example.com/rp2040.pdf")
+        );
+        assert_eq!(options.missing_datasheet("C2040"), None);
+        assert!(options.missing_datasheet("C1525").is_some());
+        // Without any overrides there is nothing to miss.
+        assert_eq!(
+            ConversionOptions::default().missing_datasheet("C1525"),
+            None
+        );
+    }
 
-    Some(format!(
-        "  (fp_text user {} (at {} {}) (layer F.SilkS)\n    (effects (font (size 1 1)))\n  )\n",
-        text, x, y
-    ))
-}
+    #[test]
+    fn unit_order_swaps_the_gates_of_a_dual_part() {
+        let units = vec!["gate-a".to_string(), "gate-b".to_string()];
+        let options = ConversionOptions {
+            unit_order: BTreeMap::from([("C7950".to_string(), vec![2, 1])]),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.ordered_units("c7950", &units),
+            [&units[1], &units[0]]
+        );
+        // Parts without an order, and positions past the last unit, change nothing.
+        assert_eq!(options.ordered_units("C1", &units), [&units[0], &units[1]]);
+        let options = ConversionOptions {
+            unit_order: BTreeMap::from([("C7950".to_string(), vec![5])]),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.ordered_units("C7950", &units),
+            [&units[0], &units[1]]
+        );
+    }
 
-async fn create_symbol_internal(
-    client: &JlcClient,
-    symbol_uuids: &[String],
-    footprint_name: &str,
-    datasheet_link: &str,
-    component_id: &str,
-    output_dir: &str,
-    symbol_lib: &str,
-    symbol_path: &str,
-) -> Result<(), JlcError> {
-    let mut lib_content = String::new();
-    lib_content.push_str("(kicad_symbol_lib (version 20210201) (generator JLC2KiCad)\n");
+    #[test]
+    fn clipboard_symbol_is_the_first_whole_symbol() {
+        let lib = "(kicad_symbol_lib (version 20220914) (generator JLC2KiCad)\n\
+                   \x20 (symbol \"OPAMP\" (in_bom yes)\n\
+                   \x20   (property \"Value\" \"OPAMP (dual)\")\n\
+                   \x20   (symbol \"OPAMP_0_1\"\n\
+                   \x20     (rectangle (start 0 0) (end 1 1))\n\
+                   \x20   )\n\
+                   \x20 )\n\
+                   \x20 (symbol \"OTHER\")\n\
+                   )\n";
+        assert_eq!(
+            clipboard_symbol(lib).unwrap(),
+            "(symbol \"OPAMP\" (in_bom yes)\n\
+             \x20   (property \"Value\" \"OPAMP (dual)\")\n\
+             \x20   (symbol \"OPAMP_0_1\"\n\
+             \x20     (rectangle (start 0 0) (end 1 1))\n\
+             \x20   )\n\
+             \x20 )\n"
+        );
+        assert_eq!(
+            clipboard_symbol("(kicad_symbol_lib (version 20220914)\n)\n"),
+            None
+        );
+    }
 
-    for (idx, symbol_uuid) in symbol_uuids.iter().enumerate() {
-        let data = client.get_symbol_data(symbol_uuid).await?;
-        
-        let title = &data.result.title;
-        let component_name = title
-            .replace(" ", "_")
-            .replace(".", "_")
-            .replace("/", "{slash}")
-            .replace("\\", "{backslash}")
-            .replace("<", "{lt}")
-            .replace(">", "{gt}")
-            .replace(":", "{colon}")
-            .replace('"', "{dblquote}");
-
-        let prefix = data.result.package_detail.data_str.head.c_para.pre.replace("?", "");
+    #[test]
+    fn pro_model_lookup_is_answered_from_the_cache() {
+        // Seeded answers stand in for an earlier conversion of the same part;
+        // a network request here would fail the test.
+        if let Ok(mut cache) = response_cache().lock() {
+            cache
+                .step_uuids
+                .insert("C900001".to_string(), Some("model-uuid".to_string()));
+            cache.step_uuids.insert("C900002".to_string(), None);
+        }
+        let client = JlcClient::new();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for _ in 0..2 {
+            let found = runtime
+                .block_on(client.resolve_step_uuid_via_pro_api(" c900001 "))
+                .unwrap();
+            assert_eq!(found.as_deref(), Some("model-uuid"));
+            let missing = runtime
+                .block_on(client.resolve_step_uuid_via_pro_api("C900002"))
+                .unwrap();
+            assert_eq!(missing, None);
+        }
+    }
 
-        let shape = &data.result.data_str.shape;
-        let (origin_x, origin_y) = (data.result.data_str.head.x, data.result.data_str.head.y);
+    #[test]
+    fn svg_arcs_are_sampled_along_their_circle() {
+        // A half circle of radius 10 from (0 0) to (20 0), bulging up.
+        let paths = parse_svg_polyline_path("M 0 0 A 10 10 0 0 1 20 0 Z");
+        let points = &paths[0];
+        assert!(points.len() > 4, "{:?}", points);
+        for (x, y) in &points[..points.len() - 1] {
+            assert!(((x - 10.0).hypot(*y) - 10.0).abs() < 1e-6, "{:?}", points);
+            assert!(*y <= 1e-9, "{:?}", points);
+        }
+        assert_eq!(points.last(), Some(&(0.0, 0.0)));
+    }
 
-        let sym_name = if idx == 0 {
-            format!("{}_0", component_name)
-        } else {
-            component_name.clone()
+    #[test]
+    fn oversized_graphics_are_reported() {
+        let mut info = FootprintInfo::default();
+        let outline: Vec<(f64, f64)> = (0..=MAX_GRAPHIC_SOURCE_POINTS)
+            .map(|i| (i as f64, (i % 2) as f64))
+            .collect();
+        assert_eq!(graphic_polygons(&[outline], "F.SilkS", &mut info), "");
+        assert_eq!(info.warnings.len(), 1);
+    }
+
+    #[test]
+    fn estimates_only_use_pro_model_answers_already_looked_up() {
+        if let Ok(mut cache) = response_cache().lock() {
+            cache
+                .step_uuids
+                .insert("C900011".to_string(), Some("model-uuid".to_string()));
+        }
+        assert_eq!(
+            cached_pro_step_uuid("c900011"),
+            Some(Some("model-uuid".to_string()))
+        );
+        assert_eq!(cached_pro_step_uuid("C900012"), None);
+        // Not an LCSC code: the lookup would not make a request either.
+        assert_eq!(cached_pro_step_uuid("X12"), Some(None));
+    }
+
+    #[test]
+    fn composite_parts_pair_symbols_with_their_linked_footprints() {
+        let symbol = |uuid: &str, title: &str, package: Option<&str>| {
+            (
+                uuid.to_string(),
+                title.to_string(),
+                package.map(str::to_string),
+            )
         };
+        let footprints = vec!["fp-relay".to_string(), "fp-driver".to_string()];
+        // Listed in the opposite order of the footprints.
+        let parts = pair_composite_parts(
+            vec![
+                symbol("s-driver", "ULN2003", Some("fp-driver")),
+                symbol("s-relay.1", "Relay.1", Some("fp-relay")),
+                symbol("s-relay.2", "Relay.2", Some("fp-relay")),
+            ],
+            &footprints,
+        );
+        let pairs: Vec<(&str, Vec<&str>)> = parts
+            .iter()
+            .map(|p| {
+                (
+                    p.footprint_uuid.as_str(),
+                    p.symbol_uuids.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("fp-driver", vec!["s-driver"]),
+                ("fp-relay", vec!["s-relay.1", "s-relay.2"])
+            ]
+        );
 
-        lib_content.push_str(&format!(
-            "  (symbol \"{}\" (pin_names hide) (pin_numbers hide) (in_bom yes) (on_board yes)\n",
-            sym_name
-        ));
+        // Without a link to a footprint of the device it stays one part.
+        let unlinked = pair_composite_parts(
+            vec![
+                symbol("a", "Relay", Some("fp-relay")),
+                symbol("b", "ULN2003", None),
+            ],
+            &footprints,
+        );
+        assert!(unlinked.is_empty());
+        let shared = pair_composite_parts(
+            vec![
+                symbol("a", "Relay", Some("fp-relay")),
+                symbol("b", "ULN2003", Some("fp-relay")),
+            ],
+            &footprints,
+        );
+        assert!(shared.is_empty());
+        assert!(!ConversionOptions::default().split_composite_devices);
+    }
+
+    #[test]
+    fn debug_dumps_strip_only_the_3d_outline_geometry() {
+        let outline = r#"SVGNODE~{"attrs":{"c_etype":"outline3D","uuid":"m1"},"childNodes":[{"attrs":{"d":"M0 0"}}]}"#;
+        let stripped = strip_model_blob(outline);
+        assert!(stripped.contains("\"uuid\":\"m1\""), "{}", stripped);
+        assert!(!stripped.contains("childNodes"), "{}", stripped);
+
+        let graphic = r#"SVGNODE~{"attrs":{"layerid":"3"},"childNodes":[{"attrs":{"d":"M0 0"}}]}"#;
+        assert_eq!(strip_model_blob(graphic), graphic);
+    }
+
+    #[test]
+    fn geometry_rows_are_reported_relative_to_the_footprint_origin() {
+        let info = FootprintInfo {
+            metric: true,
+            origin: (10.0, 20.0),
+            geometry: Some(vec![GeometryRow {
+                kind: "PAD".to_string(),
+                source: (11.0, 22.0),
+                converted: (11.0, 22.0),
+            }]),
+            ..Default::default()
+        };
+        let geometry = footprint_geometry(&info, "C1", "R0603").unwrap();
+        assert_eq!(
+            (geometry.id.as_str(), geometry.footprint.as_str()),
+            ("C1", "R0603")
+        );
+        assert_eq!(geometry.source_unit, "mm");
+        assert_eq!(geometry.rows[0].source, (11.0, 22.0));
+        assert_eq!(geometry.rows[0].converted, (1.0, 2.0));
+        assert!(footprint_geometry(&FootprintInfo::default(), "C1", "R0603").is_none());
+    }
 
-        lib_content.push_str(&format!(
-            "    (property \"Reference\" \"{}\" (id 0) (at 0 1.27 0)\n      (effects (font (size 1.27 1.27)))\n    )\n",
-            prefix
-        ));
+    #[test]
+    fn kicad_cli_check_is_skipped_without_kicad_cli() {
+        let options = ConversionOptions {
+            kicad_cli_check: true,
+            ..Default::default()
+        };
+        let mut report = ConversionReport {
+            written_libraries: vec![std::env::temp_dir()],
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut runs = 0;
+        runtime.block_on(check_with_kicad_cli(&options, &mut report, None, |_, _| {
+            runs += 1;
+            async { Err("kicad-cli is not installed".to_string()) }
+        }));
+        assert_eq!(runs, 0);
+        assert!(report.warnings.is_empty());
+        assert!(
+            report.message.contains("未找到 kicad-cli"),
+            "{}",
+            report.message
+        );
+    }
 
-        lib_content.push_str(&format!(
-            "    (property \"Value\" \"{}\" (id 1) (at 0 -2.54 0)\n      (effects (font (size 1.27 1.27)))\n    )\n",
-            title
-        ));
+    #[test]
+    fn only_libraries_written_by_the_run_are_checked() {
+        let written = WrittenLibraries::default();
+        written.record(Path::new("out/symbol/Lib.kicad_sym"));
+        written.record(Path::new("out/Lib.pretty/R0603.kicad_mod"));
+        written.record(Path::new("out/Lib.pretty/C0603.kicad_mod"));
+        written.record(Path::new("out/packages3d/R0603.step"));
+        assert_eq!(
+            written.libraries(),
+            vec![
+                PathBuf::from("out/Lib.pretty"),
+                PathBuf::from("out/symbol/Lib.kicad_sym")
+            ]
+        );
+    }
 
-        lib_content.push_str(&format!(
-            "    (property \"Footprint\" \"{}\" (id 2) (at 0 -10.16 0)\n      (effects (font (size 1.27 1.27) italic) hide)\n    )\n",
-            footprint_name
-        ));
+    #[test]
+    fn gnd_flag_becomes_a_power_symbol() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-power-{}", std::process::id()));
+        let data_str = |prefix: &str| {
+            serde_json::json!({
+                "head": { "x": 0, "y": 0, "c_para": { "pre": prefix } },
+                "shape": ["P~1~4~1~0~0~0~0~0~0~0~0~0~0~GND"],
+            })
+            .to_string()
+        };
+        let device = |id: &str, symbol: &str| OfflineDevice {
+            id: id.to_string(),
+            name: "GND".to_string(),
+            footprint_uuid: None,
+            footprint_uuids: Vec::new(),
+            symbol_uuids: vec![symbol.to_string()],
+            model_title: None,
+        };
+        let bundle = OfflineBundle {
+            symbol_data: BTreeMap::from([
+                ("flag".to_string(), data_str("#PWR?")),
+                ("clip".to_string(), data_str("BT?")),
+            ]),
+            ..Default::default()
+        };
+        create_symbols_from_offline(
+            &[device("C1", "flag"), device("C2", "clip")],
+            &BTreeMap::new(),
+            &bundle,
+            &LocalConvertOptions {
+                output_dir: dir.to_string_lossy().into_owned(),
+                symbol_lib: "lib".to_string(),
+                symbol_path: "symbol".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lib = fs::read_to_string(dir.join("symbol").join("lib.kicad_sym")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(lib.contains("(symbol \"GND_C1\" (power) "), "{}", lib);
+        assert!(lib.contains("(property \"Footprint\" \"\""), "{}", lib);
+        // A single power pin alone does not make a power flag.
+        assert!(lib.contains("(symbol \"GND_C2\" (pin_names"), "{}", lib);
+        assert_eq!(
+            power_symbol_net("BT", Some("Power Symbol"), "", &["1".to_string()], "VCC").as_deref(),
+            Some("VCC")
+        );
+    }
 
-        lib_content.push_str(&format!(
-            "    (property \"Datasheet\" \"{}\" (id 3) (at -2.286 0.127 0)\n      (effects (font (size 1.27 1.27)) (justify left) hide)\n    )\n",
-            datasheet_link
-        ));
+    #[test]
+    fn offline_footprints_get_the_pad_one_marker() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-pad-one-{}", std::process::id()));
+        let ds = serde_json::json!({
+            "head": { "x": 0, "y": 0 },
+            "shape": [
+                "PAD~RECT~0~0~6~6~1~~1~0~~0~gge1~0",
+                "PAD~RECT~20~0~6~6~1~~2~0~~0~gge2~0",
+            ],
+        })
+        .to_string();
+        let device = OfflineDevice {
+            id: "C1".to_string(),
+            name: "R0603".to_string(),
+            footprint_uuid: Some("fp".to_string()),
+            footprint_uuids: vec!["fp".to_string()],
+            symbol_uuids: Vec::new(),
+            model_title: None,
+        };
+        let write = |pad_one_marker: bool| {
+            let options = ConversionOptions {
+                pad_one_marker,
+                ..Default::default()
+            };
+            let output_dir = dir.to_string_lossy();
+            create_footprint_from_offline(
+                &device,
+                Some("R0603"),
+                &ds,
+                &output_dir,
+                "lib.pretty",
+                "packages3d",
+                &[],
+                &BTreeMap::new(),
+                &options,
+            )
+            .unwrap();
+            fs::read_to_string(dir.join("lib.pretty").join("R0603.kicad_mod")).unwrap()
+        };
+        let plain = write(false);
+        let marked = write(true);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let marker = "(layer F.SilkS) (width 0.15) (fill solid)";
+        assert!(!plain.contains(marker), "{}", plain);
+        // Pad 1 is the left one, so the dot sits further left.
+        let dot = marked
+            .lines()
+            .find(|l| l.contains(marker))
+            .unwrap_or_else(|| panic!("{}", marked));
+        assert!(dot.contains("(center -1.212 0)"), "{}", dot);
+    }
+
+    #[test]
+    fn streamed_symbol_library_holds_every_symbol_between_header_and_footer() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-sym-stream-{}", std::process::id()));
+        let options = ConversionOptions::default();
+        let mut lib = SymbolLibWriter::new(&dir, "lib", &options).unwrap();
+        for n in 0..2000 {
+            lib.push(&format!("  (symbol \"R{}\" (pin_numbers hide)\n  )\n", n))
+                .unwrap();
+        }
+        let files = lib.finish(&dir, "lib", &options).unwrap();
+        let content = fs::read_to_string(&files[0]).unwrap();
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+
+        // Dropped unfinished, the library leaves neither a file nor its temp file.
+        let unfinished = dir.join("unfinished");
+        let mut lib = SymbolLibWriter::new(&unfinished, "lib", &options).unwrap();
+        lib.push("  (symbol \"R\"\n  )\n").unwrap();
+        drop(lib);
+        let left = fs::read_dir(&unfinished).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(names, vec!["lib.kicad_sym".to_string()]);
+        assert!(
+            content.starts_with(&symbol_lib_header(&options)),
+            "{}",
+            &content[..80]
+        );
+        assert!(content.ends_with("  )\n)\n"));
+        assert_eq!(content.matches("  (symbol \"R").count(), 2000);
+        assert_eq!(content.matches('(').count(), content.matches(')').count());
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn inner_layers_map_to_kicad_inner_copper_or_are_skipped() {
+        let mut info = FootprintInfo::default();
+        let inner1 = parse_track(&["1", "21", "", "0 0 10 0"], &mut info).unwrap();
+        assert!(inner1.contains("(layer In1.Cu)"), "{}", inner1);
+        assert_eq!(layer_map("50"), "In30.Cu");
+        assert!(info.warnings.is_empty());
+
+        assert_eq!(parse_track(&["1", "51", "", "0 0 10 0"], &mut info), None);
+        assert_eq!(parse_circle(&["0", "0", "5", "1", "52"], &mut info), None);
+        assert_eq!(info.warnings.len(), 2, "{:?}", info.warnings);
+        assert!(info.warnings[0].contains("Inner31"), "{:?}", info.warnings);
+    }
+
+    #[test]
+    fn grouped_footprints_get_a_library_per_package_family() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-grouped-{}", std::process::id()));
+        let symbol = serde_json::json!({
+            "head": { "x": 0, "y": 0, "c_para": { "pre": "U?" } },
+            "shape": ["R~0~0~~~10~10~#000~1~0~none~gge1~0"],
+        })
+        .to_string();
+        let bundle = OfflineBundle {
+            devices: BTreeMap::from([(
+                "C100".to_string(),
+                OfflineDevice {
+                    id: "C100".to_string(),
+                    name: "C100".to_string(),
+                    footprint_uuid: Some("fp".to_string()),
+                    footprint_uuids: vec!["fp".to_string()],
+                    symbol_uuids: vec!["sym".to_string()],
+                    model_title: None,
+                },
+            )]),
+            symbol_data: BTreeMap::from([("sym".to_string(), symbol)]),
+            footprint_data: BTreeMap::from([(
+                "fp".to_string(),
+                r#"{"head":{"x":0,"y":0},"shape":["TRACK~1~3~~0 0 10 0~gge1~0"]}"#.to_string(),
+            )]),
+            footprint_titles: BTreeMap::from([("fp".to_string(), "SOT-23-3".to_string())]),
+            ..Default::default()
+        };
+        let opts = LocalConvertOptions {
+            output_dir: dir.to_string_lossy().into_owned(),
+            footprint_lib: "footprint.pretty".to_string(),
+            symbol_lib: "lib".to_string(),
+            symbol_path: "symbol".to_string(),
+            create_footprint: true,
+            create_symbol: true,
+            conversion: ConversionOptions {
+                footprint_grouping: FootprintGrouping::Package,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report = ConversionReport {
+            found: vec!["C100".to_string()],
+            ..Default::default()
+        };
 
-        lib_content.push_str(&format!(
-            "    (property \"ki_keywords\" \"{}\" (id 4) (at 0 0 0)\n      (effects (font (size 1.27 1.27)) hide)\n    )\n",
-            component_id
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let report = runtime.block_on(convert_from_bundle(
+            &bundle,
+            &BTreeMap::new(),
+            "elibz",
+            report,
+            &opts,
         ));
+        let footprint = dir.join("footprint_SOT.pretty").join("SOT-23-3.kicad_mod");
+        let written = footprint.is_file();
+        let lib = fs::read_to_string(dir.join("symbol").join("lib.kicad_sym")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.converted, ["C100"]);
+        assert!(written);
+        assert!(
+            lib.contains("(property \"Footprint\" \"footprint_SOT:SOT-23-3\""),
+            "{}",
+            lib
+        );
 
-        lib_content.push_str(&format!(
-            "    (property \"LCSC\" \"{}\" (id 5) (at 0 0 0)\n      (effects (font (size 1.27 1.27)) hide)\n    )\n",
-            component_id
-        ));
+        let by_maker = ConversionOptions {
+            footprint_grouping: FootprintGrouping::Manufacturer,
+            ..Default::default()
+        };
+        assert_eq!(
+            by_maker.footprint_library("footprint", "SOIC-8", Some("Texas Instruments")),
+            "footprint_Texas_Instruments"
+        );
+        assert_eq!(
+            by_maker.footprint_library("footprint", "SOIC-8", None),
+            "footprint_Other"
+        );
+        assert_eq!(
+            ConversionOptions::default().footprint_library("footprint", "SOIC-8", None),
+            "footprint"
+        );
+    }
 
-        // Parse symbol shapes
-        for line in shape {
-            let parts: Vec<&str> = line.split('~').filter(|s| !s.is_empty()).collect();
-            if parts.is_empty() {
-                continue;
-            }
+    #[test]
+    fn only_refused_head_requests_count_as_models_of_unknown_size() {
+        assert!(head_refused(reqwest::StatusCode::METHOD_NOT_ALLOWED));
+        assert!(head_refused(reqwest::StatusCode::NOT_IMPLEMENTED));
+        assert!(!head_refused(reqwest::StatusCode::NOT_FOUND));
+        assert!(!head_refused(reqwest::StatusCode::FORBIDDEN));
+    }
 
-            let model = parts[0];
-            let args: Vec<&str> = parts[1..].to_vec();
+    #[test]
+    fn url_model_ids_get_distinct_plain_cache_names() {
+        let name = |id: &str| {
+            model_cache_path(id).map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        };
+        let Some(first) = name("https://example.com/models/a?b=1") else {
+            return; // No cache folder on this machine.
+        };
+        let second = name("https://example.com/models/a?b=2").unwrap();
 
-            match model {
-                "P" => {
-                    if let Some(pin_str) = parse_symbol_pin(&args, origin_x, origin_y) {
-                        lib_content.push_str(&pin_str);
-                    }
-                }
-                "R" => {
-                    if let Some(rect_str) = parse_symbol_rect(&args, origin_x, origin_y) {
-                        lib_content.push_str(&rect_str);
-                    }
-                }
-                "E" => {
-                    if let Some(circle_str) = parse_symbol_circle(&args, origin_x, origin_y) {
-                        lib_content.push_str(&circle_str);
-                    }
-                }
-                "T" => {
-                    if let Some(text_str) = parse_symbol_text(&args, origin_x, origin_y) {
-                        lib_content.push_str(&text_str);
-                    }
-                }
-                "PL" | "PG" => {
-                    if let Some(poly_str) = parse_symbol_poly(&args, origin_x, origin_y) {
-                        lib_content.push_str(&poly_str);
-                    }
-                }
-                "A" => {
-                    // Arc - simplified
-                }
-                _ => {}
-            }
+        assert_ne!(first, second);
+        for file_name in [&first, &second] {
+            assert!(invalid_name_reason(file_name).is_none(), "{}", file_name);
+            assert!(file_name.starts_with("url_") && file_name.ends_with(".step"));
         }
-
-        lib_content.push_str("  )\n");
+        assert_eq!(name("abc123").unwrap(), "abc123.step");
     }
 
-    lib_content.push_str(")\n");
-
-    // Write to file
-    let output_path = PathBuf::from(output_dir).join(symbol_path);
-    fs::create_dir_all(&output_path)?;
-    let file_path = output_path.join(format!("{}.kicad_sym", symbol_lib));
-    let mut file = File::create(file_path)?;
-    file.write_all(lib_content.as_bytes())?;
+    #[test]
+    fn mirroring_to_the_back_swaps_layers_and_negates_x() {
+        let footprint = concat!(
+            "  (pad \"1\" smd rect (at 1.5 -2 90) (size 1 0.6) (layers F.Cu F.Paste F.Mask))\n",
+            "  (fp_text reference REF** (at 2 3) (layer F.SilkS)\n",
+            "    (effects (font (size 1 1) (thickness 0.15)))\n",
+            "  )\n",
+            "  (fp_arc (start 1 0) (mid 0 1) (end -1 0) (layer F.SilkS) (width 0.12))\n",
+        );
+        let mirrored = mirror_footprint_to_back(footprint);
+        // Sign of the turn from start over mid to end: positive is counter-clockwise.
+        let turn = |text: &str| {
+            let point = |key: &str| {
+                let at = text.find(&format!("({} ", key)).unwrap() + key.len() + 2;
+                let mut values = text[at..]
+                    .split([' ', ')'])
+                    .map(|v| v.parse::<f64>().unwrap());
+                (values.next().unwrap(), values.next().unwrap())
+            };
+            let ((sx, sy), (mx, my), (ex, ey)) = (point("start"), point("mid"), point("end"));
+            ((mx - sx) * (ey - sy) - (my - sy) * (ex - sx)).signum()
+        };
 
-    Ok(())
-}
+        assert!(
+            mirrored.contains("(at -1.5 -2 270) (size 1 0.6) (layers B.Cu B.Paste B.Mask)"),
+            "{}",
+            mirrored
+        );
+        assert!(
+            mirrored.contains("(at -2 3) (layer B.SilkS)"),
+            "{}",
+            mirrored
+        );
+        assert!(
+            mirrored.contains("(effects (font (size 1 1) (thickness 0.15)) (justify mirror))"),
+            "{}",
+            mirrored
+        );
+        assert!(
+            mirrored.contains("(fp_arc (start -1 0) (mid 0 1) (end 1 0) (layer B.SilkS)"),
+            "{}",
+            mirrored
+        );
+        assert_eq!(turn(&mirrored), -turn(footprint));
+        assert!(!mirrored.contains("F."), "{}", mirrored);
+    }
+
+    #[test]
+    fn pad_number_ranges_expand_only_when_ascending_and_numeric() {
+        assert_eq!(expand_pad_numbers("1-4"), ["1", "2", "3", "4"]);
+        assert_eq!(expand_pad_numbers(" 1,2 "), ["1", "2"]);
+        assert_eq!(expand_pad_numbers("1"), ["1"]);
+        // Descending, lettered and incomplete ranges stay single pad names.
+        assert_eq!(expand_pad_numbers("4-1"), ["4-1"]);
+        assert_eq!(expand_pad_numbers("A1-A3"), ["A1-A3"]);
+        assert_eq!(expand_pad_numbers("1-"), ["1-"]);
+        assert_eq!(expand_pad_numbers("1-500"), ["1-500"]);
+    }
+
+    #[test]
+    fn slotted_pads_get_an_oval_drill_along_their_long_side() {
+        let pad = |rotation: &str| {
+            let mut info = FootprintInfo {
+                metric: true,
+                ..Default::default()
+            };
+            let fields = [
+                "OVAL", "0", "0", "1.6", "3", "11", "", "1", "0.4", "", rotation, "gge1", "2", "",
+                "Y",
+            ];
+            parse_pad(&fields, &mut info).unwrap()
+        };
 
-fn parse_symbol_pin(args: &[&str], origin_x: f64, origin_y: f64) -> Option<String> {
-    if args.len() < 14 {
-        return None;
+        let upright = pad("0");
+        assert!(
+            upright
+                .contains("(pad \"1\" thru_hole oval (at 0 0 0) (size 1.6 3) (drill oval 0.8 2)"),
+            "{}",
+            upright
+        );
+        let turned = pad("90");
+        assert!(
+            turned.contains("(at 0 0 90) (size 1.6 3) (drill oval 0.8 2)"),
+            "{}",
+            turned
+        );
     }
 
-    let electrical_type = match args[1] {
-        "0" => "unspecified",
-        "1" => "input",
-        "2" => "output",
-        "3" => "bidirectional",
-        "4" => "power_in",
-        _ => "unspecified",
-    };
+    #[test]
+    fn arc_mid_points_follow_the_sweep_direction() {
+        let half_chord = 10.0 * 60f64.to_radians().sin();
+        let mid = |sweep: u8| {
+            let mut info = FootprintInfo {
+                metric: true,
+                ..Default::default()
+            };
+            let path = format!("M 0 0 A 10 10 0 0 {} {} 0", sweep, 2.0 * half_chord);
+            let arc = parse_arc(&["0.2", "3", "", &path], &mut info).unwrap();
+            let at = arc.find("(mid ").unwrap() + 5;
+            let mut values = arc[at..]
+                .split([' ', ')'])
+                .map(|v| v.parse::<f64>().unwrap());
+            (values.next().unwrap(), values.next().unwrap())
+        };
 
-    let pin_num = args[2];
-    let x = mil2mm(args[3].parse::<f64>().unwrap_or(0.0) - origin_x);
-    let y = -mil2mm(args[4].parse::<f64>().unwrap_or(0.0) - origin_y);
-    let rotation: i32 = args.get(5).and_then(|s| s.parse().ok()).unwrap_or(0);
-    let rotation = (rotation + 180) % 360;
-    let pin_name = args.get(13).unwrap_or(&"");
+        // A 120° arc of radius 10 bulges 5 off its chord: up (y < 0) when
+        // clockwise on screen, down when counter-clockwise.
+        let (cw, ccw) = (mid(1), mid(0));
+        assert!(
+            (cw.0 - half_chord).abs() < 1e-6 && (cw.1 + 5.0).abs() < 1e-6,
+            "{:?}",
+            cw
+        );
+        assert!(
+            (ccw.0 - half_chord).abs() < 1e-6 && (ccw.1 - 5.0).abs() < 1e-6,
+            "{:?}",
+            ccw
+        );
 
-    let length = 2.54;
+        let (_, _, cw_sweep) =
+            svg_arc_geometry((0.0, 0.0), (2.0 * half_chord, 0.0), 10.0, false, true).unwrap();
+        let (_, _, ccw_sweep) =
+            svg_arc_geometry((0.0, 0.0), (2.0 * half_chord, 0.0), 10.0, false, false).unwrap();
+        assert!((cw_sweep.to_degrees() - 120.0).abs() < 1e-6, "{}", cw_sweep);
+        assert!(
+            (ccw_sweep.to_degrees() + 120.0).abs() < 1e-6,
+            "{}",
+            ccw_sweep
+        );
+    }
 
-    Some(format!(
-        "    (pin {} line (at {} {} {}) (length {})\n      (name \"{}\" (effects (font (size 1 1))))\n      (number \"{}\" (effects (font (size 1 1))))\n    )\n",
-        electrical_type, x, y, rotation, length, pin_name, pin_num
-    ))
-}
+    #[test]
+    fn full_turn_arcs_become_circles_and_shorter_ones_stay_arcs() {
+        let arc = |sweep_deg: f64| {
+            let mut info = FootprintInfo {
+                metric: true,
+                ..Default::default()
+            };
+            // Two halves, since one SVG arc cannot end where it starts.
+            let (x, y) = (
+                10.0 * sweep_deg.to_radians().cos(),
+                10.0 * sweep_deg.to_radians().sin(),
+            );
+            let path = format!("M 10 0 A 10 10 0 0 1 -10 0 A 10 10 0 0 1 {} {}", x, y);
+            parse_arc(&["0.2", "3", "", &path], &mut info).unwrap()
+        };
 
-fn parse_symbol_rect(args: &[&str], origin_x: f64, origin_y: f64) -> Option<String> {
-    if args.len() < 6 {
-        return None;
+        let full = arc(360.0);
+        assert!(
+            full.starts_with("  (fp_circle (center 0 0) (end 10 0)"),
+            "{}",
+            full
+        );
+        let open = arc(350.0);
+        assert!(
+            !open.contains("fp_circle") && open.matches("(fp_arc").count() == 2,
+            "{}",
+            open
+        );
     }
 
-    let x1 = mil2mm(args[0].parse::<f64>().unwrap_or(0.0) - origin_x);
-    let y1 = -mil2mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_y);
-    let width = mil2mm(args[4].parse::<f64>().unwrap_or(0.0));
-    let length = mil2mm(args[5].parse::<f64>().unwrap_or(0.0));
-    let x2 = x1 + width;
-    let y2 = y1 - length;
+    #[test]
+    fn mil_and_mm_footprints_come_out_the_same() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-units-{}", std::process::id()));
+        let device = OfflineDevice {
+            id: "C1".to_string(),
+            name: "R0805".to_string(),
+            footprint_uuid: Some("fp".to_string()),
+            footprint_uuids: vec!["fp".to_string()],
+            symbol_uuids: Vec::new(),
+            model_title: None,
+        };
+        let write = |unit: Option<&str>, pads: [&str; 2]| {
+            let mut head = serde_json::json!({ "x": 0, "y": 0 });
+            if let Some(unit) = unit {
+                head["unit"] = unit.into();
+            }
+            let ds = serde_json::json!({ "head": head, "shape": pads }).to_string();
+            let lib = if unit.is_some() {
+                "mm.pretty"
+            } else {
+                "mil.pretty"
+            };
+            create_footprint_from_offline(
+                &device,
+                Some("R0805"),
+                &ds,
+                &dir.to_string_lossy(),
+                lib,
+                "packages3d",
+                &[],
+                &BTreeMap::new(),
+                &ConversionOptions::default(),
+            )
+            .unwrap();
+            fs::read_to_string(dir.join(lib).join("R0805.kicad_mod")).unwrap()
+        };
 
-    Some(format!(
-        "    (rectangle (start {} {}) (end {} {}) (stroke (width 0) (type default)) (fill (type background)))\n",
-        x1, y1, x2, y2
-    ))
-}
+        // 3.937 of EasyEDA's 10 mil units make a millimetre.
+        let mil = write(
+            None,
+            [
+                "PAD~RECT~0~0~3.937~7.874~1~~1~0~~0~gge1~0",
+                "PAD~RECT~19.685~0~3.937~7.874~1~~2~0~~0~gge2~0",
+            ],
+        );
+        let mm = write(
+            Some("mm"),
+            [
+                "PAD~RECT~0~0~1~2~1~~1~0~~0~gge1~0",
+                "PAD~RECT~5~0~1~2~1~~2~0~~0~gge2~0",
+            ],
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(mm.contains("(size 1 2)"), "{}", mm);
+        assert_eq!(mil, mm);
+    }
+
+    #[test]
+    fn local_libraries_with_a_utf8_bom_load_like_those_without() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-bom-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let device = r#"{"footprints":{"fp1":{"title":"SOIC-8"}}}"#;
+        let load = |name: &str, content: &str| {
+            let path = dir.join(name);
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            writer
+                .start_file("device.json", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+            writer.finish().unwrap();
+            load_offline_bundle_from_elibz(&path)
+                .unwrap()
+                .footprint_titles
+        };
 
-fn parse_symbol_circle(args: &[&str], origin_x: f64, origin_y: f64) -> Option<String> {
-    if args.len() < 3 {
-        return None;
-    }
+        let plain = load("plain.elibz", device);
+        let with_bom = load("bom.elibz", &format!("\u{feff}{}", device));
+        fs::remove_dir_all(&dir).unwrap();
 
-    let x = mil2mm(args[0].parse::<f64>().unwrap_or(0.0) - origin_x);
-    let y = -mil2mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_y);
-    let r = mil2mm(args[2].parse::<f64>().unwrap_or(0.0));
+        assert_eq!(plain.get("fp1").map(String::as_str), Some("SOIC-8"));
+        assert_eq!(with_bom, plain);
+        assert_eq!(
+            parse_json_lenient::<serde_json::Value>("\u{feff} {\"a\":1}\n").unwrap(),
+            serde_json::json!({ "a": 1 })
+        );
+    }
 
-    Some(format!(
-        "    (circle (center {} {}) (radius {}) (stroke (width 0) (type default)) (fill (type background)))\n",
-        x, y, r
-    ))
-}
+    #[test]
+    fn failed_atomic_writes_leave_the_original_alone() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let options = ConversionOptions::default();
+
+        // The temporary file cannot be created: a folder sits at its path.
+        let file = dir.join("lib.kicad_sym");
+        fs::write(&file, "good").unwrap();
+        fs::create_dir_all(atomic_tmp_path(&file)).unwrap();
+        assert!(write_output_file(&file, b"truncated", &options).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "good");
+
+        // The temporary file cannot be renamed over a non-empty folder.
+        let target = dir.join("footprint.kicad_mod");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("keep"), "kept").unwrap();
+        assert!(write_file_atomic(&target, b"new").is_err());
+        assert_eq!(fs::read_to_string(target.join("keep")).unwrap(), "kept");
+        assert!(!atomic_tmp_path(&target).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unwritable_output_dirs_fail_up_front_with_a_clear_error() {
+        let dir = std::env::temp_dir().join(format!("jlc2kicad-unwritable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A file where a folder should be is unwritable even for root.
+        let blocker = dir.join("blocker");
+        fs::write(&blocker, "").unwrap();
+        let output_dir = blocker.join("out").to_string_lossy().into_owned();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(create_component(
+            "C2040",
+            &output_dir,
+            "footprint",
+            "lib",
+            "symbol",
+            "packages3d",
+            vec!["STEP".to_string()],
+            true,
+            true,
+            &ConversionOptions::default(),
+        ));
+        let entries = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
 
-fn parse_symbol_text(args: &[&str], origin_x: f64, origin_y: f64) -> Option<String> {
-    if args.len() < 12 {
-        return None;
+        match result {
+            Err(JlcError::ApiError(message)) => {
+                assert!(message.contains(&output_dir), "{}", message);
+                assert!(message.contains("请检查目录权限"), "{}", message);
+            }
+            other => panic!("{:?}", other.map(|r| r.message)),
+        }
+        assert_eq!(entries, 1);
     }
 
-    let x = mil2mm(args[1].parse::<f64>().unwrap_or(0.0) - origin_x);
-    let y = -mil2mm(args[2].parse::<f64>().unwrap_or(0.0) - origin_y);
-    let rotation: i32 = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(0);
-    let rotation = (rotation + 180) % 360 * 10;
-    let text = args.get(11).unwrap_or(&"");
-
-    Some(format!(
-        "    (text \"{}\" (at {} {} {}) (effects (font (size 1.27 1.27))))\n",
-        text, x, y, rotation
-    ))
-}
+    #[test]
+    fn guarded_bodies_reject_oversized_and_html_responses() {
+        let response = |content_type: &str, body: &str| {
+            let response = http::Response::builder()
+                .header("content-type", content_type)
+                .body(body.to_string())
+                .unwrap();
+            reqwest::Response::from(response)
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
 
-fn parse_symbol_poly(args: &[&str], origin_x: f64, origin_y: f64) -> Option<String> {
-    if args.is_empty() {
-        return None;
-    }
+        let json = runtime.block_on(read_body_guarded(response("application/json", "{}"), 16));
+        assert_eq!(json.unwrap(), b"{}");
 
-    let points_str = args[0];
-    let points: Vec<f64> = points_str
-        .split(' ')
-        .filter(|s| !s.is_empty())
-        .filter_map(|s| s.parse().ok())
-        .collect();
+        let oversized = "x".repeat(17);
+        let oversized = runtime.block_on(read_body_guarded(
+            response("application/json", &oversized),
+            16,
+        ));
+        assert!(
+            matches!(oversized, Err(JlcError::ApiError(ref m)) if m.contains("响应体过大")),
+            "{:?}",
+            oversized
+        );
 
-    if points.len() < 4 {
-        return None;
+        let captcha = runtime.block_on(read_body_guarded(
+            response("text/html; charset=utf-8", "{}"),
+            16,
+        ));
+        assert!(
+            matches!(captcha, Err(JlcError::HtmlResponse(_))),
+            "{:?}",
+            captcha
+        );
+        let unlabeled = runtime.block_on(read_body_guarded(
+            response("application/octet-stream", "<!DOCTYPE html>"),
+            16,
+        ));
+        assert!(
+            matches!(unlabeled, Err(JlcError::HtmlResponse(_))),
+            "{:?}",
+            unlabeled
+        );
     }
 
-    let mut pts_str = String::new();
-    for i in (0..points.len()).step_by(2) {
-        if i + 1 < points.len() {
-            let x = mil2mm(points[i] - origin_x);
-            let y = -mil2mm(points[i + 1] - origin_y);
-            pts_str.push_str(&format!("(xy {} {}) ", x, y));
-        }
+    #[test]
+    fn demorgan_pairs_units_with_the_same_pins_in_the_same_places() {
+        let units = [
+            symbol_unit("A", &[("1", -5.08, 0.0), ("2", 5.08, 0.0)]),
+            symbol_unit("B", &[("1", -5.08, 0.0), ("2", 5.08, 0.0)]),
+        ];
+        assert_eq!(demorgan_alternates(&units), vec![None, Some(0)]);
     }
 
-    Some(format!(
-        "    (polyline (pts {}) (stroke (width 0) (type default)) (fill (type none)))\n",
-        pts_str
-    ))
+    #[test]
+    fn demorgan_keeps_units_with_moved_pins_apart() {
+        let units = [
+            symbol_unit("A", &[("1", -5.08, 0.0), ("2", 5.08, 0.0)]),
+            symbol_unit("B", &[("1", -5.08, 2.54), ("2", 5.08, 0.0)]),
+        ];
+        assert_eq!(demorgan_alternates(&units), vec![None, None]);
+    }
 }
@@ -1,14 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use jlc2kicad_tauri_lib::{
-    create_component, search_easyeda as do_easyeda, search_lcsc as do_lcsc,
-    load_local_folder as do_load, SearchResult, NetworkSettings,
+    create_component, search_easyeda_streaming, cancel_search as do_cancel_search,
+    search_lcsc as do_lcsc,
+    load_local_folder as do_load, parse_component_id_list, SearchResult, NetworkSettings,
     get_network_settings as get_net_settings, set_network_settings as set_net_settings,
+    ConversionOptions, KicadInstallation, clear_caches as do_clear_caches,
+    list_footprint_variants as do_list_variants, FootprintVariant,
+    list_footprint_thumbnails as do_list_thumbnails, FootprintThumbnail,
+    parse_kicad_schematic as do_parse_schematic, SchematicParts,
+    update_library as do_update_library, LibraryUpdateOptions, ConversionReport,
+    convert_to_archive, ArchiveOptions, open_local_bundle as do_open_bundle, expand_output_dir,
+    close_local_bundle as do_close_bundle, convert_local_bundle as do_convert_bundle,
+    BundleHandle, LocalConvertOptions, get_component_details as do_get_details, ComponentDetails,
+    plan_conversion as do_plan_conversion, ConversionPlan,
+    search_codes as do_search_codes, CodeSearchReport,
+    estimate_download as do_estimate_download, DownloadEstimate,
+    debug_dump as do_debug_dump, ComponentDump,
+    clipboard_text as do_clipboard_text, ClipboardKind,
+    check_with_kicad_cli, find_kicad_cli, CliOutput,
 };
 use serde::{Deserialize, Serialize};
-use tauri::Emitter;
-#[cfg(debug_assertions)]
-use tauri::Manager;
+use tauri::{Emitter, Manager};
+use tauri_plugin_shell::ShellExt;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateComponentOptions {
@@ -21,6 +35,8 @@ pub struct CreateComponentOptions {
     pub models: Vec<String>,
     pub create_footprint: bool,
     pub create_symbol: bool,
+    #[serde(default)]
+    pub conversion: ConversionOptions,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +50,22 @@ pub struct LocalOptions {
     pub models: Vec<String>,
     pub create_footprint: bool,
     pub create_symbol: bool,
+    #[serde(default)]
+    pub conversion: ConversionOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdListOptions {
+    pub output_dir: String,
+    pub footprint_lib: String,
+    pub symbol_lib: String,
+    pub symbol_path: String,
+    pub model_dir: String,
+    pub models: Vec<String>,
+    pub create_footprint: bool,
+    pub create_symbol: bool,
+    #[serde(default)]
+    pub conversion: ConversionOptions,
 }
 
 #[derive(Debug, Serialize)]
@@ -43,6 +75,14 @@ pub struct CommandResult {
     pub error: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct LocalCommandResult {
+    pub success: bool,
+    pub message: String,
+    pub error: Option<String>,
+    pub report: Option<ConversionReport>,
+}
+
 #[tauri::command]
 async fn create_component_cmd(
     options: CreateComponentOptions,
@@ -62,10 +102,15 @@ async fn create_component_cmd(
         options.models,
         options.create_footprint,
         options.create_symbol,
+        &options.conversion,
     )
     .await
     {
-        Ok(message) => {
+        Ok(result) => {
+            let mut message = result.message;
+            for warning in &result.warnings {
+                message.push_str(&format!("\n警告: {}", warning));
+            }
             window.emit("progress", &message).ok();
             Ok(CommandResult {
                 success: true,
@@ -93,8 +138,72 @@ fn get_default_output_dir() -> String {
 }
 
 #[tauri::command]
-async fn search_easyeda_cmd(query: String) -> Result<Vec<SearchResult>, String> {
-    do_easyeda(&query).await.map_err(|e| e.to_string())
+fn detect_kicad() -> KicadInstallation {
+    jlc2kicad_tauri_lib::detect_kicad()
+}
+
+#[tauri::command]
+async fn list_footprint_variants(component_id: String) -> Result<Vec<FootprintVariant>, String> {
+    do_list_variants(&component_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_footprint_thumbnails(
+    component_id: String,
+) -> Result<Vec<FootprintThumbnail>, String> {
+    do_list_thumbnails(&component_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_component_details(component_id: String) -> Result<ComponentDetails, String> {
+    do_get_details(&component_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn debug_dump(component_id: String) -> Result<ComponentDump, String> {
+    do_debug_dump(&component_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn clipboard_text(
+    component_id: String,
+    kind: ClipboardKind,
+    conversion: ConversionOptions,
+) -> Result<String, String> {
+    do_clipboard_text(&component_id, kind, &conversion)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_easyeda_cmd(
+    query: String,
+    window: tauri::Window,
+) -> Result<Vec<SearchResult>, String> {
+    search_easyeda_streaming(&query, |result| {
+        window.emit("search-result", result).ok();
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_codes(
+    codes: Vec<String>,
+    window: tauri::Window,
+) -> Result<CodeSearchReport, String> {
+    do_search_codes(&codes, |done, total| {
+        window
+            .emit("progress", format!("[{}/{}] 正在查询编号...", done, total))
+            .ok();
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn cancel_search() {
+    do_cancel_search();
 }
 
 #[tauri::command]
@@ -111,7 +220,7 @@ async fn load_local_folder(path: String) -> Result<Vec<SearchResult>, String> {
 async fn convert_local(
     options: LocalOptions,
     window: tauri::Window,
-) -> Result<CommandResult, String> {
+) -> Result<LocalCommandResult, String> {
     window.emit("progress", "正在转换本地文件...").ok();
     
     match jlc2kicad_tauri_lib::convert_local_folder(
@@ -124,28 +233,279 @@ async fn convert_local(
         options.models,
         options.create_footprint,
         options.create_symbol,
+        &options.conversion,
     )
     .await
     {
-        Ok(message) => {
-            window.emit("progress", &message).ok();
-            Ok(CommandResult {
+        Ok(mut report) => {
+            check_libraries(&window, &options.conversion, &mut report).await;
+            window.emit("progress", &report.message).ok();
+            Ok(LocalCommandResult {
                 success: true,
-                message,
+                message: report.message.clone(),
                 error: None,
+                report: Some(report),
             })
         }
         Err(e) => {
             let error_msg = e.to_string();
-            Ok(CommandResult {
+            Ok(LocalCommandResult {
                 success: false,
                 message: "转换失败".to_string(),
                 error: Some(error_msg),
+                report: None,
             })
         }
     }
 }
 
+/// Loads the libraries a local conversion wrote with kicad-cli, run through
+/// the shell plugin, when `kicad_cli_check` is set.
+async fn check_libraries(
+    window: &tauri::Window,
+    options: &ConversionOptions,
+    report: &mut ConversionReport,
+) {
+    if options.kicad_cli_check {
+        window.emit("progress", "正在用 kicad-cli 校验生成的库...").ok();
+    }
+    let app = window.app_handle().clone();
+    check_with_kicad_cli(options, report, find_kicad_cli(), |cli, args| {
+        let app = app.clone();
+        async move {
+            let output = app
+                .shell()
+                .command(cli)
+                .args(args)
+                .output()
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(CliOutput {
+                success: output.status.success(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        }
+    })
+    .await;
+}
+
+#[tauri::command]
+fn open_local_bundle(path: String) -> Result<BundleHandle, String> {
+    do_open_bundle(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn plan_conversion(path: String, options: LocalConvertOptions) -> Result<ConversionPlan, String> {
+    do_plan_conversion(&path, &options).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn close_local_bundle(handle: u64) {
+    do_close_bundle(handle);
+}
+
+#[tauri::command]
+async fn convert_local_bundle(
+    handle: u64,
+    component_ids: Vec<String>,
+    options: LocalConvertOptions,
+    window: tauri::Window,
+) -> Result<LocalCommandResult, String> {
+    window.emit("progress", "正在转换本地文件...").ok();
+
+    match do_convert_bundle(handle, &component_ids, &options).await {
+        Ok(mut report) => {
+            check_libraries(&window, &options.conversion, &mut report).await;
+            window.emit("progress", &report.message).ok();
+            Ok(LocalCommandResult {
+                success: true,
+                message: report.message.clone(),
+                error: None,
+                report: Some(report),
+            })
+        }
+        Err(e) => Ok(LocalCommandResult {
+            success: false,
+            message: "转换失败".to_string(),
+            error: Some(e.to_string()),
+            report: None,
+        }),
+    }
+}
+
+#[tauri::command]
+fn parse_kicad_schematic(path: String) -> Result<SchematicParts, String> {
+    do_parse_schematic(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn estimate_download(
+    text: String,
+    options: IdListOptions,
+    window: tauri::Window,
+) -> Result<DownloadEstimate, String> {
+    let list = parse_component_id_list(&text);
+    do_estimate_download(
+        &list.ids,
+        &options.models,
+        options.create_footprint,
+        options.create_symbol,
+        &options.conversion,
+        |done, total| {
+            window
+                .emit("progress", format!("[{}/{}] 正在估算下载量...", done, total))
+                .ok();
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn convert_id_list(
+    text: String,
+    options: IdListOptions,
+    window: tauri::Window,
+) -> Result<CommandResult, String> {
+    let list = parse_component_id_list(&text);
+    if list.ids.is_empty() {
+        return Ok(CommandResult {
+            success: false,
+            message: "未识别到元件编号".to_string(),
+            error: Some(format!("以下行未包含有效编号:\n{}", list.unmatched_lines.join("\n"))),
+        });
+    }
+
+    // Resolve {date}/{lib} once so a run spanning midnight stays in one folder.
+    let output_dir = expand_output_dir(&options.output_dir, None, &options.symbol_lib);
+    let total = list.ids.len();
+    let mut success = 0usize;
+    let mut failed: Vec<String> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+
+    for (idx, component_id) in list.ids.iter().enumerate() {
+        window
+            .emit("progress", format!("[{}/{}] 正在转换 {}...", idx + 1, total, component_id))
+            .ok();
+        match create_component(
+            component_id,
+            &output_dir,
+            &options.footprint_lib,
+            &options.symbol_lib,
+            &options.symbol_path,
+            &options.model_dir,
+            options.models.clone(),
+            options.create_footprint,
+            options.create_symbol,
+            &options.conversion,
+        )
+        .await
+        {
+            Ok(result) => {
+                success += 1;
+                warnings.extend(result.warnings.iter().map(|w| format!("{}: {}", component_id, w)));
+            }
+            Err(e) => failed.push(format!("{}: {}", component_id, e)),
+        }
+    }
+
+    let mut message = format!("批量转换完成，成功 {} 个，失败 {} 个", success, failed.len());
+    if !failed.is_empty() {
+        message.push_str(&format!("\n{}", failed.join("\n")));
+    }
+    if !warnings.is_empty() {
+        message.push_str(&format!("\n警告:\n{}", warnings.join("\n")));
+    }
+    if !list.unmatched_lines.is_empty() {
+        message.push_str(&format!(
+            "\n以下行未包含有效编号:\n{}",
+            list.unmatched_lines.join("\n")
+        ));
+    }
+    window.emit("progress", &message).ok();
+
+    Ok(CommandResult {
+        success: failed.is_empty(),
+        message,
+        error: None,
+    })
+}
+
+#[tauri::command]
+async fn convert_and_zip(
+    text: String,
+    options: ArchiveOptions,
+    window: tauri::Window,
+) -> Result<CommandResult, String> {
+    let list = parse_component_id_list(&text);
+    if list.ids.is_empty() {
+        return Ok(CommandResult {
+            success: false,
+            message: "未识别到元件编号".to_string(),
+            error: None,
+        });
+    }
+
+    window
+        .emit("progress", format!("正在转换 {} 个元件并打包...", list.ids.len()))
+        .ok();
+    match convert_to_archive(&list.ids, &options).await {
+        Ok(result) => {
+            window.emit("progress", &result.report.message).ok();
+            Ok(CommandResult {
+                success: result.report.failed.is_empty(),
+                message: result.report.message,
+                error: None,
+            })
+        }
+        Err(e) => Ok(CommandResult {
+            success: false,
+            message: "转换并打包失败".to_string(),
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
+#[tauri::command]
+async fn update_library(
+    symbol_lib_path: String,
+    options: LibraryUpdateOptions,
+    window: tauri::Window,
+) -> Result<CommandResult, String> {
+    window.emit("progress", "正在更新符号库...").ok();
+
+    match do_update_library(&symbol_lib_path, &options).await {
+        Ok(report) => {
+            let mut message = format!(
+                "更新完成，有变化 {} 个，无变化 {} 个，失败 {} 个",
+                report.changed.len(),
+                report.unchanged.len(),
+                report.failed.len()
+            );
+            if !report.changed.is_empty() {
+                message.push_str(&format!("\n有变化: {}", report.changed.join(", ")));
+            }
+            if !report.failed.is_empty() {
+                message.push_str(&format!("\n{}", report.failed.join("\n")));
+            }
+            if let Some(backup) = &report.backup_path {
+                message.push_str(&format!("\n原文件已备份到 {}", backup));
+            }
+            Ok(CommandResult {
+                success: report.failed.is_empty(),
+                message,
+                error: None,
+            })
+        }
+        Err(e) => Ok(CommandResult {
+            success: false,
+            message: "更新符号库失败".to_string(),
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
 #[tauri::command]
 fn get_network_settings_cmd() -> NetworkSettings {
     get_net_settings()
@@ -167,6 +527,26 @@ fn set_network_settings_cmd(settings: NetworkSettings) -> Result<CommandResult,
     }
 }
 
+#[tauri::command]
+fn clear_caches() -> Result<CommandResult, String> {
+    match do_clear_caches() {
+        Ok(report) => Ok(CommandResult {
+            success: true,
+            message: format!(
+                "已清理 {} 项缓存，释放 {:.1} KB",
+                report.entries_cleared,
+                report.bytes_freed as f64 / 1024.0
+            ),
+            error: None,
+        }),
+        Err(e) => Ok(CommandResult {
+            success: false,
+            message: "清理缓存失败".to_string(),
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
 fn main() {
     env_logger::init();
     log::info!("Starting JLC2KiCad application");
@@ -187,12 +567,30 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             create_component_cmd,
             get_default_output_dir,
+            detect_kicad,
+            list_footprint_variants,
+            list_footprint_thumbnails,
+            get_component_details,
+            debug_dump,
+            clipboard_text,
             search_easyeda_cmd,
+            cancel_search,
+            search_codes,
             search_lcsc,
             load_local_folder,
             convert_local,
+            open_local_bundle,
+            close_local_bundle,
+            plan_conversion,
+            convert_local_bundle,
+            convert_id_list,
+            estimate_download,
+            parse_kicad_schematic,
+            convert_and_zip,
+            update_library,
             get_network_settings_cmd,
             set_network_settings_cmd,
+            clear_caches,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
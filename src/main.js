@@ -6,6 +6,8 @@ const VERSION = "20260228-1";
 let searchResults = [];
 let selectedComponent = null;
 let currentSource = "easyeda";
+let streamingSearch = false;
+let localBundle = null;
 
 console.log("JLC2KiCad version:", VERSION);
 
@@ -47,6 +49,31 @@ async function init() {
   }
 
   await loadNetworkSettings();
+  await detectKicad();
+}
+
+async function detectKicad() {
+  try {
+    const kicad = await invoke("detect_kicad");
+    if (!kicad.found) return;
+
+    const hint = document.getElementById("kicadHint");
+    const parts = [`检测到 KiCad ${kicad.version}`];
+    if (kicad.output_dir) parts.push(`建议输出目录: ${kicad.output_dir}`);
+    if (kicad.model_base_variable) {
+      parts.push(`建议路径变量: ${kicad.model_base_variable}`);
+      document.getElementById("modelBaseVariable").placeholder = kicad.model_base_variable;
+    }
+    const major = parseInt(kicad.version, 10);
+    const versionSelect = document.getElementById("kicadVersion");
+    if ([...versionSelect.options].some((o) => o.value === String(major))) {
+      versionSelect.value = String(major);
+    }
+    hint.textContent = parts.join(" | ");
+    hint.classList.remove("hidden");
+  } catch (error) {
+    console.warn("KiCad 检测失败", error);
+  }
 }
 
 function setupEventListeners() {
@@ -88,6 +115,10 @@ function hideResults() {
   document.getElementById("selectedPart").textContent = "-";
   hideMessage();
   selectedComponent = null;
+  const variantSelect = document.getElementById("footprintVariant");
+  variantSelect.innerHTML = "";
+  variantSelect.classList.add("hidden");
+  clearVariantThumbnails();
 }
 
 function showResults(items) {
@@ -150,6 +181,7 @@ function showResults(items) {
         <div class="part-info">
           <span class="part-id">${item.id}</span>
           <span class="part-name">${item.name}</span>
+          ${item.jlc_class ? `<span class="part-class">${item.jlc_class === 'Basic' ? '基础库' : '扩展库'}</span>` : ''}
         </div>
         <span class="part-desc">${item.description || ''}</span>
       `;
@@ -169,6 +201,103 @@ function selectComponent(index) {
   selectedComponent = searchResults[index];
   
   document.getElementById("selectedPart").textContent = `${selectedComponent.id} - ${selectedComponent.name}`;
+  loadFootprintVariants(selectedComponent);
+  loadComponentDetails(selectedComponent);
+}
+
+async function loadComponentDetails(component) {
+  const details = document.getElementById("componentDetails");
+  details.textContent = "";
+  details.classList.add("hidden");
+  if (currentSource === "local") {
+    return;
+  }
+
+  try {
+    const info = await invoke("get_component_details", { componentId: component.id });
+    if (selectedComponent !== component) {
+      return;
+    }
+    const parts = [
+      info.footprint && `封装: ${info.footprint}`,
+      info.manufacturer && `制造商: ${info.manufacturer}`,
+      info.jlc_class && (info.jlc_class === "Basic" ? "基础库" : "扩展库"),
+      info.stock && `库存: ${info.stock}`,
+      info.price && `价格: ${info.price}`,
+      info.has_3d_model ? "有3D模型" : "无3D模型",
+    ].filter(Boolean);
+    details.textContent = parts.join(" | ");
+    details.classList.remove("hidden");
+  } catch (error) {
+    console.warn("获取元件详情失败", error);
+  }
+}
+
+async function loadFootprintVariants(component) {
+  const select = document.getElementById("footprintVariant");
+  select.innerHTML = "";
+  select.classList.add("hidden");
+  clearVariantThumbnails();
+  if (currentSource === "local") {
+    return;
+  }
+
+  try {
+    const variants = await invoke("list_footprint_variants", { componentId: component.id });
+    if (selectedComponent !== component || variants.length < 2) {
+      return;
+    }
+    variants.forEach((variant) => {
+      const option = document.createElement("option");
+      option.value = variant.is_default ? "" : variant.uuid;
+      option.textContent = variant.is_default ? `${variant.title}（默认）` : variant.title;
+      select.appendChild(option);
+    });
+    select.value = "";
+    select.classList.remove("hidden");
+    loadVariantThumbnails(component);
+  } catch (error) {
+    console.warn("获取封装变体失败", error);
+  }
+}
+
+function clearVariantThumbnails() {
+  const container = document.getElementById("variantThumbnails");
+  container.innerHTML = "";
+  container.classList.add("hidden");
+}
+
+async function loadVariantThumbnails(component) {
+  const container = document.getElementById("variantThumbnails");
+  try {
+    const thumbnails = await invoke("list_footprint_thumbnails", { componentId: component.id });
+    if (selectedComponent !== component) {
+      return;
+    }
+    const select = document.getElementById("footprintVariant");
+    thumbnails.forEach((thumb) => {
+      const value = thumb.is_default ? "" : thumb.uuid;
+      const item = document.createElement("button");
+      item.type = "button";
+      item.className = "variant-thumb";
+      item.title = thumb.title;
+      if (thumb.svg) {
+        const img = document.createElement("img");
+        img.src = `data:image/svg+xml;charset=utf-8,${encodeURIComponent(thumb.svg)}`;
+        img.alt = thumb.title;
+        item.appendChild(img);
+      } else {
+        item.textContent = "无预览";
+      }
+      item.onclick = () => {
+        select.value = value;
+      };
+      container.appendChild(item);
+    });
+    container.classList.remove("hidden");
+  } catch (error) {
+    console.warn("获取封装预览失败", error);
+  }
 }
 
 async function searchEasyEDA() {
@@ -180,6 +309,9 @@ async function searchEasyEDA() {
 
   hideResults();
   showStatus("正在搜索...");
+  searchResults = [];
+  streamingSearch = true;
+  document.getElementById("cancelSearchBtn").classList.remove("hidden");
 
   try {
     const results = await invoke("search_easyeda_cmd", { query: input });
@@ -188,7 +320,21 @@ async function searchEasyEDA() {
     showResults(results);
   } catch (error) {
     hideStatus();
+    if (searchResults.length > 0) {
+      showResults(searchResults);
+    }
     showMessage(`搜索失败: ${error}`, true);
+  } finally {
+    streamingSearch = false;
+    document.getElementById("cancelSearchBtn").classList.add("hidden");
+  }
+}
+
+async function cancelSearch() {
+  try {
+    await invoke("cancel_search");
+  } catch (error) {
+    console.warn("取消搜索失败", error);
   }
 }
 
@@ -225,10 +371,15 @@ async function selectFolder() {
       document.getElementById("localPath").value = selected;
       
       showStatus("正在加载本地数据...");
-      const results = await invoke("load_local_folder", { path: selected });
-      searchResults = results;
+      if (localBundle) {
+        await invoke("close_local_bundle", { handle: localBundle.handle });
+        localBundle = null;
+      }
+      localBundle = await invoke("open_local_bundle", { path: selected });
+      searchResults = localBundle.components;
       hideStatus();
-      showResults(results);
+      showResults(searchResults);
+      showConversionPlan(selected);
     }
   } catch (error) {
     hideStatus();
@@ -236,6 +387,129 @@ async function selectFolder() {
   }
 }
 
+// Summarises how much of a local source converts offline, assuming a full export.
+async function showConversionPlan(path) {
+  const hint = document.getElementById("localPlan");
+  hint.classList.add("hidden");
+  try {
+    const plan = await invoke("plan_conversion", {
+      path,
+      options: {
+        models: ["STEP"],
+        create_footprint: true,
+        create_symbol: true,
+        conversion: conversionOptions(),
+      },
+    });
+    hint.textContent = plan.message;
+    hint.classList.remove("hidden");
+  } catch (error) {
+    console.warn("plan_conversion failed", error);
+  }
+}
+
+// Converts from the loaded bundle; a bundle whose files changed is reopened once.
+async function convertLocal(options) {
+  const path = document.getElementById("localPath").value;
+  if (!localBundle) {
+    return invoke("convert_local", { options: { path, ...options } });
+  }
+  const convert = () =>
+    invoke("convert_local_bundle", { handle: localBundle.handle, componentIds: [], options });
+  let result = await convert();
+  if (!result.success && result.error?.includes("重新加载")) {
+    localBundle = await invoke("open_local_bundle", { path });
+    result = await convert();
+  }
+  return result;
+}
+
+// "C12345=R_0402_10k" per line; a tab or spaces also separate id and name.
+function parseNameOverrides(text) {
+  const overrides = {};
+  text.split(/\r?\n/).forEach((line) => {
+    const match = line.trim().match(/^([Cc]\d+)\s*(?:=|\t|\s)\s*(.+)$/);
+    if (match) {
+      overrides[match[1].toUpperCase()] = match[2].trim();
+    }
+  });
+  return overrides;
+}
+
+// "id,url" lines (a header row or quoted fields are fine) to a map of datasheet links.
+function parseDatasheetCsv(text) {
+  const links = {};
+  text.split(/\r?\n/).forEach((line) => {
+    const fields = line.split(/[,;\t]/).map((f) => f.trim().replace(/^"(.*)"$/, "$1").trim());
+    if (fields.length >= 2 && /^[Cc]\d+$/.test(fields[0]) && fields[1]) {
+      links[fields[0].toUpperCase()] = fields[1];
+    }
+  });
+  return links;
+}
+
+// "C12345=2,1" lines to 1-based unit positions per part.
+function parseUnitOrder(text) {
+  const orders = {};
+  text.split(/\r?\n/).forEach((line) => {
+    const match = line.trim().match(/^([Cc]\d+)\s*=\s*([\d\s,]+)$/);
+    if (match) {
+      const order = match[2].split(/[\s,]+/).filter(Boolean).map((n) => parseInt(n, 10));
+      if (order.length) {
+        orders[match[1].toUpperCase()] = order;
+      }
+    }
+  });
+  return orders;
+}
+
+function conversionOptions() {
+  return {
+    mirror_to_back: document.getElementById("mirrorToBack").checked,
+    model_base_variable: document.getElementById("modelBaseVariable").value.trim(),
+    demorgan: document.getElementById("demorgan").checked,
+    footprint_variant: document.getElementById("footprintVariant").value,
+    all_footprint_variants: document.getElementById("allFootprintVariants").checked,
+    split_composite_devices: document.getElementById("splitCompositeDevices").checked,
+    min_silk_width: parseFloat(document.getElementById("minSilkWidth").value) || 0,
+    footprint_grouping: document.getElementById("footprintGrouping").value,
+    kicad_version: parseInt(document.getElementById("kicadVersion").value, 10) || 0,
+    rollback_on_failure: document.getElementById("rollbackOnFailure").checked,
+    resume: document.getElementById("resumeConversion").checked,
+    online_fallback: document.getElementById("onlineFallback").checked,
+    model_search_dirs: document
+      .getElementById("modelSearchDirs")
+      .value.split(/\r?\n/)
+      .map((dir) => dir.trim())
+      .filter(Boolean),
+    shared_model_dir: document.getElementById("sharedModelDir").value.trim(),
+    name_overrides: parseNameOverrides(document.getElementById("nameOverrides").value),
+    datasheet_overrides: parseDatasheetCsv(document.getElementById("datasheetCsv").value),
+    unit_order: parseUnitOrder(document.getElementById("unitOrder").value),
+    embed_3d_model: document.getElementById("embed3dModel").checked,
+    coordinate_decimals: parseInt(document.getElementById("coordinateDecimals").value, 10),
+    pin_text_size: parseFloat(document.getElementById("pinTextSize").value),
+    pin_name_offset: parseFloat(document.getElementById("pinNameOffset").value),
+    simplify_tolerance: parseFloat(document.getElementById("simplifyTolerance").value),
+    name_prefix: document.getElementById("namePrefix").value.trim(),
+    prefix_manufacturer: document.getElementById("prefixManufacturer").checked,
+    write_index: document.getElementById("writeIndex").checked,
+    strict_3d: document.getElementById("strict3d").checked,
+    strict_parse: document.getElementById("strictParse").checked,
+    passive_names: document.getElementById("passiveNames").checked,
+    annotate_generator: document.getElementById("annotateGenerator").checked,
+    deterministic: document.getElementById("deterministicOutput").checked,
+    geometry_report: document.getElementById("geometryReport").checked,
+    kicad_cli_check: document.getElementById("kicadCliCheck").checked,
+    pad_one_marker: document.getElementById("padOneMarker").checked,
+    ascii_file_names: document.getElementById("asciiFileNames").checked,
+    sort_pads_and_pins: document.getElementById("sortPadsAndPins").checked,
+    symbol_per_part: document.getElementById("symbolPerPart").checked,
+    preferred_3d_format: document.getElementById("preferred3dFormat").value,
+    model_reference_only: document.getElementById("modelReferenceOnly").checked,
+  };
+}
+
 async function exportOne(type) {
   if (!selectedComponent) {
     showMessage("请先选择一个零件", true);
@@ -255,18 +529,16 @@ async function exportOne(type) {
     if (type === 'symbol') {
       // 导出器件库
       if (currentSource === "local") {
-        result = await invoke("convert_local", {
-          options: {
-            path: document.getElementById("localPath").value,
-            output_dir: outputDir,
-            footprint_lib: footprintLib,
-            symbol_lib: symbolLib,
-            symbol_path: "symbol",
-            model_dir: "packages3d",
-            models: [],
-            create_footprint: false,
-            create_symbol: true,
-          },
+        result = await convertLocal({
+          output_dir: outputDir,
+          footprint_lib: footprintLib,
+          symbol_lib: symbolLib,
+          symbol_path: "symbol",
+          model_dir: "packages3d",
+          models: [],
+          create_footprint: false,
+          create_symbol: true,
+          conversion: conversionOptions(),
         });
       } else {
         result = await invoke("create_component_cmd", {
@@ -280,24 +552,23 @@ async function exportOne(type) {
             models: [],
             create_footprint: false,
             create_symbol: true,
+            conversion: conversionOptions(),
           },
         });
       }
     } else if (type === 'footprint') {
       // 导出封装库
       if (currentSource === "local") {
-        result = await invoke("convert_local", {
-          options: {
-            path: document.getElementById("localPath").value,
-            output_dir: outputDir,
-            footprint_lib: footprintLib,
-            symbol_lib: symbolLib,
-            symbol_path: "symbol",
-            model_dir: "packages3d",
-            models: [],
-            create_footprint: true,
-            create_symbol: false,
-          },
+        result = await convertLocal({
+          output_dir: outputDir,
+          footprint_lib: footprintLib,
+          symbol_lib: symbolLib,
+          symbol_path: "symbol",
+          model_dir: "packages3d",
+          models: [],
+          create_footprint: true,
+          create_symbol: false,
+          conversion: conversionOptions(),
         });
       } else {
         result = await invoke("create_component_cmd", {
@@ -311,24 +582,23 @@ async function exportOne(type) {
             models: [],
             create_footprint: true,
             create_symbol: false,
+            conversion: conversionOptions(),
           },
         });
       }
     } else if (type === '3d') {
       // 导出3D模型
       if (currentSource === "local") {
-        result = await invoke("convert_local", {
-          options: {
-            path: document.getElementById("localPath").value,
-            output_dir: outputDir,
-            footprint_lib: footprintLib,
-            symbol_lib: symbolLib,
-            symbol_path: "symbol",
-            model_dir: "packages3d",
-            models: ["STEP"],
-            create_footprint: false,
-            create_symbol: false,
-          },
+        result = await convertLocal({
+          output_dir: outputDir,
+          footprint_lib: footprintLib,
+          symbol_lib: symbolLib,
+          symbol_path: "symbol",
+          model_dir: "packages3d",
+          models: ["STEP"],
+          create_footprint: false,
+          create_symbol: false,
+          conversion: conversionOptions(),
         });
       } else {
         result = await invoke("create_component_cmd", {
@@ -342,6 +612,7 @@ async function exportOne(type) {
             models: ["STEP"],
             create_footprint: false,
             create_symbol: false,
+            conversion: conversionOptions(),
           },
         });
       }
@@ -349,7 +620,9 @@ async function exportOne(type) {
 
     hideStatus();
 
-    if (result.success) {
+    if (result.success && result.report?.failed.length) {
+      showMessage(`⚠️ ${result.message}`, true);
+    } else if (result.success) {
       showMessage(`✅ ${result.message}`);
     } else {
       showMessage(`❌ ${result.error || "导出失败"}`, true);
@@ -360,6 +633,225 @@ async function exportOne(type) {
   }
 }
 
+async function importSchematic() {
+  try {
+    const selected = await openDialog({
+      directory: false,
+      multiple: false,
+      title: "选择 KiCad 原理图",
+      filters: [{ name: "KiCad 原理图", extensions: ["kicad_sch"] }],
+    });
+    if (!selected) {
+      return;
+    }
+
+    const parts = await invoke("parse_kicad_schematic", { path: selected });
+    if (parts.ids.length === 0) {
+      showMessage("原理图中未找到带 LCSC 编号的元件", true);
+      return;
+    }
+    document.getElementById("idListInput").value = parts.ids.join("\n");
+    let message = `已从 ${parts.sheets.length} 张图纸中导入 ${parts.ids.length} 个元件编号`;
+    if (parts.missing_sheets.length > 0) {
+      message += `\n以下子图纸无法读取:\n${parts.missing_sheets.join("\n")}`;
+    }
+    showMessage(message, parts.missing_sheets.length > 0);
+  } catch (error) {
+    showMessage(`读取原理图失败: ${error}`, true);
+  }
+}
+
+async function updateLibrary() {
+  try {
+    const selected = await openDialog({
+      directory: false,
+      multiple: false,
+      title: "选择要更新的符号库",
+      filters: [{ name: "KiCad 符号库", extensions: ["kicad_sym"] }],
+    });
+    if (!selected) {
+      return;
+    }
+
+    hideMessage();
+    showStatus("正在更新符号库...");
+    const result = await invoke("update_library", {
+      symbolLibPath: selected,
+      options: {
+        output_dir: document.getElementById("outputDir").value.trim(),
+        artifacts: {
+          footprint: true,
+          symbol: true,
+          model: true,
+          footprint_lib: document.getElementById("footprintLib").value.trim(),
+          model_dir: "packages3d",
+          conversion: { ...conversionOptions(), footprint_variant: "" },
+        },
+      },
+    });
+    hideStatus();
+    if (result.success) {
+      showMessage(`✅ ${result.message}`);
+    } else {
+      showMessage(`❌ ${result.message}${result.error ? `\n${result.error}` : ""}`, true);
+    }
+  } catch (error) {
+    hideStatus();
+    showMessage(`更新符号库失败: ${error}`, true);
+  }
+}
+
+async function convertIdList() {
+  const text = document.getElementById("idListInput").value;
+  if (!text.trim()) {
+    showMessage("请粘贴元件编号列表", true);
+    return;
+  }
+
+  hideMessage();
+  showStatus("正在批量转换...");
+
+  try {
+    const result = await invoke("convert_id_list", {
+      text,
+      options: {
+        output_dir: document.getElementById("outputDir").value.trim(),
+        footprint_lib: document.getElementById("footprintLib").value.trim(),
+        symbol_lib: document.getElementById("symbolLib").value.trim(),
+        symbol_path: "symbol",
+        model_dir: "packages3d",
+        models: ["STEP"],
+        create_footprint: true,
+        create_symbol: true,
+        conversion: { ...conversionOptions(), footprint_variant: "" },
+      },
+    });
+
+    hideStatus();
+
+    if (result.success) {
+      showMessage(`✅ ${result.message}`);
+    } else {
+      showMessage(`❌ ${result.error || result.message}`, true);
+    }
+  } catch (error) {
+    hideStatus();
+    showMessage(`❌ 批量转换失败: ${error}`, true);
+  }
+}
+
+async function estimateIdList() {
+  const text = document.getElementById("idListInput").value;
+  if (!text.trim()) {
+    showMessage("请粘贴元件编号列表", true);
+    return;
+  }
+
+  hideMessage();
+  showStatus("正在估算下载量...");
+
+  try {
+    const estimate = await invoke("estimate_download", {
+      text,
+      options: {
+        output_dir: document.getElementById("outputDir").value.trim(),
+        footprint_lib: document.getElementById("footprintLib").value.trim(),
+        symbol_lib: document.getElementById("symbolLib").value.trim(),
+        symbol_path: "symbol",
+        model_dir: "packages3d",
+        models: ["STEP"],
+        create_footprint: true,
+        create_symbol: true,
+        conversion: { ...conversionOptions(), footprint_variant: "" },
+      },
+    });
+    hideStatus();
+    const failed = estimate.parts.filter((p) => p.error).map((p) => `${p.id}: ${p.error}`);
+    showMessage(
+      `📦 ${estimate.message}${failed.length > 0 ? `\n${failed.join("\n")}` : ""}`,
+      failed.length > 0
+    );
+  } catch (error) {
+    hideStatus();
+    showMessage(`❌ 估算下载量失败: ${error}`, true);
+  }
+}
+
+async function searchIdList() {
+  const codes = document.getElementById("idListInput").value.match(/C\d+/gi) || [];
+  if (codes.length === 0) {
+    showMessage("请粘贴元件编号列表", true);
+    return;
+  }
+
+  hideMessage();
+  showStatus("正在批量查询...");
+
+  try {
+    const report = await invoke("search_codes", { codes });
+    hideStatus();
+    searchResults = report.results;
+    showResults(searchResults);
+    const missing = [...report.not_found, ...report.skipped];
+    if (missing.length > 0) {
+      showMessage(`找到 ${report.results.length} 个元件，未找到: ${missing.join(", ")}`, true);
+    } else {
+      showMessage(`✅ 找到全部 ${report.results.length} 个元件`);
+    }
+  } catch (error) {
+    hideStatus();
+    showMessage(`❌ 批量查询失败: ${error}`, true);
+  }
+}
+
+async function convertAndZip() {
+  const text = document.getElementById("idListInput").value;
+  if (!text.trim()) {
+    showMessage("请粘贴元件编号列表", true);
+    return;
+  }
+
+  hideMessage();
+  showStatus("正在转换并打包...");
+
+  try {
+    const result = await invoke("convert_and_zip", {
+      text,
+      options: {
+        output_dir: document.getElementById("outputDir").value.trim(),
+        archive_name: "",
+        footprint_lib: document.getElementById("footprintLib").value.trim(),
+        symbol_lib: document.getElementById("symbolLib").value.trim(),
+        symbol_path: "symbol",
+        model_dir: "packages3d",
+        models: ["STEP"],
+        create_footprint: true,
+        create_symbol: true,
+        conversion: { ...conversionOptions(), footprint_variant: "" },
+      },
+    });
+
+    hideStatus();
+
+    if (result.success) {
+      showMessage(`✅ ${result.message}`);
+    } else {
+      showMessage(`❌ ${result.error || result.message}`, true);
+    }
+  } catch (error) {
+    hideStatus();
+    showMessage(`❌ 转换并打包失败: ${error}`, true);
+  }
+}
+
+listen("search-result", (event) => {
+  if (!streamingSearch) return;
+  searchResults.push(event.payload);
+  showResults(searchResults);
+}).catch((error) => {
+  console.warn("search-result event listen disabled:", error);
+});
+
 listen("progress", (event) => {
   const log = document.getElementById("progressLog");
   const p = document.createElement("p");
@@ -369,12 +861,35 @@ listen("progress", (event) => {
   console.warn("progress event listen disabled:", error);
 });
 
+function parseHeaderLines(text) {
+  const headers = {};
+  text.split(/\r?\n/).forEach((line) => {
+    const match = line.trim().match(/^([^:\s]+)\s*:\s*(.*)$/);
+    if (match) {
+      headers[match[1]] = match[2].trim();
+    }
+  });
+  return headers;
+}
+
+function formatHeaderLines(headers) {
+  return Object.entries(headers || {})
+    .map(([name, value]) => `${name}: ${value}`)
+    .join("\n");
+}
+
 async function loadNetworkSettings() {
   try {
     const settings = await invoke("get_network_settings_cmd");
     document.getElementById("easyedaUseProxy").checked = !!settings.easyeda_use_proxy;
     document.getElementById("lcscUseProxy").checked = !!settings.lcsc_use_proxy;
     document.getElementById("proxyAddress").value = settings.proxy_address || "";
+    document.getElementById("easyedaCookie").value = settings.easyeda_cookie || "";
+    document.getElementById("easyedaToken").value = settings.easyeda_token || "";
+    document.getElementById("requestTimeout").value = settings.request_timeout_secs || 20;
+    document.getElementById("downloadTimeout").value = settings.download_timeout_secs || 120;
+    document.getElementById("easyedaHeaders").value = formatHeaderLines(settings.easyeda_headers);
+    document.getElementById("lcscHeaders").value = formatHeaderLines(settings.lcsc_headers);
   } catch (error) {
     console.error("加载网络设置失败", error);
   }
@@ -394,6 +909,12 @@ async function saveNetworkSettings() {
     easyeda_use_proxy: document.getElementById("easyedaUseProxy").checked,
     lcsc_use_proxy: document.getElementById("lcscUseProxy").checked,
     proxy_address: document.getElementById("proxyAddress").value.trim(),
+    easyeda_cookie: document.getElementById("easyedaCookie").value.trim(),
+    easyeda_token: document.getElementById("easyedaToken").value.trim(),
+    request_timeout_secs: parseInt(document.getElementById("requestTimeout").value, 10) || 0,
+    download_timeout_secs: parseInt(document.getElementById("downloadTimeout").value, 10) || 0,
+    easyeda_headers: parseHeaderLines(document.getElementById("easyedaHeaders").value),
+    lcsc_headers: parseHeaderLines(document.getElementById("lcscHeaders").value),
   };
 
   try {
@@ -409,10 +930,66 @@ async function saveNetworkSettings() {
   }
 }
 
+async function copyDebugDump() {
+  if (!selectedComponent || currentSource === "local") {
+    showMessage("请先选择一个在线零件", true);
+    return;
+  }
+  try {
+    const dump = await invoke("debug_dump", { componentId: selectedComponent.id });
+    await navigator.clipboard.writeText(JSON.stringify(dump, null, 2));
+    showMessage(`已复制 ${dump.id} 的调试数据，可粘贴到问题报告中`);
+  } catch (error) {
+    showMessage(`获取调试数据失败: ${error}`, true);
+  }
+}
+
+async function copyToClipboard(kind) {
+  if (!selectedComponent || currentSource === "local") {
+    showMessage("请先选择一个在线零件", true);
+    return;
+  }
+  try {
+    const text = await invoke("clipboard_text", {
+      componentId: selectedComponent.id,
+      kind,
+      conversion: conversionOptions(),
+    });
+    await navigator.clipboard.writeText(text);
+    const editor = kind === "symbol" ? "符号编辑器" : "封装编辑器";
+    showMessage(`已复制 ${selectedComponent.id}，可在 KiCad ${editor}中按 Ctrl+V 粘贴`);
+  } catch (error) {
+    showMessage(`复制失败: ${error}`, true);
+  }
+}
+
+async function clearCaches() {
+  try {
+    const result = await invoke("clear_caches");
+    if (result.success) {
+      showMessage(result.message);
+    } else {
+      showMessage(`清理失败: ${result.error || "未知错误"}`, true);
+    }
+  } catch (error) {
+    showMessage(`清理失败: ${error}`, true);
+  }
+}
+
 // Expose handlers for inline onclick bindings in index.html.
 window.searchEasyEDA = searchEasyEDA;
+window.cancelSearch = cancelSearch;
 window.searchLCSC = searchLCSC;
 window.selectFolder = selectFolder;
 window.exportOne = exportOne;
+window.convertIdList = convertIdList;
+window.estimateIdList = estimateIdList;
+window.searchIdList = searchIdList;
+window.convertAndZip = convertAndZip;
+window.importSchematic = importSchematic;
+window.updateLibrary = updateLibrary;
 window.toggleNetworkSettings = toggleNetworkSettings;
 window.saveNetworkSettings = saveNetworkSettings;
+window.clearCaches = clearCaches;
+window.copyDebugDump = copyDebugDump;
+window.copyToClipboard = copyToClipboard;